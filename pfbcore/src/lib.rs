@@ -2,20 +2,27 @@
 //! the PFB projects.
 pub mod scorecard;
 
-use crate::scorecard::{City, CommunitySurvey, ScoreCard, BNA};
+#[cfg(feature = "python")]
+use crate::scorecard::{City, CommunitySurvey, Run, ScoreCard, BNA};
+#[cfg(feature = "python")]
 use pyo3::exceptions::PyOSError;
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
 use std::fmt;
 use std::io;
+use std::str::FromStr;
 use thiserror::Error;
 
 /// Represent the PFB S3 storage base URL.
-const PFB_S3_STORAGE_BASE_URL: &str =
+pub const PFB_S3_STORAGE_BASE_URL: &str =
     "https://s3.amazonaws.com/production-pfb-storage-us-east-1/results";
 
 /// Represent the name of the "neighborhood ways" dataset.
 const DS_NEIGHBORHOOD_WAYS: &str = "neighborhood_ways";
 const DS_NEIGHBORHOOD_OVERALL_SCORES: &str = "neighborhood_overall_scores";
+const DS_OSM_EXTRACT: &str = "osm_extract";
+const DS_NEIGHBORHOOD_CENSUS_BLOCKS: &str = "neighborhood_census_blocks";
+const DS_NEIGHBORHOOD_CENSUS_BLOCK_POPULATION: &str = "neighborhood_census_block_population";
 
 /// Errors that can happen when using pfbcore.
 #[derive(Error, Debug)]
@@ -23,6 +30,9 @@ pub enum Error {
     /// Error from an underlying system.
     #[error("Internal error: {0}")]
     Internal(String),
+    /// Unknown dataset name.
+    #[error("Unknown dataset: {0}")]
+    UnknownDataset(String),
     /// Error from the URL crate.
     #[error("URL error")]
     Url {
@@ -30,6 +40,7 @@ pub enum Error {
         source: url::ParseError,
     },
     /// Error from the CSV crate.
+    #[cfg(feature = "csv")]
     #[error("CSV error")]
     Csv {
         #[from]
@@ -43,25 +54,77 @@ pub enum Error {
     },
 }
 
+#[cfg(feature = "python")]
 impl std::convert::From<Error> for PyErr {
     fn from(err: Error) -> PyErr {
         PyOSError::new_err(err.to_string())
     }
 }
 
+/// Return `true` if `uuid` is a valid run UUID.
+///
+/// A run UUID is made of 32 hexadecimal digits, in 5 groups separated by
+/// hyphens: `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx`.
+pub fn is_valid_uuid(uuid: &str) -> bool {
+    let groups = uuid.split('-').collect::<Vec<&str>>();
+    groups.len() == 5
+        && groups
+            .iter()
+            .zip([8, 4, 4, 4, 12])
+            .all(|(g, len)| g.len() == len && g.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
 /// Describe all the available city datasets.
-#[derive(Debug, PartialEq, PartialOrd, Clone, Copy)]
+///
+/// The datasets are ordered by declaration order:
+///
+/// ```
+/// use pfbcore::Dataset;
+///
+/// assert!(Dataset::NeighborhoodWays < Dataset::NeighborhoodOverallScores);
+/// ```
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub enum Dataset {
     NeighborhoodWays,
     NeighborhoodOverallScores,
+    /// OpenStreetMap extract the run was computed from.
+    ///
+    /// The extracts can weigh several gigabytes for the largest cities.
+    OsmExtract,
+    /// Boundaries of the census blocks, as a zipped shapefile.
+    NeighborhoodCensusBlocks,
+    /// Population attributes of the census blocks, as a CSV file.
+    NeighborhoodCensusBlockPopulation,
+}
+
+impl FromStr for Dataset {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            DS_NEIGHBORHOOD_WAYS => Ok(Dataset::NeighborhoodWays),
+            DS_NEIGHBORHOOD_OVERALL_SCORES => Ok(Dataset::NeighborhoodOverallScores),
+            DS_OSM_EXTRACT => Ok(Dataset::OsmExtract),
+            DS_NEIGHBORHOOD_CENSUS_BLOCKS => Ok(Dataset::NeighborhoodCensusBlocks),
+            DS_NEIGHBORHOOD_CENSUS_BLOCK_POPULATION => {
+                Ok(Dataset::NeighborhoodCensusBlockPopulation)
+            }
+            _ => Err(Error::UnknownDataset(s.into())),
+        }
+    }
 }
 
+/// Parse a dataset name.
+///
+/// # Panics
+///
+/// Panics if the name does not match any dataset. Use [`Dataset::from_str`]
+/// to handle the error instead.
 impl From<&str> for Dataset {
     fn from(item: &str) -> Self {
-        match item {
-            DS_NEIGHBORHOOD_WAYS => Dataset::NeighborhoodWays,
-            DS_NEIGHBORHOOD_OVERALL_SCORES => Dataset::NeighborhoodOverallScores,
-            _ => panic!("Cannot parse dataset name {}", item),
+        match item.parse() {
+            Ok(dataset) => dataset,
+            Err(_) => panic!("Cannot parse dataset name {}", item),
         }
     }
 }
@@ -71,6 +134,11 @@ impl fmt::Display for Dataset {
         match self {
             Dataset::NeighborhoodWays => write!(f, "{}", DS_NEIGHBORHOOD_WAYS),
             Dataset::NeighborhoodOverallScores => write!(f, "{}", DS_NEIGHBORHOOD_OVERALL_SCORES),
+            Dataset::OsmExtract => write!(f, "{}", DS_OSM_EXTRACT),
+            Dataset::NeighborhoodCensusBlocks => write!(f, "{}", DS_NEIGHBORHOOD_CENSUS_BLOCKS),
+            Dataset::NeighborhoodCensusBlockPopulation => {
+                write!(f, "{}", DS_NEIGHBORHOOD_CENSUS_BLOCK_POPULATION)
+            }
         }
     }
 }
@@ -81,14 +149,19 @@ impl Dataset {
         match self {
             Dataset::NeighborhoodWays => String::from("zip"),
             Dataset::NeighborhoodOverallScores => String::from("csv"),
+            Dataset::OsmExtract => String::from("osm"),
+            Dataset::NeighborhoodCensusBlocks => String::from("zip"),
+            Dataset::NeighborhoodCensusBlockPopulation => String::from("csv"),
         }
     }
 }
 
 /// Decribes all the objects to export to the Python pfbcore module.
+#[cfg(feature = "python")]
 #[pymodule]
-fn pfbcore(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
+fn pfbcore(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<City>()?;
+    m.add_class::<Run>()?;
     m.add_class::<CommunitySurvey>()?;
     m.add_class::<ScoreCard>()?;
     m.add_class::<BNA>()?;
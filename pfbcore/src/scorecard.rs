@@ -12,56 +12,109 @@
 //! chapter of the Py03 book for more details.
 use crate::PFB_S3_STORAGE_BASE_URL;
 use crate::{Dataset, Error};
+#[cfg(feature = "csv")]
 use csv::Reader;
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
+use serde::de::{self, Deserializer, Visitor};
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+#[cfg(feature = "csv")]
 use std::path::Path;
 use url::Url;
 
+/// Represent a Bicycle Network Analysis (BNA) run.
+///
+/// Each run analyzes a city at a given point in time, and produces a new set
+/// of datasets, stored under the run UUID.
+#[cfg_attr(feature = "python", pyclass(get_all, set_all))]
+#[derive(Debug, Default, Deserialize, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Run {
+    /// Run's unique identifier.
+    pub uuid: String,
+    /// Date of the run, e.g. `2023-05-02`, if known.
+    #[serde(default, deserialize_with = "deserialize_optional")]
+    pub date: Option<String>,
+    /// Version of the BNA which computed the run, if known.
+    #[serde(default, deserialize_with = "deserialize_optional")]
+    pub version: Option<String>,
+}
+
+impl Run {
+    /// Create a new Run, of unknown date and version.
+    pub fn new(uuid: &str) -> Self {
+        Run {
+            uuid: uuid.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Return the URL of the specified dataset.
+    pub fn url(&self, dataset: Dataset) -> Result<Url, Error> {
+        self.url_from(PFB_S3_STORAGE_BASE_URL, dataset)
+    }
+
+    /// Return the URL of the specified dataset on a mirror of the PFB storage.
+    ///
+    /// The `base` URL replaces [`PFB_S3_STORAGE_BASE_URL`], the rest of the
+    /// layout is preserved.
+    pub fn url_from(&self, base: &str, dataset: Dataset) -> Result<Url, Error> {
+        let dataset_url = format!(
+            "{}/{}/{}.{}",
+            base.trim_end_matches('/'),
+            self.uuid,
+            dataset,
+            dataset.extension()
+        );
+        Ok(Url::parse(&dataset_url)?)
+    }
+}
+
 /// Represent a PeopleForBikes city.
-#[pyclass]
+///
+/// The place itself is identified by its name, state and country. The
+/// population and ratings are the results of the `run` the city was listed
+/// with.
+#[cfg_attr(feature = "python", pyclass(get_all, set_all))]
 #[derive(Debug, Deserialize, Clone)]
 pub struct City {
     /// City name.
-    #[pyo3(get, set)]
     #[serde(rename = "City")]
     pub name: String,
     /// Country where the city is located.
-    #[pyo3(get, set)]
     #[serde(rename = "Country")]
     pub country: String,
     /// State where the city is located.
-    #[pyo3(get, set)]
     #[serde(rename = "State")]
     pub state: String,
-    /// City's unique identifier.
+    /// Run the city was listed with.
     ///
-    /// It is generated by a specific Bicyle Network Analysis (BNA) run and
-    /// should be assimilated to a version number (each run will generate a
-    /// new identifier).
-    #[pyo3(get, set)]
-    pub uuid: String,
+    /// It is read from the `uuid`, `date` and `version` columns of the CSV
+    /// files.
+    #[serde(flatten)]
+    pub run: Run,
     /// City's population.
-    #[pyo3(get, set)]
     pub population: u32,
     /// City rating.
-    #[pyo3(get, set)]
     #[serde(rename = "city_ratings_total")]
     pub ratings: f64,
     /// Rounded city rating.
-    #[pyo3(get, set)]
     #[serde(rename = "city_ratings_rounded")]
     pub ratings_rounded: u8,
+    /// Columns of the CSV file which do not match any other field, e.g.
+    /// `region` or `priority`, by column name.
+    #[serde(flatten, deserialize_with = "deserialize_extra")]
+    pub extra: HashMap<String, String>,
 }
 
-/// Define Python compatible methods.
-#[pymethods]
 impl City {
     /// Create a new City.
     ///
     /// If the `state` is not specified (a lot of countries do not have states),
     /// the name of the country is used instead.
-    #[new]
     pub fn new(
         name: &str,
         country: &str,
@@ -79,15 +132,37 @@ impl City {
             } else {
                 country.into()
             },
-            uuid: uuid.into(),
+            run: Run::new(uuid),
             population,
             ratings,
             ratings_rounded,
+            extra: HashMap::new(),
+        }
+    }
+
+    /// Return the value of a field by name.
+    ///
+    /// The names are the ones of the CSV columns (`City`, `Country`, `State`,
+    /// `uuid`, `date`, `version`, `population`, `city_ratings_total`,
+    /// `city_ratings_rounded`),
+    /// their lowercase versions, or the ones of the fields of the structure
+    /// (`name`, `country`...). The other names are looked up in the extra
+    /// columns.
+    pub fn field(&self, name: &str) -> Option<String> {
+        match name {
+            "City" | "city" | "name" => Some(self.name.clone()),
+            "Country" | "country" => Some(self.country.clone()),
+            "State" | "state" => Some(self.state.clone()),
+            "uuid" => Some(self.run.uuid.clone()),
+            "date" => self.run.date.clone(),
+            "version" => self.run.version.clone(),
+            "population" => Some(self.population.to_string()),
+            "city_ratings_total" | "ratings" => Some(self.ratings.to_string()),
+            "city_ratings_rounded" | "ratings_rounded" => Some(self.ratings_rounded.to_string()),
+            _ => self.extra.get(name).cloned(),
         }
     }
-}
 
-impl City {
     /// Return the full name of the city.
     ///
     /// The full name has the following format: `{COUNTRY}-{STATE}-{CITY_NAME}`.
@@ -95,141 +170,382 @@ impl City {
         format!("{}-{}-{}", self.country, self.state, self.name)
     }
 
-    /// Return the URL of the specified dataset.
-    pub fn url(&self, dataset: Dataset) -> Result<Url, Error> {
-        let dataset_url = format!(
-            "{}/{}/{}.{}",
-            PFB_S3_STORAGE_BASE_URL,
-            self.uuid,
-            dataset,
-            dataset.extension()
-        );
-        Ok(Url::parse(&dataset_url)?)
+    /// Return a URL and file system friendly version of the full name.
+    ///
+    /// The full name is lowercased, and each sequence of non-alphanumeric
+    /// characters is replaced by a single hyphen, e.g.
+    /// `united-states-co-fort-collins`.
+    pub fn slug(&self) -> String {
+        let mut slug = String::new();
+        for c in self.full_name().chars() {
+            if c.is_alphanumeric() {
+                slug.extend(c.to_lowercase());
+            } else if !slug.is_empty() && !slug.ends_with('-') {
+                slug.push('-');
+            }
+        }
+        if slug.ends_with('-') {
+            slug.pop();
+        }
+        slug
     }
 
     /// Read a CSV file and populate a Vector of Cities.
+    #[cfg(feature = "csv")]
     pub fn from_csv<P>(path: P) -> Result<Vec<City>, Error>
     where
         P: AsRef<Path>,
     {
-        let mut csv_reader = Reader::from_path(path)?;
-        let mut cities: Vec<City> = vec![];
-        for record in csv_reader.deserialize() {
-            cities.push(record?);
+        City::iter_csv(path)?.collect()
+    }
+
+    /// Read a CSV file one city at a time.
+    ///
+    /// Unlike [`City::from_csv`], the file is never loaded in memory as a
+    /// whole, which suits the very large lists of cities, e.g. the ones
+    /// including the historical runs.
+    #[cfg(feature = "csv")]
+    pub fn iter_csv<P>(path: P) -> Result<impl Iterator<Item = Result<City, Error>>, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let csv_reader = Reader::from_path(path)?;
+
+        Ok(csv_reader
+            .into_deserialize()
+            .map(|record| record.map_err(Error::from)))
+    }
+}
+
+/// Deserialize the extra columns of a city.
+///
+/// The CSV reader infers the type of the values of the flattened fields, so
+/// the numbers and booleans are turned back into strings.
+fn deserialize_extra<'de, D>(deserializer: D) -> Result<HashMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let values = HashMap::<String, AnyValue>::deserialize(deserializer)?;
+    Ok(values.into_iter().map(|(k, v)| (k, v.0)).collect())
+}
+
+/// Deserialize an optional scalar value as a string.
+///
+/// The empty values are treated as missing.
+fn deserialize_optional<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = Option::<AnyValue>::deserialize(deserializer)?;
+    Ok(value.map(|v| v.0).filter(|v| !v.is_empty()))
+}
+
+/// Represent any scalar value as a string.
+struct AnyValue(String);
+
+impl<'de> Deserialize<'de> for AnyValue {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct AnyValueVisitor;
+
+        impl Visitor<'_> for AnyValueVisitor {
+            type Value = AnyValue;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a scalar value")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<AnyValue, E> {
+                Ok(AnyValue(v.to_string()))
+            }
+
+            fn visit_bool<E: de::Error>(self, v: bool) -> Result<AnyValue, E> {
+                Ok(AnyValue(v.to_string()))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<AnyValue, E> {
+                Ok(AnyValue(v.to_string()))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<AnyValue, E> {
+                Ok(AnyValue(v.to_string()))
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<AnyValue, E> {
+                Ok(AnyValue(v.to_string()))
+            }
+
+            fn visit_unit<E: de::Error>(self) -> Result<AnyValue, E> {
+                Ok(AnyValue(String::new()))
+            }
+
+            fn visit_none<E: de::Error>(self) -> Result<AnyValue, E> {
+                Ok(AnyValue(String::new()))
+            }
+        }
+
+        deserializer.deserialize_any(AnyValueVisitor)
+    }
+}
+
+/// Display a city as `{CITY_NAME}, {STATE}, {COUNTRY} (uuid {UUID})`.
+///
+/// The state is omitted when it is the same as the country, and the empty
+/// fields are omitted.
+impl fmt::Display for City {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if !self.state.is_empty() && self.state != self.country {
+            write!(f, ", {}", self.state)?;
+        }
+        if !self.country.is_empty() {
+            write!(f, ", {}", self.country)?;
         }
+        if !self.run.uuid.is_empty() {
+            write!(f, " (uuid {})", self.run.uuid)?;
+        }
+        Ok(())
+    }
+}
+
+impl City {
+    /// Return the fields identifying a city run.
+    fn key(&self) -> (&str, &str, &str, &str) {
+        (&self.country, &self.state, &self.name, &self.run.uuid)
+    }
+}
+
+/// Compare cities by country, state, name and UUID.
+///
+/// The other fields (population, ratings) describe the results of a run, and
+/// are not taken into account: two cities are equal if they represent the
+/// same place analyzed by the same run.
+impl PartialEq for City {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for City {}
 
-        Ok(cities)
+impl Hash for City {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+/// Order cities by country, state, name, then UUID.
+///
+/// ```
+/// use pfbcore::scorecard::City;
+///
+/// let mut cities = vec![
+///     City::new("Pueblo", "United States", Some("CO"), "ffc8", 110841, 13.8, 14),
+///     City::new("Mildura", "Australia", Some("VIC"), "fe91", 38130, 33.4, 33),
+///     City::new("Denver", "United States", Some("CO"), "b1b2", 715522, 36.1, 36),
+///     City::new("Denver", "United States", Some("CO"), "a1a2", 715522, 35.2, 35),
+/// ];
+/// cities.sort();
+/// let names: Vec<(&str, &str)> = cities
+///     .iter()
+///     .map(|c| (c.name.as_str(), c.run.uuid.as_str()))
+///     .collect();
+/// assert_eq!(
+///     names,
+///     [
+///         ("Mildura", "fe91"),
+///         ("Denver", "a1a2"),
+///         ("Denver", "b1b2"),
+///         ("Pueblo", "ffc8")
+///     ]
+/// );
+/// ```
+impl Ord for City {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl PartialOrd for City {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Define Python compatible methods.
+#[cfg(feature = "python")]
+#[pymethods]
+impl City {
+    /// Python wrapper for the [`City::new`] method.
+    #[new]
+    #[pyo3(signature = (name, country, state, uuid, population, ratings, ratings_rounded))]
+    pub fn py_new(
+        name: &str,
+        country: &str,
+        state: Option<&str>,
+        uuid: &str,
+        population: u32,
+        ratings: f64,
+        ratings_rounded: u8,
+    ) -> Self {
+        City::new(
+            name,
+            country,
+            state,
+            uuid,
+            population,
+            ratings,
+            ratings_rounded,
+        )
+    }
+
+    /// Python wrapper for the [`fmt::Display`] implementation.
+    pub fn __str__(&self) -> String {
+        self.to_string()
     }
 }
 
 /// Represent the results from the community survey.
-#[pyclass]
+#[cfg_attr(feature = "python", pyclass(get_all, set_all))]
 #[derive(Debug, Deserialize, Clone)]
 pub struct CommunitySurvey {
     /// Perception of the quality of the bicycle network in the city.
-    #[pyo3(get, set)]
     #[serde(rename = "Community Survey - Network")]
     pub network: f64,
     /// Perceptions of acceleration and awareness of bike events and facilities in an area.
-    #[pyo3(get, set)]
     #[serde(rename = "Community Survey - Awareness")]
     pub awareness: f64,
     /// Perceptions of safety riding a bike .
-    #[pyo3(get, set)]
     #[serde(rename = "Community Survey - Safety")]
     pub safety: f64,
     /// Measure how often respondents engage in different types of riding.
-    #[pyo3(get, set)]
     #[serde(rename = "Community Survey - Ridership")]
     pub ridership: f64,
     /// Overall community survey score.
-    #[pyo3(get, set)]
     #[serde(rename = "Community Score - Total")]
     pub total: f64,
     /// Overall community survey rounded score.
-    #[pyo3(get, set)]
     #[serde(rename = "Community Score - Total, Rounded")]
     pub total_rounded: u32,
     /// Number of responses to the survey.
-    #[pyo3(get, set)]
     #[serde(rename = "Community Survey - Responses")]
     pub responses: u32,
 }
 
 /// Represent the results from the BNA.
 #[derive(Debug, Deserialize, Clone)]
-#[pyclass]
+#[cfg_attr(feature = "python", pyclass(get_all, set_all))]
 pub struct BNA {
     /// How well people can reach other people by bike.
-    #[pyo3(get, set)]
     #[serde(rename = "BNA - neighborhoods")]
     pub neighborhoods: f64,
     /// How well people can reach employment and educational opportunities by bike.
-    #[pyo3(get, set)]
     #[serde(rename = "BNA - opportunity")]
     pub opportunity: f64,
     /// How well people can reach Core Services by bike.
-    #[pyo3(get, set)]
     #[serde(rename = "BNA - essential_services")]
-    #[serde(deserialize_with = "csv::invalid_option")]
+    #[cfg_attr(feature = "csv", serde(deserialize_with = "csv::invalid_option"))]
     pub essential_services: Option<f64>,
     /// How well people can reach retail shopping opportunities by bike.
-    #[pyo3(get, set)]
     #[serde(rename = "BNA - retail")]
     pub retail: f64,
     /// How well people can reach recreation opportunities by bike.
-    #[pyo3(get, set)]
     #[serde(rename = "BNA - recreation")]
-    #[serde(deserialize_with = "csv::invalid_option")]
+    #[cfg_attr(feature = "csv", serde(deserialize_with = "csv::invalid_option"))]
     pub recreation: Option<f64>,
     /// How well people can reach major transit hubs by bike.
-    #[pyo3(get, set)]
     #[serde(rename = "BNA - transit")]
     pub transit: f64,
     /// How well the bike network gets people to the places they want to go.
-    #[pyo3(get, set)]
     #[serde(rename = "BNA - overall_score")]
     pub overall_score: f64,
 }
 
 /// Represent a city bike infrastructure.
-#[pyclass]
+#[cfg_attr(feature = "python", pyclass(get_all, set_all))]
 #[derive(Debug, Deserialize, Clone)]
 pub struct Infrastructure {
     /// Miles of low stress infrstructure.
-    #[pyo3(get, set)]
     #[serde(rename = "total_low_stress_miles")]
-    #[serde(deserialize_with = "csv::invalid_option")]
+    #[cfg_attr(feature = "csv", serde(deserialize_with = "csv::invalid_option"))]
     pub low_stress_miles: Option<f64>,
     /// Miles of high stress infrastructure.
-    #[pyo3(get, set)]
     #[serde(rename = "total_high_stress_miles")]
-    #[serde(deserialize_with = "csv::invalid_option")]
+    #[cfg_attr(feature = "csv", serde(deserialize_with = "csv::invalid_option"))]
     pub high_stress_miles: Option<f64>,
 }
 
+/// Columns of a scorecard which are not columns of its city.
+const SCORECARD_COLUMNS: [&str; 16] = [
+    "Community Survey - Network",
+    "Community Survey - Awareness",
+    "Community Survey - Safety",
+    "Community Survey - Ridership",
+    "Community Score - Total",
+    "Community Score - Total, Rounded",
+    "Community Survey - Responses",
+    "BNA - neighborhoods",
+    "BNA - opportunity",
+    "BNA - essential_services",
+    "BNA - retail",
+    "BNA - recreation",
+    "BNA - transit",
+    "BNA - overall_score",
+    "total_low_stress_miles",
+    "total_high_stress_miles",
+];
+
 /// Represent a city scorecard.
-#[pyclass]
+///
+/// The extra columns of the city only hold the columns which are not part of
+/// the scorecard.
+#[cfg_attr(feature = "python", pyclass(get_all, set_all))]
 #[derive(Debug, Deserialize, Clone)]
+#[serde(from = "ScoreCardRecord")]
 pub struct ScoreCard {
     /// City details.
-    #[pyo3(get, set)]
-    #[serde(flatten)]
     pub city: City,
     /// Community survey results.
-    #[pyo3(get, set)]
-    #[serde(flatten)]
     pub community_survey: CommunitySurvey,
     /// BNA results.
-    #[pyo3(get, set)]
-    #[serde(flatten)]
     pub bna: BNA,
     /// Infrastructure details.
-    #[pyo3(get, set)]
-    #[serde(flatten)]
     pub infrastructure: Infrastructure,
 }
 
+/// Represent a record of a scorecard file.
+///
+/// The city collects every column it does not declare, the ones of the
+/// scorecard included.
+#[derive(Deserialize)]
+struct ScoreCardRecord {
+    #[serde(flatten)]
+    city: City,
+    #[serde(flatten)]
+    community_survey: CommunitySurvey,
+    #[serde(flatten)]
+    bna: BNA,
+    #[serde(flatten)]
+    infrastructure: Infrastructure,
+}
+
+impl From<ScoreCardRecord> for ScoreCard {
+    fn from(record: ScoreCardRecord) -> Self {
+        let mut city = record.city;
+        city.extra
+            .retain(|column, _| !SCORECARD_COLUMNS.contains(&column.as_str()));
+        ScoreCard {
+            city,
+            community_survey: record.community_survey,
+            bna: record.bna,
+            infrastructure: record.infrastructure,
+        }
+    }
+}
+
+#[cfg(feature = "csv")]
 impl ScoreCard {
     /// Read a CSV file and populate a Vector of ScoreCards.
     pub fn from_csv<P>(path: P) -> Result<Vec<ScoreCard>, Error>
@@ -247,6 +563,7 @@ impl ScoreCard {
 }
 
 /// Define Python compatible methods.
+#[cfg(feature = "python")]
 #[pymethods]
 impl ScoreCard {
     /// Python wrapper for the [`ScoreCard::from_csv`] method.
@@ -260,61 +577,38 @@ impl ScoreCard {
 ///
 /// The fields must match all the fields from ScoreCard, and be represented by
 /// their short forms.
-#[pyclass]
+#[cfg_attr(feature = "python", pyclass(get_all, set_all))]
 #[derive(Debug, Serialize, Clone)]
 pub struct ShortScoreCard {
     /// City
-    #[pyo3(get, set)]
     pub ci: String,
-    #[pyo3(get, set)]
     pub co: String,
-    #[pyo3(get, set)]
     pub st: String,
-    #[pyo3(get, set)]
     pub uuid: String,
-    #[pyo3(get, set)]
     pub po: u32,
-    #[pyo3(get, set)]
     pub ra: f64,
-    #[pyo3(get, set)]
     pub rasc: u8,
 
     // Community Survey
-    #[pyo3(get, set)]
     pub nw: u8,
-    #[pyo3(get, set)]
     pub aw: u8,
-    #[pyo3(get, set)]
     pub sf: u8,
-    #[pyo3(get, set)]
     pub rs: u8,
-    #[pyo3(get, set)]
     pub total: u8,
-    #[pyo3(get, set)]
     pub cssc: u8,
-    #[pyo3(get, set)]
     pub responses: u32,
 
     // BNA
-    #[pyo3(get, set)]
     pub nh: u8,
-    #[pyo3(get, set)]
     pub op: u8,
-    #[pyo3(get, set)]
     pub es: u8,
-    #[pyo3(get, set)]
     pub ret: u8,
-    #[pyo3(get, set)]
     pub rec: u8,
-    #[pyo3(get, set)]
     pub tr: u8,
-    #[pyo3(get, set)]
     pub bnasc: u8,
 
     // Infrastructure
-    #[pyo3(get, set)]
     pub lsm: u8,
-    #[pyo3(get, set)]
     pub hsm: u8,
 }
 
@@ -324,7 +618,7 @@ impl From<&ScoreCard> for ShortScoreCard {
             ci: sc.city.name.clone(),
             co: sc.city.country.clone(),
             st: sc.city.state.clone(),
-            uuid: sc.city.uuid.clone(),
+            uuid: sc.city.run.uuid.clone(),
             po: sc.city.population,
             ra: sc.city.ratings,
             rasc: sc.city.ratings_rounded,
@@ -356,6 +650,7 @@ impl From<&ScoreCard> for ShortScoreCard {
     }
 }
 
+#[cfg(feature = "csv")]
 impl ShortScoreCard {
     // Saves a slice of ShortScoreCards to a CSV file.
     pub fn to_csv<P>(path: P, entries: &[ShortScoreCard]) -> Result<(), Error>
@@ -372,6 +667,7 @@ impl ShortScoreCard {
 }
 
 /// Define Python compatible methods.
+#[cfg(feature = "python")]
 #[pymethods]
 impl ShortScoreCard {
     /// Python wrapper for the [`ShortScoreCard::to_csv`] method.
@@ -380,3 +676,158 @@ impl ShortScoreCard {
         Ok(ShortScoreCard::to_csv(path, &entries)?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn city(name: &str, state: Option<&str>, uuid: &str, population: u32) -> City {
+        City::new(name, "United States", state, uuid, population, 36.1, 36)
+    }
+
+    /// Read the records of a CSV file.
+    #[cfg(feature = "csv")]
+    fn read<T>(content: &str) -> Vec<T>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        Reader::from_reader(content.as_bytes())
+            .into_deserialize()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    #[cfg(feature = "csv")]
+    fn read_extra_columns() {
+        let cities: Vec<City> = read(concat!(
+            "City,Country,State,uuid,date,version,population,",
+            "city_ratings_total,city_ratings_rounded,region,priority,active\n",
+            "Denver,United States,CO,b1b2,2023-05-02,,715522,36.1,36,west,2,true\n",
+        ));
+        let city = &cities[0];
+        assert_eq!(
+            city.extra,
+            HashMap::from([
+                (String::from("region"), String::from("west")),
+                (String::from("priority"), String::from("2")),
+                (String::from("active"), String::from("true")),
+            ])
+        );
+        assert_eq!(city.run.date.as_deref(), Some("2023-05-02"));
+        assert_eq!(city.run.version, None);
+        assert_eq!(city.field("region").as_deref(), Some("west"));
+        assert_eq!(city.field("priority").as_deref(), Some("2"));
+        assert_eq!(city.field("State").as_deref(), Some("CO"));
+        assert_eq!(city.field("unknown"), None);
+    }
+
+    #[test]
+    #[cfg(feature = "csv")]
+    fn read_scorecards_without_their_columns_in_extra() {
+        let mut headers = vec![
+            "City",
+            "Country",
+            "State",
+            "uuid",
+            "population",
+            "city_ratings_total",
+            "city_ratings_rounded",
+            "region",
+        ];
+        headers.extend(SCORECARD_COLUMNS);
+        let headers = headers
+            .iter()
+            .map(|h| format!("{:?}", h))
+            .collect::<Vec<_>>()
+            .join(",");
+        let mut values = vec![
+            "Denver",
+            "United States",
+            "CO",
+            "b1b2",
+            "715522",
+            "36.1",
+            "36",
+        ];
+        values.push("west");
+        values.extend(["1"; 16]);
+        let content = format!("{}\n{}\n", headers, values.join(","));
+
+        let scorecards: Vec<ScoreCard> = read(&content);
+        let city = &scorecards[0].city;
+        assert_eq!(
+            city.extra,
+            HashMap::from([(String::from("region"), String::from("west"))])
+        );
+        assert_eq!(scorecards[0].community_survey.responses, 1);
+        assert_eq!(scorecards[0].infrastructure.high_stress_miles, Some(1.0));
+    }
+
+    #[test]
+    fn display_cities() {
+        assert_eq!(
+            city("Denver", Some("CO"), "b1b2", 715522).to_string(),
+            "Denver, CO, United States (uuid b1b2)"
+        );
+        assert_eq!(
+            city("Washington", None, "", 689545).to_string(),
+            "Washington, United States"
+        );
+        let mut nowhere = city("Nowhere", Some(""), "", 0);
+        nowhere.country = String::new();
+        assert_eq!(nowhere.to_string(), "Nowhere");
+    }
+
+    #[test]
+    fn compare_cities() {
+        let denver = city("Denver", Some("CO"), "b1b2", 715522);
+        let mut rerun = denver.clone();
+        rerun.population = 1;
+        rerun.ratings = 99.0;
+        rerun
+            .extra
+            .insert(String::from("region"), String::from("west"));
+        assert_eq!(denver, rerun);
+        assert_eq!(denver.cmp(&rerun), Ordering::Equal);
+
+        let other_run = city("Denver", Some("CO"), "a1a2", 715522);
+        assert_ne!(denver, other_run);
+        assert!(other_run < denver);
+        assert!(city("Pueblo", Some("CO"), "0000", 0) > denver);
+        assert!(city("Austin", Some("TX"), "0000", 0) > denver);
+    }
+
+    #[test]
+    fn hash_cities() {
+        let denver = city("Denver", Some("CO"), "b1b2", 715522);
+        let mut rerun = denver.clone();
+        rerun.population = 1;
+        let cities: HashSet<City> = [
+            denver.clone(),
+            rerun,
+            city("Denver", Some("CO"), "a1a2", 715522),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(cities.len(), 2);
+        assert!(cities.contains(&denver));
+    }
+
+    #[test]
+    fn build_run_urls() {
+        let run = Run::new("b1b2");
+        assert_eq!(
+            run.url_from("https://mirror.example.com/pfb/", Dataset::NeighborhoodWays)
+                .unwrap()
+                .as_str(),
+            "https://mirror.example.com/pfb/b1b2/neighborhood_ways.zip"
+        );
+        assert!(run
+            .url(Dataset::NeighborhoodWays)
+            .unwrap()
+            .as_str()
+            .starts_with(PFB_S3_STORAGE_BASE_URL));
+    }
+}
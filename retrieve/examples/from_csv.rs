@@ -8,13 +8,12 @@
 
 use color_eyre::{eyre::Report, Result};
 use pfbcore::scorecard::{ScoreCard, ShortScoreCard};
-use retrieve::setup;
 
-const CITY_RATINGS_CSV: &'static str = "examples/city_ratings_2021_v15.csv";
+const CITY_RATINGS_CSV: &str = "examples/city_ratings_2021_v15.csv";
 
 fn main() -> Result<(), Report> {
     // Setup the application.
-    setup()?;
+    color_eyre::install()?;
 
     let scorecards = ScoreCard::from_csv(CITY_RATINGS_CSV)?;
     let sc = scorecards.first().unwrap();
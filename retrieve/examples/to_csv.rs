@@ -7,17 +7,16 @@
 //!
 use color_eyre::{eyre::Report, Result};
 use pfbcore::scorecard::{ScoreCard, ShortScoreCard};
-use retrieve::setup;
 use std::fs;
 
 // The paths must be relative to the Cargo.toml file.
-const CITY_RATINGS_CSV: &'static str = "examples/city_ratings_2021_v15.csv";
-const OUTPUT_DIR: &'static str = "examples/output";
-const SHORTCODES_CSV: &'static str = "examples/output/shortcodes.csv";
+const CITY_RATINGS_CSV: &str = "examples/city_ratings_2021_v15.csv";
+const OUTPUT_DIR: &str = "examples/output";
+const SHORTCODES_CSV: &str = "examples/output/shortcodes.csv";
 
 fn main() -> Result<(), Report> {
     // Setup the application.
-    setup()?;
+    color_eyre::install()?;
 
     let scorecards = ScoreCard::from_csv(CITY_RATINGS_CSV)?;
     let short_scorecards = scorecards
@@ -0,0 +1,28 @@
+//! Reads an extracted neighborhood ways dataset and summarizes the stress of
+//! its segments.
+//!
+//! Run with:
+//! ```not_rust
+//! cargo run -q --example ways -- <PATH TO .shp OR .geojson>
+//! ```
+
+use color_eyre::{eyre::eyre, eyre::Report, Result};
+use retrieve::ways::{Stress, WaysReader};
+use std::collections::BTreeMap;
+
+fn main() -> Result<(), Report> {
+    // Setup the application.
+    color_eyre::install()?;
+
+    let path = std::env::args()
+        .nth(1)
+        .ok_or_else(|| eyre!("missing dataset path"))?;
+    let mut reader = WaysReader::open(path)?;
+    let mut stress: BTreeMap<Option<Stress>, u64> = BTreeMap::new();
+    for way in reader.ways() {
+        *stress.entry(way?.stress()).or_default() += 1;
+    }
+    dbg!(&stress);
+
+    Ok(())
+}
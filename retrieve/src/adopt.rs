@@ -0,0 +1,160 @@
+//! Adopt the files of a folder populated by other means.
+//!
+//! The datasets are sometimes fetched with other tools, and handed over as a
+//! plain folder. Adopting them records them in the manifest, so that they are
+//! not downloaded again.
+//!
+//! A file is matched to a city dataset by its name first: either the name
+//! retrieve would have given to it, or any path containing both the run UUID
+//! and the dataset name, e.g. `{UUID}/neighborhood_ways.zip`. The remaining
+//! files are matched by their SHA256 hash against the entries of an external
+//! manifest, if one is provided.
+use crate::cache::sha256;
+use crate::checksum::SHA256SUMS;
+use crate::lock::LOCK;
+use crate::manifest::{Manifest, ManifestEntry, JOURNAL, MANIFEST};
+use crate::plan::file_name;
+use crate::provenance::PROVENANCE_SUFFIX;
+use crate::Error;
+use crate::{Cities, City, Dataset};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Summarize an adoption.
+#[derive(Debug, Default, Clone)]
+pub struct Adoption {
+    /// Entries describing the adopted files.
+    pub adopted: Vec<ManifestEntry>,
+    /// Files which did not match any city dataset, relative to the folder.
+    pub leftovers: Vec<PathBuf>,
+}
+
+/// Match the files located in `folder` to the `datasets` of the `cities`.
+///
+/// The files already recorded in `manifest`, along with the content of the
+/// archives it extracted, are skipped. The hashes of the `external` manifest,
+/// if any, are used to match the files whose name is not recognized.
+pub fn adopt<P>(
+    folder: P,
+    cities: &Cities,
+    datasets: &[Dataset],
+    manifest: &Manifest,
+    external: Option<&Manifest>,
+) -> Result<Adoption, Error>
+where
+    P: AsRef<Path>,
+{
+    let folder = folder.as_ref();
+    let mut paths = Vec::new();
+    collect(folder, folder, &mut paths)?;
+    paths.sort();
+
+    let mut adoption = Adoption::default();
+    for path in paths {
+        let recorded = manifest
+            .files
+            .iter()
+            .any(|e| e.path == path || e.extracted.as_ref().is_some_and(|x| path.starts_with(x)));
+        if recorded {
+            continue;
+        }
+        let full_path = folder.join(&path);
+        let hash = sha256(&full_path)?;
+        let matched = match_name(&path, cities, datasets)
+            .or_else(|| external.and_then(|m| match_hash(&hash, m, cities, datasets)));
+        let Some((city, dataset)) = matched else {
+            adoption.leftovers.push(path);
+            continue;
+        };
+        adoption.adopted.push(ManifestEntry {
+            size: fs::metadata(&full_path)?.len(),
+            path,
+            city: city.name.clone(),
+            state: city.state.clone(),
+            country: city.country.clone(),
+            uuid: city.run.uuid.clone(),
+            dataset: dataset.to_string(),
+            sha256: Some(hash),
+            ..Default::default()
+        });
+    }
+
+    Ok(adoption)
+}
+
+/// Match a file to a city dataset by its `path`.
+fn match_name<'a>(
+    path: &Path,
+    cities: &'a Cities,
+    datasets: &[Dataset],
+) -> Option<(&'a City, Dataset)> {
+    let path_lower = path.to_string_lossy().to_lowercase();
+    let name_lower = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    cities.iter().find_map(|city| {
+        let uuid = city.run.uuid.to_lowercase();
+        datasets
+            .iter()
+            .find(|&&dataset| {
+                let expected = file_name(city, dataset).to_string_lossy().to_lowercase();
+                name_lower == expected
+                    || (!uuid.is_empty()
+                        && path_lower.contains(&uuid)
+                        && name_lower.contains(&dataset.to_string()))
+            })
+            .map(|&dataset| (city, dataset))
+    })
+}
+
+/// Match a file to a city dataset by its `hash`, recorded in an external
+/// manifest.
+fn match_hash<'a>(
+    hash: &str,
+    external: &Manifest,
+    cities: &'a Cities,
+    datasets: &[Dataset],
+) -> Option<(&'a City, Dataset)> {
+    external
+        .files
+        .iter()
+        .filter(|e| e.sha256.as_deref() == Some(hash))
+        .find_map(|e| {
+            let dataset = datasets.iter().find(|d| d.to_string() == e.dataset)?;
+            let city = cities.get_by_uuid(&e.uuid)?;
+            Some((city, *dataset))
+        })
+}
+
+/// Recursively collect the files located in `folder`, relative to `root`.
+///
+/// The manifest, the checksum files, the lock file, the provenance records and
+/// the partial downloads are skipped.
+fn collect(root: &Path, folder: &Path, paths: &mut Vec<PathBuf>) -> Result<(), Error> {
+    for dir_entry in fs::read_dir(folder)? {
+        let dir_entry = dir_entry?;
+        let path = dir_entry.path();
+        let metadata = dir_entry.metadata()?;
+        if metadata.is_dir() {
+            collect(root, &path, paths)?;
+            continue;
+        }
+        let name = dir_entry.file_name();
+        let skipped = name == MANIFEST
+            || name == JOURNAL
+            || name == SHA256SUMS
+            || name == LOCK
+            || name.to_string_lossy().ends_with(PROVENANCE_SUFFIX)
+            || path
+                .extension()
+                .is_some_and(|e| e == "sha256" || e == "part" || e == "tmp");
+        if metadata.is_file() && !skipped {
+            if let Ok(relative) = path.strip_prefix(root) {
+                paths.push(relative.to_path_buf());
+            }
+        }
+    }
+
+    Ok(())
+}
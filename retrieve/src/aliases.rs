@@ -0,0 +1,97 @@
+//! Resolve the aliases of the city names, e.g. "Saint Paul" for "St. Paul".
+//!
+//! The inventory spreadsheets and the run metadata do not always spell the
+//! names the same way. An alias table maps each alias to the canonical name of
+//! the city, and is consulted when the cities are selected by name and when
+//! they are looked up in the city ratings API. The aliases are matched without
+//! regard to the case and the diacritics.
+//!
+//! The table can be read from a CSV file with `alias` and `name` columns:
+//!
+//! ```csv
+//! alias,name
+//! Saint Paul,St. Paul
+//! Saint Louis,St. Louis
+//! ```
+use crate::collation::{eq_folded, fold};
+use crate::Error;
+use std::collections::HashMap;
+#[cfg(feature = "csv")]
+use {serde::Deserialize, std::path::Path};
+
+/// Map the aliases of the city names to their canonical name.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Aliases(HashMap<String, String>);
+
+/// Describe a row of an alias file.
+#[cfg(feature = "csv")]
+#[derive(Debug, Deserialize)]
+struct AliasRecord {
+    alias: String,
+    name: String,
+}
+
+impl Aliases {
+    /// Create an empty alias table.
+    pub fn new() -> Self {
+        Aliases::default()
+    }
+
+    /// Read an alias table from a CSV file with `alias` and `name` columns.
+    #[cfg(feature = "csv")]
+    pub fn from_csv<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let mut reader = csv::Reader::from_path(path)
+            .map_err(|e| Error::Parse(format!("cannot read {}: {}", path.display(), e)))?;
+        let mut aliases = Aliases::new();
+        for record in reader.deserialize() {
+            let record: AliasRecord = record
+                .map_err(|e| Error::Parse(format!("invalid alias in {}: {}", path.display(), e)))?;
+            aliases.insert(&record.alias, &record.name);
+        }
+
+        Ok(aliases)
+    }
+
+    /// Register `alias` as another name of the city named `name`.
+    pub fn insert(&mut self, alias: &str, name: &str) {
+        self.0.insert(fold(alias.trim()), name.trim().to_string());
+    }
+
+    /// Add the aliases of another table to this one.
+    pub fn extend(&mut self, other: Aliases) {
+        self.0.extend(other.0);
+    }
+
+    /// Return `true` if the table does not have any alias.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Return the canonical name of `name`, which is `name` itself if it is not
+    /// an alias.
+    pub fn resolve<'a>(&'a self, name: &'a str) -> &'a str {
+        self.0.get(&fold(name.trim())).map_or(name, String::as_str)
+    }
+
+    /// Return `true` if two names designate the same city once resolved.
+    pub fn matches(&self, a: &str, b: &str) -> bool {
+        eq_folded(self.resolve(a), self.resolve(b))
+    }
+}
+
+/// Parse an alias specified as `ALIAS=NAME`, e.g. `Saint Paul=St. Paul`.
+pub fn parse_alias(value: &str) -> Result<(String, String), Error> {
+    match value.split_once('=') {
+        Some((alias, name)) if !alias.trim().is_empty() && !name.trim().is_empty() => {
+            Ok((alias.trim().to_string(), name.trim().to_string()))
+        }
+        _ => Err(Error::Parse(format!(
+            "invalid alias {:?}, expected `ALIAS=NAME`",
+            value
+        ))),
+    }
+}
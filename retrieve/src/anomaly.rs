@@ -0,0 +1,129 @@
+//! Flag the datasets whose size changed suspiciously between two runs.
+//!
+//! When the `neighborhood_ways` of a new run is 90% smaller than the one of
+//! the previous run, it is almost always an upstream bug. The manifest of the
+//! download folder records the size of the datasets of the previous runs, and
+//! is used as the history the retrieved datasets are compared with.
+use crate::download::{DownloadReport, DownloadStatus};
+use crate::manifest::{Manifest, ManifestEntry};
+use crate::Error;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// Relative size change beyond which a dataset is flagged by default.
+pub const DEFAULT_SIZE_CHANGE_THRESHOLD: f64 = 0.5;
+
+/// Describe a dataset whose size changed beyond the threshold.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SizeAnomaly {
+    /// Path of the file, relative to the download folder.
+    pub path: PathBuf,
+    /// Full name of the city.
+    pub city: String,
+    /// Name of the dataset.
+    pub dataset: String,
+    /// Run UUID of the previous dataset.
+    pub previous_uuid: String,
+    /// Run UUID of the new dataset.
+    pub uuid: String,
+    /// Size of the previous dataset, in bytes.
+    pub previous_size: u64,
+    /// Size of the new dataset, in bytes.
+    pub size: u64,
+}
+
+impl SizeAnomaly {
+    /// Return the relative size change, e.g. -0.9 for a dataset 90% smaller.
+    pub fn change(&self) -> f64 {
+        relative_change(self.previous_size, self.size)
+    }
+}
+
+/// Identify a dataset of a city, whatever the run.
+type DatasetKey = (String, String, String, String);
+
+/// Record the last known size of the dataset of each city.
+#[derive(Debug, Default, Clone)]
+pub struct SizeHistory(HashMap<DatasetKey, ManifestEntry>);
+
+impl SizeHistory {
+    /// Read the history from the manifest of a download folder, before it is
+    /// updated by a new batch.
+    ///
+    /// When a manifest lists several runs of a dataset, the most recently
+    /// retrieved one is kept.
+    pub fn from_manifest(manifest: &Manifest) -> Self {
+        let mut history: HashMap<DatasetKey, ManifestEntry> = HashMap::new();
+        for entry in &manifest.files {
+            let key = (
+                entry.city.clone(),
+                entry.state.clone(),
+                entry.country.clone(),
+                entry.dataset.clone(),
+            );
+            match history.get(&key) {
+                Some(known) if known.retrieved_at >= entry.retrieved_at => (),
+                _ => {
+                    history.insert(key, entry.clone());
+                }
+            }
+        }
+        SizeHistory(history)
+    }
+
+    /// Return the datasets of a report, produced by a new run, whose size
+    /// changed by more than `threshold` from the previous run, e.g. 0.5 for
+    /// 50%.
+    pub fn anomalies(
+        &self,
+        report: &DownloadReport,
+        threshold: f64,
+    ) -> Result<Vec<SizeAnomaly>, Error> {
+        let mut anomalies = Vec::new();
+        for outcome in &report.outcomes {
+            if !matches!(
+                outcome.status,
+                DownloadStatus::Downloaded | DownloadStatus::Cached
+            ) {
+                continue;
+            }
+            let download = &outcome.download;
+            let key = (
+                download.city.name.clone(),
+                download.city.state.clone(),
+                download.city.country.clone(),
+                download.dataset.to_string(),
+            );
+            let Some(previous) = self.0.get(&key) else {
+                continue;
+            };
+            if previous.uuid == download.run.uuid || previous.size == 0 {
+                continue;
+            }
+            let size = fs::metadata(&outcome.path)?.len();
+            if relative_change(previous.size, size).abs() > threshold {
+                anomalies.push(SizeAnomaly {
+                    path: download.file_name.clone(),
+                    city: download.city.to_string(),
+                    dataset: download.dataset.to_string(),
+                    previous_uuid: previous.uuid.clone(),
+                    uuid: download.run.uuid.clone(),
+                    previous_size: previous.size,
+                    size,
+                });
+            }
+        }
+
+        Ok(anomalies)
+    }
+}
+
+/// Return the relative change from `previous` to `size`.
+fn relative_change(previous: u64, size: u64) -> f64 {
+    match previous {
+        0 => 0.0,
+        _ => (size as f64 - previous as f64) / previous as f64,
+    }
+}
@@ -0,0 +1,130 @@
+//! Record the network requests in an audit log.
+//!
+//! The audit log is a JSON Lines file, which is only ever appended to, with
+//! one record per request, e.g.
+//!
+//! ```json
+//! {"timestamp":"2024-05-02T14:03:11.512Z","method":"GET","url":"https://...","status":200,"bytes":1048576,"duration_ms":812}
+//! ```
+//!
+//! It is independent from the human oriented output and from the traces.
+use crate::timestamp::rfc3339;
+use crate::Error;
+use serde::Serialize;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use url::Url;
+
+/// Describe a request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AuditRecord {
+    /// Time the request was sent, as an RFC 3339 timestamp in UTC.
+    pub timestamp: String,
+    /// HTTP method.
+    pub method: String,
+    /// Requested URL, before it is presigned.
+    pub url: String,
+    /// Status code of the response, if one was received.
+    pub status: Option<u16>,
+    /// Number of bytes of the response body which were received.
+    pub bytes: u64,
+    /// Duration of the request, including the transfer of the body, in
+    /// milliseconds.
+    pub duration_ms: u64,
+    /// Error which interrupted the request, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl AuditRecord {
+    /// Describe a request to `url` sent at `started`, and completed now.
+    pub fn new(method: &str, url: &Url, started: SystemTime) -> Self {
+        AuditRecord {
+            timestamp: rfc3339(started),
+            method: method.to_string(),
+            url: url.to_string(),
+            status: None,
+            bytes: 0,
+            duration_ms: started.elapsed().unwrap_or_default().as_millis() as u64,
+            error: None,
+        }
+    }
+
+    /// Set the status code of the response.
+    pub fn status(mut self, status: u16) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    /// Set the number of bytes received.
+    pub fn bytes(mut self, bytes: u64) -> Self {
+        self.bytes = bytes;
+        self
+    }
+
+    /// Set the error which interrupted the request.
+    pub fn error<E>(mut self, error: &E) -> Self
+    where
+        E: fmt::Display + ?Sized,
+    {
+        self.error = Some(error.to_string());
+        self
+    }
+}
+
+/// Append the records to an audit log.
+///
+/// The log can be cloned and shared between tasks: the records are written
+/// one at a time.
+#[derive(Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+    file: Arc<Mutex<File>>,
+}
+
+impl fmt::Debug for AuditLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AuditLog")
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl AuditLog {
+    /// Open the audit log located at `path`, creating it if needed.
+    pub fn open<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(AuditLog {
+            path: path.to_path_buf(),
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// Return the path of the audit log.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append a record.
+    pub fn record(&self, record: &AuditRecord) -> Result<(), Error> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|_| Error::Parse(String::from("audit log poisoned")))?;
+        file.write_all(line.as_bytes())?;
+        file.flush()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+//! Authenticate the requests sent to protected endpoints.
+//!
+//! Three mechanisms are supported, and can be combined:
+//!
+//! - static headers, like `Authorization: Bearer <TOKEN>`, sent with every
+//!   request
+//! - bearer tokens sent with the requests to a specific host, e.g. the tokens
+//!   stored in the OS keyring
+//! - a presigning hook, which turns the URL of an artifact into a signed URL
+//!   right before it is requested
+use crate::Error;
+use std::process::Command;
+use url::Url;
+
+/// Turn the URL of an artifact into a presigned URL.
+///
+/// The hook is called once per download attempt, so that the signatures with
+/// a short expiration time stay valid. It runs on the blocking thread pool of
+/// the runtime, and may block.
+pub trait Presign: Send + Sync {
+    /// Return the presigned version of `url`.
+    fn presign(&self, url: &Url) -> Result<Url, Error>;
+}
+
+impl<F> Presign for F
+where
+    F: Fn(&Url) -> Result<Url, Error> + Send + Sync,
+{
+    fn presign(&self, url: &Url) -> Result<Url, Error> {
+        self(url)
+    }
+}
+
+/// Presign the URLs using an external command.
+///
+/// The command receives the URL to sign as its last argument, and must print
+/// the signed URL on its standard output, e.g.
+/// `sign-url.sh --expires-in 300`.
+#[derive(Debug, Clone)]
+pub struct CommandPresigner {
+    program: String,
+    args: Vec<String>,
+}
+
+impl CommandPresigner {
+    /// Create a presigner from a command line.
+    ///
+    /// The command line is split on white spaces.
+    pub fn new(command_line: &str) -> Result<Self, Error> {
+        let mut parts = command_line.split_whitespace().map(String::from);
+        let program = parts
+            .next()
+            .ok_or_else(|| Error::Parse(String::from("empty presign command")))?;
+
+        Ok(CommandPresigner {
+            program,
+            args: parts.collect(),
+        })
+    }
+}
+
+impl Presign for CommandPresigner {
+    fn presign(&self, url: &Url) -> Result<Url, Error> {
+        let output = Command::new(&self.program)
+            .args(&self.args)
+            .arg(url.as_str())
+            .output()?;
+        if !output.status.success() {
+            return Err(Error::Auth(format!(
+                "presign command failed ({}): {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        let signed = String::from_utf8_lossy(&output.stdout);
+
+        Ok(Url::parse(signed.trim())?)
+    }
+}
+
+/// Return the host of `url`, followed by its port if it is not the default
+/// one, e.g. `mirror.example.org:8443`.
+///
+/// The credentials of a host are looked up with this key.
+pub fn host_key(url: &Url) -> Option<String> {
+    let host = url.host_str()?;
+    let key = match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    };
+
+    Some(key.to_lowercase())
+}
+
+/// Parse a header specified as `Name: value`.
+pub fn parse_header(header: &str) -> Result<(String, String), Error> {
+    let (name, value) = header.split_once(':').ok_or_else(|| {
+        Error::Parse(format!(
+            "invalid header {:?}, expected `Name: value`",
+            header
+        ))
+    })?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(Error::Parse(format!(
+            "invalid header {:?}, missing name",
+            header
+        )));
+    }
+
+    Ok((name.to_string(), value.trim().to_string()))
+}
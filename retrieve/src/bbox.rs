@@ -0,0 +1,141 @@
+//! Compute and compare the bounding boxes of the cities.
+//!
+//! The bounding boxes are expressed in WGS84 longitudes and latitudes, in
+//! degrees, whatever the projection of the dataset they are computed from.
+use crate::Error;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+#[cfg(feature = "extract")]
+use {
+    crate::projection::{shapefile_projection, wgs84, Reprojection},
+    std::path::Path,
+};
+
+/// Number of points sampled along each edge of a projected bounding box.
+#[cfg(feature = "extract")]
+const EDGE_SAMPLES: u32 = 8;
+
+/// Represent a bounding box.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BoundingBox {
+    /// Minimum longitude.
+    pub min_lon: f64,
+    /// Minimum latitude.
+    pub min_lat: f64,
+    /// Maximum longitude.
+    pub max_lon: f64,
+    /// Maximum latitude.
+    pub max_lat: f64,
+}
+
+impl BoundingBox {
+    /// Return `true` if the two bounding boxes overlap.
+    pub fn intersects(&self, other: &BoundingBox) -> bool {
+        self.min_lon <= other.max_lon
+            && other.min_lon <= self.max_lon
+            && self.min_lat <= other.max_lat
+            && other.min_lat <= self.max_lat
+    }
+
+    /// Compute the bounding box of the shapefile located at `path`.
+    ///
+    /// The bounding box recorded in the shapefile header is reprojected using
+    /// the `.prj` file next to it. Without `.prj` file, the coordinates are
+    /// assumed to be longitudes and latitudes.
+    #[cfg(feature = "extract")]
+    pub fn from_shapefile<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let reader = shapefile::ShapeReader::from_path(path)?;
+        let bbox = &reader.header().bbox;
+        let (min, max) = ((bbox.min.x, bbox.min.y), (bbox.max.x, bbox.max.y));
+        let from = match shapefile_projection(path)? {
+            Some(from) if !from.is_latlong() => from,
+            _ => return Ok(BoundingBox::from_corners(min, max)),
+        };
+
+        // Reproject points sampled along the edges, since straight edges get
+        // curved by the reprojection.
+        let reprojection = Reprojection::new(from, wgs84());
+        let mut reprojected = BoundingBox {
+            min_lon: f64::INFINITY,
+            min_lat: f64::INFINITY,
+            max_lon: f64::NEG_INFINITY,
+            max_lat: f64::NEG_INFINITY,
+        };
+        for i in 0..=EDGE_SAMPLES {
+            let t = f64::from(i) / f64::from(EDGE_SAMPLES);
+            let x = min.0 + t * (max.0 - min.0);
+            let y = min.1 + t * (max.1 - min.1);
+            for (x, y) in [(x, min.1), (x, max.1), (min.0, y), (max.0, y)] {
+                let (lon, lat) = reprojection.point(x, y)?;
+                reprojected.extend(lon, lat);
+            }
+        }
+
+        Ok(reprojected)
+    }
+
+    /// Extend the bounding box to include a point.
+    #[cfg(feature = "extract")]
+    fn extend(&mut self, lon: f64, lat: f64) {
+        self.min_lon = self.min_lon.min(lon);
+        self.min_lat = self.min_lat.min(lat);
+        self.max_lon = self.max_lon.max(lon);
+        self.max_lat = self.max_lat.max(lat);
+    }
+
+    /// Create a bounding box from its corners, in degrees.
+    #[cfg(feature = "extract")]
+    fn from_corners(min: (f64, f64), max: (f64, f64)) -> Self {
+        BoundingBox {
+            min_lon: min.0,
+            min_lat: min.1,
+            max_lon: max.0,
+            max_lat: max.1,
+        }
+    }
+}
+
+/// Parse a bounding box specified as `MIN_LON,MIN_LAT,MAX_LON,MAX_LAT`.
+impl FromStr for BoundingBox {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            Error::Parse(format!(
+                "invalid bounding box {:?}, expected `MIN_LON,MIN_LAT,MAX_LON,MAX_LAT`",
+                s
+            ))
+        };
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|_| invalid())?;
+        match values[..] {
+            [min_lon, min_lat, max_lon, max_lat] if min_lon <= max_lon && min_lat <= max_lat => {
+                Ok(BoundingBox {
+                    min_lon,
+                    min_lat,
+                    max_lon,
+                    max_lat,
+                })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for BoundingBox {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{},{},{},{}",
+            self.min_lon, self.min_lat, self.max_lon, self.max_lat
+        )
+    }
+}
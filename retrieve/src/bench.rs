@@ -0,0 +1,95 @@
+//! Measure the throughput achievable against the storage from the current
+//! network, to tune the number of parallel requests and of segments.
+//!
+//! Each trial downloads the same dataset as many times as there are parallel
+//! requests, with the regular [`Downloader`], into a scratch folder which is
+//! removed afterwards.
+use crate::download::Downloader;
+use crate::plan::{Plan, PlannedDownload};
+use crate::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Share of the best throughput a trial must reach to be recommended.
+const RECOMMENDATION_RATIO: f64 = 0.9;
+
+/// Describe the result of a trial.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trial {
+    /// Number of segments of each file.
+    pub segments: u16,
+    /// Number of files downloaded simultaneously.
+    pub concurrency: u16,
+    /// Number of bytes downloaded.
+    pub bytes: u64,
+    /// Time spent downloading the files.
+    pub elapsed: Duration,
+    /// Number of failed downloads.
+    pub failures: usize,
+}
+
+impl Trial {
+    /// Return the throughput of the trial, in bytes per second.
+    pub fn throughput(&self) -> f64 {
+        match self.elapsed.as_secs_f64() {
+            secs if secs > 0.0 => self.bytes as f64 / secs,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Download a dataset `concurrency` times at once, each file split into
+/// `segments`, into a scratch subfolder of `folder`.
+pub async fn run_trial(
+    planned: &PlannedDownload,
+    segments: u16,
+    concurrency: u16,
+    folder: &Path,
+) -> Result<Trial, Error> {
+    let concurrency = concurrency.max(1);
+    let scratch = folder.join(format!("{}x{}", segments, concurrency));
+    let mut plan = Plan::default();
+    for i in 0..concurrency {
+        let mut download = planned.clone();
+        download.file_name = PathBuf::from(format!("{}.{}", i, planned.dataset.extension()));
+        plan.downloads.push(download);
+    }
+    let downloader = Downloader::builder()
+        .destination_folder(&scratch)
+        .parallel_requests(concurrency)
+        .retries(0)
+        .segments(segments)
+        .segment_threshold(0)
+        .build()?;
+    let result = downloader.fetch_all(&plan).await;
+    let _ = fs::remove_dir_all(&scratch);
+    let report = result?;
+
+    Ok(Trial {
+        segments,
+        concurrency,
+        bytes: report.total_bytes(),
+        elapsed: report.elapsed,
+        failures: report.failures().count(),
+    })
+}
+
+/// Return the trial to recommend.
+///
+/// This is the least demanding trial, in number of requests, which reaches
+/// 90% of the best throughput without failures: beyond it, more requests only
+/// load the storage for little gain.
+pub fn recommend(trials: &[Trial]) -> Option<&Trial> {
+    let successful = trials.iter().filter(|t| t.failures == 0);
+    let best = successful
+        .clone()
+        .map(Trial::throughput)
+        .fold(0.0, f64::max);
+    if best <= 0.0 {
+        return None;
+    }
+    successful
+        .filter(|t| t.throughput() >= best * RECOMMENDATION_RATIO)
+        .min_by_key(|t| (t.segments as u32 * t.concurrency as u32, t.concurrency))
+}
@@ -0,0 +1,46 @@
+//! Limit the time spent retrieving the datasets of each city.
+//!
+//! The budget of a city starts with its first download. Once it is exhausted,
+//! the downloads of the city which are still running are aborted and the
+//! pending ones are skipped, so that one pathologically slow transfer does not
+//! hold the whole batch open. The other cities are not affected.
+use crate::plan::PlannedDownload;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Track the time budget of each city.
+///
+/// The budgets can be cloned and are shared between the clones.
+#[derive(Debug, Clone)]
+pub struct CityBudget {
+    budget: Duration,
+    deadlines: Arc<Mutex<HashMap<String, Instant>>>,
+}
+
+impl CityBudget {
+    /// Allow `budget` to retrieve the datasets of each city.
+    pub fn new(budget: Duration) -> Self {
+        CityBudget {
+            budget,
+            deadlines: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Return the time allowed for each city.
+    pub fn budget(&self) -> Duration {
+        self.budget
+    }
+
+    /// Return the instant the budget of the city of a planned download is
+    /// exhausted, starting it if this is the first download of the city.
+    pub fn deadline(&self, planned: &PlannedDownload) -> Instant {
+        let key = format!("{}/{}", planned.city, planned.run.uuid);
+        *self
+            .deadlines
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert_with(|| Instant::now() + self.budget)
+    }
+}
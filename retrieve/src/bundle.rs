@@ -0,0 +1,351 @@
+//! Assemble the reproduction bundle of a city.
+//!
+//! A bundle is a tar archive compressed with zstd, which holds everything
+//! needed to reproduce a BNA run: the datasets of the city, their provenance
+//! records, a `SHA256SUMS` file and a manifest restricted to the city. The
+//! paths are relative to the download folder, so that a bundle unpacks into
+//! the standard layout.
+//!
+//! The modification times of the members are set to the time recorded in the
+//! output files, so that bundling the same files with `SOURCE_DATE_EPOCH` set
+//! produces the same archive.
+//!
+//! A bundle is verified against its own `SHA256SUMS` file when it is inspected
+//! or extracted, and its manifest is merged into the manifest of the download
+//! folder it is extracted to. The members which are not listed in the
+//! `SHA256SUMS` file are rejected.
+use crate::cache::sha256;
+use crate::checksum::{self, parse_sums, to_slash, Verification, SHA256SUMS};
+use crate::collation::fold;
+use crate::manifest::{Manifest, ManifestEntry, MANIFEST};
+use crate::provenance::{self, Provenance};
+use crate::timestamp;
+use crate::Error;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tar::{Archive, Builder, Header};
+
+/// Extension of the bundles.
+pub const BUNDLE_EXTENSION: &str = "tar.zst";
+
+/// Suffix of the members being extracted, until the bundle is verified.
+const STAGING_SUFFIX: &str = ".unbundling";
+
+/// Describe the content of a bundle.
+#[derive(Debug, Default, Clone)]
+pub struct BundleContent {
+    /// Manifest of the bundle.
+    pub manifest: Manifest,
+    /// Size of each member, indexed by path.
+    pub members: BTreeMap<PathBuf, u64>,
+    /// Verification of the members against the checksums of the bundle.
+    pub verification: Verification,
+}
+
+/// Return the entries of a manifest describing the files of a city.
+///
+/// The city is identified by its name, its full name (e.g. "Austin, Texas,
+/// United States") or its run UUID, without regard to case or diacritics.
+pub fn city_entries<'a>(
+    manifest: &'a Manifest,
+    city: &str,
+) -> Result<Vec<&'a ManifestEntry>, Error> {
+    let city = city.trim();
+    let folded = fold(city);
+    let entries: Vec<&ManifestEntry> = manifest
+        .files
+        .iter()
+        .filter(|e| {
+            fold(&e.city) == folded
+                || e.uuid.eq_ignore_ascii_case(city)
+                || fold(&full_name(e)) == folded
+        })
+        .collect();
+    let runs: BTreeSet<&str> = entries.iter().map(|e| e.uuid.as_str()).collect();
+    match runs.len() {
+        0 => Err(Error::Parse(format!(
+            "no city named {:?} in the manifest",
+            city
+        ))),
+        1 => Ok(entries),
+        _ => Err(Error::Parse(format!(
+            "{:?} matches several runs ({}), specify the run UUID instead",
+            city,
+            runs.into_iter().collect::<Vec<_>>().join(", ")
+        ))),
+    }
+}
+
+/// Write the bundle of the files described by `entries`, located in `folder`,
+/// to `writer`.
+///
+/// The `writer` is flushed once the bundle is complete, so that a buffered
+/// writer reports its errors.
+pub fn write_bundle<W>(folder: &Path, entries: &[&ManifestEntry], writer: W) -> Result<(), Error>
+where
+    W: Write,
+{
+    let mtime = timestamp::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let encoder = zstd::Encoder::new(writer, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+    let mut tar = Builder::new(encoder);
+    let mut manifest = Manifest::default();
+    let mut sums = String::new();
+    for entry in entries {
+        let path = folder.join(&entry.path);
+        let mut entry = (*entry).clone();
+        if entry.sha256.is_none() {
+            entry.sha256 = Some(sha256(&path)?);
+        }
+
+        // Dataset.
+        let file = File::open(&path)?;
+        let size = file.metadata()?.len();
+        append(&mut tar, &entry.path, size, mtime, file)?;
+
+        // Provenance record.
+        let mut record = serde_json::to_string_pretty(&Provenance::from_entry(&entry))?;
+        record.push('\n');
+        let record_path = provenance::sidecar_path(&entry.path);
+        append_bytes(&mut tar, &record_path, record.as_bytes(), mtime)?;
+
+        if let Some(hash) = &entry.sha256 {
+            sums.push_str(&format!("{}  {}\n", hash, to_slash(&entry.path)));
+        }
+        sums.push_str(&format!(
+            "{:x}  {}\n",
+            Sha256::digest(record.as_bytes()),
+            to_slash(&record_path)
+        ));
+        manifest.upsert(entry);
+    }
+    append_bytes(&mut tar, Path::new(SHA256SUMS), sums.as_bytes(), mtime)?;
+    let mut content = serde_json::to_string_pretty(&manifest)?;
+    content.push('\n');
+    append_bytes(&mut tar, Path::new(MANIFEST), content.as_bytes(), mtime)?;
+    let mut writer = tar.into_inner()?.finish()?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Read a bundle, and verify its members against its checksums.
+pub fn inspect<R>(reader: R) -> Result<BundleContent, Error>
+where
+    R: Read,
+{
+    let (content, _) = read_bundle(reader, None)?;
+
+    Ok(content)
+}
+
+/// Extract a bundle into the download `folder`, and record its files in the
+/// manifest and the `SHA256SUMS` file of the folder.
+///
+/// The members are only moved in place once they all match their checksums.
+/// Otherwise, the folder is left unchanged, and the returned verification
+/// tells which members are wrong. The staged members are removed if the bundle
+/// cannot be read or moved in place.
+pub fn extract<R>(reader: R, folder: &Path) -> Result<BundleContent, Error>
+where
+    R: Read,
+{
+    let (content, hashes) = read_bundle(reader, Some(folder))?;
+    let staged: Vec<PathBuf> = hashes.keys().map(|p| folder.join(p)).collect();
+    if !content.verification.is_ok() {
+        for path in &staged {
+            let _ = fs::remove_file(staging_path(path));
+        }
+        return Ok(content);
+    }
+    for (i, path) in staged.iter().enumerate() {
+        if let Err(e) = fs::rename(staging_path(path), path) {
+            for path in &staged[i..] {
+                let _ = fs::remove_file(staging_path(path));
+            }
+            return Err(e.into());
+        }
+    }
+
+    // Register the datasets.
+    let mut manifest = Manifest::load(folder)?;
+    for entry in &content.manifest.files {
+        manifest.upsert(entry.clone());
+    }
+    manifest.save(folder)?;
+    let sums: Vec<(PathBuf, String)> = content
+        .manifest
+        .files
+        .iter()
+        .filter_map(|e| Some((e.path.clone(), hashes.get(&e.path)?.clone())))
+        .collect();
+    checksum::update_sums(folder, &sums)?;
+
+    Ok(content)
+}
+
+/// Read the members of a bundle, and verify them against its checksums.
+///
+/// If `folder` is set, the members other than the manifest and the checksums
+/// are staged there, next to their final path, and removed if the bundle is
+/// invalid. Return the content of the bundle along with the hash of each
+/// staged member.
+fn read_bundle<R>(
+    reader: R,
+    folder: Option<&Path>,
+) -> Result<(BundleContent, BTreeMap<PathBuf, String>), Error>
+where
+    R: Read,
+{
+    let mut staged = Vec::new();
+    let result = read_members(reader, folder, &mut staged);
+    if result.is_err() {
+        for path in &staged {
+            let _ = fs::remove_file(path);
+        }
+    }
+    result
+}
+
+/// Read the members of a bundle, recording the files staged in `folder` to
+/// `staged`.
+fn read_members<R>(
+    reader: R,
+    folder: Option<&Path>,
+    staged: &mut Vec<PathBuf>,
+) -> Result<(BundleContent, BTreeMap<PathBuf, String>), Error>
+where
+    R: Read,
+{
+    let mut archive = Archive::new(zstd::Decoder::new(reader)?);
+    let mut members = BTreeMap::new();
+    let mut hashes = BTreeMap::new();
+    let (mut manifest, mut sums) = (None, None);
+    for member in archive.entries()? {
+        let mut member = member?;
+        if !member.header().entry_type().is_file() {
+            continue;
+        }
+        let path = member.path()?.into_owned();
+        // Never write outside of the download folder.
+        if path.as_os_str().is_empty()
+            || !path.components().all(|c| matches!(c, Component::Normal(_)))
+        {
+            return Err(Error::Parse(format!(
+                "invalid member {} in the bundle",
+                path.display()
+            )));
+        }
+        members.insert(path.clone(), member.size());
+        if path == Path::new(MANIFEST) || path == Path::new(SHA256SUMS) {
+            let mut text = String::new();
+            member.read_to_string(&mut text)?;
+            match path == Path::new(MANIFEST) {
+                true => manifest = Some(serde_json::from_str::<Manifest>(&text)?),
+                false => sums = Some(parse_sums(&text, &path)?),
+            }
+            continue;
+        }
+        let hash = match folder {
+            Some(folder) => {
+                let staging = staging_path(&folder.join(&path));
+                if let Some(parent) = staging.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                staged.push(staging.clone());
+                io::copy(&mut member, &mut File::create(&staging)?)?;
+                sha256(&staging)?
+            }
+            None => {
+                let mut hasher = Sha256::new();
+                io::copy(&mut member, &mut hasher)?;
+                format!("{:x}", hasher.finalize())
+            }
+        };
+        hashes.insert(path, hash);
+    }
+    let manifest =
+        manifest.ok_or_else(|| Error::Parse(format!("the bundle does not have a {}", MANIFEST)))?;
+    let sums =
+        sums.ok_or_else(|| Error::Parse(format!("the bundle does not have a {}", SHA256SUMS)))?;
+    if let Some(path) = hashes.keys().find(|p| !sums.contains_key(*p)) {
+        return Err(Error::Parse(format!(
+            "member {} of the bundle is not listed in its {}",
+            path.display(),
+            SHA256SUMS
+        )));
+    }
+    let mut verification = Verification::default();
+    for (path, expected) in sums {
+        match hashes.get(&path) {
+            None => verification.missing.push(path),
+            Some(hash) if *hash == expected => verification.verified.push(path),
+            Some(_) => verification.mismatched.push(path),
+        }
+    }
+    let content = BundleContent {
+        manifest,
+        members,
+        verification,
+    };
+
+    Ok((content, hashes))
+}
+
+/// Return the path a member is staged to before being moved to `path`.
+fn staging_path(path: &Path) -> PathBuf {
+    let mut staged = path.as_os_str().to_owned();
+    staged.push(STAGING_SUFFIX);
+    PathBuf::from(staged)
+}
+
+/// Return the full name of the city of an entry, e.g. "Austin, Texas, United
+/// States".
+fn full_name(entry: &ManifestEntry) -> String {
+    let mut name = entry.city.clone();
+    if !entry.state.is_empty() && entry.state != entry.country {
+        name.push_str(", ");
+        name.push_str(&entry.state);
+    }
+    if !entry.country.is_empty() {
+        name.push_str(", ");
+        name.push_str(&entry.country);
+    }
+    name
+}
+
+/// Add a member of `size` bytes, read from `data`, to the archive.
+fn append<W, R>(
+    tar: &mut Builder<W>,
+    path: &Path,
+    size: u64,
+    mtime: u64,
+    data: R,
+) -> Result<(), Error>
+where
+    W: Write,
+    R: Read,
+{
+    let mut header = Header::new_gnu();
+    header.set_size(size);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    header.set_cksum();
+    tar.append_data(&mut header, path, data)?;
+
+    Ok(())
+}
+
+/// Add a member holding `data` to the archive.
+fn append_bytes<W>(tar: &mut Builder<W>, path: &Path, data: &[u8], mtime: u64) -> Result<(), Error>
+where
+    W: Write,
+{
+    append(tar, path, data.len() as u64, mtime, data)
+}
@@ -0,0 +1,147 @@
+//! Share downloaded artifacts between several download folders.
+//!
+//! The cache is content-addressed: each artifact is stored once under its
+//! SHA256 hash in the `blobs` folder, and the `refs` folder maps the URL it was
+//! downloaded from to that hash. Since a run UUID identifies the content of a
+//! dataset, a URL which was already retrieved never needs to hit the network
+//! again.
+//!
+//! Files are hardlinked between the cache and the download folders whenever
+//! possible, and copied otherwise (e.g. when they are located on different
+//! file systems). As a consequence, a file from a download folder must not be
+//! modified in place.
+use crate::Error;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// Name of the folder containing the artifacts.
+const BLOBS: &str = "blobs";
+/// Name of the folder mapping the URLs to the artifacts.
+const REFS: &str = "refs";
+
+/// Represent a content-addressed download cache.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    root: PathBuf,
+}
+
+impl Cache {
+    /// Open the cache located in `root`, creating it if needed.
+    pub fn open<P>(root: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let root = root.as_ref().to_path_buf();
+        fs::create_dir_all(root.join(BLOBS))?;
+        fs::create_dir_all(root.join(REFS))?;
+
+        Ok(Cache { root })
+    }
+
+    /// Return the path of the artifact downloaded from `url`, if it is cached.
+    pub fn lookup(&self, url: &Url) -> Option<PathBuf> {
+        self.lookup_hash(url).map(|hash| self.blob_path(&hash))
+    }
+
+    /// Return the SHA256 hash of the artifact downloaded from `url`, if it is
+    /// cached.
+    pub fn lookup_hash(&self, url: &Url) -> Option<String> {
+        let hash = fs::read_to_string(self.ref_path(url)).ok()?;
+        let hash = hash.trim();
+        if hash.len() != 64 {
+            return None;
+        }
+        self.blob_path(hash).is_file().then(|| hash.to_string())
+    }
+
+    /// Place the artifact downloaded from `url` at `destination`.
+    ///
+    /// Return the SHA256 hash of the artifact, or `None` if it is not cached.
+    pub fn restore<P>(&self, url: &Url, destination: P) -> Result<Option<String>, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let hash = match self.lookup_hash(url) {
+            Some(hash) => hash,
+            None => return Ok(None),
+        };
+        let destination = destination.as_ref();
+        if destination.exists() {
+            fs::remove_file(destination)?;
+        }
+        link_or_copy(&self.blob_path(&hash), destination)?;
+
+        Ok(Some(hash))
+    }
+
+    /// Add the artifact located at `path`, downloaded from `url`, to the cache.
+    ///
+    /// Return the SHA256 hash of the artifact.
+    pub fn insert<P>(&self, url: &Url, path: P) -> Result<String, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let hash = sha256(path)?;
+        self.insert_hashed(url, path, &hash)?;
+
+        Ok(hash)
+    }
+
+    /// Add the artifact located at `path`, downloaded from `url`, to the cache,
+    /// when its SHA256 `hash` is already known.
+    pub fn insert_hashed<P>(&self, url: &Url, path: P, hash: &str) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let blob = self.blob_path(hash);
+        if !blob.exists() {
+            fs::create_dir_all(blob.parent().unwrap())?;
+            link_or_copy(path, &blob)?;
+        }
+
+        // Write the reference atomically, as another process may be reading it.
+        let reference = self.ref_path(url);
+        let tmp = reference.with_extension("tmp");
+        fs::write(&tmp, hash)?;
+        fs::rename(&tmp, &reference)?;
+
+        Ok(())
+    }
+
+    /// Return the path of the blob identified by `hash`.
+    fn blob_path(&self, hash: &str) -> PathBuf {
+        self.root.join(BLOBS).join(&hash[..2]).join(hash)
+    }
+
+    /// Return the path of the reference for `url`.
+    fn ref_path(&self, url: &Url) -> PathBuf {
+        let key = format!("{:x}", Sha256::digest(url.as_str().as_bytes()));
+        self.root.join(REFS).join(key)
+    }
+}
+
+/// Compute the SHA256 hash of the file located at `path`.
+pub fn sha256<P>(path: P) -> Result<String, Error>
+where
+    P: AsRef<Path>,
+{
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Hardlink `from` to `to`, or copy it if the link cannot be created.
+fn link_or_copy(from: &Path, to: &Path) -> Result<(), Error> {
+    if fs::hard_link(from, to).is_err() {
+        fs::copy(from, to)?;
+    }
+
+    Ok(())
+}
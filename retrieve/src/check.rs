@@ -0,0 +1,372 @@
+//! Validate a city file without network access.
+//!
+//! The checks catch the malformed city files before they reach a batch, e.g.
+//! in the continuous integration of the repository hosting the inventory. The
+//! whole file is checked, and every problem is reported with its line:
+//!
+//! - errors: missing or duplicate columns, empty required fields, invalid run
+//!   UUIDs or numbers, and run UUIDs listed twice;
+//! - warnings: suspicious values, like a city without a run UUID, a null
+//!   population, a rating out of range or inconsistent with its rounded
+//!   version, or a city listed twice for the same date.
+use crate::Error;
+use csv::StringRecord;
+use pfbcore::is_valid_uuid;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+/// Columns every city file must have.
+pub const REQUIRED_COLUMNS: [&str; 7] = [
+    "City",
+    "Country",
+    "State",
+    "uuid",
+    "population",
+    "city_ratings_total",
+    "city_ratings_rounded",
+];
+
+/// Columns which must not be empty.
+const REQUIRED_FIELDS: [&str; 2] = ["City", "Country"];
+
+/// Describe how serious a problem is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The value is suspicious, but the file can be used.
+    Warning,
+    /// The file cannot be used as is.
+    Error,
+}
+
+/// Describe a problem found in a city file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    /// How serious the problem is.
+    pub severity: Severity,
+    /// Line of the problem, starting at 1 with the headers.
+    pub line: u64,
+    /// Column of the problem, if it concerns a single one.
+    pub column: Option<String>,
+    /// Description of the problem.
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: {}: ", self.line, severity)?;
+        if let Some(column) = &self.column {
+            write!(f, "{}: ", column)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Collect the problems of a city file.
+#[derive(Debug, Default)]
+struct Problems(Vec<Problem>);
+
+impl Problems {
+    /// Record a problem.
+    fn push(&mut self, severity: Severity, line: u64, column: Option<&str>, message: String) {
+        self.0.push(Problem {
+            severity,
+            line,
+            column: column.map(String::from),
+            message,
+        });
+    }
+}
+
+/// Check the city file located at `path`.
+///
+/// Return the problems found, in the order of the file. Only the errors
+/// preventing the file from being read at all, e.g. a missing file, are
+/// returned as an error.
+pub fn check_file<P>(path: P) -> Result<Vec<Problem>, Error>
+where
+    P: AsRef<Path>,
+{
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_path(path)
+        .map_err(pfbcore::Error::from)?;
+    let headers = reader.headers().map_err(pfbcore::Error::from)?.clone();
+    let mut problems = Problems::default();
+
+    // Check the headers.
+    let mut columns: HashMap<&str, usize> = HashMap::new();
+    for (i, name) in headers.iter().enumerate() {
+        if columns.insert(name, i).is_some() {
+            problems.push(
+                Severity::Error,
+                1,
+                Some(name),
+                String::from("duplicate column"),
+            );
+        }
+    }
+    for name in REQUIRED_COLUMNS {
+        if !columns.contains_key(name) {
+            problems.push(
+                Severity::Error,
+                1,
+                Some(name),
+                String::from("missing column"),
+            );
+        }
+    }
+
+    // Check the records.
+    let mut uuids: HashMap<String, u64> = HashMap::new();
+    let mut cities: HashMap<(String, String, String, String), u64> = HashMap::new();
+    for record in reader.records() {
+        let record = record.map_err(pfbcore::Error::from)?;
+        let line = record.position().map_or(0, |p| p.line());
+        if record.len() != headers.len() {
+            problems.push(
+                Severity::Error,
+                line,
+                None,
+                format!("found {} fields, expected {}", record.len(), headers.len()),
+            );
+            continue;
+        }
+        check_record(&record, &headers, &columns, line, &mut problems);
+
+        let field = |name: &str| field(&record, &columns, name).unwrap_or_default();
+        let uuid = field("uuid").trim().to_ascii_lowercase();
+        if !uuid.is_empty() {
+            if let Some(first) = uuids.insert(uuid.clone(), line) {
+                problems.push(
+                    Severity::Error,
+                    line,
+                    Some("uuid"),
+                    format!("run UUID {:?} already listed on line {}", uuid, first),
+                );
+            }
+        }
+        let key = (
+            field("Country").to_string(),
+            field("State").to_string(),
+            field("City").to_string(),
+            field("date").to_string(),
+        );
+        if let Some(first) = cities.insert(key, line) {
+            problems.push(
+                Severity::Warning,
+                line,
+                Some("City"),
+                format!("city already listed on line {} for the same date", first),
+            );
+        }
+    }
+
+    Ok(problems.0)
+}
+
+/// Check the fields of a record.
+fn check_record(
+    record: &StringRecord,
+    headers: &StringRecord,
+    columns: &HashMap<&str, usize>,
+    line: u64,
+    problems: &mut Problems,
+) {
+    let field = |name: &str| field(record, columns, name);
+
+    for (name, value) in headers.iter().zip(record.iter()) {
+        if value != value.trim() {
+            problems.push(
+                Severity::Warning,
+                line,
+                Some(name),
+                format!("leading or trailing spaces in {:?}", value),
+            );
+        }
+    }
+    for name in REQUIRED_FIELDS {
+        if field(name).is_some_and(|v| v.trim().is_empty()) {
+            problems.push(
+                Severity::Error,
+                line,
+                Some(name),
+                String::from("empty value"),
+            );
+        }
+    }
+
+    match field("uuid").map(str::trim) {
+        Some("") => problems.push(
+            Severity::Warning,
+            line,
+            Some("uuid"),
+            String::from("no run UUID, the city cannot be downloaded"),
+        ),
+        Some(uuid) if !is_valid_uuid(uuid) => problems.push(
+            Severity::Error,
+            line,
+            Some("uuid"),
+            format!("invalid run UUID {:?}", uuid),
+        ),
+        _ => (),
+    }
+
+    if let Some(value) = field("population") {
+        match value.trim().parse::<u32>() {
+            Ok(0) => problems.push(
+                Severity::Warning,
+                line,
+                Some("population"),
+                String::from("null population"),
+            ),
+            Ok(_) => (),
+            Err(_) => problems.push(
+                Severity::Error,
+                line,
+                Some("population"),
+                format!("invalid population {:?}", value),
+            ),
+        }
+    }
+    let ratings = field("city_ratings_total").and_then(|value| match value.trim().parse::<f64>() {
+        Ok(ratings) if ratings.is_finite() => {
+            if !(0.0..=100.0).contains(&ratings) {
+                problems.push(
+                    Severity::Warning,
+                    line,
+                    Some("city_ratings_total"),
+                    format!("rating {} out of the 0-100 range", ratings),
+                );
+            }
+            Some(ratings)
+        }
+        _ => {
+            problems.push(
+                Severity::Error,
+                line,
+                Some("city_ratings_total"),
+                format!("invalid rating {:?}", value),
+            );
+            None
+        }
+    });
+    if let Some(value) = field("city_ratings_rounded") {
+        match (value.trim().parse::<u8>(), ratings) {
+            (Ok(rounded), Some(ratings)) if (f64::from(rounded) - ratings).abs() > 1.0 => {
+                problems.push(
+                    Severity::Warning,
+                    line,
+                    Some("city_ratings_rounded"),
+                    format!("rounded rating {} inconsistent with {}", rounded, ratings),
+                );
+            }
+            (Ok(_), _) => (),
+            (Err(_), _) => problems.push(
+                Severity::Error,
+                line,
+                Some("city_ratings_rounded"),
+                format!("invalid rounded rating {:?}", value),
+            ),
+        }
+    }
+}
+
+/// Return the value of a column of a record, if the column exists.
+fn field<'a>(
+    record: &'a StringRecord,
+    columns: &HashMap<&str, usize>,
+    name: &str,
+) -> Option<&'a str> {
+    columns.get(name).and_then(|&i| record.get(i))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process};
+
+    const HEADERS: &str =
+        "City,Country,State,uuid,population,city_ratings_total,city_ratings_rounded\n";
+
+    /// Check a city file with the given `content`.
+    fn check(name: &str, content: &str) -> Vec<(u64, Option<String>, Severity)> {
+        let path = env::temp_dir().join(format!("retrieve-check-{}-{}.csv", name, process::id()));
+        fs::write(&path, content).unwrap();
+        let problems = check_file(&path);
+        fs::remove_file(&path).unwrap();
+        problems
+            .unwrap()
+            .into_iter()
+            .map(|p| (p.line, p.column, p.severity))
+            .collect()
+    }
+
+    fn problem(line: u64, column: &str, severity: Severity) -> (u64, Option<String>, Severity) {
+        (line, Some(column.to_string()), severity)
+    }
+
+    #[test]
+    fn check_valid_file() {
+        let content = format!(
+            "{}{}{}",
+            HEADERS,
+            "Austin,USA,TX,8d2a8a6c-0c2b-4c4d-9b4e-3b6f5e8c1a2b,950000,40.5,41\n",
+            "Boston,USA,MA,1b7e0f0a-2f6c-4b8e-8a4d-7c9e6d5f4a3b,650000,30,30\n"
+        );
+        assert!(check("valid", &content).is_empty());
+    }
+
+    #[test]
+    fn check_headers() {
+        let content = "City,Country,State,State,uuid,population,city_ratings_total\n";
+        assert_eq!(
+            check("headers", content),
+            vec![
+                problem(1, "State", Severity::Error),
+                problem(1, "city_ratings_rounded", Severity::Error),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_records() {
+        let content = format!(
+            "{}{}{}{}{}{}",
+            HEADERS,
+            "Austin,USA,TX,8d2a8a6c-0c2b-4c4d-9b4e-3b6f5e8c1a2b,950000,40.5,41\n",
+            "Austin,USA,TX,8D2A8A6C-0C2B-4C4D-9B4E-3B6F5E8C1A2B,950000,40.5,41\n",
+            " Dallas,USA,TX,,0,120,12\n",
+            ",USA,TX,not-a-uuid,abc,x,y\n",
+            "Denver,USA\n"
+        );
+        assert_eq!(
+            check("records", &content),
+            vec![
+                problem(3, "uuid", Severity::Error),
+                problem(3, "City", Severity::Warning),
+                problem(4, "City", Severity::Warning),
+                problem(4, "uuid", Severity::Warning),
+                problem(4, "population", Severity::Warning),
+                problem(4, "city_ratings_total", Severity::Warning),
+                problem(4, "city_ratings_rounded", Severity::Warning),
+                problem(5, "City", Severity::Error),
+                problem(5, "uuid", Severity::Error),
+                problem(5, "population", Severity::Error),
+                problem(5, "city_ratings_total", Severity::Error),
+                problem(5, "city_ratings_rounded", Severity::Error),
+                (6, None, Severity::Error),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_missing_file() {
+        let path = env::temp_dir().join(format!("retrieve-check-missing-{}.csv", process::id()));
+        assert!(check_file(path).is_err());
+    }
+}
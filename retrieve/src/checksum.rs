@@ -0,0 +1,149 @@
+//! Write and read SHA256 checksums in the format of `sha256sum`.
+//!
+//! Each downloaded file can get a `.sha256` sidecar, and the whole download
+//! folder a combined `SHA256SUMS` file, so that the artifacts can be verified
+//! with `sha256sum -c`.
+use crate::cache::sha256;
+use crate::Error;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the file listing the checksums of a download folder.
+pub const SHA256SUMS: &str = "SHA256SUMS";
+
+/// Return the path of the checksum sidecar of `path`.
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".sha256");
+    PathBuf::from(sidecar)
+}
+
+/// Write the checksum sidecar of the file located at `path`.
+///
+/// The sidecar references the file by its name only, as it is stored next to
+/// it.
+pub fn write_sidecar(path: &Path, hash: &str) -> Result<(), Error> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| Error::Parse(format!("invalid file path {:?}", path)))?;
+    let line = format!("{}  {}\n", hash, name.to_string_lossy());
+    fs::write(sidecar_path(path), line)?;
+
+    Ok(())
+}
+
+/// Read a checksum file.
+///
+/// Return the hash of each file, indexed by file path relative to the checksum
+/// file.
+pub fn read_sums<P>(path: P) -> Result<BTreeMap<PathBuf, String>, Error>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    parse_sums(&fs::read_to_string(path)?, path)
+}
+
+/// Parse the `content` of a checksum file, read from `path`.
+///
+/// Return the hash of each file, indexed by file path relative to the checksum
+/// file.
+pub fn parse_sums(content: &str, path: &Path) -> Result<BTreeMap<PathBuf, String>, Error> {
+    let mut sums = BTreeMap::new();
+    for (n, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (hash, name) = line
+            .split_once(' ')
+            .filter(|(hash, _)| hash.len() == 64)
+            .ok_or_else(|| {
+                Error::Parse(format!("invalid checksum at {}:{}", path.display(), n + 1))
+            })?;
+        // The file name is preceded by '*' in binary mode, and ' ' otherwise.
+        let name = name.strip_prefix(['*', ' ']).unwrap_or(name);
+        sums.insert(PathBuf::from(name), hash.to_lowercase());
+    }
+
+    Ok(sums)
+}
+
+/// Add checksums to the `SHA256SUMS` file of `folder`.
+///
+/// The existing entries are kept, unless they refer to one of the new files.
+/// The file paths are relative to `folder`.
+pub fn update_sums<P>(folder: P, entries: &[(PathBuf, String)]) -> Result<(), Error>
+where
+    P: AsRef<Path>,
+{
+    let path = folder.as_ref().join(SHA256SUMS);
+    let mut sums = if path.exists() {
+        read_sums(&path)?
+    } else {
+        BTreeMap::new()
+    };
+    for (name, hash) in entries {
+        sums.insert(name.clone(), hash.clone());
+    }
+
+    let content: String = sums
+        .iter()
+        .map(|(name, hash)| format!("{}  {}\n", hash, to_slash(name)))
+        .collect();
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, content)?;
+    fs::rename(&tmp, &path)?;
+
+    Ok(())
+}
+
+/// Represent the result of a verification.
+#[derive(Debug, Default, Clone)]
+pub struct Verification {
+    /// Files matching their checksum.
+    pub verified: Vec<PathBuf>,
+    /// Files not matching their checksum.
+    pub mismatched: Vec<PathBuf>,
+    /// Files which could not be found.
+    pub missing: Vec<PathBuf>,
+}
+
+impl Verification {
+    /// Return `true` if all the files match their checksum.
+    pub fn is_ok(&self) -> bool {
+        self.mismatched.is_empty() && self.missing.is_empty()
+    }
+}
+
+/// Verify the files of `folder` against their checksums.
+///
+/// The file paths of `sums` are relative to `folder`. The network is never
+/// used, only the local files are read.
+pub fn verify<P>(folder: P, sums: &BTreeMap<PathBuf, String>) -> Result<Verification, Error>
+where
+    P: AsRef<Path>,
+{
+    let folder = folder.as_ref();
+    let mut verification = Verification::default();
+    for (name, expected) in sums {
+        let path = folder.join(name);
+        if !path.is_file() {
+            verification.missing.push(path);
+        } else if sha256(&path)? == *expected {
+            verification.verified.push(path);
+        } else {
+            verification.mismatched.push(path);
+        }
+    }
+
+    Ok(verification)
+}
+
+/// Format a relative path with `/` separators, as expected by `sha256sum`.
+pub(crate) fn to_slash(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
@@ -0,0 +1,282 @@
+//! Manage collections of cities.
+use crate::collation::fold;
+use pfbcore::scorecard::City;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+#[cfg(feature = "csv")]
+use {crate::Error, csv::StringRecord, pfbcore::is_valid_uuid, std::io::Read, std::path::Path};
+
+/// Minimum similarity score for a city to be returned by [`Cities::search`].
+const MIN_SEARCH_SCORE: f64 = 0.8;
+
+/// Read the cities of a CSV file, optionally validating them.
+///
+/// ```no_run
+/// use retrieve::cities::CitiesReader;
+///
+/// let cities = CitiesReader::new()
+///     .validate(true)
+///     .read("city_ratings.csv")
+///     .unwrap();
+/// ```
+#[cfg(feature = "csv")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CitiesReader {
+    #[cfg(feature = "parallel")]
+    parallel: bool,
+    validate: bool,
+}
+
+#[cfg(feature = "csv")]
+impl CitiesReader {
+    /// Create a reader parsing the records sequentially, without validation.
+    pub fn new() -> Self {
+        CitiesReader::default()
+    }
+
+    /// Parse and validate the records on all the available cores.
+    ///
+    /// The cities are still returned in the order of the file.
+    #[cfg(feature = "parallel")]
+    pub fn parallel(mut self, parallel: bool) -> Self {
+        self.parallel = parallel;
+        self
+    }
+
+    /// Reject the cities whose run UUID is set but invalid.
+    pub fn validate(mut self, validate: bool) -> Self {
+        self.validate = validate;
+        self
+    }
+
+    /// Read the CSV file located at `path`.
+    pub fn read<P>(&self, path: P) -> Result<Cities, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut reader = csv::Reader::from_path(path).map_err(pfbcore::Error::from)?;
+        let headers = reader.headers().map_err(pfbcore::Error::from)?.clone();
+        let records = reader
+            .records()
+            .collect::<Result<Vec<StringRecord>, _>>()
+            .map_err(pfbcore::Error::from)?;
+        let parse = |record: &StringRecord| -> Result<City, Error> {
+            let city: City = record
+                .deserialize(Some(&headers))
+                .map_err(pfbcore::Error::from)?;
+            if self.validate && !city.run.uuid.is_empty() && !is_valid_uuid(&city.run.uuid) {
+                return Err(Error::Parse(format!(
+                    "invalid run UUID {:?} on line {}",
+                    city.run.uuid,
+                    record.position().map_or(0, |p| p.line())
+                )));
+            }
+            Ok(city)
+        };
+
+        #[cfg(feature = "parallel")]
+        if self.parallel {
+            return records.par_iter().map(parse).collect();
+        }
+        records.iter().map(parse).collect()
+    }
+}
+
+/// Represent a collection of cities.
+#[derive(Debug, Default, Clone)]
+pub struct Cities(Vec<City>);
+
+impl Cities {
+    /// Create a new collection of cities.
+    pub fn new(cities: Vec<City>) -> Self {
+        Cities(cities)
+    }
+
+    /// Read a CSV file and populate a collection of cities.
+    #[cfg(feature = "csv")]
+    pub fn from_csv<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Cities(City::from_csv(path)?))
+    }
+
+    /// Read the cities of a CSV document from `reader`.
+    #[cfg(feature = "csv")]
+    pub fn from_reader<R>(reader: R) -> Result<Self, Error>
+    where
+        R: Read,
+    {
+        let cities = csv::Reader::from_reader(reader)
+            .into_deserialize()
+            .collect::<Result<Vec<City>, _>>()
+            .map_err(pfbcore::Error::from)?;
+
+        Ok(Cities(cities))
+    }
+
+    /// Return the cities as a slice.
+    pub fn as_slice(&self) -> &[City] {
+        &self.0
+    }
+
+    /// Return an iterator over the cities.
+    pub fn iter(&self) -> std::slice::Iter<'_, City> {
+        self.0.iter()
+    }
+
+    /// Return the number of cities.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Return `true` if the collection does not contain any city.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Return the city analyzed by the run identified by `uuid`.
+    ///
+    /// The comparison is case insensitive.
+    pub fn get_by_uuid(&self, uuid: &str) -> Option<&City> {
+        self.0
+            .iter()
+            .find(|c| c.run.uuid.eq_ignore_ascii_case(uuid))
+    }
+
+    /// Return the city whose full name is `full_name`.
+    ///
+    /// The comparison ignores the case and the diacritics. Refer to
+    /// [`City::full_name`] for the format of the full name.
+    pub fn get_by_full_name(&self, full_name: &str) -> Option<&City> {
+        let full_name = fold(full_name);
+        self.0.iter().find(|c| fold(&c.full_name()) == full_name)
+    }
+
+    /// Return the first city named `name`, ignoring the case and the
+    /// diacritics.
+    pub fn get_by_name(&self, name: &str) -> Option<&City> {
+        let name = fold(name);
+        self.0.iter().find(|c| fold(&c.name) == name)
+    }
+
+    /// Group the cities by country.
+    ///
+    /// The countries are sorted, and the cities keep their order.
+    pub fn group_by_country(&self) -> BTreeMap<String, Cities> {
+        let mut groups: BTreeMap<String, Cities> = BTreeMap::new();
+        for city in &self.0 {
+            groups
+                .entry(city.country.clone())
+                .or_default()
+                .0
+                .push(city.clone());
+        }
+        groups
+    }
+
+    /// Group the cities by country, then by state.
+    ///
+    /// The countries and states are sorted, and the cities keep their order.
+    pub fn group_by_state(&self) -> BTreeMap<String, BTreeMap<String, Cities>> {
+        self.group_by_country()
+            .into_iter()
+            .map(|(country, cities)| {
+                let mut states: BTreeMap<String, Cities> = BTreeMap::new();
+                for city in cities {
+                    states.entry(city.state.clone()).or_default().0.push(city);
+                }
+                (country, states)
+            })
+            .collect()
+    }
+
+    /// Search the cities whose name is similar to `query`.
+    ///
+    /// The matching ignores the case and the diacritics, and tolerates typos.
+    /// The matches are returned with their similarity score, between 0 and 1,
+    /// from the most similar to the least similar.
+    pub fn search(&self, query: &str) -> Vec<(City, f64)> {
+        let query = fold(query);
+        let mut matches = self
+            .0
+            .iter()
+            .map(|city| {
+                let score = strsim::jaro_winkler(&query, &fold(&city.name));
+                (city.clone(), score)
+            })
+            .filter(|(_, score)| *score >= MIN_SEARCH_SCORE)
+            .collect::<Vec<(City, f64)>>();
+        matches.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        matches
+    }
+
+    /// Pick `count` cities at random.
+    ///
+    /// The same `seed` always picks the same cities from the same collection,
+    /// whatever the platform or the version of retrieve. The cities keep
+    /// their order.
+    pub fn sample(&self, count: usize, seed: u64) -> Cities {
+        let mut indices: Vec<usize> = (0..self.0.len()).collect();
+        let count = count.min(indices.len());
+        let mut state = seed;
+        for i in 0..count {
+            let j = i + (splitmix64(&mut state) % (indices.len() - i) as u64) as usize;
+            indices.swap(i, j);
+        }
+        let mut picked = indices[..count].to_vec();
+        picked.sort_unstable();
+
+        picked.into_iter().map(|i| self.0[i].clone()).collect()
+    }
+}
+
+/// Return the next number of the SplitMix64 generator.
+///
+/// It is implemented here rather than pulled from a crate to keep the samples
+/// stable across releases.
+pub(crate) fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl From<Vec<City>> for Cities {
+    fn from(cities: Vec<City>) -> Self {
+        Cities(cities)
+    }
+}
+
+impl From<Cities> for Vec<City> {
+    fn from(cities: Cities) -> Self {
+        cities.0
+    }
+}
+
+impl FromIterator<City> for Cities {
+    fn from_iter<I: IntoIterator<Item = City>>(iter: I) -> Self {
+        Cities(iter.into_iter().collect())
+    }
+}
+
+impl IntoIterator for Cities {
+    type Item = City;
+    type IntoIter = std::vec::IntoIter<City>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Cities {
+    type Item = &'a City;
+    type IntoIter = std::slice::Iter<'a, City>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
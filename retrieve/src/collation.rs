@@ -0,0 +1,112 @@
+//! Match and sort international city names.
+//!
+//! The names are matched once folded: decomposed (NFKD), stripped of their
+//! diacritics and lowercased, so that "Malmo" matches "Malmö" and "SAO PAULO"
+//! matches "São Paulo".
+//!
+//! They are sorted by their folded form by default, which puts "Écija" next
+//! to "Ecatepec" rather than after "Zaragoza". With the `collation` feature,
+//! they can be sorted following the rules of a locale instead, e.g. Swedish
+//! sorts "Örebro" after "Zürich".
+#[cfg(feature = "collation")]
+use crate::Error;
+#[cfg(feature = "collation")]
+use icu_collator::{Collator, CollatorOptions, Strength};
+#[cfg(feature = "collation")]
+use icu_locid::Locale;
+use std::cmp::Ordering;
+use std::fmt;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Fold a name for matching: decompose it, strip its diacritics, and lowercase
+/// it.
+///
+/// The letters which do not decompose, e.g. "ß" or "ø", are replaced by their
+/// usual transliteration.
+pub fn fold(value: &str) -> String {
+    let mut folded = String::with_capacity(value.len());
+    for c in value.nfkd().filter(|&c| !is_combining_mark(c)) {
+        match c {
+            'ß' | 'ẞ' => folded.push_str("ss"),
+            'æ' | 'Æ' => folded.push_str("ae"),
+            'œ' | 'Œ' => folded.push_str("oe"),
+            'ø' | 'Ø' => folded.push('o'),
+            'đ' | 'Đ' => folded.push('d'),
+            'ł' | 'Ł' => folded.push('l'),
+            'ı' => folded.push('i'),
+            _ => folded.extend(c.to_lowercase()),
+        }
+    }
+    folded
+}
+
+/// Return `true` if two names are equal once folded.
+pub fn eq_folded(a: &str, b: &str) -> bool {
+    fold(a) == fold(b)
+}
+
+/// Compare two names by their folded form, then by their exact form, so that
+/// the order is total.
+pub fn compare(a: &str, b: &str) -> Ordering {
+    fold(a).cmp(&fold(b)).then_with(|| a.cmp(b))
+}
+
+/// Return the key sorting a name like [`compare`].
+pub fn sort_key(value: &str) -> (String, String) {
+    (fold(value), value.to_string())
+}
+
+/// Sort the names, by their folded form or following the rules of a locale.
+#[derive(Default)]
+pub struct Collation {
+    #[cfg(feature = "collation")]
+    collator: Option<Collator>,
+}
+
+impl Collation {
+    /// Sort the names by their folded form.
+    pub fn new() -> Self {
+        Collation::default()
+    }
+
+    /// Sort the names following the rules of a `locale`, e.g. "sv" or
+    /// "de-AT".
+    ///
+    /// The case is only used to break the ties.
+    #[cfg(feature = "collation")]
+    pub fn for_locale(locale: &str) -> Result<Self, Error> {
+        let parsed: Locale = locale
+            .parse()
+            .map_err(|_| Error::Parse(format!("invalid locale {:?}", locale)))?;
+        let mut options = CollatorOptions::new();
+        options.strength = Some(Strength::Tertiary);
+        let collator = Collator::try_new(&parsed.into(), options)
+            .map_err(|e| Error::Parse(format!("unsupported locale {:?}: {}", locale, e)))?;
+
+        Ok(Collation {
+            collator: Some(collator),
+        })
+    }
+
+    /// Compare two names.
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        #[cfg(feature = "collation")]
+        if let Some(collator) = &self.collator {
+            return collator.compare(a, b);
+        }
+        compare(a, b)
+    }
+}
+
+impl fmt::Debug for Collation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        #[cfg(feature = "collation")]
+        let locale = self.collator.is_some();
+        #[cfg(not(feature = "collation"))]
+        let locale = false;
+        f.debug_struct("Collation")
+            .field("locale", &locale)
+            .finish()
+    }
+}
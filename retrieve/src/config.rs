@@ -0,0 +1,136 @@
+//! Read the configuration file.
+//!
+//! The configuration file, `retrieve.toml` by default, holds named profiles,
+//! e.g. one for the public PFB storage and one for an internal mirror:
+//!
+//! ```toml
+//! [profile.public]
+//! destination_folder = "public"
+//!
+//! [profile.internal]
+//! base_url = "https://mirror.example.org/results"
+//! token_env = "MIRROR_TOKEN"
+//! destination_folder = "/data/pfb"
+//! file_name = "{country}/{state}/{city}/{dataset}.{ext}"
+//! parallel_requests = 50
+//!
+//! [profile.internal.layout]
+//! neighborhood_ways = "{uuid}/ways/{dataset}.{ext}"
+//!
+//! [profile.internal.dataset_concurrency]
+//! neighborhood_ways = 2
+//! neighborhood_overall_scores = 16
+//!
+//! [profile.internal.aliases]
+//! "Saint Paul" = "St. Paul"
+//! ```
+//!
+//! The settings of the selected profile are used as the default values of the
+//! command line options, so the options given explicitly take precedence.
+use crate::Error;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the configuration file looked up in the current directory.
+pub const CONFIG: &str = "retrieve.toml";
+
+/// Describe the settings of a profile.
+///
+/// Every setting is optional: the ones which are not set keep their usual
+/// default value.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    /// Base URL of the storage to download from, replacing the PFB storage.
+    pub base_url: Option<String>,
+    /// Extra headers sent with every request, e.g. `X-Api-Key: <KEY>`.
+    pub headers: Vec<String>,
+    /// Name of the environment variable holding a bearer token.
+    ///
+    /// The token itself does not belong in the configuration file.
+    pub token_env: Option<String>,
+    /// Command presigning each URL.
+    pub presign_command: Option<String>,
+    /// Destination folder.
+    pub destination_folder: Option<PathBuf>,
+    /// Template of the file names.
+    pub file_name: Option<String>,
+    /// Make the file names valid on every platform.
+    pub portable_names: Option<bool>,
+    /// Number of files downloaded simultaneously.
+    pub parallel_requests: Option<u16>,
+    /// Number of times a failing download is retried.
+    pub retries: Option<u16>,
+    /// Number of segments the large files are split into.
+    pub segments: Option<u16>,
+    /// Paths of the datasets in the storage, by dataset name, overriding the
+    /// default layout.
+    pub layout: BTreeMap<String, String>,
+    /// Maximum number of files of a dataset downloaded simultaneously, by
+    /// dataset name.
+    pub dataset_concurrency: BTreeMap<String, u16>,
+    /// Maximum number of files of the cities of a country downloaded
+    /// simultaneously, by country.
+    pub country_concurrency: BTreeMap<String, u16>,
+    /// Canonical names of the cities, by alias, e.g. `"Saint Paul" = "St.
+    /// Paul"`.
+    pub aliases: BTreeMap<String, String>,
+}
+
+impl Profile {
+    /// Return the headers of the profile, including the `Authorization` header
+    /// built from the token.
+    ///
+    /// Fail if the environment variable holding the token is not set.
+    pub fn all_headers(&self) -> Result<Vec<String>, Error> {
+        let mut headers = self.headers.clone();
+        if let Some(name) = &self.token_env {
+            let token = env::var(name).map_err(|_| {
+                Error::Parse(format!(
+                    "the environment variable {} holding the token is not set",
+                    name
+                ))
+            })?;
+            headers.push(format!("Authorization: Bearer {}", token));
+        }
+
+        Ok(headers)
+    }
+}
+
+/// Represent the configuration file.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Profiles, by name.
+    #[serde(default, rename = "profile")]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl Config {
+    /// Read the configuration file located at `path`.
+    pub fn load<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)?;
+        toml::from_str(&content)
+            .map_err(|e| Error::Parse(format!("invalid configuration {}: {}", path.display(), e)))
+    }
+
+    /// Return the profile named `name`.
+    pub fn profile(&self, name: &str) -> Result<&Profile, Error> {
+        self.profiles.get(name).ok_or_else(|| {
+            let names: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            Error::Parse(format!(
+                "unknown profile {:?}, expected one of: {}",
+                name,
+                names.join(", ")
+            ))
+        })
+    }
+}
@@ -0,0 +1,229 @@
+//! Convert the extracted shapefiles to other formats.
+//!
+//! The features can be clipped to a boundary while they are converted, so that
+//! only the area of interest is kept.
+use crate::projection::{shapefile_projection, wgs84, Reprojection};
+use crate::Error;
+use geo::{BooleanOps, Intersects, MapCoords};
+use geo_types::{Coord, Geometry, MultiLineString, MultiPoint, MultiPolygon};
+use proj4rs::proj::Proj;
+use serde_json::{Map, Value};
+use shapefile::dbase::FieldValue;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Describe the options of a conversion.
+#[derive(Debug, Clone, Default)]
+pub struct ConvertOptions {
+    /// Boundary to clip the features to, in WGS84 longitudes and latitudes.
+    pub clip: Option<MultiPolygon<f64>>,
+    /// Coordinate reference system of the output.
+    ///
+    /// The coordinate reference system of the dataset is kept if not set.
+    pub crs: Option<Proj>,
+}
+
+/// Describe the output formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// GeoJSON feature collection.
+    GeoJson,
+}
+
+impl Format {
+    /// Return the file extension of the format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::GeoJson => "geojson",
+        }
+    }
+}
+
+/// Return the path of the converted version of the shapefile located at
+/// `path`.
+pub fn output_path(path: &Path, format: Format) -> PathBuf {
+    path.with_extension(format.extension())
+}
+
+/// Read a boundary from a GeoJSON file.
+///
+/// All the polygons of the file are merged into the boundary, whether the file
+/// contains a feature collection, a single feature or a bare geometry. The
+/// coordinates are WGS84 longitudes and latitudes, as mandated by GeoJSON.
+pub fn read_boundary<P>(path: P) -> Result<MultiPolygon<f64>, Error>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let geojson: geojson::GeoJson = fs::read_to_string(path)?
+        .parse()
+        .map_err(|e| Error::Parse(format!("invalid GeoJSON {}: {}", path.display(), e)))?;
+    let geometries: Vec<geojson::Geometry> = match geojson {
+        geojson::GeoJson::FeatureCollection(collection) => collection
+            .features
+            .into_iter()
+            .filter_map(|f| f.geometry)
+            .collect(),
+        geojson::GeoJson::Feature(feature) => feature.geometry.into_iter().collect(),
+        geojson::GeoJson::Geometry(geometry) => vec![geometry],
+    };
+    let mut polygons = Vec::new();
+    for geometry in geometries {
+        match Geometry::<f64>::try_from(geometry) {
+            Ok(Geometry::Polygon(polygon)) => polygons.push(polygon),
+            Ok(Geometry::MultiPolygon(multi)) => polygons.extend(multi),
+            _ => (),
+        }
+    }
+    if polygons.is_empty() {
+        return Err(Error::Parse(format!(
+            "no polygon found in {}",
+            path.display()
+        )));
+    }
+
+    Ok(MultiPolygon::new(polygons))
+}
+
+/// Convert the shapefile located at `path` to a GeoJSON file at `output`.
+///
+/// If a `clip` boundary is provided, the features are clipped to it, and the
+/// features outside of it are dropped. The features are then reprojected to
+/// the output coordinate reference system, if any. A shapefile without `.prj`
+/// file is assumed to use WGS84 longitudes and latitudes.
+///
+/// Return the number of features written.
+pub fn to_geojson<P, Q>(path: P, output: Q, options: &ConvertOptions) -> Result<u64, Error>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let projection = shapefile_projection(path)?.unwrap_or_else(wgs84);
+
+    // Express the boundary in the projection of the shapefile.
+    let clip = match &options.clip {
+        Some(boundary) if !projection.is_latlong() => {
+            let reprojection = Reprojection::new(wgs84(), projection.clone());
+            Some(reproject(boundary, &reprojection)?)
+        }
+        boundary => boundary.clone(),
+    };
+    let reprojection = options
+        .crs
+        .as_ref()
+        .map(|crs| Reprojection::new(projection, crs.clone()));
+
+    let mut reader = shapefile::Reader::from_path(path)?;
+    let mut writer = BufWriter::new(File::create(output)?);
+    writer.write_all(br#"{"type":"FeatureCollection","features":["#)?;
+    let mut count: u64 = 0;
+    for item in reader.iter_shapes_and_records() {
+        let (shape, record) = item?;
+        let geometry = match Geometry::<f64>::try_from(shape) {
+            Ok(geometry) => geometry,
+            Err(_) => continue,
+        };
+        let geometry = match &clip {
+            Some(boundary) => match clip_geometry(geometry, boundary) {
+                Some(geometry) => geometry,
+                None => continue,
+            },
+            None => geometry,
+        };
+        let geometry = match &reprojection {
+            Some(reprojection) => reproject(&geometry, reprojection)?,
+            None => geometry,
+        };
+        let properties: Map<String, Value> = record
+            .into_iter()
+            .map(|(name, value)| (name, to_json(value)))
+            .collect();
+        let feature = geojson::Feature {
+            bbox: None,
+            geometry: Some(geojson::Geometry::from(&geometry)),
+            id: None,
+            properties: Some(properties),
+            foreign_members: None,
+        };
+        if count > 0 {
+            writer.write_all(b",")?;
+        }
+        serde_json::to_writer(&mut writer, &feature)?;
+        count += 1;
+    }
+    writer.write_all(b"]}")?;
+    writer.flush()?;
+
+    Ok(count)
+}
+
+/// Clip a geometry to a boundary.
+///
+/// Return `None` if the geometry is outside of the boundary.
+fn clip_geometry(geometry: Geometry<f64>, boundary: &MultiPolygon<f64>) -> Option<Geometry<f64>> {
+    let clipped = match geometry {
+        Geometry::LineString(line) => {
+            Geometry::MultiLineString(boundary.clip(&MultiLineString::new(vec![line]), false))
+        }
+        Geometry::MultiLineString(lines) => Geometry::MultiLineString(boundary.clip(&lines, false)),
+        Geometry::Polygon(polygon) => Geometry::MultiPolygon(boundary.intersection(&polygon)),
+        Geometry::MultiPolygon(polygons) => {
+            Geometry::MultiPolygon(boundary.intersection(&polygons))
+        }
+        Geometry::MultiPoint(points) => Geometry::MultiPoint(MultiPoint::new(
+            points
+                .into_iter()
+                .filter(|p| boundary.intersects(p))
+                .collect(),
+        )),
+        other => return boundary.intersects(&other).then_some(other),
+    };
+    let empty = match &clipped {
+        Geometry::MultiLineString(lines) => lines.0.is_empty(),
+        Geometry::MultiPolygon(polygons) => polygons.0.is_empty(),
+        Geometry::MultiPoint(points) => points.0.is_empty(),
+        _ => false,
+    };
+
+    (!empty).then_some(clipped)
+}
+
+/// Reproject a geometry.
+pub(crate) fn reproject<G>(geometry: &G, reprojection: &Reprojection) -> Result<G::Output, Error>
+where
+    G: MapCoords<f64, f64>,
+{
+    geometry.try_map_coords(|c| {
+        let (x, y) = reprojection.point(c.x, c.y)?;
+        Ok(Coord { x, y })
+    })
+}
+
+/// Convert a shapefile attribute to a JSON value.
+pub(crate) fn to_json(value: FieldValue) -> Value {
+    match value {
+        FieldValue::Character(Some(s)) | FieldValue::Memo(s) => Value::String(s),
+        FieldValue::Numeric(Some(n)) | FieldValue::Double(n) | FieldValue::Currency(n) => number(n),
+        FieldValue::Float(Some(n)) => number(f64::from(n)),
+        FieldValue::Integer(n) => Value::from(n),
+        FieldValue::Logical(Some(b)) => Value::Bool(b),
+        FieldValue::Date(Some(d)) => Value::String(d.to_string()),
+        FieldValue::Character(None)
+        | FieldValue::Numeric(None)
+        | FieldValue::Float(None)
+        | FieldValue::Logical(None)
+        | FieldValue::Date(None) => Value::Null,
+        other => Value::String(other.to_string()),
+    }
+}
+
+/// Convert a number to a JSON value, preferring integers when possible.
+fn number(n: f64) -> Value {
+    if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
+        Value::from(n as i64)
+    } else {
+        serde_json::Number::from_f64(n).map_or(Value::Null, Value::Number)
+    }
+}
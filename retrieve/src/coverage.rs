@@ -0,0 +1,108 @@
+//! Compare a download folder with a list of cities.
+//!
+//! The coverage tells, for each city and dataset, whether the folder contains
+//! the dataset of the current run, of a previous run, or nothing at all.
+use crate::cities::Cities;
+use crate::manifest::{Manifest, ManifestEntry};
+use crate::plan::file_name;
+use pfbcore::scorecard::City;
+use pfbcore::Dataset;
+use std::path::Path;
+
+/// Describe the presence of a dataset in a download folder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Coverage {
+    /// The dataset of the current run is present.
+    Present,
+    /// Only the dataset of a previous run, identified by its UUID, is present.
+    Stale(String),
+    /// The dataset is missing.
+    Missing,
+}
+
+/// Describe the coverage of a dataset of a city.
+#[derive(Debug, Clone)]
+pub struct CoverageEntry {
+    /// City the dataset belongs to.
+    pub city: City,
+    /// Dataset.
+    pub dataset: Dataset,
+    /// Presence of the dataset.
+    pub coverage: Coverage,
+}
+
+/// Summarize the coverage of a list of cities by a download folder.
+#[derive(Debug, Default, Clone)]
+pub struct CoverageReport {
+    /// Coverage of each dataset of each city, in the order of the list.
+    pub entries: Vec<CoverageEntry>,
+}
+
+impl CoverageReport {
+    /// Cross-reference the `datasets` of the `cities` with the files recorded
+    /// in the `manifest` of `folder`.
+    ///
+    /// A dataset is present if a file retrieved from the same run is recorded
+    /// in the manifest, whatever its name, and still exists. Otherwise, it is
+    /// stale if the file named after the city was retrieved from another run.
+    /// The cities without run UUID are ignored.
+    pub fn new<P>(cities: &Cities, datasets: &[Dataset], manifest: &Manifest, folder: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let folder = folder.as_ref();
+        let exists = |e: &&ManifestEntry| folder.join(&e.path).is_file();
+        let mut entries = Vec::new();
+        for city in cities.iter().filter(|c| !c.run.uuid.is_empty()) {
+            for &dataset in datasets {
+                let name = dataset.to_string();
+                let current = manifest
+                    .files
+                    .iter()
+                    .filter(|e| e.dataset == name && e.uuid.eq_ignore_ascii_case(&city.run.uuid))
+                    .any(|e| exists(&e));
+                let coverage = if current {
+                    Coverage::Present
+                } else {
+                    match manifest.get(file_name(city, dataset)).filter(exists) {
+                        Some(entry) => Coverage::Stale(entry.uuid.clone()),
+                        None => Coverage::Missing,
+                    }
+                };
+                entries.push(CoverageEntry {
+                    city: city.clone(),
+                    dataset,
+                    coverage,
+                });
+            }
+        }
+
+        CoverageReport { entries }
+    }
+
+    /// Return the number of datasets which are present.
+    pub fn present(&self) -> usize {
+        self.count(|c| *c == Coverage::Present)
+    }
+
+    /// Return the number of datasets which are stale.
+    pub fn stale(&self) -> usize {
+        self.count(|c| matches!(c, Coverage::Stale(_)))
+    }
+
+    /// Return the number of datasets which are missing.
+    pub fn missing(&self) -> usize {
+        self.count(|c| *c == Coverage::Missing)
+    }
+
+    /// Return the number of entries matching `predicate`.
+    fn count<F>(&self, predicate: F) -> usize
+    where
+        F: Fn(&Coverage) -> bool,
+    {
+        self.entries
+            .iter()
+            .filter(|e| predicate(&e.coverage))
+            .count()
+    }
+}
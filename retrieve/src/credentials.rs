@@ -0,0 +1,46 @@
+//! Store the tokens of the authenticated mirrors in the OS keyring.
+//!
+//! The tokens are stored under the `retrieve` service, with the key returned
+//! by [`host_key`] as user name, so they never appear in the configuration
+//! file or in the shell history.
+use crate::auth::host_key;
+use crate::Error;
+use keyring::Entry;
+use url::Url;
+
+/// Name of the service the tokens are stored under.
+pub const KEYRING_SERVICE: &str = "retrieve";
+
+/// Return the keyring entry of the host of `url`.
+fn entry(url: &Url) -> Result<Entry, Error> {
+    let key = host_key(url).ok_or_else(|| Error::Parse(format!("{} has no host", url)))?;
+
+    Ok(Entry::new(KEYRING_SERVICE, &key)?)
+}
+
+/// Store the `token` of the host of `url`, replacing the previous one.
+pub fn store(url: &Url, token: &str) -> Result<(), Error> {
+    entry(url)?.set_password(token)?;
+
+    Ok(())
+}
+
+/// Return the token of the host of `url`, if one is stored.
+pub fn load(url: &Url) -> Result<Option<String>, Error> {
+    match entry(url)?.get_password() {
+        Ok(token) => Ok(Some(token)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Remove the token of the host of `url`.
+///
+/// Return `false` if no token was stored.
+pub fn remove(url: &Url) -> Result<bool, Error> {
+    match entry(url)?.delete_credential() {
+        Ok(()) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,110 @@
+//! Discover the layout of the PFB storage.
+//!
+//! The storage may publish a layout descriptor at `{BASE_URL}/layout.json`,
+//! listing the available datasets along with their paths, e.g.
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "datasets": {
+//!     "neighborhood_ways": "{uuid}/ways/{dataset}.{ext}"
+//!   }
+//! }
+//! ```
+//!
+//! The paths accept the placeholders of the
+//! [`FileNameTemplate`](crate::template::FileNameTemplate). A copy of the
+//! descriptor matching the current layout is pinned in the binary, and is used
+//! when the published one cannot be retrieved.
+use crate::http::HttpClient;
+use crate::layout::Layout;
+use crate::s3::error_for_status;
+use crate::Error;
+use pfbcore::Dataset;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use url::Url;
+
+/// Name of the layout descriptor, relative to the base URL of the storage.
+pub const DESCRIPTOR: &str = "layout.json";
+
+/// Latest version of the layout descriptor this release understands.
+pub const DESCRIPTOR_VERSION: u32 = 1;
+
+/// Layout descriptor pinned in the binary.
+pub const PINNED_DESCRIPTOR: &str = include_str!("layout.json");
+
+/// Describe the layout of the storage.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LayoutDescriptor {
+    /// Version of the descriptor format.
+    pub version: u32,
+    /// Paths of the available datasets, by dataset name.
+    pub datasets: BTreeMap<String, String>,
+}
+
+impl LayoutDescriptor {
+    /// Parse a layout descriptor.
+    ///
+    /// Fail if the descriptor is more recent than this release.
+    pub fn parse(content: &str) -> Result<Self, Error> {
+        let descriptor: LayoutDescriptor = serde_json::from_str(content)?;
+        if descriptor.version > DESCRIPTOR_VERSION {
+            return Err(Error::Parse(format!(
+                "unsupported layout descriptor version {}, expected at most {}",
+                descriptor.version, DESCRIPTOR_VERSION
+            )));
+        }
+
+        Ok(descriptor)
+    }
+
+    /// Return the descriptor pinned in the binary.
+    pub fn pinned() -> Self {
+        LayoutDescriptor::parse(PINNED_DESCRIPTOR).expect("the pinned layout descriptor is valid")
+    }
+
+    /// Return the URL of the descriptor of the storage located at `base`.
+    pub fn url(base: &Url) -> Result<Url, Error> {
+        Ok(Url::parse(&format!(
+            "{}/{}",
+            base.as_str().trim_end_matches('/'),
+            DESCRIPTOR
+        ))?)
+    }
+
+    /// Retrieve the descriptor published by the storage located at `base`,
+    /// with the requests sent by `client`.
+    pub async fn fetch(client: &HttpClient, base: &Url) -> Result<Self, Error> {
+        let url = LayoutDescriptor::url(base)?;
+        let response = client.send(client.get(&url)).await?;
+        let response = error_for_status(response).await?;
+
+        LayoutDescriptor::parse(&response.text().await?)
+    }
+
+    /// Return the available datasets known to this release.
+    ///
+    /// The datasets introduced by a later release are ignored.
+    pub fn datasets(&self) -> Vec<Dataset> {
+        self.datasets
+            .keys()
+            .filter_map(|name| name.parse().ok())
+            .collect()
+    }
+
+    /// Build the layout described by the descriptor.
+    ///
+    /// Only the datasets it lists are available.
+    pub fn layout(&self) -> Result<Layout, Error> {
+        let mut layout = Layout::new();
+        for (name, pattern) in &self.datasets {
+            if let Ok(dataset) = name.parse::<Dataset>() {
+                layout.set(dataset, pattern.parse()?);
+            }
+        }
+        layout.restrict(self.datasets());
+
+        Ok(layout)
+    }
+}
@@ -0,0 +1,79 @@
+//! Control the resolution of the host names.
+//!
+//! Some networks have a broken IPv6 (or IPv4) connectivity, where the
+//! connections hang until they time out. Restricting the resolution to one
+//! family of addresses avoids them. The address of a host can also be
+//! overridden, e.g. to reach the PFB storage through a private endpoint.
+use crate::Error;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::net::{IpAddr, SocketAddr};
+use tokio::net::lookup_host;
+
+/// Describe the family of the addresses to connect to.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IpFamily {
+    /// Connect to any address.
+    #[default]
+    Any,
+    /// Only connect to IPv4 addresses.
+    V4,
+    /// Only connect to IPv6 addresses.
+    V6,
+}
+
+impl IpFamily {
+    /// Return `true` if the address belongs to the family.
+    pub fn contains(self, address: &SocketAddr) -> bool {
+        match self {
+            IpFamily::Any => true,
+            IpFamily::V4 => address.is_ipv4(),
+            IpFamily::V6 => address.is_ipv6(),
+        }
+    }
+}
+
+/// Resolve the host names with the system resolver, only keeping the
+/// addresses of a family.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FamilyResolver(pub(crate) IpFamily);
+
+impl Resolve for FamilyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let family = self.0;
+        Box::pin(async move {
+            let addresses: Vec<SocketAddr> = lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|a| family.contains(a))
+                .collect();
+            if addresses.is_empty() {
+                return Err(format!("no {:?} address found for {}", family, name.as_str()).into());
+            }
+            let addresses: Addrs = Box::new(addresses.into_iter());
+            Ok(addresses)
+        })
+    }
+}
+
+/// Parse an address override specified as `HOST=ADDRESS`.
+///
+/// The address may include a port, otherwise the port of the URL is used.
+pub fn parse_resolve(value: &str) -> Result<(String, SocketAddr), Error> {
+    let invalid = || {
+        Error::Parse(format!(
+            "invalid override {:?}, expected `HOST=ADDRESS`",
+            value
+        ))
+    };
+    let (host, address) = value.split_once('=').ok_or_else(invalid)?;
+    let host = host.trim();
+    let address = address.trim();
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    let address = match address.parse::<SocketAddr>() {
+        Ok(address) => address,
+        Err(_) => SocketAddr::new(address.parse::<IpAddr>().map_err(|_| invalid())?, 0),
+    };
+
+    Ok((host.to_string(), address))
+}
@@ -0,0 +1,1993 @@
+//! Download the planned datasets.
+//!
+//! The downloads are performed concurrently. Each file is first written next
+//! to its destination with a `.part` extension, then renamed once complete, so
+//! that an interrupted download never leaves a truncated file behind.
+//!
+//! The large files can be split into segments, downloaded in parallel with
+//! range requests and written in place in the `.part` file. The support of the
+//! range requests is probed with a HEAD request, and the file is downloaded as
+//! a single stream if the server ignores them. A failing segment is retried on
+//! its own, and the reassembled file is checked against the size and, when the
+//! ETag is the MD5 hash of the file, the hash announced by the server before
+//! being renamed.
+use crate::audit::{AuditLog, AuditRecord};
+use crate::auth::Presign;
+use crate::budget::CityBudget;
+use crate::cache::Cache;
+use crate::checksum;
+use crate::dns::{FamilyResolver, IpFamily};
+use crate::faults::{FaultInjection, INJECTED_FAILURE};
+use crate::fixtures::Fixtures;
+use crate::http::HttpClient;
+use crate::layout::Layout;
+use crate::limits::ConcurrencyLimits;
+use crate::manifest::{Checkpoint, Manifest};
+use crate::plan::{Plan, PlannedDownload};
+use crate::portable::extended_length_path;
+use crate::progress::{FileProgress, Progress};
+use crate::queue::DownloadQueue;
+use crate::s3::S3Error;
+use crate::shutdown::Shutdown;
+use crate::strict::{ResponseMetadata, StrictMode};
+use crate::timestamp;
+use crate::Error;
+use bytes::Bytes;
+use futures::future;
+use futures::stream::{self, StreamExt};
+use md5::Md5;
+use pfbcore::scorecard::City;
+use pfbcore::{Dataset, PFB_S3_STORAGE_BASE_URL};
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, ACCEPT_ENCODING, ACCEPT_RANGES, CONTENT_ENCODING,
+    CONTENT_LENGTH, CONTENT_TYPE, ETAG, RANGE,
+};
+use reqwest::{Client, RequestBuilder, StatusCode};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::io::{self, SeekFrom};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::Semaphore;
+use tracing::{debug, info, instrument, warn};
+use url::Url;
+
+/// User-Agent sent by default, e.g. `retrieve/0.1.2`.
+pub const DEFAULT_USER_AGENT: &str =
+    concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// Maximum duration to establish a connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Maximum duration without receiving any data.
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Time given to the transfers in progress to complete after a shutdown.
+const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+/// Minimum size of the files split into segments.
+const DEFAULT_SEGMENT_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+/// Maximum size of the datasets downloaded in memory.
+const DEFAULT_MEMORY_LIMIT: u64 = 32 * 1024 * 1024;
+
+/// Compressed encodings accepted from the servers.
+const ACCEPTED_ENCODINGS: &str = "gzip, br, zstd";
+
+/// Header announcing how S3 encrypted a file.
+const SERVER_SIDE_ENCRYPTION: &str = "x-amz-server-side-encryption";
+
+/// Maximum delay between two attempts of a failing download.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Describe the HTTP version used to talk to the servers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HttpVersion {
+    /// Negotiate the version with the server.
+    #[default]
+    Auto,
+    /// Only use HTTP/1.1.
+    Http1,
+    /// Only use HTTP/2, without negotiation.
+    Http2,
+}
+
+/// Describe the outcome of a download.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DownloadStatus {
+    /// The file was downloaded.
+    Downloaded,
+    /// The file was restored from the cache.
+    Cached,
+    /// The download failed.
+    Failed(String),
+    /// The download was not started, or was aborted, because of a shutdown.
+    Interrupted,
+    /// The download was not started, or was aborted, because the time budget
+    /// of the city was exhausted.
+    TimedOut,
+}
+
+/// Represent the result of a planned download.
+#[derive(Debug, Clone)]
+pub struct DownloadOutcome {
+    /// Planned download.
+    pub download: PlannedDownload,
+    /// Path of the downloaded file.
+    pub path: PathBuf,
+    /// URL the file was downloaded from, which depends on the mirror which
+    /// served it. `None` if the file was not downloaded.
+    pub source: Option<Url>,
+    /// SHA256 hash of the file. `None` if the retrieval failed.
+    pub sha256: Option<String>,
+    /// ETag announced by the server, without the quotes. `None` if the file
+    /// was not downloaded, or if the server did not send one.
+    pub etag: Option<String>,
+    /// Content encoding of the file, e.g. "gzip", if it was stored as
+    /// compressed by the server. Refer to [`DownloaderBuilder::keep_compressed`].
+    pub content_encoding: Option<String>,
+    /// Status of the download.
+    pub status: DownloadStatus,
+    /// Time spent retrieving the file, including the retries.
+    pub duration: Duration,
+    /// Number of bytes downloaded. Zero if the file was restored from the
+    /// cache.
+    pub bytes: u64,
+    /// Number of retries.
+    pub retries: u16,
+    /// HTTP status code of each response, in the order they were received.
+    pub statuses: Vec<u16>,
+    /// `true` if the failure was injected, refer to [`FaultInjection`].
+    pub injected: bool,
+    /// Error response of S3 which failed the download, if any.
+    pub s3_error: Option<S3Error>,
+    /// SHA256 hash of the local file which was replaced by a different
+    /// content, if any.
+    pub previous_sha256: Option<String>,
+    /// Path of the backup of the replaced file, if any.
+    pub backup: Option<PathBuf>,
+}
+
+impl DownloadOutcome {
+    /// Return the average throughput of the download, in bytes per second.
+    pub fn throughput(&self) -> f64 {
+        throughput(self.bytes, self.duration)
+    }
+}
+
+/// Summarize the downloads of a plan.
+#[derive(Debug, Default, Clone)]
+pub struct DownloadReport {
+    /// Outcome of each download, in the order of the plan.
+    pub outcomes: Vec<DownloadOutcome>,
+    /// Time spent retrieving the whole batch.
+    pub elapsed: Duration,
+}
+
+impl DownloadReport {
+    /// Return the number of injected failures.
+    pub fn injected(&self) -> usize {
+        self.outcomes.iter().filter(|o| o.injected).count()
+    }
+
+    /// Return the outcomes of the failed downloads.
+    pub fn failures(&self) -> impl Iterator<Item = &DownloadOutcome> {
+        self.outcomes
+            .iter()
+            .filter(|o| matches!(o.status, DownloadStatus::Failed(_)))
+    }
+
+    /// Return the outcomes of the downloads skipped because the time budget
+    /// of their city was exhausted.
+    pub fn timed_out(&self) -> impl Iterator<Item = &DownloadOutcome> {
+        self.outcomes
+            .iter()
+            .filter(|o| o.status == DownloadStatus::TimedOut)
+    }
+
+    /// Return the number of bytes downloaded.
+    pub fn total_bytes(&self) -> u64 {
+        self.outcomes.iter().map(|o| o.bytes).sum()
+    }
+
+    /// Return the overall throughput of the batch, in bytes per second.
+    pub fn throughput(&self) -> f64 {
+        throughput(self.total_bytes(), self.elapsed)
+    }
+
+    /// Return the downloads, from the slowest to the fastest.
+    pub fn slowest(&self) -> Vec<&DownloadOutcome> {
+        let mut outcomes = self
+            .outcomes
+            .iter()
+            .filter(|o| o.status == DownloadStatus::Downloaded)
+            .collect::<Vec<_>>();
+        outcomes.sort_by_key(|o| std::cmp::Reverse(o.duration));
+        outcomes
+    }
+}
+
+/// Build a [`Downloader`].
+#[derive(Clone)]
+pub struct DownloaderBuilder {
+    destination_folder: PathBuf,
+    parallel_requests: u16,
+    retries: u16,
+    cache: Option<Cache>,
+    mirrors: Vec<Url>,
+    layout: Layout,
+    user_agent: String,
+    headers: Vec<(String, String)>,
+    tokens: Vec<(String, String)>,
+    presign: Option<Arc<dyn Presign>>,
+    progress: bool,
+    checksums: bool,
+    max_file_size: Option<u64>,
+    shutdown: Shutdown,
+    grace_period: Duration,
+    fault_injection: Option<FaultInjection>,
+    fixtures: Option<Fixtures>,
+    audit_log: Option<AuditLog>,
+    checkpoint: Option<Checkpoint>,
+    limits: ConcurrencyLimits,
+    city_budget: Option<CityBudget>,
+    backup_dir: Option<PathBuf>,
+    ip_family: IpFamily,
+    overrides: Vec<(String, SocketAddr)>,
+    http_version: HttpVersion,
+    pool_max_idle_per_host: Option<usize>,
+    pool_idle_timeout: Option<Duration>,
+    tcp_keepalive: Option<Duration>,
+    segments: u16,
+    segment_threshold: u64,
+    memory_limit: u64,
+    strict: Option<StrictMode>,
+    previous_manifest: Option<Manifest>,
+    keep_compressed: bool,
+}
+
+impl Default for DownloaderBuilder {
+    fn default() -> Self {
+        DownloaderBuilder {
+            destination_folder: PathBuf::from("output"),
+            parallel_requests: 25,
+            retries: 3,
+            cache: None,
+            mirrors: Vec::new(),
+            layout: Layout::default(),
+            user_agent: String::from(DEFAULT_USER_AGENT),
+            headers: Vec::new(),
+            tokens: Vec::new(),
+            presign: None,
+            progress: false,
+            checksums: false,
+            max_file_size: None,
+            shutdown: Shutdown::new(),
+            grace_period: DEFAULT_GRACE_PERIOD,
+            fault_injection: None,
+            fixtures: None,
+            audit_log: None,
+            checkpoint: None,
+            limits: ConcurrencyLimits::new(),
+            city_budget: None,
+            backup_dir: None,
+            ip_family: IpFamily::Any,
+            overrides: Vec::new(),
+            http_version: HttpVersion::Auto,
+            pool_max_idle_per_host: None,
+            pool_idle_timeout: None,
+            tcp_keepalive: None,
+            segments: 1,
+            segment_threshold: DEFAULT_SEGMENT_THRESHOLD,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+            strict: None,
+            previous_manifest: None,
+            keep_compressed: false,
+        }
+    }
+}
+
+impl fmt::Debug for DownloaderBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DownloaderBuilder")
+            .field("destination_folder", &self.destination_folder)
+            .field("parallel_requests", &self.parallel_requests)
+            .field("retries", &self.retries)
+            .field("cache", &self.cache)
+            .field("mirrors", &self.mirrors)
+            .field("layout", &self.layout)
+            .field("user_agent", &self.user_agent)
+            .field("headers", &self.headers.len())
+            .field("tokens", &self.tokens.len())
+            .field("presign", &self.presign.is_some())
+            .field("progress", &self.progress)
+            .field("checksums", &self.checksums)
+            .field("max_file_size", &self.max_file_size)
+            .field("shutdown", &self.shutdown.is_requested())
+            .field("grace_period", &self.grace_period)
+            .field("fault_injection", &self.fault_injection)
+            .field("fixtures", &self.fixtures)
+            .field("audit_log", &self.audit_log)
+            .field("checkpoint", &self.checkpoint)
+            .field("limits", &self.limits)
+            .field("city_budget", &self.city_budget)
+            .field("backup_dir", &self.backup_dir)
+            .field("ip_family", &self.ip_family)
+            .field("overrides", &self.overrides)
+            .field("http_version", &self.http_version)
+            .field("pool_max_idle_per_host", &self.pool_max_idle_per_host)
+            .field("pool_idle_timeout", &self.pool_idle_timeout)
+            .field("tcp_keepalive", &self.tcp_keepalive)
+            .field("segments", &self.segments)
+            .field("segment_threshold", &self.segment_threshold)
+            .field("memory_limit", &self.memory_limit)
+            .field("strict", &self.strict)
+            .field("previous_manifest", &self.previous_manifest.is_some())
+            .field("keep_compressed", &self.keep_compressed)
+            .finish()
+    }
+}
+
+impl DownloaderBuilder {
+    /// Set the folder where the files are saved.
+    pub fn destination_folder<P>(mut self, folder: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.destination_folder = folder.into();
+        self
+    }
+
+    /// Set the number of files to download simultaneously.
+    pub fn parallel_requests(mut self, count: u16) -> Self {
+        self.parallel_requests = count;
+        self
+    }
+
+    /// Set the number of times to retry a failing download.
+    pub fn retries(mut self, count: u16) -> Self {
+        self.retries = count;
+        self
+    }
+
+    /// Set the cache shared between download folders.
+    pub fn cache(mut self, cache: Option<Cache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Add a mirror of the PFB storage.
+    ///
+    /// The mirrors are tried in the order they were added: if one of them
+    /// fails with a server error or a timeout, the same file is requested
+    /// from the next one. Once mirrors are set, the original PFB storage is
+    /// only used if it is added as a mirror too.
+    pub fn mirror(mut self, base_url: Url) -> Self {
+        self.mirrors.push(base_url);
+        self
+    }
+
+    /// Set the layout used to locate the datasets on the mirrors.
+    pub fn layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Set the User-Agent sent with every request.
+    ///
+    /// Defaults to [`DEFAULT_USER_AGENT`].
+    pub fn user_agent<S>(mut self, user_agent: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Add a header sent with every request, e.g. `Authorization`.
+    pub fn header<N, V>(mut self, name: N, value: V) -> Self
+    where
+        N: Into<String>,
+        V: Into<String>,
+    {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Send a bearer token with the requests to `host`.
+    ///
+    /// The host is compared to the key returned by
+    /// [`host_key`](crate::auth::host_key), i.e. it must
+    /// include the port if it is not the default one.
+    pub fn bearer_token<H, T>(mut self, host: H, token: T) -> Self
+    where
+        H: Into<String>,
+        T: Into<String>,
+    {
+        self.tokens.push((host.into().to_lowercase(), token.into()));
+        self
+    }
+
+    /// Set the hook presigning the URLs before they are requested.
+    pub fn presign<P>(mut self, presign: P) -> Self
+    where
+        P: Presign + 'static,
+    {
+        self.presign = Some(Arc::new(presign));
+        self
+    }
+
+    /// Display the progress of the downloads on the terminal.
+    pub fn progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Write the checksums of the retrieved files.
+    ///
+    /// A `.sha256` sidecar is written next to each file, and the checksums are
+    /// added to the `SHA256SUMS` file of the destination folder.
+    pub fn checksums(mut self, checksums: bool) -> Self {
+        self.checksums = checksums;
+        self
+    }
+
+    /// Skip the files larger than `size` bytes.
+    ///
+    /// The size is checked against the `Content-Length` of the response when
+    /// it is known, and while the file is written otherwise.
+    pub fn max_file_size(mut self, size: Option<u64>) -> Self {
+        self.max_file_size = size;
+        self
+    }
+
+    /// Set the signal stopping the downloads.
+    ///
+    /// Once the shutdown is requested, no new download starts, and the ones in
+    /// progress are aborted if they do not complete within the grace period.
+    pub fn shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Set the time given to the transfers in progress to complete after a
+    /// shutdown.
+    ///
+    /// Defaults to 30 seconds.
+    pub fn grace_period(mut self, grace_period: Duration) -> Self {
+        self.grace_period = grace_period;
+        self
+    }
+
+    /// Fail a random fraction of the downloads on purpose.
+    ///
+    /// Meant to test how the consumers of the files handle partial failures.
+    /// The injected failures are flagged in the outcomes.
+    pub fn fault_injection(mut self, fault_injection: Option<FaultInjection>) -> Self {
+        self.fault_injection = fault_injection;
+        self
+    }
+
+    /// Record the HTTP responses, and replay them on the next runs.
+    ///
+    /// Refer to [`Fixtures`] for details.
+    pub fn fixtures(mut self, fixtures: Option<Fixtures>) -> Self {
+        self.fixtures = fixtures;
+        self
+    }
+
+    /// Append a record describing each request to an audit log.
+    ///
+    /// The replayed responses are not recorded, since no request is sent.
+    pub fn audit_log(mut self, audit_log: Option<AuditLog>) -> Self {
+        self.audit_log = audit_log;
+        self
+    }
+
+    /// Record each retrieved file in the manifest as soon as it completes, so
+    /// that the progress of an interrupted batch is not lost.
+    pub fn checkpoint(mut self, checkpoint: Option<Checkpoint>) -> Self {
+        self.checkpoint = checkpoint;
+        self
+    }
+
+    /// Keep a copy of the local files in `backup_dir` before they are replaced
+    /// by a different content.
+    pub fn backup_dir<P>(mut self, backup_dir: Option<P>) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.backup_dir = backup_dir.map(|p| p.as_ref().to_path_buf());
+        self
+    }
+
+    /// Download at most `limit` files of the `dataset` at once.
+    ///
+    /// The limit applies on top of the number of parallel requests.
+    pub fn dataset_concurrency(mut self, dataset: Dataset, limit: u16) -> Self {
+        self.limits.dataset(dataset, limit);
+        self
+    }
+
+    /// Download at most `limit` files of the cities of the `country` at once.
+    ///
+    /// The limit applies on top of the number of parallel requests.
+    pub fn country_concurrency(mut self, country: &str, limit: u16) -> Self {
+        self.limits.country(country, limit);
+        self
+    }
+
+    /// Allow at most `budget` to retrieve the datasets of each city, starting
+    /// with its first download.
+    ///
+    /// Once the budget of a city is exhausted, its remaining downloads are
+    /// aborted or skipped, and reported as [`DownloadStatus::TimedOut`].
+    pub fn city_timeout(mut self, budget: Option<Duration>) -> Self {
+        self.city_budget = budget.map(CityBudget::new);
+        self
+    }
+
+    /// Only connect to the addresses of this family.
+    ///
+    /// Defaults to [`IpFamily::Any`].
+    pub fn ip_family(mut self, family: IpFamily) -> Self {
+        self.ip_family = family;
+        self
+    }
+
+    /// Connect to `address` instead of resolving `host`.
+    ///
+    /// If the port of the address is 0, the port of the URL is used.
+    pub fn resolve<S>(mut self, host: S, address: SocketAddr) -> Self
+    where
+        S: Into<String>,
+    {
+        self.overrides.push((host.into(), address));
+        self
+    }
+
+    /// Set the HTTP version used to talk to the servers.
+    ///
+    /// Defaults to [`HttpVersion::Auto`].
+    pub fn http_version(mut self, version: HttpVersion) -> Self {
+        self.http_version = version;
+        self
+    }
+
+    /// Set the maximum number of idle connections kept open to each host.
+    ///
+    /// Defaults to no limit.
+    pub fn pool_max_idle_per_host(mut self, count: Option<usize>) -> Self {
+        self.pool_max_idle_per_host = count;
+        self
+    }
+
+    /// Set the time an idle connection is kept open.
+    ///
+    /// Defaults to 90 seconds.
+    pub fn pool_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.pool_idle_timeout = timeout;
+        self
+    }
+
+    /// Set the interval of the TCP keep-alive probes.
+    ///
+    /// Defaults to no keep-alive probes.
+    pub fn tcp_keepalive(mut self, interval: Option<Duration>) -> Self {
+        self.tcp_keepalive = interval;
+        self
+    }
+
+    /// Set the number of segments the large files are split into.
+    ///
+    /// The segments are downloaded in parallel with range requests, if the
+    /// server supports them. Defaults to 1, i.e. the files are not split.
+    pub fn segments(mut self, count: u16) -> Self {
+        self.segments = count;
+        self
+    }
+
+    /// Set the minimum size of the files split into segments, in bytes.
+    ///
+    /// Defaults to 64 MiB.
+    pub fn segment_threshold(mut self, size: u64) -> Self {
+        self.segment_threshold = size;
+        self
+    }
+
+    /// Set the maximum size of the datasets downloaded in memory, in bytes.
+    ///
+    /// Defaults to 32 MiB, which fits the tabular datasets but not the
+    /// archives. Refer to [`Downloader::fetch_bytes`].
+    pub fn memory_limit(mut self, size: u64) -> Self {
+        self.memory_limit = size;
+        self
+    }
+
+    /// Store the files as compressed by the server, instead of decompressing
+    /// them.
+    ///
+    /// The compressed encodings are always negotiated with the servers, which
+    /// saves a lot of bandwidth on the CSV datasets. By default, the responses
+    /// are decompressed transparently. Keep them compressed to populate a
+    /// mirror serving them with their `Content-Encoding`, which is recorded in
+    /// the outcomes and the manifest. The [cache](DownloaderBuilder::cache) is
+    /// not used when the files are kept compressed.
+    pub fn keep_compressed(mut self, keep_compressed: bool) -> Self {
+        self.keep_compressed = keep_compressed;
+        self
+    }
+
+    /// Fail the downloads whose remote metadata is unexpected, refer to
+    /// [`StrictMode`].
+    ///
+    /// The responses are not compressed in strict mode, unless they are kept
+    /// compressed, so that their length is announced.
+    pub fn strict(mut self, strict: Option<StrictMode>) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Compare the sizes of the files in strict mode with the sizes recorded
+    /// in `manifest`, e.g. the manifest of the previous snapshot.
+    ///
+    /// Defaults to the manifest of the destination folder, loaded when the
+    /// downloader is built.
+    pub fn previous_manifest(mut self, manifest: Option<Manifest>) -> Self {
+        self.previous_manifest = manifest;
+        self
+    }
+
+    /// Build the [`HttpClient`] sending the other requests with the same
+    /// headers, tokens, network settings and audit log as the downloader.
+    ///
+    /// Fail if the User-Agent or one of the headers is invalid.
+    pub fn client(&self) -> Result<HttpClient, Error> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| Error::Parse(format!("invalid header name {:?}: {}", name, e)))?;
+            let mut value = HeaderValue::from_str(value)
+                .map_err(|e| Error::Parse(format!("invalid header value for {}: {}", name, e)))?;
+            value.set_sensitive(true);
+            headers.append(name, value);
+        }
+        let user_agent = HeaderValue::from_str(&self.user_agent).map_err(|e| {
+            Error::Parse(format!("invalid User-Agent {:?}: {}", self.user_agent, e))
+        })?;
+        // The client only negotiates the encodings it decompresses itself.
+        if self.keep_compressed {
+            headers
+                .entry(ACCEPT_ENCODING)
+                .or_insert(HeaderValue::from_static(ACCEPTED_ENCODINGS));
+        }
+        let mut client = Client::builder()
+            .user_agent(user_agent)
+            .default_headers(headers)
+            .connect_timeout(CONNECT_TIMEOUT)
+            .read_timeout(READ_TIMEOUT)
+            .tcp_keepalive(self.tcp_keepalive);
+        if self.keep_compressed || self.strict.is_some() {
+            client = client.no_gzip().no_brotli().no_zstd();
+        }
+        client = match self.http_version {
+            HttpVersion::Auto => client,
+            HttpVersion::Http1 => client.http1_only(),
+            HttpVersion::Http2 => client.http2_prior_knowledge(),
+        };
+        if let Some(count) = self.pool_max_idle_per_host {
+            client = client.pool_max_idle_per_host(count);
+        }
+        if let Some(timeout) = self.pool_idle_timeout {
+            client = client.pool_idle_timeout(timeout);
+        }
+        if self.ip_family != IpFamily::Any {
+            client = client.dns_resolver(Arc::new(FamilyResolver(self.ip_family)));
+        }
+        for (host, address) in &self.overrides {
+            client = client.resolve(host, *address);
+        }
+
+        Ok(HttpClient::from_parts(
+            client.build()?,
+            self.tokens.clone(),
+            self.audit_log.clone(),
+        ))
+    }
+
+    /// Build the [`Downloader`].
+    ///
+    /// Fail if the User-Agent or one of the headers is invalid, or if the
+    /// manifest of the destination folder cannot be read in strict mode.
+    pub fn build(self) -> Result<Downloader, Error> {
+        let previous_sizes = match (&self.strict, &self.previous_manifest) {
+            (None, _) => HashMap::new(),
+            (Some(_), Some(manifest)) => sizes(manifest, &self.destination_folder),
+            (Some(_), None) => {
+                let manifest = Manifest::load(&self.destination_folder)?;
+                sizes(&manifest, &self.destination_folder)
+            }
+        };
+
+        Ok(Downloader {
+            http: self.client()?,
+            destination_folder: self.destination_folder,
+            parallel_requests: self.parallel_requests.max(1),
+            retries: self.retries,
+            // The cache only holds decompressed files.
+            cache: self.cache.filter(|_| !self.keep_compressed),
+            mirrors: self.mirrors,
+            layout: self.layout,
+            presign: self.presign,
+            progress: self.progress,
+            checksums: self.checksums,
+            max_file_size: self.max_file_size,
+            shutdown: self.shutdown,
+            grace_period: self.grace_period,
+            fault_injection: self.fault_injection,
+            fixtures: self.fixtures,
+            audit_log: self.audit_log,
+            checkpoint: self.checkpoint,
+            limits: self.limits,
+            city_budget: self.city_budget,
+            backup_dir: self.backup_dir,
+            segments: self.segments.max(1),
+            segment_threshold: self.segment_threshold,
+            memory_limit: self.memory_limit,
+            strict: self.strict,
+            previous_sizes: Arc::new(previous_sizes),
+        })
+    }
+}
+
+/// Return the size recorded in a `manifest` for each file, indexed by its path
+/// in the download `folder`.
+fn sizes(manifest: &Manifest, folder: &Path) -> HashMap<PathBuf, u64> {
+    manifest
+        .files
+        .iter()
+        .map(|e| (extended_length_path(&folder.join(&e.path)), e.size))
+        .collect()
+}
+
+/// Download the datasets of a [`Plan`].
+#[derive(Clone)]
+pub struct Downloader {
+    http: HttpClient,
+    destination_folder: PathBuf,
+    parallel_requests: u16,
+    retries: u16,
+    cache: Option<Cache>,
+    mirrors: Vec<Url>,
+    layout: Layout,
+    presign: Option<Arc<dyn Presign>>,
+    progress: bool,
+    checksums: bool,
+    max_file_size: Option<u64>,
+    shutdown: Shutdown,
+    grace_period: Duration,
+    fault_injection: Option<FaultInjection>,
+    fixtures: Option<Fixtures>,
+    audit_log: Option<AuditLog>,
+    checkpoint: Option<Checkpoint>,
+    limits: ConcurrencyLimits,
+    city_budget: Option<CityBudget>,
+    backup_dir: Option<PathBuf>,
+    segments: u16,
+    segment_threshold: u64,
+    memory_limit: u64,
+    strict: Option<StrictMode>,
+    previous_sizes: Arc<HashMap<PathBuf, u64>>,
+}
+
+impl fmt::Debug for Downloader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Downloader")
+            .field("http", &self.http)
+            .field("destination_folder", &self.destination_folder)
+            .field("parallel_requests", &self.parallel_requests)
+            .field("retries", &self.retries)
+            .field("cache", &self.cache)
+            .field("mirrors", &self.mirrors)
+            .field("layout", &self.layout)
+            .field("presign", &self.presign.is_some())
+            .field("progress", &self.progress)
+            .field("checksums", &self.checksums)
+            .field("max_file_size", &self.max_file_size)
+            .field("shutdown", &self.shutdown.is_requested())
+            .field("grace_period", &self.grace_period)
+            .field("fault_injection", &self.fault_injection)
+            .field("fixtures", &self.fixtures)
+            .field("audit_log", &self.audit_log)
+            .field("checkpoint", &self.checkpoint)
+            .field("limits", &self.limits)
+            .field("city_budget", &self.city_budget)
+            .field("backup_dir", &self.backup_dir)
+            .field("segments", &self.segments)
+            .field("segment_threshold", &self.segment_threshold)
+            .field("memory_limit", &self.memory_limit)
+            .field("strict", &self.strict)
+            .field("previous_sizes", &self.previous_sizes.len())
+            .finish()
+    }
+}
+
+impl Downloader {
+    /// Return a builder to configure a [`Downloader`].
+    pub fn builder() -> DownloaderBuilder {
+        DownloaderBuilder::default()
+    }
+
+    /// Return the client sending the requests.
+    pub fn client(&self) -> &HttpClient {
+        &self.http
+    }
+
+    /// Return the folder where the files are saved.
+    pub fn destination_folder(&self) -> &PathBuf {
+        &self.destination_folder
+    }
+
+    /// Download all the datasets of the plan.
+    ///
+    /// The datasets which are already present in the cache are restored from
+    /// it instead, and the new ones are added to it.
+    #[instrument(skip_all, fields(downloads = plan.downloads.len()))]
+    pub async fn fetch_all(&self, plan: &Plan) -> Result<DownloadReport, Error> {
+        // Ensure the output folder exists.
+        fs::create_dir_all(&self.destination_folder).await?;
+
+        let start = Instant::now();
+        let progress = Progress::new(plan.downloads.len() as u64, self.progress);
+        // The downloads wait for their limits before taking one of the
+        // parallel slots, so that the slots are not held by the downloads of a
+        // limited dataset or country while the other ones could start.
+        let slots = Semaphore::new(self.parallel_requests as usize);
+        let window = match self.limits.is_empty() {
+            true => self.parallel_requests as usize,
+            false => plan.downloads.len().max(1),
+        };
+        let outcomes = stream::iter(&plan.downloads)
+            .map(|planned| async {
+                let _permits = self.limits.acquire(planned).await;
+                let _slot = slots.acquire().await;
+                let outcome = self.fetch(planned, &progress).await?;
+                self.record_checkpoint(&outcome).await;
+                Ok(outcome)
+            })
+            .buffered(window)
+            .collect::<Vec<Result<DownloadOutcome, Error>>>()
+            .await;
+        progress.finish();
+        let report = DownloadReport {
+            outcomes: outcomes.into_iter().collect::<Result<_, _>>()?,
+            elapsed: start.elapsed(),
+        };
+        info!(
+            bytes = report.total_bytes(),
+            elapsed_ms = report.elapsed.as_millis() as u64,
+            failures = report.failures().count(),
+            "batch complete"
+        );
+
+        // Record the checksums of the retrieved files.
+        if self.checksums {
+            self.record_checksums(&report)?;
+        }
+
+        Ok(report)
+    }
+
+    /// Add the checksums of the retrieved files to the `SHA256SUMS` file.
+    fn record_checksums(&self, report: &DownloadReport) -> Result<(), Error> {
+        let entries: Vec<(PathBuf, String)> = report
+            .outcomes
+            .iter()
+            .filter_map(|o| Some((o.download.file_name.clone(), o.sha256.clone()?)))
+            .collect();
+        checksum::update_sums(&self.destination_folder, &entries)
+    }
+
+    /// Record a retrieved file in the checkpointed manifest, if any.
+    ///
+    /// A failure is logged without failing the download, which is recorded in
+    /// the manifest at the end of the batch anyway.
+    async fn record_checkpoint(&self, outcome: &DownloadOutcome) {
+        let Some(checkpoint) = self.checkpoint.clone() else {
+            return;
+        };
+        let outcome = outcome.clone();
+        match tokio::task::spawn_blocking(move || checkpoint.record(&outcome)).await {
+            Ok(Ok(())) => (),
+            Ok(Err(e)) => warn!(error = %e, "cannot record the checkpoint"),
+            Err(e) => warn!(error = %e, "cannot record the checkpoint"),
+        }
+    }
+
+    /// Download the datasets of a queue, until it is closed and empty.
+    ///
+    /// Unlike [`Downloader::fetch_all`], the outcomes are reported in the order
+    /// the downloads complete. The downloads start in the order of the queue:
+    /// a download waiting for its limits keeps its worker busy.
+    pub async fn run(&self, queue: &DownloadQueue) -> Result<DownloadReport, Error> {
+        fs::create_dir_all(&self.destination_folder).await?;
+
+        let start = Instant::now();
+        let progress = Progress::new(0, self.progress);
+        let outcomes = Mutex::new(Vec::new());
+        let (progress_ref, outcomes_ref) = (&progress, &outcomes);
+        let workers = (0..self.parallel_requests).map(|_| async move {
+            while let Some(planned) = queue.next().await {
+                progress_ref.add(1);
+                let _permits = self.limits.acquire(&planned).await;
+                let outcome = self.fetch(&planned, progress_ref).await?;
+                self.record_checkpoint(&outcome).await;
+                outcomes_ref.lock().unwrap().push(outcome);
+            }
+            Ok::<_, Error>(())
+        });
+        let result = future::try_join_all(workers).await;
+        progress.finish();
+        result?;
+        let report = DownloadReport {
+            outcomes: outcomes.into_inner().unwrap(),
+            elapsed: start.elapsed(),
+        };
+        if self.checksums {
+            self.record_checksums(&report)?;
+        }
+
+        Ok(report)
+    }
+
+    /// Download a dataset of a city in memory.
+    ///
+    /// The dataset is neither saved in the destination folder nor added to the
+    /// cache. The download fails if the dataset is larger than the memory
+    /// limit, refer to [`DownloaderBuilder::memory_limit`]. Like the planned
+    /// downloads, the dataset is located after the [`Layout`] of the
+    /// downloader.
+    #[instrument(skip_all, fields(city = %city, dataset = %dataset))]
+    pub async fn fetch_bytes(&self, city: &City, dataset: Dataset) -> Result<Bytes, Error> {
+        let planned = PlannedDownload {
+            city: city.clone(),
+            run: city.run.clone(),
+            dataset,
+            url: self
+                .layout
+                .url(PFB_S3_STORAGE_BASE_URL, city, &city.run, dataset)?,
+            file_name: PathBuf::new(),
+        };
+        let mut attempt: u16 = 0;
+        loop {
+            let mut last_error = None;
+            for source in self.sources(&planned)? {
+                match self.request_bytes(&source, dataset).await {
+                    Ok(bytes) => return Ok(bytes),
+                    Err(e) if e.is_retryable() => last_error = Some((source, e)),
+                    Err(e) => return Err(memory_error(&source, e)),
+                }
+            }
+            let (source, e) = last_error.expect("a dataset has at least one source");
+            if attempt >= self.retries || self.shutdown.is_requested() {
+                return Err(memory_error(&source, e));
+            }
+            attempt += 1;
+            warn!(attempt, error = %e, "download failed, retrying");
+            tokio::time::sleep(retry_delay(attempt)).await;
+        }
+    }
+
+    /// Return the URL to request for `requested`, presigned by the hook if any.
+    ///
+    /// The hook may block, e.g. while an external command runs, so it is called
+    /// on the blocking thread pool.
+    async fn presigned(&self, requested: &Url) -> Result<Url, AttemptError> {
+        let Some(presign) = &self.presign else {
+            return Ok(requested.clone());
+        };
+        let presign = Arc::clone(presign);
+        let requested = requested.clone();
+        tokio::task::spawn_blocking(move || presign.presign(&requested))
+            .await
+            .map_err(|e| AttemptError::Fatal(Error::Auth(format!("presign hook failed: {}", e))))?
+            .map_err(AttemptError::Fatal)
+    }
+
+    /// Request a `dataset` from `requested`, and buffer it in memory.
+    ///
+    /// In strict mode, the metadata of the response is checked like the one of
+    /// a new file, since no local file is replaced.
+    async fn request_bytes(
+        &self,
+        requested: &Url,
+        dataset: Dataset,
+    ) -> Result<Bytes, AttemptError> {
+        let url = self.presigned(requested).await?;
+        let started = SystemTime::now();
+        let mut status = None;
+        let mut buffer = Vec::new();
+        let result = async {
+            let mut response = self.get(&url).send().await?;
+            status = Some(response.status());
+            if !response.status().is_success() {
+                let status = response.status();
+                return match S3Error::from_response(response).await {
+                    Some(s3) => Err(AttemptError::S3(s3)),
+                    None => Err(AttemptError::Status(status)),
+                };
+            }
+            if let Some(length) = response.content_length().filter(|&l| l > self.memory_limit) {
+                return Err(AttemptError::TooLarge(length));
+            }
+            self.check_metadata(dataset, response.content_length(), response.headers(), None)?;
+            while let Some(chunk) = response.chunk().await? {
+                if (buffer.len() + chunk.len()) as u64 > self.memory_limit {
+                    return Err(AttemptError::TooLarge((buffer.len() + chunk.len()) as u64));
+                }
+                buffer.extend_from_slice(&chunk);
+            }
+            Ok(())
+        }
+        .await;
+        if let Some(audit_log) = &self.audit_log {
+            let mut record = AuditRecord::new("GET", requested, started).bytes(buffer.len() as u64);
+            if let Some(status) = status {
+                record = record.status(status.as_u16());
+            }
+            if let Err(e) = &result {
+                record = record.error(e);
+            }
+            audit_log.record(&record).map_err(AttemptError::Fatal)?;
+        }
+
+        result.map(|()| Bytes::from(buffer))
+    }
+
+    /// Download a single dataset.
+    ///
+    /// A failing download is reported in the outcome, the errors are reserved
+    /// to the failures of the local file system.
+    #[instrument(
+        skip_all,
+        fields(
+            city = %planned.city.name,
+            state = %planned.city.state,
+            country = %planned.city.country,
+            uuid = %planned.run.uuid,
+            dataset = %planned.dataset,
+        )
+    )]
+    async fn fetch(
+        &self,
+        planned: &PlannedDownload,
+        progress: &Progress,
+    ) -> Result<DownloadOutcome, Error> {
+        let start = Instant::now();
+        let path = extended_length_path(&self.destination_folder.join(&planned.file_name));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let mut outcome = DownloadOutcome {
+            download: planned.clone(),
+            path: path.clone(),
+            source: None,
+            sha256: None,
+            etag: None,
+            content_encoding: None,
+            status: DownloadStatus::Cached,
+            duration: Duration::ZERO,
+            bytes: 0,
+            retries: 0,
+            statuses: Vec::new(),
+            injected: false,
+            s3_error: None,
+            previous_sha256: None,
+            backup: None,
+        };
+
+        // Do not start new downloads once a shutdown is requested.
+        if self.shutdown.is_requested() {
+            debug!("not started, shutting down");
+            outcome.status = DownloadStatus::Interrupted;
+            return Ok(outcome);
+        }
+
+        // Skip the download if the budget of the city is already exhausted.
+        let deadline = self.city_budget.as_ref().map(|b| b.deadline(planned));
+        if deadline.is_some_and(|d| d <= Instant::now()) {
+            debug!("not started, city time budget exhausted");
+            outcome.status = DownloadStatus::TimedOut;
+            return Ok(outcome);
+        }
+
+        // Fail on purpose, before touching the existing files.
+        if self.fault_injection.is_some_and(|f| f.fails(planned)) {
+            warn!("failure injected");
+            progress
+                .start(planned.file_name.display().to_string())
+                .failure(INJECTED_FAILURE);
+            outcome.status = DownloadStatus::Failed(String::from(INJECTED_FAILURE));
+            outcome.injected = true;
+            return Ok(outcome);
+        }
+
+        // Hash the local file before it is replaced, and keep a copy of it.
+        let previous = self.back_up(planned, &path).await?;
+
+        // Restore the file from the cache if possible.
+        let cached = match &self.cache {
+            Some(cache) => cache.restore(&planned.url, &path)?,
+            None => None,
+        };
+        if let Some(hash) = cached {
+            debug!("restored from the cache");
+            outcome.sha256 = Some(hash);
+        } else {
+            let file_progress = progress.start(planned.file_name.display().to_string());
+            let mut attempt: u16 = 0;
+            loop {
+                let mirrors =
+                    self.attempt_mirrors(planned, &path, &file_progress, &mut outcome.statuses);
+                let result = tokio::select! {
+                    result = mirrors => result,
+                    _ = self.grace_period_elapsed() => Err(AttemptError::Interrupted),
+                    _ = budget_exhausted(deadline) => Err(AttemptError::TimedOut),
+                };
+                match result {
+                    Ok((source, retrieved)) => {
+                        outcome.status = DownloadStatus::Downloaded;
+                        outcome.source = Some(source);
+                        outcome.sha256 = Some(retrieved.sha256);
+                        outcome.etag = retrieved.etag;
+                        outcome.content_encoding = retrieved.content_encoding;
+                        outcome.bytes = fs::metadata(&path).await?.len();
+                        info!(
+                            bytes = outcome.bytes,
+                            attempt,
+                            elapsed_ms = start.elapsed().as_millis() as u64,
+                            "downloaded"
+                        );
+                        break;
+                    }
+                    Err(AttemptError::Interrupted) => {
+                        // The aborted attempt did not clean up after itself.
+                        let _ = fs::remove_file(part_path(&path)).await;
+                        warn!(attempt, "aborted, shutting down");
+                        outcome.status = DownloadStatus::Interrupted;
+                        break;
+                    }
+                    Err(AttemptError::TimedOut) => {
+                        let _ = fs::remove_file(part_path(&path)).await;
+                        warn!(attempt, "aborted, city time budget exhausted");
+                        outcome.status = DownloadStatus::TimedOut;
+                        break;
+                    }
+                    Err(e)
+                        if attempt < self.retries
+                            && e.is_retryable()
+                            && !self.shutdown.is_requested() =>
+                    {
+                        attempt += 1;
+                        warn!(attempt, error = %e, "attempt failed, retrying");
+                        outcome.retries = attempt;
+                        // Back off further when S3 asks to slow down.
+                        let delay = match e.is_throttled() {
+                            true => retry_delay(attempt) * 2,
+                            false => retry_delay(attempt),
+                        };
+                        tokio::time::sleep(delay).await;
+                    }
+                    Err(e) => {
+                        warn!(attempt, error = %e, "failed");
+                        outcome.status = DownloadStatus::Failed(e.to_string());
+                        if let AttemptError::S3(s3) = e {
+                            outcome.s3_error = Some(s3);
+                        }
+                        break;
+                    }
+                }
+            }
+            match &outcome.status {
+                DownloadStatus::Failed(reason) => file_progress.failure(reason),
+                DownloadStatus::Interrupted => file_progress.failure("interrupted"),
+                DownloadStatus::TimedOut => file_progress.failure("timed out"),
+                _ => file_progress.success(),
+            }
+
+            // Add the new file to the cache.
+            if let (Some(cache), Some(hash)) = (&self.cache, &outcome.sha256) {
+                cache.insert_hashed(&planned.url, &path, hash)?;
+            }
+        }
+
+        // Only record the replaced file if its content changed.
+        if let Some((hash, backup)) = previous {
+            let replaced = matches!(
+                outcome.status,
+                DownloadStatus::Downloaded | DownloadStatus::Cached
+            ) && outcome.sha256.as_ref() != Some(&hash);
+            if replaced {
+                warn!(previous = %hash, "replaced a different local file");
+                outcome.previous_sha256 = Some(hash);
+                outcome.backup = backup;
+            } else if let Some(backup) = backup {
+                let _ = fs::remove_file(backup).await;
+            }
+        }
+
+        // Write the checksum sidecar.
+        if let (true, Some(hash)) = (self.checksums, &outcome.sha256) {
+            checksum::write_sidecar(&path, hash)?;
+        }
+        outcome.duration = start.elapsed();
+
+        Ok(outcome)
+    }
+
+    /// Hash the file located at `path`, if it exists, and keep a copy of it in
+    /// the backup folder, if any.
+    ///
+    /// The copy is a hard link when possible, so it costs nothing until the
+    /// file is replaced.
+    async fn back_up(
+        &self,
+        planned: &PlannedDownload,
+        path: &Path,
+    ) -> Result<Option<(String, Option<PathBuf>)>, Error> {
+        if !fs::try_exists(path).await? {
+            return Ok(None);
+        }
+        let (hash, _) = hash_file(path).await?;
+        let Some(folder) = &self.backup_dir else {
+            return Ok(Some((hash, None)));
+        };
+        let seconds = timestamp::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut name = planned.file_name.as_os_str().to_owned();
+        name.push(format!(".{}.bak", seconds));
+        let backup = folder.join(name);
+        if let Some(parent) = backup.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        if fs::hard_link(path, &backup).await.is_err() {
+            fs::copy(path, &backup).await?;
+        }
+
+        Ok(Some((hash, Some(backup))))
+    }
+
+    /// Wait until the grace period following a shutdown request is elapsed.
+    async fn grace_period_elapsed(&self) {
+        self.shutdown.requested().await;
+        tokio::time::sleep(self.grace_period).await;
+    }
+
+    /// Return the URLs of a dataset, in the order they must be tried.
+    fn sources(&self, planned: &PlannedDownload) -> Result<Vec<Url>, Error> {
+        if self.mirrors.is_empty() {
+            return Ok(vec![planned.url.clone()]);
+        }
+        self.mirrors
+            .iter()
+            .map(|mirror| {
+                self.layout.url(
+                    mirror.as_str(),
+                    &planned.city,
+                    &planned.run,
+                    planned.dataset,
+                )
+            })
+            .collect()
+    }
+
+    /// Try to download a dataset from each mirror in turn.
+    ///
+    /// Return the URL which served the file, along with its description.
+    /// The next mirror is only tried if the failure may be temporary.
+    async fn attempt_mirrors(
+        &self,
+        planned: &PlannedDownload,
+        path: &Path,
+        progress: &FileProgress,
+        statuses: &mut Vec<u16>,
+    ) -> Result<(Url, Retrieved), AttemptError> {
+        let mut last_error = None;
+        for source in self.sources(planned).map_err(AttemptError::Fatal)? {
+            match self
+                .attempt(&source, planned.dataset, path, progress, statuses)
+                .await
+            {
+                Ok(retrieved) => return Ok((source, retrieved)),
+                Err(e) if e.is_retryable() => last_error = Some(e),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(last_error.expect("a dataset has at least one source"))
+    }
+
+    /// Perform a single download attempt.
+    ///
+    /// The file is hashed while it is written, and its SHA256 hash returned.
+    /// The status code of the response is added to `statuses`.
+    async fn attempt(
+        &self,
+        url: &Url,
+        dataset: Dataset,
+        path: &Path,
+        progress: &FileProgress,
+        statuses: &mut Vec<u16>,
+    ) -> Result<Retrieved, AttemptError> {
+        // Replay the recorded response if possible.
+        if let Some(fixtures) = &self.fixtures {
+            if let Some(fixture) = fixtures.lookup(url).map_err(AttemptError::Fatal)? {
+                debug!(status = fixture.status, "replayed");
+                statuses.push(fixture.status);
+                let status = StatusCode::from_u16(fixture.status)
+                    .map_err(|e| AttemptError::Fatal(Error::Parse(e.to_string())))?;
+                return match fixture.sha256 {
+                    Some(hash) => {
+                        fixtures.replay(url, path).map_err(AttemptError::Fatal)?;
+                        Ok(Retrieved {
+                            sha256: hash,
+                            etag: None,
+                            content_encoding: None,
+                        })
+                    }
+                    None => Err(AttemptError::Status(status)),
+                };
+            }
+            if fixtures.replay_only() {
+                return Err(AttemptError::Fatal(Error::Parse(format!(
+                    "no response recorded for {}",
+                    url
+                ))));
+            }
+        }
+
+        let started = SystemTime::now();
+        let sent = statuses.len();
+        let mut received = 0;
+        let result = self
+            .request(url, dataset, path, progress, statuses, &mut received)
+            .await;
+        if let Some(audit_log) = &self.audit_log {
+            let mut record = AuditRecord::new("GET", url, started).bytes(received);
+            if let Some(&status) = statuses.get(sent) {
+                record = record.status(status);
+            }
+            if let Err(e) = &result {
+                record = record.error(e);
+            }
+            audit_log.record(&record).map_err(AttemptError::Fatal)?;
+        }
+
+        result
+    }
+
+    /// Request a dataset from `requested`, and write it at `path`.
+    ///
+    /// The number of bytes of the body which were received is counted in
+    /// `received`.
+    async fn request(
+        &self,
+        requested: &Url,
+        dataset: Dataset,
+        path: &Path,
+        progress: &FileProgress,
+        statuses: &mut Vec<u16>,
+        received: &mut u64,
+    ) -> Result<Retrieved, AttemptError> {
+        let url = self.presigned(requested).await?;
+        let part = part_path(path);
+
+        // Split the large files into segments if the server supports it.
+        if self.segments > 1 {
+            if let Some(headers) = self.probe_segments(&url).await? {
+                statuses.push(StatusCode::OK.as_u16());
+                let length = header_length(&headers).unwrap_or_default();
+                self.check_metadata(dataset, Some(length), &headers, Some(path))?;
+                progress.set_length(Some(length));
+                let etag = header_etag(&headers);
+                let result: Result<String, AttemptError> = async {
+                    *received = self.fetch_segments(&url, &part, length, progress).await?;
+                    let (sha256, md5) = hash_file(&part).await?;
+                    verify_reassembly(*received, length, md5_etag(&headers).as_deref(), &md5)?;
+                    Ok(sha256)
+                }
+                .await;
+                match result {
+                    Err(AttemptError::RangeIgnored) => {
+                        debug!("range requests ignored, downloading as a single stream");
+                        let _ = fs::remove_file(&part).await;
+                        statuses.pop();
+                    }
+                    result => {
+                        let sha256 = self
+                            .complete(requested, StatusCode::OK, &part, path, result)
+                            .await?;
+                        return Ok(Retrieved {
+                            sha256,
+                            etag,
+                            content_encoding: None,
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut response = self.get(&url).send().await?;
+        let status = response.status();
+        statuses.push(status.as_u16());
+        debug!(
+            status = status.as_u16(),
+            length = response.content_length(),
+            "response"
+        );
+        if !status.is_success() {
+            if let Some(fixtures) = &self.fixtures {
+                fixtures
+                    .record_status(requested, status.as_u16())
+                    .map_err(AttemptError::Fatal)?;
+            }
+            return match S3Error::from_response(response).await {
+                Some(s3) => Err(AttemptError::S3(s3)),
+                None => Err(AttemptError::Status(status)),
+            };
+        }
+        self.check_metadata(
+            dataset,
+            response.content_length(),
+            response.headers(),
+            Some(path),
+        )?;
+        progress.set_length(response.content_length());
+        let etag = header_etag(response.headers());
+        let content_encoding = header_encoding(response.headers());
+
+        let max_file_size = self.max_file_size.unwrap_or(u64::MAX);
+        let result: Result<String, AttemptError> = async {
+            let mut file = fs::File::create(&part).await?;
+            let mut hasher = Sha256::new();
+            while let Some(chunk) = response.chunk().await? {
+                *received += chunk.len() as u64;
+                if *received > max_file_size {
+                    return Err(AttemptError::TooLarge(*received));
+                }
+                file.write_all(&chunk).await?;
+                hasher.update(&chunk);
+                progress.advance(chunk.len() as u64);
+            }
+            file.flush().await?;
+            Ok(format!("{:x}", hasher.finalize()))
+        }
+        .await;
+        let sha256 = self
+            .complete(requested, status, &part, path, result)
+            .await?;
+
+        Ok(Retrieved {
+            sha256,
+            etag,
+            content_encoding,
+        })
+    }
+
+    /// Check the announced `length` and the `headers` of a response before
+    /// its body is retrieved, against the maximum file size and the strict
+    /// mode.
+    ///
+    /// In strict mode, the length is compared with the size recorded in the
+    /// previous manifest for the file replaced at `path`, if any.
+    fn check_metadata(
+        &self,
+        dataset: Dataset,
+        length: Option<u64>,
+        headers: &HeaderMap,
+        path: Option<&Path>,
+    ) -> Result<(), AttemptError> {
+        let max_file_size = self.max_file_size.unwrap_or(u64::MAX);
+        if let Some(length) = length.filter(|&l| l > max_file_size) {
+            return Err(AttemptError::TooLarge(length));
+        }
+        if let Some(strict) = &self.strict {
+            let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+            let metadata = ResponseMetadata {
+                content_length: length,
+                etag: header(ETAG),
+                content_type: header(CONTENT_TYPE),
+            };
+            let previous_size = path.and_then(|p| self.previous_sizes.get(p).copied());
+            strict
+                .check(dataset, &metadata, previous_size)
+                .map_err(AttemptError::Strict)?;
+        }
+
+        Ok(())
+    }
+
+    /// Move the `part` file of a successful download to `path`, and record
+    /// its body in the fixtures, or remove it if the download failed.
+    async fn complete(
+        &self,
+        requested: &Url,
+        status: StatusCode,
+        part: &Path,
+        path: &Path,
+        result: Result<String, AttemptError>,
+    ) -> Result<String, AttemptError> {
+        let result = match result {
+            Ok(sha256) => fs::rename(part, path)
+                .await
+                .map(|_| sha256)
+                .map_err(AttemptError::from),
+            Err(e) => Err(e),
+        };
+        if result.is_err() {
+            let _ = fs::remove_file(part).await;
+        }
+        if let (Some(fixtures), Ok(_)) = (&self.fixtures, &result) {
+            fixtures
+                .record_body(requested, status.as_u16(), path)
+                .map_err(AttemptError::Fatal)?;
+        }
+
+        result
+    }
+
+    /// Probe `url` with a HEAD request, and return the headers of the response
+    /// if the file can be downloaded as segments.
+    ///
+    /// `None` is returned if the request fails, or if the file is small,
+    /// compressed, or its server does not support the range requests: the file
+    /// is then downloaded as a single stream.
+    async fn probe_segments(&self, url: &Url) -> Result<Option<HeaderMap>, AttemptError> {
+        let started = SystemTime::now();
+        let result = self.head(url).send().await;
+        if let Some(audit_log) = &self.audit_log {
+            let record = AuditRecord::new("HEAD", url, started);
+            let record = match &result {
+                Ok(response) => record.status(response.status().as_u16()),
+                Err(e) => record.error(e),
+            };
+            audit_log.record(&record).map_err(AttemptError::Fatal)?;
+        }
+        let Ok(response) = result else {
+            return Ok(None);
+        };
+        let headers = response.headers();
+        let length = header_length(headers).unwrap_or_default();
+        let segmented = response.status().is_success()
+            && length > 0
+            && length >= self.segment_threshold
+            && header_encoding(headers).is_none()
+            && headers.get(ACCEPT_RANGES).is_some_and(|v| v == "bytes");
+        debug!(length, segmented, "probed");
+
+        Ok(segmented.then(|| headers.clone()))
+    }
+
+    /// Download a file of `length` bytes as segments, written in place in
+    /// `part`.
+    ///
+    /// Return the number of bytes received for the segments.
+    async fn fetch_segments(
+        &self,
+        url: &Url,
+        part: &Path,
+        length: u64,
+        progress: &FileProgress,
+    ) -> Result<u64, AttemptError> {
+        let file = fs::File::create(part).await?;
+        file.set_len(length).await?;
+        drop(file);
+        let segments = segment_ranges(length, self.segments);
+        debug!(segments = segments.len(), "segmented download");
+        let received = future::try_join_all(
+            segments
+                .into_iter()
+                .map(|(start, end)| self.fetch_segment(url, part, start, end, progress)),
+        )
+        .await?;
+
+        Ok(received.into_iter().sum())
+    }
+
+    /// Download a segment of a file, retrying it on its own if it fails.
+    ///
+    /// Return the number of bytes received for the segment.
+    async fn fetch_segment(
+        &self,
+        url: &Url,
+        part: &Path,
+        start: u64,
+        end: u64,
+        progress: &FileProgress,
+    ) -> Result<u64, AttemptError> {
+        let mut attempt: u16 = 0;
+        loop {
+            match self.attempt_segment(url, part, start, end, progress).await {
+                Ok(received) => return Ok(received),
+                Err(e)
+                    if attempt < self.retries
+                        && e.is_retryable()
+                        && !self.shutdown.is_requested() =>
+                {
+                    attempt += 1;
+                    warn!(attempt, start, end, error = %e, "segment failed, retrying");
+                    tokio::time::sleep(retry_delay(attempt)).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Prepare a GET request to `url`, authenticated with the bearer token of
+    /// its host, if any.
+    fn get(&self, url: &Url) -> RequestBuilder {
+        self.http.get(url)
+    }
+
+    /// Prepare a HEAD request to `url`, authenticated like a GET request.
+    fn head(&self, url: &Url) -> RequestBuilder {
+        self.http.head(url)
+    }
+
+    /// Download the bytes `start..=end` of a file, and write them at the same
+    /// offset in `part`.
+    ///
+    /// Return the number of bytes received.
+    async fn attempt_segment(
+        &self,
+        url: &Url,
+        part: &Path,
+        start: u64,
+        end: u64,
+        progress: &FileProgress,
+    ) -> Result<u64, AttemptError> {
+        let started = SystemTime::now();
+        let mut status = None;
+        let mut received = 0;
+        let mut written = 0;
+        let result = async {
+            let mut response = self
+                .get(url)
+                .header(RANGE, format!("bytes={}-{}", start, end))
+                .send()
+                .await?;
+            status = Some(response.status());
+            if !response.status().is_success() {
+                let status = response.status();
+                return match S3Error::from_response(response).await {
+                    Some(s3) => Err(AttemptError::S3(s3)),
+                    None => Err(AttemptError::Status(status)),
+                };
+            }
+            if response.status() == StatusCode::OK {
+                return Err(AttemptError::RangeIgnored);
+            }
+            if response.status() != StatusCode::PARTIAL_CONTENT {
+                return Err(AttemptError::Status(response.status()));
+            }
+            let expected = end - start + 1;
+            let mut file = fs::OpenOptions::new().write(true).open(part).await?;
+            file.seek(SeekFrom::Start(start)).await?;
+            while let Some(chunk) = response.chunk().await? {
+                received += chunk.len() as u64;
+                if received > expected {
+                    break;
+                }
+                file.write_all(&chunk).await?;
+                written += chunk.len() as u64;
+                progress.advance(chunk.len() as u64);
+            }
+            file.flush().await?;
+            if received != expected {
+                return Err(AttemptError::IO(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "received {} bytes for the range {}-{}, expected {}",
+                        received, start, end, expected
+                    ),
+                )));
+            }
+
+            Ok(received)
+        }
+        .await;
+        if let Some(audit_log) = &self.audit_log {
+            let mut record = AuditRecord::new("GET", url, started).bytes(received);
+            if let Some(status) = status {
+                record = record.status(status.as_u16());
+            }
+            if let Err(e) = &result {
+                record = record.error(e);
+            }
+            audit_log.record(&record).map_err(AttemptError::Fatal)?;
+        }
+        // The segment is downloaded again from its start if it is retried.
+        if result.is_err() {
+            progress.rewind(written);
+        }
+
+        result
+    }
+}
+
+/// Return the length announced by the `Content-Length` header.
+///
+/// [`reqwest::Response::content_length`] cannot be used for the HEAD
+/// requests, whose body is always empty.
+fn header_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+}
+
+/// Return the ETag of a response, without its quotes.
+fn header_etag(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim_matches('"').to_string())
+}
+
+/// Return the ETag of a response if it is the MD5 hash of the file, in lower
+/// case.
+///
+/// The ETag of S3 is the MD5 hash of the file, unless the file was uploaded in
+/// several parts (`<hash>-<parts>`) or is encrypted with KMS.
+fn md5_etag(headers: &HeaderMap) -> Option<String> {
+    let kms = headers
+        .get(SERVER_SIDE_ENCRYPTION)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("aws:kms"));
+    header_etag(headers)
+        .filter(|e| !kms && e.len() == 32 && e.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(|e| e.to_lowercase())
+}
+
+/// Return the content encoding of a response, if it is compressed.
+///
+/// The decompressed responses do not announce their length, and the ranges of
+/// the compressed ones cannot be trusted.
+fn header_encoding(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.eq_ignore_ascii_case("identity"))
+        .map(String::from)
+}
+
+/// Return the inclusive byte ranges splitting a file of `length` bytes into
+/// `count` segments of the same size.
+fn segment_ranges(length: u64, count: u16) -> Vec<(u64, u64)> {
+    let size = length.div_ceil(count.max(1) as u64).max(1);
+    (0..length)
+        .step_by(size as usize)
+        .map(|start| (start, (start + size).min(length) - 1))
+        .collect()
+}
+
+/// Compute the SHA256 and MD5 hashes of the file located at `path`.
+async fn hash_file(path: &Path) -> Result<(String, String), io::Error> {
+    let mut file = fs::File::open(path).await?;
+    let mut sha256 = Sha256::new();
+    let mut md5 = Md5::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        sha256.update(&buffer[..read]);
+        md5.update(&buffer[..read]);
+    }
+
+    Ok((
+        format!("{:x}", sha256.finalize()),
+        format!("{:x}", md5.finalize()),
+    ))
+}
+
+/// Check a file reassembled from segments against the `length` of the
+/// response, and its `md5` hash against the ETag, if it is the MD5 hash of the
+/// file.
+///
+/// The `received` bytes are compared with the length, since the `.part` file
+/// is allocated to its final size before the segments are written.
+fn verify_reassembly(
+    received: u64,
+    length: u64,
+    etag: Option<&str>,
+    md5: &str,
+) -> Result<(), AttemptError> {
+    if received != length {
+        return Err(AttemptError::IO(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "received {} bytes for the segments, expected {}",
+                received, length
+            ),
+        )));
+    }
+    if let Some(etag) = etag.filter(|&e| e != md5) {
+        return Err(AttemptError::IO(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("reassembled file has MD5 {}, expected {}", md5, etag),
+        )));
+    }
+
+    Ok(())
+}
+
+/// Describe a file retrieved by a download attempt.
+#[derive(Debug)]
+struct Retrieved {
+    /// SHA256 hash of the file.
+    sha256: String,
+    /// ETag announced by the server.
+    etag: Option<String>,
+    /// Content encoding of the stored file, if it is compressed.
+    content_encoding: Option<String>,
+}
+
+/// Describe why a download attempt failed.
+#[derive(Debug)]
+enum AttemptError {
+    /// The server answered with an unsuccessful status code.
+    Status(StatusCode),
+    /// S3 answered with an error document.
+    S3(S3Error),
+    /// The request failed.
+    Http(reqwest::Error),
+    /// The file could not be written.
+    IO(std::io::Error),
+    /// The file is larger than the maximum file size.
+    TooLarge(u64),
+    /// The remote metadata is unexpected, in strict mode.
+    Strict(String),
+    /// The server answered a range request with the whole file.
+    RangeIgnored,
+    /// The attempt failed for a reason which will not change on retry.
+    Fatal(Error),
+    /// The attempt was aborted because of a shutdown.
+    Interrupted,
+    /// The attempt was aborted because the time budget of the city was
+    /// exhausted.
+    TimedOut,
+}
+
+impl AttemptError {
+    /// Return `true` if the attempt may succeed when retried.
+    fn is_retryable(&self) -> bool {
+        match self {
+            AttemptError::Status(status) => {
+                status.is_server_error()
+                    || *status == StatusCode::REQUEST_TIMEOUT
+                    || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            AttemptError::S3(e) => e.is_retryable(),
+            AttemptError::Http(_) | AttemptError::IO(_) => true,
+            AttemptError::TooLarge(_)
+            | AttemptError::Strict(_)
+            | AttemptError::RangeIgnored
+            | AttemptError::Fatal(_)
+            | AttemptError::Interrupted
+            | AttemptError::TimedOut => false,
+        }
+    }
+
+    /// Return `true` if the requests are throttled.
+    fn is_throttled(&self) -> bool {
+        match self {
+            AttemptError::S3(e) => e.is_throttled(),
+            AttemptError::Status(status) => *status == StatusCode::TOO_MANY_REQUESTS,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for AttemptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AttemptError::Status(status) => write!(f, "HTTP {}", status),
+            AttemptError::S3(e) => write!(f, "{}", e),
+            AttemptError::Http(e) => write!(f, "{}", e),
+            AttemptError::IO(e) => write!(f, "I/O error: {}", e),
+            AttemptError::TooLarge(size) => {
+                write!(f, "file too large ({} bytes or more), skipped", size)
+            }
+            AttemptError::Strict(reason) => write!(f, "{}", reason),
+            AttemptError::RangeIgnored => write!(f, "range request ignored by the server"),
+            AttemptError::Fatal(e) => write!(f, "{}", e),
+            AttemptError::Interrupted => write!(f, "interrupted"),
+            AttemptError::TimedOut => write!(f, "city time budget exhausted"),
+        }
+    }
+}
+
+impl From<reqwest::Error> for AttemptError {
+    fn from(e: reqwest::Error) -> Self {
+        AttemptError::Http(e)
+    }
+}
+
+impl From<std::io::Error> for AttemptError {
+    fn from(e: std::io::Error) -> Self {
+        AttemptError::IO(e)
+    }
+}
+
+/// Download the datasets of a city in memory.
+pub trait FetchDataset {
+    /// Download a dataset in memory with the default settings, e.g.
+    /// `city.fetch(Dataset::NeighborhoodOverallScores).await`.
+    ///
+    /// Each call builds a new [`Downloader`]. Build one and call
+    /// [`Downloader::fetch_bytes`] instead to reuse the connections, or to
+    /// change the memory limit.
+    fn fetch(&self, dataset: Dataset) -> impl Future<Output = Result<Bytes, Error>> + Send;
+}
+
+impl FetchDataset for City {
+    async fn fetch(&self, dataset: Dataset) -> Result<Bytes, Error> {
+        Downloader::builder()
+            .build()?
+            .fetch_bytes(self, dataset)
+            .await
+    }
+}
+
+/// Convert the failure of an in-memory download from `url` into an error.
+fn memory_error(url: &Url, e: AttemptError) -> Error {
+    match e {
+        AttemptError::Fatal(e) => e,
+        AttemptError::Http(e) => e.into(),
+        AttemptError::IO(e) => e.into(),
+        AttemptError::TooLarge(size) => Error::Parse(format!(
+            "{} is too large to be downloaded in memory ({} bytes or more)",
+            url, size
+        )),
+        e => Error::Parse(format!("cannot download {}: {}", url, e)),
+    }
+}
+
+/// Return the path of the partial file used while downloading `path`.
+fn part_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+/// Return the throughput of a transfer, in bytes per second.
+fn throughput(bytes: u64, duration: Duration) -> f64 {
+    if duration.is_zero() {
+        return 0.0;
+    }
+    bytes as f64 / duration.as_secs_f64()
+}
+
+/// Wait until the `deadline` of a city, forever if there is none.
+async fn budget_exhausted(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => future::pending().await,
+    }
+}
+
+/// Return the delay to wait before the `attempt`-th retry.
+fn retry_delay(attempt: u16) -> Duration {
+    let delay = Duration::from_secs(1 << attempt.saturating_sub(1).min(5));
+    delay.min(MAX_RETRY_DELAY)
+}
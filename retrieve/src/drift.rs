@@ -0,0 +1,163 @@
+//! Compare two copies of the PFB storage, e.g. a public mirror and the
+//! upstream storage.
+//!
+//! The comparison only sends HEAD requests, and reports the datasets which
+//! are missing from one side, or whose size or ETag differ.
+use crate::http::HttpClient;
+use crate::layout::Layout;
+use crate::plan::{Plan, PlannedDownload};
+use crate::Error;
+use futures::stream::{self, StreamExt};
+use reqwest::header::{CONTENT_LENGTH, ETAG};
+use std::fmt;
+use url::Url;
+
+/// Describe a dataset as announced by one side.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RemoteMetadata {
+    /// Status code of the response, `None` if the request failed.
+    pub status: Option<u16>,
+    /// Size of the file, in bytes.
+    pub length: Option<u64>,
+    /// ETag of the file, without the quotes.
+    pub etag: Option<String>,
+}
+
+impl RemoteMetadata {
+    /// Return `true` if the file exists.
+    pub fn is_present(&self) -> bool {
+        self.status.is_some_and(|s| (200..300).contains(&s))
+    }
+}
+
+impl fmt::Display for RemoteMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.status {
+            None => write!(f, "unreachable"),
+            Some(status) if !self.is_present() => write!(f, "HTTP {}", status),
+            Some(_) => {
+                match self.length {
+                    Some(length) => write!(f, "{} bytes", length)?,
+                    None => write!(f, "unknown size")?,
+                }
+                if let Some(etag) = &self.etag {
+                    write!(f, ", ETag {}", etag)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Describe how the two copies of a dataset differ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Drift {
+    /// Both copies have the same size and ETag.
+    Identical,
+    /// The dataset is missing from the left side.
+    MissingLeft,
+    /// The dataset is missing from the right side.
+    MissingRight,
+    /// The dataset is missing from both sides.
+    MissingBoth,
+    /// The copies have different sizes.
+    Size,
+    /// The copies have the same size, but different ETags.
+    Etag,
+}
+
+/// Compare the two copies of a dataset.
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    /// Planned download of the dataset.
+    pub download: PlannedDownload,
+    /// Metadata announced by the left side.
+    pub left: RemoteMetadata,
+    /// Metadata announced by the right side.
+    pub right: RemoteMetadata,
+}
+
+impl Comparison {
+    /// Return how the two copies differ.
+    ///
+    /// The sizes and ETags are only compared when both sides announce them.
+    pub fn drift(&self) -> Drift {
+        match (self.left.is_present(), self.right.is_present()) {
+            (false, false) => return Drift::MissingBoth,
+            (false, true) => return Drift::MissingLeft,
+            (true, false) => return Drift::MissingRight,
+            (true, true) => (),
+        }
+        if let (Some(left), Some(right)) = (self.left.length, self.right.length) {
+            if left != right {
+                return Drift::Size;
+            }
+        }
+        if let (Some(left), Some(right)) = (&self.left.etag, &self.right.etag) {
+            if left != right {
+                return Drift::Etag;
+            }
+        }
+        Drift::Identical
+    }
+}
+
+/// Compare the datasets of a `plan` in the storages located at `left` and
+/// `right`, with at most `parallel` requests sent by `client` at once.
+///
+/// The comparisons are returned in the order of the plan.
+pub async fn compare_remotes(
+    client: &HttpClient,
+    plan: &Plan,
+    layout: &Layout,
+    left: &Url,
+    right: &Url,
+    parallel: usize,
+) -> Result<Vec<Comparison>, Error> {
+    let mut pairs = Vec::new();
+    for download in &plan.downloads {
+        let url = |base: &Url| {
+            layout.url(
+                base.as_str(),
+                &download.city,
+                &download.run,
+                download.dataset,
+            )
+        };
+        pairs.push((download, url(left)?, url(right)?));
+    }
+    let comparisons = stream::iter(pairs)
+        .map(|(download, left, right)| async move {
+            let (left, right) = futures::join!(head(client, left), head(client, right));
+            Comparison {
+                download: download.clone(),
+                left,
+                right,
+            }
+        })
+        .buffered(parallel.max(1))
+        .collect()
+        .await;
+
+    Ok(comparisons)
+}
+
+/// Describe the file located at `url` with a HEAD request.
+async fn head(client: &HttpClient, url: Url) -> RemoteMetadata {
+    let Ok(response) = client.send(client.head(&url)).await else {
+        return RemoteMetadata::default();
+    };
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().trim_matches('"').to_string())
+    };
+
+    RemoteMetadata {
+        status: Some(response.status().as_u16()),
+        length: header(CONTENT_LENGTH).and_then(|v| v.parse().ok()),
+        etag: header(ETAG),
+    }
+}
@@ -0,0 +1,278 @@
+//! Evict files from a download folder.
+//!
+//! The files are visited from the least recently used to the most recently
+//! used. A file is evicted if it was not used within the age limit, or if the
+//! folder still exceeds its size budget. Only the files recorded in the
+//! manifest are evicted, along with the files derived from them.
+use crate::manifest::Manifest;
+use crate::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Describe the rules used to evict files from a download folder.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EvictionPolicy {
+    /// Evict the files which were not accessed for longer than this duration.
+    pub older_than: Option<Duration>,
+    /// Maximum size of the folder, in bytes.
+    pub max_size: Option<u64>,
+}
+
+/// Describe a file candidate for eviction.
+#[derive(Debug, Clone)]
+struct Entry {
+    path: PathBuf,
+    size: u64,
+    last_used: SystemTime,
+}
+
+/// Summarize an eviction.
+#[derive(Debug, Default, Clone)]
+pub struct Eviction {
+    /// Files which were removed.
+    pub removed: Vec<PathBuf>,
+    /// Number of bytes reclaimed.
+    pub reclaimed: u64,
+    /// Size of the folder after the eviction, in bytes.
+    pub remaining: u64,
+}
+
+impl EvictionPolicy {
+    /// Apply the policy to the files recorded in the manifest of `folder`.
+    ///
+    /// A file is evicted along with its [artifacts](Manifest::artifacts), and
+    /// its entry is removed from the manifest. The other files of the folder,
+    /// e.g. the manifest itself or the `SHA256SUMS` file, are never evicted,
+    /// but count towards its size. The last access time is used to determine
+    /// how recently a file was used, falling back to the last modification
+    /// time on platforms where it is not available.
+    ///
+    /// When `dry_run` is set, the files are reported but not removed.
+    pub fn apply<P>(&self, folder: P, dry_run: bool) -> Result<Eviction, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let folder = folder.as_ref();
+        let mut manifest = Manifest::load(folder)?;
+        let mut entries = Vec::new();
+        for file in &manifest.files {
+            let Some(metadata) = fs::metadata(folder.join(&file.path))
+                .ok()
+                .filter(|m| m.is_file())
+            else {
+                continue;
+            };
+            let mut size = 0;
+            for artifact in manifest.artifacts(&file.path) {
+                size += disk_usage(&folder.join(artifact))?;
+            }
+            entries.push(Entry {
+                path: file.path.clone(),
+                size,
+                last_used: metadata.accessed().or_else(|_| metadata.modified())?,
+            });
+        }
+
+        // Sort the entries from the least recently used to the most recently used.
+        entries.sort_by_key(|e| e.last_used);
+
+        let now = SystemTime::now();
+        let mut remaining = disk_usage(folder)?;
+        let mut evicted = Vec::new();
+        let mut eviction = Eviction::default();
+        for entry in entries {
+            let expired = self
+                .older_than
+                .is_some_and(|age| now.duration_since(entry.last_used).unwrap_or_default() > age);
+            let over_budget = self.max_size.is_some_and(|max| remaining > max);
+            if !expired && !over_budget {
+                continue;
+            }
+            remaining = remaining.saturating_sub(entry.size);
+            eviction.reclaimed += entry.size;
+            eviction.removed.push(folder.join(&entry.path));
+            evicted.push(entry.path);
+        }
+        eviction.remaining = remaining;
+        if !dry_run && !evicted.is_empty() {
+            let result = manifest.remove(folder, &evicted);
+            // Record the files removed before a failure.
+            manifest.save(folder)?;
+            result?;
+        }
+
+        Ok(eviction)
+    }
+}
+
+/// Return the size of the file or the folder tree located at `path`, in
+/// bytes, or 0 if it does not exist.
+///
+/// The symbolic links are not followed.
+fn disk_usage(path: &Path) -> Result<u64, Error> {
+    let metadata = match fs::symlink_metadata(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        metadata => metadata?,
+    };
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        size += disk_usage(&entry?.path())?;
+    }
+
+    Ok(size)
+}
+
+/// Parse a human readable duration, like `90d` or `12h`.
+///
+/// The supported units are `s`, `m`, `h`, `d` and `w`.
+pub fn parse_duration(value: &str) -> Result<Duration, Error> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| Error::Parse(format!("invalid duration {:?}", value)))?;
+    let seconds = match unit.trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" | "" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => {
+            return Err(Error::Parse(format!(
+                "invalid duration unit in {:?}",
+                value
+            )))
+        }
+    };
+
+    amount
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(|| Error::Parse(format!("duration {:?} out of range", value)))
+}
+
+/// Parse a human readable size, like `200GB` or `512MiB`.
+///
+/// Both the decimal (`KB`, `MB`, `GB`, `TB`) and the binary (`KiB`, `MiB`,
+/// `GiB`, `TiB`) units are supported. A value without unit is in bytes.
+pub fn parse_size(value: &str) -> Result<u64, Error> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: f64 = amount
+        .parse()
+        .map_err(|_| Error::Parse(format!("invalid size {:?}", value)))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1_000,
+        "m" | "mb" => 1_000_000,
+        "g" | "gb" => 1_000_000_000,
+        "t" | "tb" => 1_000_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        _ => return Err(Error::Parse(format!("invalid size unit in {:?}", value))),
+    };
+
+    Ok((amount * multiplier as f64) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum::{self, SHA256SUMS};
+    use crate::manifest::{ManifestEntry, MANIFEST};
+    use std::{env, process};
+
+    #[test]
+    fn parse_durations() {
+        assert_eq!(
+            parse_duration("90").unwrap(),
+            Duration::from_secs(90 * 86_400)
+        );
+        assert_eq!(
+            parse_duration("12h").unwrap(),
+            Duration::from_secs(12 * 3_600)
+        );
+        assert_eq!(
+            parse_duration(" 2 w ").unwrap(),
+            Duration::from_secs(14 * 86_400)
+        );
+        for value in ["", "d", "-1d", "1y", "1.5d", "99999999999999999d"] {
+            assert!(
+                matches!(parse_duration(value), Err(Error::Parse(_))),
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn parse_sizes() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("1.5KB").unwrap(), 1_500);
+        assert_eq!(parse_size("2 MiB").unwrap(), 2 << 20);
+        for value in ["", "GB", "1PB"] {
+            assert!(
+                matches!(parse_size(value), Err(Error::Parse(_))),
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn evict_recorded_files() {
+        let folder = env::temp_dir().join(format!("retrieve-evict-{}", process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(folder.join("a")).unwrap();
+        let mut manifest = Manifest::default();
+        for path in ["a.zip", "b.zip"] {
+            let path = PathBuf::from(path);
+            fs::write(folder.join(&path), "data").unwrap();
+            fs::write(folder.join(checksum::sidecar_path(&path)), "hash").unwrap();
+            manifest.upsert(ManifestEntry {
+                path,
+                size: 4,
+                ..Default::default()
+            });
+        }
+        fs::write(folder.join("a").join("ways.shp"), "data").unwrap();
+        manifest.files[0].extracted = Some(PathBuf::from("a"));
+        manifest.save(&folder).unwrap();
+        fs::write(folder.join(SHA256SUMS), "sums").unwrap();
+        let kept = fs::metadata(folder.join(MANIFEST)).unwrap().len() + 4;
+        let policy = EvictionPolicy {
+            older_than: None,
+            max_size: Some(0),
+        };
+
+        let eviction = policy.apply(&folder, true).unwrap();
+        assert_eq!(eviction.removed.len(), 2);
+        assert_eq!(eviction.reclaimed, 20);
+        assert_eq!(eviction.remaining, kept);
+        assert!(folder.join("a.zip").is_file());
+
+        let eviction = policy.apply(&folder, false).unwrap();
+        assert_eq!(eviction.reclaimed, 20);
+        for path in ["a.zip", "a.zip.sha256", "a", "b.zip", "b.zip.sha256"] {
+            assert!(!folder.join(path).exists(), "{}", path);
+        }
+        assert!(folder.join(MANIFEST).is_file());
+        assert!(folder.join(SHA256SUMS).is_file());
+        assert!(Manifest::load(&folder).unwrap().files.is_empty());
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,76 @@
+//! Export a plan as the input file of another download tool.
+//!
+//! Some environments only allow vetted download tools: the planned downloads
+//! can be performed by [aria2](https://aria2.github.io/) or curl instead.
+//!
+//! - aria2: `aria2c --input-file plan.txt`
+//! - curl: `curl --parallel --config plan.txt`
+use crate::plan::{Plan, PlannedDownload};
+use crate::Error;
+use std::io::Write;
+use std::path::Path;
+
+/// Describe the formats a plan can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Input file of `aria2c --input-file`.
+    Aria2,
+    /// Configuration file of `curl --config`.
+    CurlConfig,
+}
+
+/// Write the downloads of the `plan` in the `format`, saving the files into
+/// `folder`.
+///
+/// The `checksum` function returns the expected SHA256 hash of a download, if
+/// known. aria2 verifies it, whereas curl does not support checksums, so they
+/// are written as comments.
+pub fn export_plan<W, F>(
+    plan: &Plan,
+    format: ExportFormat,
+    folder: &Path,
+    checksum: F,
+    mut writer: W,
+) -> Result<(), Error>
+where
+    W: Write,
+    F: Fn(&PlannedDownload) -> Option<String>,
+{
+    if format == ExportFormat::CurlConfig {
+        writeln!(writer, "create-dirs")?;
+        writeln!(writer, "fail")?;
+        writeln!(writer)?;
+    }
+    for download in &plan.downloads {
+        let output = folder.join(&download.file_name);
+        let checksum = checksum(download);
+        match format {
+            ExportFormat::Aria2 => {
+                writeln!(writer, "{}", download.url)?;
+                writeln!(writer, "  out={}", output.display())?;
+                if let Some(checksum) = checksum {
+                    writeln!(writer, "  checksum=sha-256={}", checksum)?;
+                }
+            }
+            ExportFormat::CurlConfig => {
+                writeln!(writer, "# {} for {}", download.dataset, download.city)?;
+                if let Some(checksum) = checksum {
+                    writeln!(writer, "# sha256 {}", checksum)?;
+                }
+                writeln!(writer, "url = {}", curl_quote(download.url.as_str()))?;
+                writeln!(
+                    writer,
+                    "output = {}",
+                    curl_quote(&output.display().to_string())
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Quote a value of a curl configuration file.
+fn curl_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
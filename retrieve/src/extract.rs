@@ -0,0 +1,209 @@
+//! Extract the downloaded archives.
+//!
+//! The members to extract can be selected with glob patterns, refer to
+//! [`MemberFilter`]. The selected members of a remote archive can also be
+//! retrieved on their own, without downloading the whole archive.
+use crate::remote::{RemoteFile, ZipEntry};
+use crate::Error;
+use flate2::read::DeflateDecoder;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use zip::ZipArchive;
+
+/// Compression method of the stored members.
+const METHOD_STORED: u16 = 0;
+
+/// Compression method of the deflated members.
+const METHOD_DEFLATED: u16 = 8;
+
+/// Maximum number of bytes allocated upfront for a member, whatever the size
+/// announced by the archive.
+const MAX_PREALLOCATION: u64 = 64 * 1024 * 1024;
+
+/// Select the members of an archive with glob patterns, e.g. `*.shp`.
+///
+/// The patterns support the `*` and `?` wildcards, and are matched against
+/// the file names of the members, ignoring the case, unless they contain a
+/// `/`, in which case they are matched against the whole path. An empty
+/// filter selects all the members.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MemberFilter {
+    patterns: Vec<String>,
+}
+
+impl MemberFilter {
+    /// Return `true` if the member named `name` is selected.
+    pub fn matches(&self, name: &str) -> bool {
+        if self.patterns.is_empty() {
+            return true;
+        }
+        let name = name.to_lowercase();
+        let file_name = name.rsplit('/').next().unwrap_or_default();
+        self.patterns.iter().any(|pattern| {
+            let text = if pattern.contains('/') {
+                name.as_str()
+            } else {
+                file_name
+            };
+            glob_match(pattern.as_bytes(), text.as_bytes())
+        })
+    }
+}
+
+/// Parse a comma separated list of glob patterns, e.g. `*.shp,*.dbf`.
+impl FromStr for MemberFilter {
+    type Err = Error;
+
+    fn from_str(patterns: &str) -> Result<Self, Self::Err> {
+        let patterns: Vec<String> = patterns
+            .split(',')
+            .map(|p| p.trim().to_lowercase())
+            .filter(|p| !p.is_empty())
+            .collect();
+        if patterns.is_empty() {
+            return Err(Error::Parse(String::from("empty list of patterns")));
+        }
+
+        Ok(MemberFilter { patterns })
+    }
+}
+
+/// Return `true` if `text` matches the glob `pattern`.
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match (pattern.first(), text.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob_match(&pattern[1..], text) || (!text.is_empty() && glob_match(pattern, &text[1..]))
+        }
+        (Some(b'?'), Some(_)) => glob_match(&pattern[1..], &text[1..]),
+        (Some(p), Some(t)) if p == t => glob_match(&pattern[1..], &text[1..]),
+        _ => false,
+    }
+}
+
+/// Return the folder where the archive located at `path` is extracted.
+///
+/// The archive is extracted next to itself, in a folder named after it without
+/// its extension, e.g. `usa-colorado-boulder-neighborhood_ways/`.
+pub fn extraction_folder(path: &Path) -> PathBuf {
+    path.with_extension("")
+}
+
+/// Extract the zip archive located at `path` into `destination`.
+///
+/// Return the paths of the extracted files. The entries which would be written
+/// outside of `destination` are rejected.
+pub fn extract_zip<P, Q>(path: P, destination: Q) -> Result<Vec<PathBuf>, Error>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    extract_zip_filtered(path, destination, &MemberFilter::default())
+}
+
+/// Extract the members of the zip archive located at `path` selected by the
+/// `filter` into `destination`.
+///
+/// Refer to [`extract_zip`] for details.
+pub fn extract_zip_filtered<P, Q>(
+    path: P,
+    destination: Q,
+    filter: &MemberFilter,
+) -> Result<Vec<PathBuf>, Error>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let destination = destination.as_ref();
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let mut extracted = Vec::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let name = entry.enclosed_name().ok_or_else(|| {
+            Error::Parse(format!(
+                "invalid entry {:?} in {}",
+                entry.name(),
+                path.display()
+            ))
+        })?;
+        let target = destination.join(name);
+        if entry.is_dir() {
+            fs::create_dir_all(&target)?;
+            continue;
+        }
+        if !filter.matches(entry.name()) {
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        io::copy(&mut entry, &mut File::create(&target)?)?;
+        extracted.push(target);
+    }
+
+    Ok(extracted)
+}
+
+/// Retrieve the members of a remote zip archive selected by the `filter`,
+/// and write them into `destination`, without downloading the whole archive.
+///
+/// Return the paths of the extracted files. Only the stored and deflated
+/// members are supported, and their size and CRC-32 checksum are checked
+/// against the central directory.
+pub async fn extract_remote<P>(
+    file: &RemoteFile,
+    filter: &MemberFilter,
+    destination: P,
+) -> Result<Vec<PathBuf>, Error>
+where
+    P: AsRef<Path>,
+{
+    let destination = destination.as_ref();
+    let mut extracted = Vec::new();
+    let entries: Vec<ZipEntry> = file
+        .zip_entries()
+        .await?
+        .into_iter()
+        .filter(|e| !e.name.ends_with('/') && filter.matches(&e.name))
+        .collect();
+    for entry in &entries {
+        let name = Path::new(&entry.name);
+        if !name.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(Error::Parse(format!("invalid entry {:?}", entry.name)));
+        }
+        let data = file.zip_member(entry).await?;
+        let data = match entry.method {
+            METHOD_STORED => data,
+            METHOD_DEFLATED => {
+                let mut inflated = Vec::with_capacity(entry.size.min(MAX_PREALLOCATION) as usize);
+                DeflateDecoder::new(data.as_slice())
+                    .take(entry.size.saturating_add(1))
+                    .read_to_end(&mut inflated)?;
+                inflated
+            }
+            method => {
+                return Err(Error::Parse(format!(
+                    "unsupported compression method {} for {:?}",
+                    method, entry.name
+                )))
+            }
+        };
+        if data.len() as u64 != entry.size || crc32fast::hash(&data) != entry.crc32 {
+            return Err(Error::Parse(format!(
+                "corrupted member {:?}, its size or CRC-32 checksum does not match",
+                entry.name
+            )));
+        }
+        let target = destination.join(name);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&target, data)?;
+        extracted.push(target);
+    }
+
+    Ok(extracted)
+}
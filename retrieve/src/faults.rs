@@ -0,0 +1,92 @@
+//! Inject synthetic download failures.
+//!
+//! The fault injection tests how the consumers of retrieve cope with partial
+//! failures, without depending on the availability of the PFB storage. The
+//! failing downloads are chosen at random, but the same seed always fails the
+//! same downloads. They are reported as [`DownloadStatus::Failed`] with the
+//! [`INJECTED_FAILURE`] reason, and flagged as injected in their outcome.
+//!
+//! [`DownloadStatus::Failed`]: crate::download::DownloadStatus::Failed
+use crate::cities::splitmix64;
+use crate::plan::PlannedDownload;
+use crate::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// Reason of the injected failures.
+pub const INJECTED_FAILURE: &str = "synthetic failure (fault injection)";
+
+/// Describe the fraction of the downloads which must fail.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FaultInjection {
+    /// Probability of a download to fail, between 0 and 1.
+    pub probability: f64,
+    /// Seed choosing the failing downloads.
+    pub seed: u64,
+}
+
+impl FaultInjection {
+    /// Create a fault injection failing the downloads with `probability`.
+    pub fn new(probability: f64, seed: u64) -> Result<Self, Error> {
+        if !(0.0..=1.0).contains(&probability) {
+            return Err(Error::Parse(format!(
+                "invalid failure probability {}, expected a value between 0 and 1",
+                probability
+            )));
+        }
+
+        Ok(FaultInjection { probability, seed })
+    }
+
+    /// Return `true` if the download must fail.
+    ///
+    /// The decision only depends on the seed and on the URL of the download.
+    pub fn fails(&self, planned: &PlannedDownload) -> bool {
+        let mut state = planned.url.as_str().bytes().fold(self.seed, |hash, b| {
+            (hash ^ b as u64).wrapping_mul(FNV_PRIME)
+        });
+        let draw = (splitmix64(&mut state) >> 11) as f64 / (1u64 << 53) as f64;
+        draw < self.probability
+    }
+}
+
+/// Prime of the 64-bit FNV hash.
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Parse a fault injection specified as `p=PROBABILITY[,seed=SEED]`.
+impl FromStr for FaultInjection {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut probability = None;
+        let mut seed = 0;
+        for setting in value.split(',') {
+            let invalid = || {
+                Error::Parse(format!(
+                    "invalid fault injection {:?}, expected `p=PROBABILITY[,seed=SEED]`",
+                    value
+                ))
+            };
+            let (key, setting) = setting.split_once('=').ok_or_else(invalid)?;
+            match key.trim() {
+                "p" => probability = Some(setting.trim().parse().map_err(|_| invalid())?),
+                "seed" => seed = setting.trim().parse().map_err(|_| invalid())?,
+                _ => return Err(invalid()),
+            }
+        }
+        let probability = probability.ok_or_else(|| {
+            Error::Parse(format!(
+                "missing failure probability in fault injection {:?}",
+                value
+            ))
+        })?;
+
+        FaultInjection::new(probability, seed)
+    }
+}
+
+impl fmt::Display for FaultInjection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "p={},seed={}", self.probability, self.seed)
+    }
+}
@@ -0,0 +1,426 @@
+//! Select the cities with filter expressions.
+//!
+//! An expression compares the fields of a city with values, refer to
+//! [`City::field`], e.g. `country == "USA" && state != TX`. The comparisons
+//! can be combined with `&&`, `||`, `!` and parentheses. The supported
+//! operators are `==` (or `=`), `!=`, `<`, `<=`, `>` and `>=`.
+//!
+//! The values are quoted with double or single quotes when they contain
+//! spaces or operators. Two numbers are compared numerically, and the other
+//! values alphabetically, ignoring the case. A missing field is an empty
+//! string.
+use crate::collation;
+use crate::Error;
+use pfbcore::scorecard::City;
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// Represent a comparison operator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    /// `==` or `=`.
+    Eq,
+    /// `!=`.
+    Ne,
+    /// `<`.
+    Lt,
+    /// `<=`.
+    Le,
+    /// `>`.
+    Gt,
+    /// `>=`.
+    Ge,
+}
+
+impl Operator {
+    /// Return `true` if the ordering satisfies the operator.
+    fn accepts(self, ordering: Ordering) -> bool {
+        match self {
+            Operator::Eq => ordering == Ordering::Equal,
+            Operator::Ne => ordering != Ordering::Equal,
+            Operator::Lt => ordering == Ordering::Less,
+            Operator::Le => ordering != Ordering::Greater,
+            Operator::Gt => ordering == Ordering::Greater,
+            Operator::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+/// Represent a filter expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    /// Compare a field with a value.
+    Compare {
+        /// Name of the field.
+        field: String,
+        /// Comparison operator.
+        operator: Operator,
+        /// Value to compare with.
+        value: String,
+    },
+    /// Both expressions must match.
+    And(Box<Filter>, Box<Filter>),
+    /// One of the expressions must match.
+    Or(Box<Filter>, Box<Filter>),
+    /// The expression must not match.
+    Not(Box<Filter>),
+}
+
+impl Filter {
+    /// Return `true` if the city matches the expression.
+    pub fn matches(&self, city: &City) -> bool {
+        match self {
+            Filter::Compare {
+                field,
+                operator,
+                value,
+            } => {
+                let actual = city.field(field).unwrap_or_default();
+                operator.accepts(compare(&actual, value))
+            }
+            Filter::And(left, right) => left.matches(city) && right.matches(city),
+            Filter::Or(left, right) => left.matches(city) || right.matches(city),
+            Filter::Not(filter) => !filter.matches(city),
+        }
+    }
+}
+
+/// Compare two values numerically if they are both numbers, and
+/// alphabetically otherwise, ignoring the case and the diacritics.
+fn compare(a: &str, b: &str) -> Ordering {
+    match (a.trim().parse::<f64>(), b.trim().parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        _ => collation::fold(a).cmp(&collation::fold(b)),
+    }
+}
+
+impl FromStr for Filter {
+    type Err = Error;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(expression)?;
+        let mut parser = Parser {
+            expression,
+            tokens: &tokens,
+            position: 0,
+        };
+        let filter = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(parser.error(&format!("unexpected {}", token)));
+        }
+
+        Ok(filter)
+    }
+}
+
+/// Represent a token of an expression.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Field name or unquoted value.
+    Word(String),
+    /// Quoted value.
+    Quoted(String),
+    /// Comparison operator.
+    Operator(Operator),
+    /// `&&`.
+    And,
+    /// `||`.
+    Or,
+    /// `!`.
+    Not,
+    /// `(`.
+    Open,
+    /// `)`.
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{:?}", word),
+            Token::Quoted(value) => write!(f, "{:?}", value),
+            Token::Operator(operator) => write!(f, "operator {:?}", operator),
+            Token::And => write!(f, "`&&`"),
+            Token::Or => write!(f, "`||`"),
+            Token::Not => write!(f, "`!`"),
+            Token::Open => write!(f, "`(`"),
+            Token::Close => write!(f, "`)`"),
+        }
+    }
+}
+
+/// Split an expression into tokens.
+fn tokenize(expression: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '&' if chars.next_if_eq(&'&').is_some() => Token::And,
+            '|' if chars.next_if_eq(&'|').is_some() => Token::Or,
+            '!' if chars.next_if_eq(&'=').is_some() => Token::Operator(Operator::Ne),
+            '!' => Token::Not,
+            '=' => {
+                chars.next_if_eq(&'=');
+                Token::Operator(Operator::Eq)
+            }
+            '<' if chars.next_if_eq(&'=').is_some() => Token::Operator(Operator::Le),
+            '<' => Token::Operator(Operator::Lt),
+            '>' if chars.next_if_eq(&'=').is_some() => Token::Operator(Operator::Ge),
+            '>' => Token::Operator(Operator::Gt),
+            '"' | '\'' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\\') => value.extend(chars.next()),
+                        Some(q) if q == c => break,
+                        Some(other) => value.push(other),
+                        None => {
+                            return Err(Error::Parse(format!(
+                                "unclosed quote in filter {:?}",
+                                expression
+                            )))
+                        }
+                    }
+                }
+                Token::Quoted(value)
+            }
+            '&' | '|' => {
+                return Err(Error::Parse(format!(
+                    "invalid operator {:?} in filter {:?}, expected `{}{}`",
+                    c, expression, c, c
+                )))
+            }
+            c => {
+                let mut word = String::from(c);
+                while let Some(next) =
+                    chars.next_if(|n| !n.is_whitespace() && !"()!=<>&|\"'".contains(*n))
+                {
+                    word.push(next);
+                }
+                Token::Word(word)
+            }
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+/// Parse the tokens of an expression by recursive descent.
+struct Parser<'a> {
+    expression: &'a str,
+    tokens: &'a [Token],
+    position: usize,
+}
+
+impl Parser<'_> {
+    /// Return the next token without consuming it.
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    /// Consume the next token if it is `token`.
+    fn eat(&mut self, token: &Token) -> bool {
+        let matches = self.peek() == Some(token);
+        if matches {
+            self.position += 1;
+        }
+        matches
+    }
+
+    /// Consume the next token.
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.position += 1;
+        token
+    }
+
+    /// Return a parse error about the expression.
+    fn error(&self, message: &str) -> Error {
+        Error::Parse(format!("{} in filter {:?}", message, self.expression))
+    }
+
+    /// Parse `and ("||" and)*`.
+    fn or(&mut self) -> Result<Filter, Error> {
+        let mut filter = self.and()?;
+        while self.eat(&Token::Or) {
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+
+        Ok(filter)
+    }
+
+    /// Parse `not ("&&" not)*`.
+    fn and(&mut self) -> Result<Filter, Error> {
+        let mut filter = self.not()?;
+        while self.eat(&Token::And) {
+            filter = Filter::And(Box::new(filter), Box::new(self.not()?));
+        }
+
+        Ok(filter)
+    }
+
+    /// Parse `"!" not | "(" or ")" | comparison`.
+    fn not(&mut self) -> Result<Filter, Error> {
+        if self.eat(&Token::Not) {
+            return Ok(Filter::Not(Box::new(self.not()?)));
+        }
+        if self.eat(&Token::Open) {
+            let filter = self.or()?;
+            if !self.eat(&Token::Close) {
+                return Err(self.error("missing `)`"));
+            }
+            return Ok(filter);
+        }
+
+        self.comparison()
+    }
+
+    /// Parse `field operator value`.
+    fn comparison(&mut self) -> Result<Filter, Error> {
+        let field = match self.next() {
+            Some(Token::Word(field)) | Some(Token::Quoted(field)) => field,
+            Some(token) => return Err(self.error(&format!("expected a field, found {}", token))),
+            None => return Err(self.error("expected a field")),
+        };
+        let operator = match self.next() {
+            Some(Token::Operator(operator)) => operator,
+            _ => return Err(self.error(&format!("expected an operator after {:?}", field))),
+        };
+        let value = match self.next() {
+            Some(Token::Word(value)) | Some(Token::Quoted(value)) => value,
+            // `FIELD=` compares the field with an empty value.
+            None | Some(Token::And) | Some(Token::Or) | Some(Token::Close) => {
+                self.position -= 1;
+                String::new()
+            }
+            Some(token) => return Err(self.error(&format!("expected a value, found {}", token))),
+        };
+
+        Ok(Filter::Compare {
+            field,
+            operator,
+            value,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compare(field: &str, operator: Operator, value: &str) -> Filter {
+        Filter::Compare {
+            field: field.to_string(),
+            operator,
+            value: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn parse_comparisons() {
+        for (expression, operator) in [
+            ("state == TX", Operator::Eq),
+            ("state = TX", Operator::Eq),
+            ("state != TX", Operator::Ne),
+            ("state < TX", Operator::Lt),
+            ("state <= TX", Operator::Le),
+            ("state > TX", Operator::Gt),
+            ("state >= TX", Operator::Ge),
+            ("state>=TX", Operator::Ge),
+        ] {
+            let filter: Filter = expression.parse().unwrap();
+            assert_eq!(filter, compare("state", operator, "TX"), "{}", expression);
+        }
+    }
+
+    #[test]
+    fn parse_quoted_values() {
+        let filter: Filter = r#"City == "Fort Collins""#.parse().unwrap();
+        assert_eq!(filter, compare("City", Operator::Eq, "Fort Collins"));
+        let filter: Filter = r"City == 'O\'Fallon'".parse().unwrap();
+        assert_eq!(filter, compare("City", Operator::Eq, "O'Fallon"));
+        let filter: Filter = "name == 'a && b'".parse().unwrap();
+        assert_eq!(filter, compare("name", Operator::Eq, "a && b"));
+    }
+
+    #[test]
+    fn parse_empty_value() {
+        let filter: Filter = "date=".parse().unwrap();
+        assert_eq!(filter, compare("date", Operator::Eq, ""));
+        let filter: Filter = "(date=) && state == TX".parse().unwrap();
+        assert_eq!(
+            filter,
+            Filter::And(
+                Box::new(compare("date", Operator::Eq, "")),
+                Box::new(compare("state", Operator::Eq, "TX"))
+            )
+        );
+    }
+
+    #[test]
+    fn parse_precedence() {
+        let filter: Filter = "a == 1 || b == 2 && !c == 3".parse().unwrap();
+        assert_eq!(
+            filter,
+            Filter::Or(
+                Box::new(compare("a", Operator::Eq, "1")),
+                Box::new(Filter::And(
+                    Box::new(compare("b", Operator::Eq, "2")),
+                    Box::new(Filter::Not(Box::new(compare("c", Operator::Eq, "3"))))
+                ))
+            )
+        );
+        let filter: Filter = "(a == 1 || b == 2) && c == 3".parse().unwrap();
+        assert_eq!(
+            filter,
+            Filter::And(
+                Box::new(Filter::Or(
+                    Box::new(compare("a", Operator::Eq, "1")),
+                    Box::new(compare("b", Operator::Eq, "2"))
+                )),
+                Box::new(compare("c", Operator::Eq, "3"))
+            )
+        );
+    }
+
+    #[test]
+    fn parse_invalid_expressions() {
+        for expression in [
+            "",
+            "state",
+            "state TX",
+            "state == 'TX",
+            "state == TX & country == USA",
+            "state == TX | country == USA",
+            "(state == TX",
+            "state == TX)",
+            "state == TX country == USA",
+            "== TX",
+            "state == <",
+        ] {
+            assert!(
+                matches!(expression.parse::<Filter>(), Err(Error::Parse(_))),
+                "{}",
+                expression
+            );
+        }
+    }
+
+    #[test]
+    fn match_cities() {
+        let city = City::new("Fort Collins", "USA", Some("CO"), "", 170_000, 55.6, 56);
+        let matches = |expression: &str| expression.parse::<Filter>().unwrap().matches(&city);
+        assert!(matches("country == usa && state != TX"));
+        assert!(matches("population > 99999"));
+        assert!(!matches("population < 99999"));
+        assert!(matches("ratings >= 55.6"));
+        assert!(matches("City == 'fort collins'"));
+        assert!(matches("date == ''"));
+        assert!(!matches("!(state == CO)"));
+    }
+}
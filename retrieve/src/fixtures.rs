@@ -0,0 +1,125 @@
+//! Record the HTTP responses, and replay them without network access.
+//!
+//! The fixtures make the runs of retrieve deterministic, e.g. in the
+//! integration tests of the tools consuming the datasets. Each response is
+//! stored in the fixtures folder under the SHA256 hash of the URL it was
+//! requested from: the body in a `.body` file, and the status code and the
+//! hash of the body in a `.json` file. The URLs are recorded before they are
+//! presigned, so that the fixtures do not depend on the credentials.
+//!
+//! A response is replayed if it was recorded, and requested and recorded
+//! otherwise, unless the fixtures are replayed only.
+use crate::cache::sha256;
+use crate::Error;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// Describe a recorded response.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fixture {
+    /// URL the response was received from.
+    pub url: String,
+    /// HTTP status code.
+    pub status: u16,
+    /// SHA256 hash of the body. `None` if the request was unsuccessful.
+    pub sha256: Option<String>,
+}
+
+/// Represent a folder of recorded responses.
+#[derive(Debug, Clone)]
+pub struct Fixtures {
+    root: PathBuf,
+    replay_only: bool,
+}
+
+impl Fixtures {
+    /// Open the fixtures located in `root`, creating the folder if needed.
+    ///
+    /// If `replay_only` is set, the responses which were not recorded are
+    /// not requested, and the downloads fail instead.
+    pub fn open<P>(root: P, replay_only: bool) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let root = root.as_ref().to_path_buf();
+        fs::create_dir_all(&root)?;
+
+        Ok(Fixtures { root, replay_only })
+    }
+
+    /// Return `true` if the responses which were not recorded must not be
+    /// requested.
+    pub fn replay_only(&self) -> bool {
+        self.replay_only
+    }
+
+    /// Return the response recorded for `url`, if any.
+    pub fn lookup(&self, url: &Url) -> Result<Option<Fixture>, Error> {
+        let path = self.path(url, "json");
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let fixture: Fixture = serde_json::from_slice(&fs::read(path)?)?;
+        if fixture.sha256.is_some() && !self.path(url, "body").is_file() {
+            return Ok(None);
+        }
+
+        Ok(Some(fixture))
+    }
+
+    /// Copy the body of the response recorded for `url` to `destination`.
+    pub fn replay<P>(&self, url: &Url, destination: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        fs::copy(self.path(url, "body"), destination)?;
+
+        Ok(())
+    }
+
+    /// Record an unsuccessful response.
+    pub fn record_status(&self, url: &Url, status: u16) -> Result<(), Error> {
+        self.write(
+            url,
+            &Fixture {
+                url: url.to_string(),
+                status,
+                sha256: None,
+            },
+        )
+    }
+
+    /// Record a successful response, whose body was saved at `path`.
+    pub fn record_body<P>(&self, url: &Url, status: u16, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        fs::copy(path, self.path(url, "body"))?;
+        let fixture = Fixture {
+            url: url.to_string(),
+            status,
+            sha256: Some(sha256(path)?),
+        };
+        self.write(url, &fixture)
+    }
+
+    /// Write the description of a response atomically.
+    fn write(&self, url: &Url, fixture: &Fixture) -> Result<(), Error> {
+        let path = self.path(url, "json");
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(fixture)?)?;
+        fs::rename(&tmp, &path)?;
+
+        Ok(())
+    }
+
+    /// Return the path of the file recording the response for `url`.
+    fn path(&self, url: &Url, extension: &str) -> PathBuf {
+        let key = format!("{:x}", Sha256::digest(url.as_str().as_bytes()));
+        self.root.join(key).with_extension(extension)
+    }
+}
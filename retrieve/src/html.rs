@@ -0,0 +1,222 @@
+//! Summarize a batch of downloads as an HTML report.
+//!
+//! The report is a single self-contained page, with inline styles and no
+//! script, so it can be attached to an email or archived as is. It lists the
+//! suspicious size changes since the previous run, the retrieved cities with
+//! the size of their files, the failures, and the scores of the cities along
+//! with the share of low stress segments when the statistics of the networks
+//! were computed.
+use crate::anomaly::SizeAnomaly;
+use crate::download::{DownloadOutcome, DownloadReport, DownloadStatus};
+use crate::manifest::Manifest;
+use crate::timestamp;
+use crate::Error;
+use indicatif::HumanBytes;
+use std::fmt::Write as _;
+use std::io::Write;
+
+/// Style sheet of the report.
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin-bottom:2em}\
+th,td{border:1px solid #ccc;padding:4px 8px;text-align:left}\
+th{background:#f0f0f0}td.number{text-align:right}\
+.ok{color:#1a7f37}.anomaly{color:#cf222e;font-weight:bold}.failed{color:#cf222e}.interrupted{color:#9a6700}";
+
+/// Escape the special characters of a text for HTML.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Write the HTML report of a batch of downloads.
+///
+/// The sizes, scores and statistics are taken from the `manifest` of the
+/// download folder. The links to the files are the paths recorded in the
+/// manifest, prefixed with `link_prefix`, e.g. an empty string when the report
+/// is stored in the download folder.
+pub fn write_html_report<W>(
+    report: &DownloadReport,
+    anomalies: &[SizeAnomaly],
+    manifest: &Manifest,
+    link_prefix: &str,
+    mut writer: W,
+) -> Result<(), Error>
+where
+    W: Write,
+{
+    let mut html = String::new();
+    let retrieved = report
+        .outcomes
+        .iter()
+        .filter(|o| {
+            matches!(
+                o.status,
+                DownloadStatus::Downloaded | DownloadStatus::Cached
+            )
+        })
+        .count();
+    let failures: Vec<&DownloadOutcome> = report.failures().collect();
+    let generated_at = timestamp::rfc3339(timestamp::now());
+
+    // Header and totals.
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>retrieve report</title>\n<style>{}</style>\n</head>\n<body>\n\
+         <h1>Data refresh report</h1>\n\
+         <p>Generated at {} by {} {}.</p>\n\
+         <ul>\n<li>{} files retrieved, {} failed</li>\n\
+         <li>{} downloaded in {:.1}s</li>\n</ul>\n",
+        STYLE,
+        escape(&generated_at),
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        retrieved,
+        failures.len(),
+        HumanBytes(report.total_bytes()),
+        report.elapsed.as_secs_f64()
+    );
+
+    // Suspicious size changes, first so that they are not overlooked.
+    if !anomalies.is_empty() {
+        html.push_str(
+            "<h2 class=\"anomaly\">Suspicious size changes</h2>\n<table>\n<tr><th>City</th>\
+             <th>Dataset</th><th>Previous size</th><th>Size</th><th>Change</th></tr>\n",
+        );
+        for anomaly in anomalies {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td class=\"number\">{}</td>\
+                 <td class=\"number\">{}</td><td class=\"number anomaly\">{:+.0}%</td></tr>",
+                escape(&anomaly.city),
+                escape(&anomaly.dataset),
+                HumanBytes(anomaly.previous_size),
+                HumanBytes(anomaly.size),
+                anomaly.change() * 100.0
+            );
+        }
+        html.push_str("</table>\n");
+    }
+
+    // Files.
+    html.push_str(
+        "<h2>Files</h2>\n<table>\n<tr><th>City</th><th>Dataset</th><th>Status</th>\
+         <th>Size</th><th>File</th></tr>\n",
+    );
+    for outcome in &report.outcomes {
+        let download = &outcome.download;
+        let (label, class) = match &outcome.status {
+            DownloadStatus::Downloaded => ("downloaded", "ok"),
+            DownloadStatus::Cached => ("cached", "ok"),
+            DownloadStatus::Failed(_) => ("failed", "failed"),
+            DownloadStatus::Interrupted => ("interrupted", "interrupted"),
+            DownloadStatus::TimedOut => ("timed out", "interrupted"),
+        };
+        let (size, link) = match manifest.get(&download.file_name) {
+            Some(entry) if class == "ok" => {
+                let path = entry.path.to_string_lossy();
+                (
+                    HumanBytes(entry.size).to_string(),
+                    format!(
+                        "<a href=\"{}{}\">{}</a>",
+                        escape(link_prefix),
+                        escape(&path),
+                        escape(&path)
+                    ),
+                )
+            }
+            _ => (String::new(), String::new()),
+        };
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td class=\"{}\">{}</td>\
+             <td class=\"number\">{}</td><td>{}</td></tr>",
+            escape(&download.city.to_string()),
+            download.dataset,
+            class,
+            label,
+            size,
+            link
+        );
+    }
+    html.push_str("</table>\n");
+
+    // Failures.
+    if !failures.is_empty() {
+        html.push_str(
+            "<h2>Failures</h2>\n<table>\n<tr><th>City</th><th>Dataset</th>\
+             <th>Reason</th></tr>\n",
+        );
+        for outcome in &failures {
+            let reason = match &outcome.status {
+                DownloadStatus::Failed(reason) => reason.as_str(),
+                _ => "",
+            };
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape(&outcome.download.city.to_string()),
+                outcome.download.dataset,
+                escape(reason)
+            );
+        }
+        html.push_str("</table>\n");
+    }
+
+    // Scores, one row per city, in the order of the report.
+    let mut cities: Vec<&DownloadOutcome> = Vec::new();
+    for outcome in &report.outcomes {
+        if !cities
+            .iter()
+            .any(|o| o.download.city == outcome.download.city)
+        {
+            cities.push(outcome);
+        }
+    }
+    html.push_str(
+        "<h2>Scores</h2>\n<table>\n<tr><th>City</th><th>Population</th>\
+         <th>Score</th><th>Low stress segments</th></tr>\n",
+    );
+    for outcome in &cities {
+        let city = &outcome.download.city;
+        let low_stress = manifest
+            .files
+            .iter()
+            .filter(|e| e.uuid == outcome.download.run.uuid)
+            .find_map(|e| e.stats.as_ref())
+            .and_then(|stats| {
+                let total: u64 = stats.stress.values().sum();
+                let low = stats.stress.get("low").copied().unwrap_or(0);
+                (total > 0).then(|| format!("{:.1}%", 100.0 * low as f64 / total as f64))
+            })
+            .unwrap_or_default();
+        let (population, score) = if city.population > 0 {
+            (city.population.to_string(), format!("{:.1}", city.ratings))
+        } else {
+            (String::new(), String::new())
+        };
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td class=\"number\">{}</td><td class=\"number\">{}</td>\
+             <td class=\"number\">{}</td></tr>",
+            escape(&city.to_string()),
+            population,
+            score,
+            low_stress
+        );
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    writer.write_all(html.as_bytes())?;
+
+    Ok(())
+}
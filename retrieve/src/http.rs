@@ -0,0 +1,118 @@
+//! Send the requests which are not downloads, e.g. the HEAD requests, the
+//! previews, the descriptor of the storage or the notifications.
+//!
+//! The [`HttpClient`] is built from the same configuration as the
+//! [`Downloader`](crate::download::Downloader): the headers, the tokens of
+//! the mirrors and the network settings apply to every request, and every
+//! request is recorded in the audit log.
+use crate::audit::{AuditLog, AuditRecord};
+use crate::auth::host_key;
+use crate::download::Downloader;
+use crate::Error;
+use reqwest::{Client, Method, RequestBuilder, Response};
+use std::fmt;
+use std::time::SystemTime;
+use url::Url;
+
+/// Send authenticated and audited requests.
+///
+/// The client can be cloned cheaply, and the clones share their connections.
+#[derive(Clone)]
+pub struct HttpClient {
+    client: Client,
+    tokens: Vec<(String, String)>,
+    audit_log: Option<AuditLog>,
+}
+
+impl fmt::Debug for HttpClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HttpClient")
+            .field("tokens", &self.tokens.len())
+            .field("audit_log", &self.audit_log)
+            .finish()
+    }
+}
+
+impl HttpClient {
+    /// Create a client with the default configuration of a
+    /// [`Downloader`](crate::download::Downloader).
+    pub fn new() -> Result<Self, Error> {
+        Downloader::builder().client()
+    }
+
+    /// Create a client sending the requests with `client`.
+    ///
+    /// The requests to the hosts of the `tokens` are authenticated with their
+    /// bearer token.
+    pub(crate) fn from_parts(
+        client: Client,
+        tokens: Vec<(String, String)>,
+        audit_log: Option<AuditLog>,
+    ) -> Self {
+        HttpClient {
+            client,
+            tokens,
+            audit_log,
+        }
+    }
+
+    /// Prepare a request to `url`, authenticated with the bearer token of its
+    /// host, if any.
+    pub fn request(&self, method: Method, url: &Url) -> RequestBuilder {
+        let request = self.client.request(method, url.clone());
+        let token = host_key(url).and_then(|key| {
+            self.tokens
+                .iter()
+                .find(|(host, _)| *host == key)
+                .map(|(_, token)| token)
+        });
+        match token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    /// Prepare a GET request to `url`.
+    pub fn get(&self, url: &Url) -> RequestBuilder {
+        self.request(Method::GET, url)
+    }
+
+    /// Prepare a HEAD request to `url`.
+    pub fn head(&self, url: &Url) -> RequestBuilder {
+        self.request(Method::HEAD, url)
+    }
+
+    /// Prepare a POST request to `url`.
+    pub fn post(&self, url: &Url) -> RequestBuilder {
+        self.request(Method::POST, url)
+    }
+
+    /// Send a `request`, and record it in the audit log.
+    ///
+    /// The request is recorded once the headers of the response are received,
+    /// with the length announced for its body. Fail if the record cannot be
+    /// written.
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, Error> {
+        let (client, request) = request.build_split();
+        let request = request?;
+        let method = request.method().clone();
+        let url = request.url().clone();
+        let started = SystemTime::now();
+        let result = client.execute(request).await;
+        if let Some(audit_log) = &self.audit_log {
+            let mut record = AuditRecord::new(method.as_str(), &url, started);
+            match &result {
+                Ok(response) => {
+                    record = record.status(response.status().as_u16());
+                    if method != Method::HEAD {
+                        record = record.bytes(response.content_length().unwrap_or_default());
+                    }
+                }
+                Err(e) => record = record.error(e),
+            }
+            audit_log.record(&record)?;
+        }
+
+        Ok(result?)
+    }
+}
@@ -0,0 +1,214 @@
+//! Read the city inventory from a Git repository.
+//!
+//! The canonical list of cities often lives in a Git repository. It is
+//! designated by a URL like
+//! `git+https://github.com/org/repo.git#path=cities.csv@main`: the repository,
+//! then the path of the file and the reference to fetch it at, which defaults
+//! to `HEAD`.
+//!
+//! Only the requested commit is fetched, without checking out the files, with
+//! the `git` program, which uses the credentials of the user. The commit is
+//! recorded in the manifest, so that the inventory a batch was planned from is
+//! known.
+use crate::Error;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::{self, Command};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Prefix of the URLs of the inventories hosted in Git.
+pub const GIT_PREFIX: &str = "git+";
+
+/// Reference fetched when none is specified.
+const DEFAULT_REFERENCE: &str = "HEAD";
+
+/// Describe a city inventory hosted in Git.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitInventory {
+    /// URL of the repository, e.g. "https://github.com/org/repo.git".
+    pub repository: String,
+    /// Path of the inventory in the repository.
+    pub path: String,
+    /// Reference to fetch, e.g. a branch, a tag or a commit hash.
+    pub reference: String,
+}
+
+/// Describe the version of an inventory, as recorded in the manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InventorySource {
+    /// URL of the repository.
+    pub repository: String,
+    /// Path of the inventory in the repository.
+    pub path: String,
+    /// Requested reference.
+    pub reference: String,
+    /// Hash of the commit the reference resolved to.
+    pub commit: String,
+}
+
+impl GitInventory {
+    /// Parse an inventory URL like `git+https://host/repo.git#path=cities.csv@main`.
+    ///
+    /// Return `None` if the value does not start with `git+`, i.e. designates a
+    /// local file.
+    pub fn parse(value: &str) -> Result<Option<Self>, Error> {
+        let Some(url) = value.strip_prefix(GIT_PREFIX) else {
+            return Ok(None);
+        };
+        let invalid = || {
+            Error::Parse(format!(
+                "invalid Git inventory {:?}, expected `git+URL#path=PATH[@REF]`",
+                value
+            ))
+        };
+        let (repository, fragment) = url.split_once('#').ok_or_else(invalid)?;
+        let location = fragment.strip_prefix("path=").ok_or_else(invalid)?;
+        let (path, reference) = match location.rsplit_once('@') {
+            Some((path, reference)) => (path, reference),
+            None => (location, DEFAULT_REFERENCE),
+        };
+        if repository.is_empty() || path.is_empty() || reference.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Some(GitInventory {
+            repository: repository.to_string(),
+            path: path.trim_start_matches('/').to_string(),
+            reference: reference.to_string(),
+        }))
+    }
+
+    /// Fetch the inventory, in a private repository of the temporary folder
+    /// which is removed afterwards.
+    ///
+    /// Return the content of the inventory along with its source.
+    pub fn fetch(&self) -> Result<(Vec<u8>, InventorySource), Error> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .subsec_nanos();
+        let clone = env::temp_dir().join(format!("retrieve-inventory-{}-{}", process::id(), nanos));
+        private_dir(&clone)?;
+        let result = self.fetch_in(&clone);
+        let _ = fs::remove_dir_all(&clone);
+        let (content, commit) = result?;
+        let source = InventorySource {
+            repository: self.repository.clone(),
+            path: self.path.clone(),
+            reference: self.reference.clone(),
+            commit,
+        };
+
+        Ok((content, source))
+    }
+
+    /// Fetch the requested commit into an empty repository at `clone`, and
+    /// return the content of the inventory along with the commit hash.
+    fn fetch_in(&self, clone: &Path) -> Result<(Vec<u8>, String), Error> {
+        git(clone, &["init", "--quiet"])?;
+        git(
+            clone,
+            &[
+                "fetch",
+                "--quiet",
+                "--depth",
+                "1",
+                "--",
+                &self.repository,
+                &self.reference,
+            ],
+        )?;
+        let commit = String::from_utf8_lossy(&git(clone, &["rev-parse", "FETCH_HEAD"])?)
+            .trim()
+            .to_string();
+        let content = git(clone, &["show", &format!("{}:{}", commit, self.path)])?;
+
+        Ok((content, commit))
+    }
+}
+
+/// Create the folder at `path`, only accessible to the user, failing if it
+/// already exists.
+fn private_dir(path: &Path) -> Result<(), Error> {
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(path)?;
+
+    Ok(())
+}
+
+/// Run a git command in `folder`, and return its standard output.
+fn git(folder: &Path, args: &[&str]) -> Result<Vec<u8>, Error> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(folder)
+        .args(args)
+        .output()
+        .map_err(|e| Error::Parse(format!("cannot run git: {}", e)))?;
+    if !output.status.success() {
+        return Err(Error::Parse(format!(
+            "git {} failed ({}): {}",
+            args.first().unwrap_or(&""),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_local_files() {
+        assert_eq!(GitInventory::parse("cities.csv").unwrap(), None);
+        assert_eq!(
+            GitInventory::parse("https://host/cities.csv").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_git_inventories() {
+        assert_eq!(
+            GitInventory::parse("git+https://host/repo.git#path=data/cities.csv@v1.2").unwrap(),
+            Some(GitInventory {
+                repository: String::from("https://host/repo.git"),
+                path: String::from("data/cities.csv"),
+                reference: String::from("v1.2"),
+            })
+        );
+        assert_eq!(
+            GitInventory::parse("git+ssh://git@host/repo.git#path=/cities.csv").unwrap(),
+            Some(GitInventory {
+                repository: String::from("ssh://git@host/repo.git"),
+                path: String::from("cities.csv"),
+                reference: String::from(DEFAULT_REFERENCE),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_invalid_git_inventories() {
+        for value in [
+            "git+",
+            "git+https://host/repo.git",
+            "git+https://host/repo.git#cities.csv",
+            "git+#path=cities.csv",
+            "git+https://host/repo.git#path=",
+            "git+https://host/repo.git#path=@main",
+            "git+https://host/repo.git#path=cities.csv@",
+        ] {
+            assert!(
+                matches!(GitInventory::parse(value), Err(Error::Parse(_))),
+                "{}",
+                value
+            );
+        }
+    }
+}
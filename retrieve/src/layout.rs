@@ -0,0 +1,93 @@
+//! Locate the datasets in the PFB storage.
+//!
+//! The datasets of a run are stored as `{BASE_URL}/{uuid}/{dataset}.{ext}`.
+//! When the layout of the storage changes, the path of a dataset can be
+//! overridden with a pattern using the placeholders of the
+//! [`FileNameTemplate`], e.g. `{uuid}/ways/{dataset}.{ext}`, without waiting
+//! for a new release.
+use crate::template::FileNameTemplate;
+use crate::Error;
+use pfbcore::scorecard::{City, Run};
+use pfbcore::Dataset;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Component;
+use url::Url;
+
+/// Describe the paths of the datasets, relative to the base URL of the
+/// storage.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Layout {
+    patterns: BTreeMap<Dataset, FileNameTemplate>,
+    available: Option<BTreeSet<Dataset>>,
+}
+
+impl Layout {
+    /// Create the default layout of the PFB storage.
+    pub fn new() -> Self {
+        Layout::default()
+    }
+
+    /// Override the path of the `dataset` with a `pattern`.
+    pub fn set(&mut self, dataset: Dataset, pattern: FileNameTemplate) {
+        self.patterns.insert(dataset, pattern);
+    }
+
+    /// Only make the `datasets` available.
+    pub fn restrict<I>(&mut self, datasets: I)
+    where
+        I: IntoIterator<Item = Dataset>,
+    {
+        self.available = Some(datasets.into_iter().collect());
+    }
+
+    /// Return `true` if the `dataset` is available in the storage.
+    ///
+    /// Every dataset is available unless the layout is restricted.
+    pub fn is_available(&self, dataset: Dataset) -> bool {
+        self.available
+            .as_ref()
+            .is_none_or(|available| available.contains(&dataset))
+    }
+
+    /// Return `true` if no path is overridden and every dataset is available.
+    pub fn is_default(&self) -> bool {
+        self.patterns.is_empty() && self.available.is_none()
+    }
+
+    /// Return the URL of the `dataset` produced by the `run` of the `city`, in
+    /// the storage located at `base`.
+    pub fn url(&self, base: &str, city: &City, run: &Run, dataset: Dataset) -> Result<Url, Error> {
+        let Some(pattern) = self.patterns.get(&dataset) else {
+            return Ok(run.url_from(base, dataset)?);
+        };
+        let path = pattern
+            .render(city, run, dataset)?
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+
+        Ok(Url::parse(&format!(
+            "{}/{}",
+            base.trim_end_matches('/'),
+            path
+        ))?)
+    }
+}
+
+/// Parse a path override specified as `DATASET=PATTERN`, e.g.
+/// `neighborhood_ways={uuid}/ways.zip`.
+pub fn parse_override(value: &str) -> Result<(Dataset, FileNameTemplate), Error> {
+    let (dataset, pattern) = value.split_once('=').ok_or_else(|| {
+        Error::Parse(format!(
+            "invalid layout override {:?}, expected `DATASET=PATTERN`",
+            value
+        ))
+    })?;
+    let dataset: Dataset = dataset.trim().parse()?;
+
+    Ok((dataset, pattern.trim().parse()?))
+}
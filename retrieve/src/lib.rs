@@ -1,12 +1,288 @@
-use color_eyre::{eyre::Report, Result};
+//! Retrieve PFB datasets.
+//!
+//! The library is split into features, so that consumers only pull in the
+//! dependencies they need:
+//!
+//! - `core`: models, URL building and file management
+//! - `csv`: read the city lists from CSV files
+//! - `parallel`: parse the city lists in parallel
+//! - `config`: read the profiles of the configuration file
+//! - `download`: download the datasets
+//! - `extract`: extract the archives and compute statistics about the datasets
+//! - `convert`: convert the extracted datasets to other formats
+//! - `server`: serve the datasets over an HTTP API
+//! - `keyring`: store the tokens of the authenticated mirrors in the OS keyring
+//! - `email`: notify the operators by email when a batch completes or fails
+//! - `sign`: sign the manifests and checksum files with minisign
+//! - `parquet`: export the city inventory and the scores as Parquet files
+//! - `sqlite`: load the tabular datasets into a SQLite database
+//! - `postgis`: load the extracted geometries into a PostGIS database
+//! - `tiles`: build vector tiles from the neighborhood ways with tippecanoe
+//! - `thumbnail`: render a thumbnail of the neighborhood ways of each city
+//! - `bundle`: assemble the reproduction bundles of the cities
+//! - `collation`: sort the city names following the rules of a locale
+//! - `cli`: command line interface (enabled by default)
+//!
+//! The command line interface itself lives in the `retrieve` binary, and is a
+//! thin layer over the [`plan`] and [`download`] modules.
+use std::io;
+#[cfg(feature = "tiles")]
+use std::path::PathBuf;
+#[cfg(feature = "tiles")]
+use std::process::ExitStatus;
+use thiserror::Error;
 
-pub mod cli;
+#[cfg(feature = "download")]
+pub mod adopt;
+pub mod aliases;
+#[cfg(feature = "download")]
+pub mod anomaly;
+#[cfg(feature = "download")]
+pub mod audit;
+#[cfg(feature = "download")]
+pub mod auth;
+pub mod bbox;
+#[cfg(feature = "download")]
+pub mod bench;
+#[cfg(feature = "download")]
+pub mod budget;
+#[cfg(feature = "bundle")]
+pub mod bundle;
+#[cfg(feature = "download")]
+pub mod cache;
+#[cfg(feature = "csv")]
+pub mod check;
+#[cfg(feature = "download")]
+pub mod checksum;
+pub mod cities;
+pub mod collation;
+#[cfg(feature = "config")]
+pub mod config;
+#[cfg(feature = "convert")]
+pub mod convert;
+#[cfg(feature = "download")]
+pub mod coverage;
+#[cfg(feature = "keyring")]
+pub mod credentials;
+#[cfg(feature = "download")]
+pub mod descriptor;
+#[cfg(feature = "download")]
+pub mod dns;
+#[cfg(feature = "download")]
+pub mod download;
+#[cfg(feature = "download")]
+pub mod drift;
+#[cfg(feature = "download")]
+pub mod evict;
+pub mod export;
+#[cfg(feature = "extract")]
+pub mod extract;
+#[cfg(feature = "download")]
+pub mod faults;
+pub mod filter;
+#[cfg(feature = "download")]
+pub mod fixtures;
+#[cfg(feature = "download")]
+pub mod html;
+#[cfg(feature = "download")]
+pub mod http;
+pub mod inventory;
+pub mod layout;
+#[cfg(feature = "download")]
+pub mod limits;
+#[cfg(feature = "sqlite")]
+pub mod load;
+pub mod lock;
+#[cfg(feature = "download")]
+pub mod lookup;
+#[cfg(feature = "download")]
+pub mod manifest;
+#[cfg(feature = "email")]
+pub mod notify;
+pub mod plan;
+pub mod portable;
+#[cfg(feature = "postgis")]
+pub mod postgis;
+#[cfg(feature = "download")]
+pub mod progress;
+#[cfg(feature = "extract")]
+pub mod projection;
+#[cfg(feature = "download")]
+pub mod provenance;
+#[cfg(feature = "download")]
+pub mod queue;
+#[cfg(feature = "download")]
+pub mod remote;
+#[cfg(feature = "download")]
+pub mod review;
+#[cfg(feature = "download")]
+pub mod s3;
+pub mod selection;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "download")]
+pub mod shutdown;
+#[cfg(feature = "sign")]
+pub mod signature;
+#[cfg(feature = "download")]
+pub mod snapshot;
+pub mod stats;
+#[cfg(feature = "download")]
+pub mod strict;
+#[cfg(feature = "download")]
+pub mod sync;
+#[cfg(feature = "parquet")]
+pub mod table;
+pub mod template;
+#[cfg(feature = "thumbnail")]
+pub mod thumbnail;
+#[cfg(feature = "tiles")]
+pub mod tiles;
+pub mod timestamp;
+#[cfg(feature = "convert")]
+pub mod ways;
+#[cfg(feature = "download")]
+pub mod webhook;
 
-/// Setup the application.
-///
-/// Set up the `color_eyre` hooks.
-pub fn setup() -> Result<(), Report> {
-    color_eyre::install()?;
+pub use cities::Cities;
+pub use pfbcore::scorecard::{City, Run};
+pub use pfbcore::Dataset;
 
-    Ok(())
+/// Errors that can happen when using retrieve.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// Error while parsing a value.
+    #[error("Parse error: {0}")]
+    Parse(String),
+    /// Error from the pfbcore crate.
+    #[error("pfbcore error")]
+    Pfbcore {
+        #[from]
+        source: pfbcore::Error,
+    },
+    /// Authentication error.
+    #[error("Authentication error: {0}")]
+    Auth(String),
+    /// Error from the URL crate.
+    #[error("URL error")]
+    Url {
+        #[from]
+        source: url::ParseError,
+    },
+    /// Error from the JSON crate.
+    #[cfg(feature = "download")]
+    #[error("JSON error")]
+    Json {
+        #[from]
+        source: serde_json::Error,
+    },
+    /// Error from the zip crate.
+    #[cfg(feature = "extract")]
+    #[error("Zip error")]
+    Zip {
+        #[from]
+        source: zip::result::ZipError,
+    },
+    /// Error from the shapefile crate.
+    #[cfg(feature = "extract")]
+    #[error("Shapefile error")]
+    Shapefile {
+        #[from]
+        source: shapefile::Error,
+    },
+    /// Error from the HTTP client.
+    #[cfg(feature = "download")]
+    #[error("HTTP error")]
+    Http {
+        #[from]
+        source: reqwest::Error,
+    },
+    /// Error response of S3.
+    #[cfg(feature = "download")]
+    #[error("S3 error: {source}")]
+    S3 {
+        #[from]
+        source: s3::S3Error,
+    },
+    /// Error from the OS keyring.
+    #[cfg(feature = "keyring")]
+    #[error("Keyring error")]
+    Keyring {
+        #[from]
+        source: keyring::Error,
+    },
+    /// Error from the email builder.
+    #[cfg(feature = "email")]
+    #[error("Email error")]
+    Email {
+        #[from]
+        source: lettre::error::Error,
+    },
+    /// Error from the SMTP transport.
+    #[cfg(feature = "email")]
+    #[error("SMTP error")]
+    Smtp {
+        #[from]
+        source: lettre::transport::smtp::Error,
+    },
+    /// Invalid email address.
+    #[cfg(feature = "email")]
+    #[error("Email address error")]
+    Address {
+        #[from]
+        source: lettre::address::AddressError,
+    },
+    /// Error from the minisign crate.
+    #[cfg(feature = "sign")]
+    #[error("Signature error")]
+    Signature {
+        #[from]
+        source: minisign::PError,
+    },
+    /// Error from the Arrow crates.
+    #[cfg(feature = "parquet")]
+    #[error("Arrow error")]
+    Arrow {
+        #[from]
+        source: arrow_schema::ArrowError,
+    },
+    /// Error from the Parquet crate.
+    #[cfg(feature = "parquet")]
+    #[error("Parquet error")]
+    Parquet {
+        #[from]
+        source: parquet::errors::ParquetError,
+    },
+    /// Error from the SQLite database.
+    #[cfg(feature = "sqlite")]
+    #[error("SQLite error")]
+    Sqlite {
+        #[from]
+        source: rusqlite::Error,
+    },
+    /// Error from the PostgreSQL client.
+    #[cfg(feature = "postgis")]
+    #[error("PostgreSQL error")]
+    Postgres {
+        #[from]
+        source: postgres::Error,
+    },
+    /// The tippecanoe command cannot be started.
+    #[cfg(feature = "tiles")]
+    #[error("Cannot run {}", .program.display())]
+    TippecanoeSpawn {
+        program: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    /// The tippecanoe command failed.
+    #[cfg(feature = "tiles")]
+    #[error("Tippecanoe failed ({status})")]
+    TippecanoeStatus { status: ExitStatus },
+    /// I/O Error.
+    #[error("I/O error")]
+    IOError {
+        #[from]
+        source: io::Error,
+    },
 }
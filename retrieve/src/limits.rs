@@ -0,0 +1,106 @@
+//! Limit the number of simultaneous downloads by dataset and by country.
+//!
+//! The large archives and the small CSV files do not share the same global
+//! limit well: a few concurrent `neighborhood_ways` archives are enough to
+//! saturate a link, while the score files are mostly latency. The limits set
+//! here apply on top of the number of parallel requests, e.g.
+//! `neighborhood_ways=2` lets at most two archives download at once while the
+//! remaining slots serve the other datasets. A download only takes a slot once
+//! its limits allow it to start.
+use crate::plan::PlannedDownload;
+use crate::Error;
+use pfbcore::Dataset;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Limit the simultaneous downloads of some datasets and countries.
+///
+/// The limits can be cloned and are shared between the clones.
+#[derive(Debug, Default, Clone)]
+pub struct ConcurrencyLimits {
+    datasets: BTreeMap<Dataset, Arc<Semaphore>>,
+    countries: BTreeMap<String, Arc<Semaphore>>,
+}
+
+impl ConcurrencyLimits {
+    /// Create limits which do not restrict anything.
+    pub fn new() -> Self {
+        ConcurrencyLimits::default()
+    }
+
+    /// Download at most `limit` files of the `dataset` at once.
+    pub fn dataset(&mut self, dataset: Dataset, limit: u16) {
+        self.datasets
+            .insert(dataset, Arc::new(Semaphore::new(limit.max(1) as usize)));
+    }
+
+    /// Download at most `limit` files of the cities of the `country` at once.
+    ///
+    /// The countries are compared without regard to case.
+    pub fn country(&mut self, country: &str, limit: u16) {
+        self.countries.insert(
+            country.trim().to_lowercase(),
+            Arc::new(Semaphore::new(limit.max(1) as usize)),
+        );
+    }
+
+    /// Return `true` if no limit is set.
+    pub fn is_empty(&self) -> bool {
+        self.datasets.is_empty() && self.countries.is_empty()
+    }
+
+    /// Wait until a planned download is allowed to start.
+    ///
+    /// The download must hold the returned permits until it completes.
+    pub async fn acquire(&self, planned: &PlannedDownload) -> Vec<OwnedSemaphorePermit> {
+        let country = planned.city.country.trim().to_lowercase();
+        let semaphores = [
+            self.datasets.get(&planned.dataset),
+            self.countries.get(&country),
+        ];
+        let mut permits = Vec::new();
+        // The semaphores are always acquired in the same order, so that two
+        // downloads never wait for each other.
+        for semaphore in semaphores.into_iter().flatten() {
+            if let Ok(permit) = semaphore.clone().acquire_owned().await {
+                permits.push(permit);
+            }
+        }
+        permits
+    }
+}
+
+/// Parse a limit specified as `KEY=LIMIT`.
+fn parse_limit(value: &str) -> Result<(&str, u16), Error> {
+    let (key, limit) = value.split_once('=').ok_or_else(|| {
+        Error::Parse(format!(
+            "invalid concurrency limit {:?}, expected `KEY=LIMIT`",
+            value
+        ))
+    })?;
+    let limit = limit
+        .trim()
+        .parse::<u16>()
+        .ok()
+        .filter(|&l| l > 0)
+        .ok_or_else(|| Error::Parse(format!("invalid concurrency limit {:?}", limit)))?;
+
+    Ok((key.trim(), limit))
+}
+
+/// Parse a dataset limit specified as `DATASET=LIMIT`, e.g.
+/// `neighborhood_ways=2`.
+pub fn parse_dataset_limit(value: &str) -> Result<(Dataset, u16), Error> {
+    let (dataset, limit) = parse_limit(value)?;
+
+    Ok((dataset.parse()?, limit))
+}
+
+/// Parse a country limit specified as `COUNTRY=LIMIT`, e.g.
+/// `United States=8`.
+pub fn parse_country_limit(value: &str) -> Result<(String, u16), Error> {
+    let (country, limit) = parse_limit(value)?;
+
+    Ok((country.to_string(), limit))
+}
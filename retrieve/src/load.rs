@@ -0,0 +1,221 @@
+//! Load the tabular datasets into a SQLite database.
+//!
+//! Each CSV dataset recorded in the manifest of a download folder is imported
+//! into a table named after the dataset, e.g. `neighborhood_overall_scores`,
+//! with an extra `uuid` column identifying the run. The `runs` table describes
+//! the city analyzed by each run, so the datasets can be queried right away:
+//!
+//! ```sql
+//! SELECT runs.city, scores.score_id, scores.score_normalized
+//! FROM neighborhood_overall_scores AS scores JOIN runs USING (uuid);
+//! ```
+//!
+//! The type of each column, `INTEGER`, `REAL` or `TEXT`, is inferred from its
+//! values. Loading a run again replaces its rows, so the database can be
+//! refreshed after each fetch.
+use crate::manifest::{Manifest, ManifestEntry};
+use crate::Error;
+use pfbcore::Dataset;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, Transaction};
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Name of the table describing the runs.
+pub const RUNS_TABLE: &str = "runs";
+
+/// Describe the result of a load.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LoadReport {
+    /// Number of files loaded.
+    pub files: usize,
+    /// Number of rows inserted.
+    pub rows: usize,
+}
+
+/// Return the column identifying a row of a dataset, if any, which is indexed
+/// along with the run UUID.
+fn key_column(dataset: &str) -> Option<&'static str> {
+    match dataset.parse().ok()? {
+        Dataset::NeighborhoodOverallScores => Some("score_id"),
+        Dataset::NeighborhoodCensusBlockPopulation => Some("blockid10"),
+        _ => None,
+    }
+}
+
+/// Load the CSV datasets of the download `folder` into the database.
+pub fn load_folder<P>(folder: P, connection: &mut Connection) -> Result<LoadReport, Error>
+where
+    P: AsRef<Path>,
+{
+    let folder = folder.as_ref();
+    let manifest = Manifest::load(folder)?;
+    let transaction = connection.transaction()?;
+    transaction.execute(
+        &format!(
+            "CREATE TABLE IF NOT EXISTS {} (uuid TEXT PRIMARY KEY, city TEXT, state TEXT, country TEXT)",
+            quote(RUNS_TABLE)
+        ),
+        [],
+    )?;
+    let mut report = LoadReport::default();
+    for entry in &manifest.files {
+        let is_csv = entry
+            .path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("csv"));
+        if !is_csv {
+            continue;
+        }
+        report.rows += load_file(&transaction, &folder.join(&entry.path), entry)?;
+        report.files += 1;
+    }
+    transaction.commit()?;
+
+    Ok(report)
+}
+
+/// Load a CSV dataset, replacing the rows of its run.
+///
+/// Return the number of rows inserted.
+fn load_file(
+    transaction: &Transaction,
+    path: &Path,
+    entry: &ManifestEntry,
+) -> Result<usize, Error> {
+    let mut reader = csv::Reader::from_path(path).map_err(pfbcore::Error::from)?;
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(pfbcore::Error::from)?
+        .iter()
+        .map(String::from)
+        .collect();
+    let records = reader
+        .records()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(pfbcore::Error::from)?;
+    let types: Vec<&str> = (0..headers.len())
+        .map(|i| column_type(records.iter().filter_map(|r| r.get(i))))
+        .collect();
+
+    // Create the table, or add the columns it is missing.
+    let table = quote(&entry.dataset);
+    transaction.execute(
+        &format!("CREATE TABLE IF NOT EXISTS {} (uuid TEXT NOT NULL)", table),
+        [],
+    )?;
+    let existing = columns(transaction, &entry.dataset)?;
+    for (name, kind) in headers.iter().zip(&types) {
+        if !existing.contains(name) {
+            transaction.execute(
+                &format!("ALTER TABLE {} ADD COLUMN {} {}", table, quote(name), kind),
+                [],
+            )?;
+        }
+    }
+    transaction.execute(
+        &format!(
+            "CREATE INDEX IF NOT EXISTS {} ON {} (uuid)",
+            quote(&format!("{}_uuid", entry.dataset)),
+            table
+        ),
+        [],
+    )?;
+    if let Some(key) = key_column(&entry.dataset).filter(|k| headers.iter().any(|h| h == k)) {
+        transaction.execute(
+            &format!(
+                "CREATE INDEX IF NOT EXISTS {} ON {} (uuid, {})",
+                quote(&format!("{}_{}", entry.dataset, key)),
+                table,
+                quote(key)
+            ),
+            [],
+        )?;
+    }
+
+    // Replace the rows of the run.
+    transaction.execute(
+        &format!(
+            "INSERT OR REPLACE INTO {} VALUES (?1, ?2, ?3, ?4)",
+            quote(RUNS_TABLE)
+        ),
+        params![entry.uuid, entry.city, entry.state, entry.country],
+    )?;
+    transaction.execute(
+        &format!("DELETE FROM {} WHERE uuid = ?1", table),
+        [&entry.uuid],
+    )?;
+    let names: Vec<String> = headers.iter().map(|h| quote(h)).collect();
+    let placeholders: Vec<String> = (1..=headers.len() + 1).map(|i| format!("?{}", i)).collect();
+    let mut statement = transaction.prepare(&format!(
+        "INSERT INTO {} (uuid, {}) VALUES ({})",
+        table,
+        names.join(", "),
+        placeholders.join(", ")
+    ))?;
+    for record in &records {
+        let values = types.iter().enumerate().map(|(i, kind)| {
+            let field = record.get(i).unwrap_or_default();
+            typed_value(field, kind)
+        });
+        let row = std::iter::once(Value::Text(entry.uuid.clone())).chain(values);
+        statement.execute(params_from_iter(row))?;
+    }
+
+    Ok(records.len())
+}
+
+/// Return the names of the columns of a table.
+fn columns(transaction: &Transaction, table: &str) -> Result<HashSet<String>, Error> {
+    let mut statement = transaction.prepare(&format!(
+        "SELECT name FROM pragma_table_info({})",
+        literal(table)
+    ))?;
+    let names = statement
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<_, _>>()?;
+
+    Ok(names)
+}
+
+/// Return the narrowest type which can hold all the values of a column.
+///
+/// The empty values are ignored, and stored as `NULL`.
+fn column_type<'a, I>(values: I) -> &'static str
+where
+    I: Iterator<Item = &'a str>,
+{
+    let mut kind = "INTEGER";
+    for value in values.map(str::trim).filter(|v| !v.is_empty()) {
+        if kind == "INTEGER" && value.parse::<i64>().is_err() {
+            kind = "REAL";
+        }
+        if kind == "REAL" && value.parse::<f64>().is_err() {
+            return "TEXT";
+        }
+    }
+    kind
+}
+
+/// Convert a CSV field to a value of the column type.
+fn typed_value(field: &str, kind: &str) -> Value {
+    let trimmed = field.trim();
+    if trimmed.is_empty() {
+        return Value::Null;
+    }
+    match kind {
+        "INTEGER" => trimmed.parse().map_or(Value::Null, Value::Integer),
+        "REAL" => trimmed.parse().map_or(Value::Null, Value::Real),
+        _ => Value::Text(field.to_string()),
+    }
+}
+
+/// Quote an SQL identifier.
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Quote an SQL string literal.
+fn literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
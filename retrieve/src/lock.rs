@@ -0,0 +1,74 @@
+//! Prevent concurrent runs against the same download folder.
+//!
+//! A run takes an advisory lock on the `.retrieve.lock` file of the download
+//! folder, which is released when the run completes, even if the process is
+//! killed. The file itself is left in place, and holds the process ID of the
+//! last run which locked the folder.
+use crate::Error;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Name of the lock file.
+pub const LOCK: &str = ".retrieve.lock";
+
+/// Hold the lock of a download folder, until it is dropped.
+#[derive(Debug)]
+pub struct FolderLock {
+    path: PathBuf,
+    file: File,
+}
+
+impl FolderLock {
+    /// Lock the download `folder`, creating it if needed.
+    ///
+    /// If another instance holds the lock, wait for it to be released if
+    /// `wait` is `true`, and fail otherwise.
+    pub fn acquire<P>(folder: P, wait: bool) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let folder = folder.as_ref();
+        fs::create_dir_all(folder)?;
+        let path = folder.join(LOCK);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        match file.try_lock() {
+            Ok(()) => (),
+            Err(TryLockError::WouldBlock) if wait => file.lock()?,
+            Err(TryLockError::WouldBlock) => {
+                let owner = fs::read_to_string(&path).unwrap_or_default();
+                let owner = match owner.trim() {
+                    "" => String::new(),
+                    pid => format!(" (process {})", pid),
+                };
+                return Err(Error::Parse(format!(
+                    "{} is used by another instance of retrieve{}",
+                    folder.display(),
+                    owner
+                )));
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+        file.set_len(0)?;
+        writeln!(file, "{}", process::id())?;
+
+        Ok(FolderLock { path, file })
+    }
+
+    /// Return the path of the lock file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for FolderLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
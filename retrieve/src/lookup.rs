@@ -0,0 +1,140 @@
+//! Resolve the run UUID of a city from the city ratings API.
+//!
+//! The API lists the ratings of the cities matching a name:
+//! `GET {API}/ratings?city={NAME}` returns a JSON array of [`Rating`], one per
+//! run. The latest run of a city is the one created last.
+use crate::collation::eq_folded;
+use crate::http::HttpClient;
+use crate::Error;
+use pfbcore::scorecard::City;
+use serde::Deserialize;
+use url::Url;
+
+/// Base URL of the public city ratings API.
+pub const DEFAULT_RATINGS_API: &str = "https://api.peopleforbikes.xyz";
+
+/// Describe a run, as returned by the city ratings API.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Rating {
+    /// Name of the city.
+    #[serde(alias = "name")]
+    pub city: String,
+    /// State or region of the city.
+    #[serde(default, alias = "region")]
+    pub state: Option<String>,
+    /// Country of the city.
+    pub country: String,
+    /// Run UUID.
+    #[serde(alias = "bna_uuid", alias = "id")]
+    pub uuid: String,
+    /// Date the run was created, as an RFC 3339 timestamp.
+    #[serde(default)]
+    pub created_at: Option<String>,
+    /// Rounded city rating.
+    #[serde(default)]
+    pub score: Option<f64>,
+}
+
+impl From<Rating> for City {
+    fn from(rating: Rating) -> Self {
+        let score = rating.score.unwrap_or_default();
+        let mut city = City::new(
+            &rating.city,
+            &rating.country,
+            rating.state.as_deref(),
+            &rating.uuid,
+            0,
+            score,
+            score.round() as u8,
+        );
+        city.run.date = rating.created_at;
+        city
+    }
+}
+
+/// Represent a query like `Fort Collins, CO` or `Paris, France`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    /// Name of the city.
+    pub name: String,
+    /// State or country of the city, if specified.
+    pub region: Option<String>,
+}
+
+impl Query {
+    /// Parse a `NAME[, STATE_OR_COUNTRY]` query.
+    pub fn parse(query: &str) -> Result<Self, Error> {
+        let (name, region) = match query.split_once(',') {
+            Some((name, region)) => (name.trim(), Some(region.trim().to_string())),
+            None => (query.trim(), None),
+        };
+        if name.is_empty() {
+            return Err(Error::Parse(format!("invalid city {:?}", query)));
+        }
+
+        Ok(Query {
+            name: name.to_string(),
+            region: region.filter(|r| !r.is_empty()),
+        })
+    }
+
+    /// Return `true` if the rating describes the city of the query.
+    ///
+    /// The comparison ignores the case and the diacritics of the names, and
+    /// the region matches either the state or the country.
+    pub fn matches(&self, rating: &Rating) -> bool {
+        eq_folded(&rating.city, &self.name)
+            && self.region.as_ref().is_none_or(|region| {
+                eq_folded(&rating.country, region)
+                    || rating.state.as_ref().is_some_and(|s| eq_folded(s, region))
+            })
+    }
+}
+
+/// Query the city ratings API.
+#[derive(Debug, Clone)]
+pub struct RatingsApi {
+    client: HttpClient,
+    base: Url,
+}
+
+impl RatingsApi {
+    /// Create a client for the API located at `base`, sending its requests
+    /// with `client`.
+    pub fn new(client: &HttpClient, base: Url) -> Self {
+        RatingsApi {
+            client: client.clone(),
+            base,
+        }
+    }
+
+    /// Return the runs of the cities matching the query, latest first.
+    pub async fn ratings(&self, query: &Query) -> Result<Vec<Rating>, Error> {
+        let mut url = self.base.join("ratings")?;
+        url.query_pairs_mut().append_pair("city", &query.name);
+        let response = self
+            .client
+            .send(self.client.get(&url))
+            .await?
+            .error_for_status()?;
+        let mut ratings: Vec<Rating> = response
+            .json::<Vec<Rating>>()
+            .await?
+            .into_iter()
+            .filter(|r| query.matches(r))
+            .collect();
+        ratings.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+
+        Ok(ratings)
+    }
+
+    /// Return the city analyzed by the latest run matching the query.
+    pub async fn latest(&self, query: &Query) -> Result<Option<City>, Error> {
+        Ok(self
+            .ratings(query)
+            .await?
+            .into_iter()
+            .next()
+            .map(City::from))
+    }
+}
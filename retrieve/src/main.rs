@@ -1,55 +1,2135 @@
-use clap::Parser;
-use color_eyre::{eyre::Report, Result};
-use downloader::{Download, Downloader};
-use pfbcore::scorecard::City;
-use pfbcore::Dataset;
-use retrieve::cli::Args;
-use retrieve::setup;
-use std::fs;
-
-fn main() -> Result<(), Report> {
+#[cfg(feature = "sqlite")]
+use crate::cli::LoadArgs;
+#[cfg(feature = "postgis")]
+use crate::cli::PostgisArgs;
+#[cfg(feature = "self-update")]
+use crate::cli::SelfUpdateArgs;
+#[cfg(feature = "server")]
+use crate::cli::ServeArgs;
+#[cfg(feature = "tiles")]
+use crate::cli::TilesArgs;
+#[cfg(feature = "sign")]
+use crate::cli::VerifyManifestArgs;
+use crate::cli::{
+    AdoptArgs, ApplyArgs, BenchArgs, CheckArgs, CleanArgs, Cli, CliDataset, CliOrder, Command,
+    CompareRemotesArgs, DownloadArgs, FetchArgs, ListArgs, LsArgs, OutputFormat, PeekArgs,
+    PlanArgs, PlanCommand, PlanDiffArgs, RemoteArgs, StatusArgs, SyncArgs, VerifyArgs, WatchArgs,
+};
+#[cfg(feature = "keyring")]
+use crate::cli::{AuthArgs, AuthCommand};
+#[cfg(feature = "bundle")]
+use crate::cli::{BundleArgs, BundleCommand, BundleExtractArgs, BundleInspectArgs};
+#[cfg(feature = "parquet")]
+use crate::cli::{ExportArgs, Table, TableFormat};
+use clap::{CommandFactory, FromArgMatches, Parser, ValueEnum};
+use color_eyre::{
+    eyre::{eyre, Report, WrapErr},
+    Result,
+};
+#[cfg(feature = "keyring")]
+use console::Term;
+use indicatif::HumanBytes;
+#[cfg(feature = "parquet")]
+use pfbcore::scorecard::ScoreCard;
+use pfbcore::PFB_S3_STORAGE_BASE_URL;
+use retrieve::adopt::adopt;
+use retrieve::aliases::Aliases;
+use retrieve::anomaly::{SizeAnomaly, SizeHistory};
+use retrieve::audit::AuditLog;
+#[cfg(feature = "keyring")]
+use retrieve::auth::host_key;
+use retrieve::auth::CommandPresigner;
+use retrieve::bbox::BoundingBox;
+use retrieve::bench;
+#[cfg(feature = "bundle")]
+use retrieve::bundle;
+use retrieve::cache::Cache;
+use retrieve::check::{check_file, Severity};
+use retrieve::checksum::{self, Verification, SHA256SUMS};
+use retrieve::collation::{self, Collation};
+use retrieve::config::{Config, CONFIG};
+use retrieve::convert::{output_path, read_boundary, to_geojson, ConvertOptions, Format};
+use retrieve::coverage::{Coverage, CoverageReport};
+#[cfg(feature = "keyring")]
+use retrieve::credentials;
+use retrieve::descriptor::LayoutDescriptor;
+use retrieve::dns::IpFamily;
+use retrieve::download::{
+    DownloadOutcome, DownloadReport, DownloadStatus, Downloader, DownloaderBuilder,
+};
+use retrieve::drift::{self, Drift};
+use retrieve::evict::EvictionPolicy;
+use retrieve::export::export_plan;
+use retrieve::extract::{extract_remote, extract_zip_filtered, extraction_folder};
+use retrieve::fixtures::Fixtures;
+use retrieve::html::write_html_report;
+use retrieve::http::HttpClient;
+use retrieve::inventory::{GitInventory, InventorySource};
+use retrieve::layout::Layout;
+#[cfg(feature = "sqlite")]
+use retrieve::load::load_folder;
+use retrieve::lock::FolderLock;
+use retrieve::lookup::{Query, RatingsApi};
+#[cfg(any(feature = "email", feature = "sign"))]
+use retrieve::manifest::MANIFEST;
+use retrieve::manifest::{Checkpoint, Manifest, ManifestEntry};
+#[cfg(feature = "email")]
+use retrieve::notify::{self, EmailNotifier};
+use retrieve::plan::{priority, read_uuids, Plan, PlannedDownload};
+#[cfg(feature = "postgis")]
+use retrieve::postgis::load_geometries;
+use retrieve::provenance::Provenance;
+use retrieve::remote::{content_lengths, etags, RemoteFile, ZipEntry};
+use retrieve::review::{Action, PlanDiff, PlanFile};
+use retrieve::selection::Selection;
+#[cfg(feature = "server")]
+use retrieve::server::Server;
+use retrieve::shutdown::Shutdown;
+#[cfg(feature = "sign")]
+use retrieve::signature;
+use retrieve::snapshot::Snapshots;
+use retrieve::stats::{find_shapefile, WaysStats};
+use retrieve::strict::StrictMode;
+use retrieve::sync::{Comparison, Synchronization};
+#[cfg(feature = "parquet")]
+use retrieve::table;
+#[cfg(feature = "thumbnail")]
+use retrieve::thumbnail::{render_ways, thumbnail_path, ThumbnailOptions};
+#[cfg(feature = "tiles")]
+use retrieve::tiles::{build_tiles, TileOptions};
+use retrieve::webhook::{post_summary, RunSummary};
+use retrieve::{Cities, City, Dataset};
+#[cfg(feature = "sqlite")]
+use rusqlite::Connection;
+#[cfg(feature = "sign")]
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::io;
+#[cfg(feature = "keyring")]
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+mod cli;
+mod output;
+#[cfg(feature = "otel")]
+mod telemetry;
+
+/// Maximum number of suggestions displayed when a city cannot be found.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Maximum number of downloads listed in the timing summary.
+const MAX_SLOWEST: usize = 5;
+
+/// Number of bytes read from the beginning of the files to preview them.
+const PEEK_BYTES: u64 = 64 * 1024;
+
+/// Exit code of a run interrupted by a signal.
+const EXIT_INTERRUPTED: i32 = 130;
+
+#[tokio::main]
+async fn main() -> Result<(), Report> {
     // Setup the application.
-    setup()?;
+    color_eyre::install()?;
+
+    // Read the CLI arguments, then again with the defaults of the profile.
+    let mut cli = Cli::parse();
+    if let Some(name) = &cli.profile {
+        let path = cli.config.clone().unwrap_or_else(|| PathBuf::from(CONFIG));
+        let config = Config::load(&path)
+            .wrap_err_with(|| format!("cannot read the configuration {}", path.display()))?;
+        let profile = config.profile(name)?;
+        let command = cli::apply_profile(Cli::command(), profile)?;
+        cli = Cli::from_arg_matches(&command.get_matches()).unwrap_or_else(|e| e.exit());
+        cli::apply_profile_headers(&mut cli.command, profile)?;
+    }
+    output::setup_colors(cli.no_color);
+
+    // Export the traces.
+    #[cfg(feature = "otel")]
+    let telemetry = cli
+        .otlp_endpoint
+        .as_ref()
+        .map(telemetry::init)
+        .transpose()?;
+
+    let result = match cli.command {
+        Command::Fetch(args) => fetch(*args).await,
+        Command::Plan(args) => plan(*args).await,
+        Command::Apply(args) => apply(*args).await,
+        Command::Sync(args) => sync(*args).await,
+        Command::Watch(args) => watch(*args).await,
+        Command::List(args) => list(args),
+        Command::Check(args) => check(args),
+        Command::Status(args) => status(args),
+        Command::Verify(args) => verify(args),
+        #[cfg(feature = "sign")]
+        Command::VerifyManifest(args) => verify_manifest(args),
+        Command::Clean(args) => clean(args),
+        Command::Adopt(args) => adopt_folder(args),
+        Command::Peek(args) => peek(args).await,
+        Command::Ls(args) => ls(args).await,
+        Command::CompareRemotes(args) => compare_remotes(args).await,
+        Command::Bench(args) => bench(args).await,
+        #[cfg(feature = "bundle")]
+        Command::Bundle(args) => bundle(args),
+        #[cfg(feature = "parquet")]
+        Command::Export(args) => export(args),
+        #[cfg(feature = "sqlite")]
+        Command::Load(args) => load(args),
+        #[cfg(feature = "postgis")]
+        Command::Postgis(args) => tokio::task::spawn_blocking(move || postgis(args)).await?,
+        #[cfg(feature = "tiles")]
+        Command::Tiles(args) => tiles(args),
+        #[cfg(feature = "self-update")]
+        Command::SelfUpdate(args) => tokio::task::spawn_blocking(move || self_update(args)).await?,
+        #[cfg(feature = "server")]
+        Command::Serve(args) => serve(args).await,
+        #[cfg(feature = "keyring")]
+        Command::Auth(args) => auth(args),
+    };
+
+    // Flush the pending traces.
+    #[cfg(feature = "otel")]
+    if let Some(provider) = telemetry {
+        provider.shutdown()?;
+    }
+
+    result
+}
+
+/// Retrieve the city datasets.
+async fn fetch(mut args: FetchArgs) -> Result<(), Report> {
+    // Plan the sync against the current snapshot, which seeds the new one.
+    let snapshots = args
+        .snapshot
+        .then(|| Snapshots::new(&args.download.destination_folder));
+    let started = SystemTime::now();
+    if let Some(snapshots) = &snapshots {
+        args.download.destination_folder = snapshots
+            .current()
+            .unwrap_or_else(|| snapshots.path(started));
+    }
+
+    let datasets = datasets(&args.download);
+    let layout = layout(&args.download).await?;
+    let plan = plan_fetch(&args, &datasets, &layout).await?;
+    if let Some(snapshots) = &snapshots {
+        args.download.destination_folder = snapshots.path(started);
+    }
+    if !args.download.quiet {
+        let names: Vec<String> = datasets.iter().map(Dataset::to_string).collect();
+        eprintln!("Retrieving {}.", names.join(", "));
+    }
+    let client = http_client(&args.download)?;
+    let shutdown = shutdown_on_signal();
+    let result = match &snapshots {
+        Some(snapshots) => {
+            retrieve_snapshot(&args, snapshots, started, plan, &layout, &shutdown).await
+        }
+        None => retrieve(&args.download, plan, &layout, &shutdown).await,
+    };
+    notify(&args.download, &client, &result).await;
+    result?;
+    if shutdown.is_requested() {
+        process::exit(EXIT_INTERRUPTED);
+    }
+
+    Ok(())
+}
+
+/// Retrieve the planned datasets in a new snapshot of a sync started at
+/// `started`, seeded with the other files of the current snapshot, then
+/// promote the snapshot if the sync is complete, or remove it otherwise.
+///
+/// The folder holding the snapshots stays locked during the sync, and the
+/// sizes of the previous runs are read from the current snapshot.
+async fn retrieve_snapshot(
+    args: &FetchArgs,
+    snapshots: &Snapshots,
+    started: SystemTime,
+    plan: Plan,
+    layout: &Layout,
+    shutdown: &Shutdown,
+) -> Result<DownloadReport, Report> {
+    let _lock = lock_folder(snapshots.base(), args.download.wait_lock).await?;
+    let previous = match snapshots.current() {
+        Some(current) => Manifest::load(current)?,
+        None => Manifest::default(),
+    };
+    let snapshot = snapshots.create(started)?;
+    let seeded = match snapshots.seed(&snapshot, &plan) {
+        Ok(seeded) => seeded,
+        Err(e) => {
+            remove_snapshot(&snapshot);
+            return Err(e.into());
+        }
+    };
+    if seeded.files > 0 && !args.download.quiet {
+        eprintln!(
+            "Linked {} files ({}) of the current snapshot.",
+            seeded.files,
+            HumanBytes(seeded.bytes)
+        );
+    }
+    let result = retrieve_locked(&args.download, plan, layout, &previous, shutdown).await;
+    match &result {
+        Ok(report) if !shutdown.is_requested() => {
+            promote_snapshot(args, snapshots, &snapshot, report)?
+        }
+        _ => remove_snapshot(&snapshot),
+    }
+
+    result
+}
+
+/// Remove a snapshot whose sync failed, was interrupted or is incomplete, so
+/// that it is neither promoted nor kept by the pruning.
+fn remove_snapshot(snapshot: &Path) {
+    match fs::remove_dir_all(snapshot) {
+        Ok(()) => eprintln!("Removed the incomplete snapshot {}.", snapshot.display()),
+        Err(e) => eprintln!("Failed to remove {}: {}", snapshot.display(), e),
+    }
+}
+
+/// Hardlink the unchanged files of a snapshot to the previous snapshots, point
+/// the `current` link to it, then prune the old snapshots.
+///
+/// The snapshot is removed if its sync is incomplete.
+fn promote_snapshot(
+    args: &FetchArgs,
+    snapshots: &Snapshots,
+    snapshot: &Path,
+    report: &DownloadReport,
+) -> Result<(), Report> {
+    let complete = report.outcomes.iter().all(|o| {
+        matches!(
+            o.status,
+            DownloadStatus::Downloaded | DownloadStatus::Cached
+        )
+    });
+    if !complete {
+        remove_snapshot(snapshot);
+        return Ok(());
+    }
+    let deduplication = snapshots.deduplicate(snapshot)?;
+    if deduplication.files > 0 && !args.download.quiet {
+        eprintln!(
+            "Linked {} unchanged files ({}) to the previous snapshots.",
+            deduplication.files,
+            HumanBytes(deduplication.bytes)
+        );
+    }
+    snapshots.promote(snapshot)?;
+    if !args.download.quiet {
+        eprintln!("{} is the current snapshot.", snapshot.display());
+    }
+    if let Some(keep) = args.keep_snapshots {
+        for removed in snapshots.prune(keep)? {
+            if !args.download.quiet {
+                eprintln!("Removed the snapshot {}.", removed.display());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Describe the planned downloads in a plan file for review, or export them as
+/// the input file of another download tool.
+///
+/// The checksums are taken from the manifest of the destination folder.
+async fn plan(args: PlanArgs) -> Result<(), Report> {
+    if let Some(PlanCommand::Diff(diff)) = &args.command {
+        return plan_diff(diff);
+    }
+    let Some(format) = args.format else {
+        return plan_file(args).await;
+    };
+    let download = &args.fetch.download;
+    let layout = layout(download).await?;
+    let plan = plan_fetch(&args.fetch, &datasets(download), &layout).await?;
+    let folder = &download.destination_folder;
+    let manifest = Manifest::load(folder)?;
+    let checksum = |d: &PlannedDownload| {
+        manifest
+            .sha256(&d.run.uuid, &d.dataset.to_string())
+            .map(String::from)
+    };
+    let format = format.into();
+    match &args.output {
+        Some(path) => export_plan(&plan, format, folder, checksum, File::create(path)?)?,
+        None => export_plan(&plan, format, folder, checksum, io::stdout().lock())?,
+    }
+
+    Ok(())
+}
+
+/// Describe what a fetch would do to the download folder in a plan file.
+async fn plan_file(mut args: PlanArgs) -> Result<(), Report> {
+    // Keep the current files in the plan, marked as skipped.
+    let sync = std::mem::replace(&mut args.fetch.sync, false);
+    let download = &args.fetch.download;
+    let layout = layout(download).await?;
+    let plan = plan_fetch(&args.fetch, &datasets(download), &layout).await?;
+    let folder = &download.destination_folder;
+    let manifest = Manifest::load(folder)?;
+    let mut plan_file = PlanFile::new(&plan, folder, &manifest, sync);
+
+    // List the files the eviction policy would delete.
+    if args.older_than.is_some() || args.max_size.is_some() {
+        let policy = EvictionPolicy {
+            older_than: args.older_than,
+            max_size: args.max_size,
+        };
+        plan_file.deletions = policy
+            .apply(folder, true)?
+            .removed
+            .into_iter()
+            .map(|p| p.strip_prefix(folder).map(Path::to_path_buf).unwrap_or(p))
+            .collect();
+    }
+    match &args.output {
+        Some(path) => plan_file.write(File::create(path)?)?,
+        None => plan_file.write(io::stdout().lock())?,
+    }
+    if !download.quiet {
+        eprintln!(
+            "Plan: {} to download, {} to overwrite, {} to skip, {} to delete.",
+            plan_file.count(Action::Download),
+            plan_file.count(Action::Overwrite),
+            plan_file.count(Action::Skip),
+            plan_file.deletions.len()
+        );
+    }
+
+    Ok(())
+}
+
+/// Summarize what changed between two plans.
+fn plan_diff(args: &PlanDiffArgs) -> Result<(), Report> {
+    let read = |path: &Path| -> Result<PlanFile, Report> {
+        match path.is_dir() {
+            true => Ok(PlanFile::from_manifest(&Manifest::load(path)?, path)),
+            false => PlanFile::read(path)
+                .wrap_err_with(|| format!("cannot read the plan {}", path.display())),
+        }
+    };
+    let diff = PlanDiff::new(&read(&args.old)?, &read(&args.new)?);
+    if args.output_format == OutputFormat::Json {
+        println!("{}", serde_json::to_string(&diff)?);
+        return Ok(());
+    }
+    if diff.is_empty() {
+        eprintln!("No change.");
+        return Ok(());
+    }
+    for city in &diff.new_cities {
+        println!("NEW      {}", city);
+    }
+    for updated in &diff.updated_cities {
+        println!(
+            "UPDATED  {}: {} -> {}",
+            updated.city, updated.old_uuid, updated.new_uuid
+        );
+    }
+    for city in &diff.removed_cities {
+        println!("REMOVED  {}", city);
+    }
+    for path in &diff.new_files {
+        println!("ADDED    {}", path.display());
+    }
+    for path in &diff.removed_files {
+        println!("DROPPED  {}", path.display());
+    }
+    for path in &diff.deletions {
+        println!("EVICTED  {}", path.display());
+    }
+    eprintln!(
+        "{} new, {} updated, {} removed city(ies); {} added, {} dropped, {} evicted file(s).",
+        diff.new_cities.len(),
+        diff.updated_cities.len(),
+        diff.removed_cities.len(),
+        diff.new_files.len(),
+        diff.removed_files.len(),
+        diff.deletions.len()
+    );
+
+    Ok(())
+}
+
+/// Apply a reviewed plan file.
+///
+/// The plan is rejected if it was made for another download folder, or if the
+/// download folder changed since it was made. The folder stays locked from the
+/// check to the end of the downloads.
+async fn apply(args: ApplyArgs) -> Result<(), Report> {
+    let plan_file = PlanFile::read(&args.plan)
+        .wrap_err_with(|| format!("cannot read the plan {}", args.plan.display()))?;
+    let folder = &args.download.destination_folder;
+    if !same_folder(&plan_file.destination_folder, folder) {
+        return Err(eyre!(
+            "the plan was made for {}, not {}",
+            plan_file.destination_folder.display(),
+            folder.display()
+        ));
+    }
+
+    // Check the folder and evict the files.
+    let _lock = lock_folder(folder, args.download.wait_lock).await?;
+    let mut manifest = Manifest::load(folder)?;
+    plan_file.check(&manifest)?;
+    if !plan_file.deletions.is_empty() {
+        let result = manifest.remove(folder, &plan_file.deletions);
+        manifest.save(folder)?;
+        result?;
+    }
+    let plan = plan_file.to_plan()?;
+    let layout = layout(&args.download).await?;
+    let client = http_client(&args.download)?;
+    let shutdown = shutdown_on_signal();
+    let result = retrieve_locked(&args.download, plan, &layout, &manifest, &shutdown).await;
+    notify(&args.download, &client, &result).await;
+    result?;
+    if shutdown.is_requested() {
+        process::exit(EXIT_INTERRUPTED);
+    }
+
+    Ok(())
+}
+
+/// Synchronize a download folder with a list of cities, like rsync.
+///
+/// The unchanged files are skipped, and the files which are not planned
+/// anymore are deleted once every transfer succeeded. The download folder
+/// stays locked from the comparison to the deletions.
+async fn sync(mut args: SyncArgs) -> Result<(), Report> {
+    args.fetch.from_csv = Some(args.inventory.clone());
+    args.fetch.download.destination_folder = args.folder.clone();
+    // Compare the files with the remote ones rather than with the last sync.
+    args.fetch.sync = false;
+    args.fetch.snapshot = false;
+    let download = &args.fetch.download;
+    let client = http_client(download)?;
+    let layout = layout(download).await?;
+    let plan = plan_fetch(&args.fetch, &datasets(download), &layout).await?;
+    let folder = &download.destination_folder;
+    let urls: Vec<Url> = plan.downloads.iter().map(|d| d.url.clone()).collect();
+    let parallel = download.parallel_requests as usize;
+    let comparison = match args.checksum {
+        true => Comparison::Checksum(etags(&client, &urls, parallel).await?),
+        false => Comparison::Size(content_lengths(&client, &urls, parallel).await?),
+    };
+    let _lock = lock_folder(folder, download.wait_lock).await?;
+    let manifest = Manifest::load(folder)?;
+    let synchronization = Synchronization::new(plan, folder, &manifest, &comparison, args.delete)?;
+
+    if args.dry_run {
+        for planned in &synchronization.plan.downloads {
+            println!("transfer {}", planned.file_name.display());
+        }
+        for path in &synchronization.deletions {
+            println!("delete {}", path.display());
+        }
+        eprintln!(
+            "Would transfer {} file(s), keep {} unchanged file(s), and delete {} file(s).",
+            synchronization.plan.downloads.len(),
+            synchronization.unchanged.len(),
+            synchronization.deletions.len()
+        );
+        return Ok(());
+    }
+
+    let shutdown = shutdown_on_signal();
+    let plan = synchronization.plan.clone();
+    let result = retrieve_locked(download, plan, &layout, &manifest, &shutdown).await;
+    notify(download, &client, &result).await;
+    let report = result?;
+    if shutdown.is_requested() {
+        process::exit(EXIT_INTERRUPTED);
+    }
 
-    // Read the CLI arguments.
-    let args = Args::parse();
+    // Like rsync, keep the files if a transfer failed.
+    if synchronization.deletions.is_empty() {
+        return Ok(());
+    }
+    if report.failures().next().is_some() || report.timed_out().next().is_some() {
+        eprintln!("Some transfers failed, skipping the deletions.");
+        return Ok(());
+    }
+    let mut manifest = Manifest::load(folder)?;
+    synchronization.delete(folder, &mut manifest)?;
+    manifest.save(folder)?;
+    if !download.quiet {
+        for path in &synchronization.deletions {
+            eprintln!("Deleted {}", path.display());
+        }
+    }
 
-    // Prepare the variable holding the list of cities to process.
-    let mut cities: Vec<City> = Vec::new();
+    Ok(())
+}
 
-    // Prepare the list of items to retrieve from a CSV file.
-    if let Some(csv) = args.from_csv {
-        cities = City::from_csv(csv)?;
+/// Read the city inventory, from a local file or from a Git repository.
+fn inventory(csv: &str, quiet: bool) -> Result<(Cities, Option<InventorySource>), Report> {
+    let Some(git) = GitInventory::parse(csv)? else {
+        return Ok((Cities::from_csv(csv)?, None));
+    };
+    let (content, source) = git
+        .fetch()
+        .wrap_err_with(|| format!("cannot fetch the inventory {}", csv))?;
+    if !quiet {
+        eprintln!(
+            "Read {} from {} at {} ({}).",
+            source.path, source.repository, source.reference, source.commit
+        );
     }
 
-    // Ensure the output folder exists.
-    if !args.destination_folder.exists() {
-        fs::create_dir_all(&args.destination_folder)?;
+    Ok((Cities::from_reader(content.as_slice())?, Some(source)))
+}
+
+/// Plan the downloads, either from a list of cities or from run UUIDs only.
+async fn plan_fetch(
+    args: &FetchArgs,
+    datasets: &[Dataset],
+    layout: &Layout,
+) -> Result<Plan, Report> {
+    let mut plan = match (&args.from_csv, &args.lookup) {
+        (Some(csv), _) => {
+            let (cities, inventory) = inventory(csv, args.download.quiet)?;
+            let mut plan = plan_cities(args, &cities, datasets)?;
+            plan.inventory = inventory;
+            plan
+        }
+        (None, Some(query)) => plan_lookup(args, query, datasets).await?,
+        (None, None) => plan_uuids(args, datasets)?,
+    };
+
+    name_files(&args.download, layout, &mut plan)?;
+    plan.sort();
+
+    // Skip the datasets which did not change since the last sync.
+    if args.sync {
+        let folder = &args.download.destination_folder;
+        let manifest = Manifest::load(folder)?;
+        plan.downloads.retain(|d| {
+            let current = manifest.is_current(d, folder);
+            if current && !args.download.quiet {
+                eprintln!(
+                    "Skipping {} for {}: unchanged since the last sync",
+                    d.dataset, d.city
+                );
+            }
+            !current
+        });
     }
+    order(args, &mut plan).await?;
+
+    Ok(plan)
+}
+
+/// Order the downloads of a sorted plan.
+async fn order(args: &FetchArgs, plan: &mut Plan) -> Result<(), Report> {
+    match args.order {
+        CliOrder::FileName => (),
+        CliOrder::Alphabetical => {
+            plan.prioritize(|d| {
+                (
+                    collation::sort_key(&d.city.full_name()),
+                    d.dataset.to_string(),
+                )
+            });
+        }
+        CliOrder::SmallestFirst => {
+            let urls: Vec<Url> = plan.downloads.iter().map(|d| d.url.clone()).collect();
+            let parallel = args.download.parallel_requests as usize;
+            let client = http_client(&args.download)?;
+            let lengths = content_lengths(&client, &urls, parallel).await?;
+            // The files whose size is unknown come last.
+            plan.prioritize(|d| lengths.get(&d.url).copied().unwrap_or(u64::MAX));
+        }
+        CliOrder::Priority => {
+            let column = &args.priority_column;
+            // The cities without a priority come last.
+            plan.prioritize(|d| priority(&d.city, column).map_or((1, 0), |p| (0, p)));
+        }
+    }
+
+    Ok(())
+}
+
+/// Periodically retrieve the datasets of the cities which were not retrieved yet.
+///
+/// The list of cities is read again on each check, and only the datasets whose
+/// run UUID changed since the previous check are downloaded. The errors are
+/// reported without stopping the watch.
+async fn watch(args: WatchArgs) -> Result<(), Report> {
+    let datasets = datasets(&args.download);
+    let folder = &args.download.destination_folder;
+    let client = http_client(&args.download)?;
+    let shutdown = shutdown_on_signal();
+    loop {
+        let check = async {
+            let layout = layout(&args.download).await?;
+            let mut plan = Plan::from_csv(&args.cities, &Selection::default(), &datasets)?;
+            name_files(&args.download, &layout, &mut plan)?;
+            let manifest = Manifest::load(folder)?;
+            plan.downloads.retain(|d| !manifest.is_current(d, folder));
+            if !plan.is_empty() {
+                if !args.download.quiet {
+                    eprintln!("Retrieving {} new file(s).", plan.downloads.len());
+                }
+                let result = retrieve(&args.download, plan, &layout, &shutdown).await;
+                notify(&args.download, &client, &result).await;
+                result?;
+            }
+            Ok::<_, Report>(())
+        };
+        if let Err(e) = check.await {
+            eprintln!("Check failed: {:?}", e);
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(args.interval) => (),
+            _ = shutdown.requested() => (),
+        }
+        if shutdown.is_requested() {
+            process::exit(EXIT_INTERRUPTED);
+        }
+    }
+}
+
+/// Request a shutdown when the process receives SIGINT (Ctrl-C) or SIGTERM.
+fn shutdown_on_signal() -> Shutdown {
+    let shutdown = Shutdown::new();
+    let handle = shutdown.clone();
+    tokio::spawn(async move {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            let mut terminate = signal(SignalKind::terminate()).expect("SIGTERM handler");
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => (),
+                _ = terminate.recv() => (),
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = tokio::signal::ctrl_c().await;
+        eprintln!("Interrupted, waiting for the transfers in progress to complete...");
+        handle.request();
+    });
+
+    shutdown
+}
+
+/// Build the layout of the storage from its descriptor, if it must be
+/// discovered, and from the overrides.
+///
+/// The pinned descriptor is used if the one of the storage cannot be
+/// retrieved.
+async fn layout(args: &DownloadArgs) -> Result<Layout, Report> {
+    let mut layout = if args.discover_layout {
+        let base = match args.mirrors.first() {
+            Some(mirror) => mirror.clone(),
+            None => Url::parse(PFB_S3_STORAGE_BASE_URL)?,
+        };
+        discover_layout(&http_client(args)?, &base).await?
+    } else {
+        Layout::new()
+    };
+    for (dataset, pattern) in &args.layout {
+        layout.set(*dataset, pattern.clone());
+    }
+
+    Ok(layout)
+}
+
+/// Build the layout of the storage located at `base` from its descriptor.
+///
+/// The pinned descriptor is used if the one of the storage cannot be
+/// retrieved.
+async fn discover_layout(client: &HttpClient, base: &Url) -> Result<Layout, Report> {
+    let descriptor = match LayoutDescriptor::fetch(client, base).await {
+        Ok(descriptor) => descriptor,
+        Err(e) => {
+            eprintln!(
+                "Cannot retrieve the layout descriptor of {}, using the pinned one: {}",
+                base, e
+            );
+            LayoutDescriptor::pinned()
+        }
+    };
+
+    Ok(descriptor.layout()?)
+}
+
+/// Locate the planned datasets after the layout, name the planned files after
+/// the template, and make them portable if needed.
+///
+/// The datasets which are not available in the storage are skipped.
+fn name_files(args: &DownloadArgs, layout: &Layout, plan: &mut Plan) -> Result<(), Report> {
+    if !layout.is_default() {
+        for dataset in datasets(args) {
+            if !layout.is_available(dataset) && !args.quiet {
+                eprintln!("Skipping {}: not available in the storage", dataset);
+            }
+        }
+        plan.downloads.retain(|d| layout.is_available(d.dataset));
+        plan.relayout(layout)?;
+    }
+    if let Some(template) = &args.file_name {
+        plan.rename(template)?;
+    }
+    if args.portable_names || cfg!(windows) {
+        plan.make_portable();
+    }
+
+    Ok(())
+}
+
+/// Convert the datasets of the CLI to the ones of the library.
+///
+/// All the datasets are retrieved if none is specified.
+fn datasets(args: &DownloadArgs) -> Vec<Dataset> {
+    if args.datasets.is_empty() {
+        CliDataset::value_variants()
+            .iter()
+            .map(|&d| d.into())
+            .collect()
+    } else {
+        args.datasets.iter().map(|&d| d.into()).collect()
+    }
+}
+
+/// Lock a download or cache `folder`, waiting for the other instances to
+/// release it if `wait` is set.
+async fn lock_folder(folder: &Path, wait: bool) -> Result<FolderLock, Report> {
+    let folder = folder.to_path_buf();
+
+    Ok(tokio::task::spawn_blocking(move || FolderLock::acquire(folder, wait)).await??)
+}
+
+/// Return `true` if two paths designate the same folder.
+fn same_folder(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Retrieve the planned datasets, post-process them, and run the hook.
+async fn retrieve(
+    args: &DownloadArgs,
+    plan: Plan,
+    layout: &Layout,
+    shutdown: &Shutdown,
+) -> Result<DownloadReport, Report> {
+    // Keep the other instances out of the download folder.
+    let _lock = lock_folder(&args.destination_folder, args.wait_lock).await?;
+    let previous = Manifest::load(&args.destination_folder)?;
+    retrieve_locked(args, plan, layout, &previous, shutdown).await
+}
+
+/// Retrieve the planned datasets, once the download folder is locked.
+///
+/// The `previous` manifest describes the files of the previous runs, e.g. the
+/// ones of the current snapshot.
+async fn retrieve_locked(
+    args: &DownloadArgs,
+    mut plan: Plan,
+    layout: &Layout,
+    previous: &Manifest,
+    shutdown: &Shutdown,
+) -> Result<DownloadReport, Report> {
+    // Keep the other instances out of the cache.
+    let inventory = plan.inventory.take();
+    let _cache_lock = match &args.cache_dir {
+        Some(cache_dir) => Some(lock_folder(cache_dir, args.wait_lock).await?),
+        None => None,
+    };
+
+    // Skip the cities outside of the region of interest.
+    if let Some(region) = &args.bbox {
+        plan.downloads.retain(|d| match previous.bbox(&d.run.uuid) {
+            Some(bbox) if !bbox.intersects(region) => {
+                if !args.quiet {
+                    eprintln!(
+                        "Skipping {} for {}: outside of the bounding box",
+                        d.dataset, d.city
+                    );
+                }
+                false
+            }
+            _ => true,
+        });
+    }
+
+    // Open the shared cache.
+    let cache = match &args.cache_dir {
+        Some(cache_dir) => Some(Cache::open(cache_dir)?),
+        None => None,
+    };
+
+    // Open the recorded responses.
+    let fixtures = match &args.fixtures {
+        Some(folder) => Some(Fixtures::open(folder, args.replay_only)?),
+        None => None,
+    };
 
     // Prepare the downloader.
-    let mut downloader = Downloader::builder()
-        .download_folder(&args.destination_folder)
+    let mut builder = network(args)?
+        .destination_folder(&args.destination_folder)
+        .layout(layout.clone())
         .parallel_requests(args.parallel_requests)
         .retries(args.retries)
-        .build()
-        .unwrap();
+        .cache(cache)
+        .checksums(args.write_checksums)
+        .max_file_size(args.max_file_size)
+        .segments(args.segments)
+        .segment_threshold(args.segment_threshold)
+        .shutdown(shutdown.clone())
+        .grace_period(args.grace_period)
+        .city_timeout(args.city_timeout)
+        .fault_injection(args.fault_inject)
+        .fixtures(fixtures)
+        .checkpoint(Some(Checkpoint::open(&args.destination_folder)?))
+        .backup_dir(args.backup.as_ref())
+        .previous_manifest(Some(previous.clone()))
+        .progress(!args.quiet);
+    for mirror in &args.mirrors {
+        builder = builder.mirror(mirror.clone());
+    }
+    for (dataset, limit) in &args.dataset_concurrency {
+        builder = builder.dataset_concurrency(*dataset, *limit);
+    }
+    for (country, limit) in &args.country_concurrency {
+        builder = builder.country_concurrency(country, *limit);
+    }
+    if let Some(command) = &args.presign_command {
+        builder = builder.presign(CommandPresigner::new(command)?);
+    }
+    let downloader = builder.build()?;
 
-    // Prepare the downloads.
-    let downloads = cities
+    // Start the download operations, remembering the sizes of the previous
+    // runs.
+    let history = SizeHistory::from_manifest(previous);
+    let report = downloader.fetch_all(&plan).await?;
+    // The anomalies are only informative, the files must still be recorded.
+    let anomalies = history
+        .anomalies(&report, args.max_size_change)
+        .unwrap_or_else(|e| {
+            eprintln!("Failed to detect the size anomalies: {}", e);
+            Vec::new()
+        });
+    if let (Some(primary), false) = (args.mirrors.first(), args.quiet) {
+        for outcome in &report.outcomes {
+            let download = &outcome.download;
+            let primary_url = layout.url(
+                primary.as_str(),
+                &download.city,
+                &download.run,
+                download.dataset,
+            )?;
+            match &outcome.source {
+                Some(source) if *source != primary_url => eprintln!(
+                    "Retrieved {} for {} from fallback mirror {}",
+                    download.dataset, download.city, source
+                ),
+                _ => (),
+            }
+        }
+    }
+    if !args.quiet {
+        for outcome in &report.outcomes {
+            if let Some(backup) = &outcome.backup {
+                eprintln!(
+                    "Replaced {}, previous version kept in {}",
+                    outcome.path.display(),
+                    backup.display()
+                );
+            }
+        }
+    }
+    record(args, &report, inventory)?;
+    if let Some(path) = &args.html_report {
+        write_report(args, &report, &anomalies, path)?;
+    }
+    if let Some(hook) = &args.hook {
+        for outcome in &report.outcomes {
+            if outcome.status == DownloadStatus::Downloaded {
+                if let Err(e) = run_hook(hook, outcome).await {
+                    eprintln!("Hook failed for {}: {}", outcome.path.display(), e);
+                }
+            }
+        }
+    }
+    match args.output_format {
+        OutputFormat::Text if !args.quiet => {
+            output::print_cities(&report);
+            output::print_anomalies(&anomalies);
+            summarize(&report);
+        }
+        OutputFormat::Text => output::print_anomalies(&anomalies),
+        OutputFormat::Json => output::print_json(&report, &anomalies)?,
+    }
+
+    Ok(report)
+}
+
+/// Configure the requests of a downloader: the headers, the tokens of the
+/// mirrors, the network settings and the audit log.
+///
+/// The requests other than the downloads are sent by the [`HttpClient`] built
+/// from the same configuration.
+fn network(args: &DownloadArgs) -> Result<DownloaderBuilder, Report> {
+    let audit_log = args.audit_log.as_ref().map(AuditLog::open).transpose()?;
+    let mut builder = Downloader::builder()
+        .keep_compressed(args.keep_compressed)
+        .strict(args.strict.then_some(StrictMode {
+            max_size_deviation: args.max_size_deviation,
+        }))
+        .audit_log(audit_log)
+        .ip_family(ip_family(args))
+        .http_version(args.http_version.into())
+        .pool_max_idle_per_host(args.pool_max_idle)
+        .pool_idle_timeout(args.pool_idle_timeout)
+        .tcp_keepalive(args.tcp_keepalive);
+    if let Some(user_agent) = &args.user_agent {
+        builder = builder.user_agent(user_agent);
+    }
+    for (name, value) in args.profile_headers.iter().chain(&args.headers) {
+        builder = builder.header(name, value);
+    }
+    // Authenticate the mirrors with their stored tokens, unless a token is
+    // given explicitly.
+    #[cfg(feature = "keyring")]
+    if !args
+        .profile_headers
         .iter()
-        .filter(|c| !c.uuid.is_empty())
-        .map(|c| {
-            let ds: Dataset = args.dataset.into();
-            downloader::Download::new(c.url(ds).unwrap().as_str()).file_name(std::path::Path::new(
-                &format!("{}-{}.{}", &c.full_name(), &ds, &ds.extension()),
-            ))
+        .chain(&args.headers)
+        .any(|(name, _)| name.eq_ignore_ascii_case("authorization"))
+    {
+        for mirror in &args.mirrors {
+            match credentials::load(mirror) {
+                Ok(Some(token)) => {
+                    if let Some(host) = host_key(mirror) {
+                        builder = builder.bearer_token(host, token);
+                    }
+                }
+                Ok(None) => (),
+                Err(e) => eprintln!("Cannot read the token of {}: {}", mirror, e),
+            }
+        }
+    }
+    for (host, address) in &args.resolve {
+        builder = builder.resolve(host, *address);
+    }
+
+    Ok(builder)
+}
+
+/// Build the client sending the requests other than the downloads.
+fn http_client(args: &DownloadArgs) -> Result<HttpClient, Report> {
+    Ok(network(args)?.client()?)
+}
+
+/// Write the HTML report of a batch.
+///
+/// The files are linked with relative paths when the report is stored in the
+/// download folder, and with absolute `file://` URLs otherwise.
+fn write_report(
+    args: &DownloadArgs,
+    report: &DownloadReport,
+    anomalies: &[SizeAnomaly],
+    path: &Path,
+) -> Result<(), Report> {
+    let folder = fs::canonicalize(&args.destination_folder)?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => fs::canonicalize(parent)?,
+        _ => env::current_dir()?,
+    };
+    let prefix = if parent == folder {
+        String::new()
+    } else {
+        Url::from_directory_path(&folder)
+            .map_err(|_| eyre!("invalid folder {}", folder.display()))?
+            .to_string()
+    };
+    let manifest = Manifest::load(&args.destination_folder)?;
+    write_html_report(report, anomalies, &manifest, &prefix, File::create(path)?)?;
+    if !args.quiet {
+        eprintln!("Report written to {}.", path.display());
+    }
+
+    Ok(())
+}
+
+/// Send the summary of a batch to the webhooks and the addresses to notify.
+///
+/// The summary is posted with the `client` of the command. The notification
+/// failures are reported without failing the batch.
+async fn notify(args: &DownloadArgs, client: &HttpClient, result: &Result<DownloadReport, Report>) {
+    let summary = match result {
+        Ok(report) => RunSummary::from_report(report),
+        Err(e) => RunSummary::from_error(&e.to_string()),
+    };
+    let format = args.webhook_format.into();
+    for webhook in &args.notify_webhook {
+        if let Err(e) = post_summary(client, webhook, format, &summary).await {
+            eprintln!("Failed to post the summary to {}: {}", webhook, e);
+        }
+    }
+
+    #[cfg(feature = "email")]
+    notify_email(args, result).await;
+}
+
+/// Send the summary of a batch to the addresses to notify.
+#[cfg(feature = "email")]
+async fn notify_email(args: &DownloadArgs, result: &Result<DownloadReport, Report>) {
+    if args.notify_email.is_empty() {
+        return;
+    }
+    let (subject, body) = match result {
+        Ok(report) => (notify::subject(report), notify::summary(report)),
+        Err(e) => (String::from("retrieve: batch failed"), format!("{:#}\n", e)),
+    };
+    let manifest = args.destination_folder.join(MANIFEST);
+    let sent = async {
+        EmailNotifier::new(&args.smtp_url, &args.notify_from, &args.notify_email)?
+            .send(&subject, &body, Some(&manifest))
+            .await
+    };
+    if let Err(e) = sent.await {
+        eprintln!("Failed to send the notification: {}", e);
+    }
+}
+
+/// Return the family of the addresses to connect to.
+fn ip_family(args: &DownloadArgs) -> IpFamily {
+    match (args.ipv4_only, args.ipv6_only) {
+        (true, _) => IpFamily::V4,
+        (_, true) => IpFamily::V6,
+        _ => IpFamily::Any,
+    }
+}
+
+/// Print the slowest downloads, and the overall throughput.
+fn summarize(report: &DownloadReport) {
+    let injected = report.injected();
+    if injected > 0 {
+        eprintln!(
+            "{} failures were injected on purpose (--fault-inject).",
+            injected
+        );
+    }
+    let slowest = report.slowest();
+    if slowest.is_empty() {
+        return;
+    }
+    eprintln!("Slowest downloads:");
+    eprintln!(
+        "  {:>8}  {:>10}  {:>12}  {:>7}  FILE",
+        "TIME", "SIZE", "THROUGHPUT", "RETRIES"
+    );
+    for outcome in slowest.iter().take(MAX_SLOWEST) {
+        eprintln!(
+            "  {:>7.1}s  {:>10}  {:>10}/s  {:>7}  {}",
+            outcome.duration.as_secs_f64(),
+            HumanBytes(outcome.bytes).to_string(),
+            HumanBytes(outcome.throughput() as u64).to_string(),
+            outcome.retries,
+            outcome.download.file_name.display()
+        );
+    }
+    eprintln!(
+        "{} downloaded in {:.1}s ({}/s).",
+        HumanBytes(report.total_bytes()),
+        report.elapsed.as_secs_f64(),
+        HumanBytes(report.throughput() as u64)
+    );
+}
+
+/// Run the hook for a downloaded file.
+///
+/// The command line is split following the quoting rules of the shell, without
+/// running a shell, and the details of the file are passed in the environment.
+async fn run_hook(hook: &str, outcome: &DownloadOutcome) -> Result<(), Report> {
+    let parts = shell_words::split(hook)?;
+    let (program, args) = parts
+        .split_first()
+        .ok_or_else(|| eyre!("empty hook command"))?;
+    let download = &outcome.download;
+    let status = tokio::process::Command::new(program)
+        .args(args)
+        .env("RETRIEVE_PATH", &outcome.path)
+        .env("RETRIEVE_CITY", &download.city.name)
+        .env("RETRIEVE_UUID", &download.run.uuid)
+        // RETRIEVE_DATASET already selects the datasets to retrieve.
+        .env("RETRIEVE_FILE_DATASET", download.dataset.to_string())
+        .status()
+        .await?;
+    if !status.success() {
+        return Err(eyre!("hook command failed ({})", status));
+    }
+
+    Ok(())
+}
+
+/// Plan the downloads of the selected cities.
+fn plan_cities(args: &FetchArgs, cities: &Cities, datasets: &[Dataset]) -> Result<Plan, Report> {
+    // Select the cities to process.
+    let selection = Selection {
+        names: args.cities.clone(),
+        uuids: args.uuids.clone(),
+        states: args.states.clone(),
+        countries: args.countries.clone(),
+        all_matches: args.all_matches,
+        aliases: aliases(args)?,
+        filters: args.filters.clone(),
+        run_after: args.run_after.clone(),
+        run_before: args.run_before.clone(),
+    };
+    let selected = selection.apply(cities);
+    if !selected.ambiguous.is_empty() {
+        for (name, candidates) in &selected.ambiguous {
+            eprintln!("Several places are named {:?}:", name);
+            for city in candidates {
+                eprintln!("  {}", city);
+            }
+        }
+        return Err(eyre!(
+            "ambiguous city names, specify --state or --country, or use --all-matches"
+        ));
+    }
+    for name in &selected.unmatched {
+        eprintln!("No city named {:?}.", name);
+        let suggestions = cities.search(name);
+        if !suggestions.is_empty() {
+            eprintln!("Did you mean:");
+            for (city, _) in suggestions.iter().take(MAX_SUGGESTIONS) {
+                eprintln!("  {}", city);
+            }
+        }
+    }
+    for uuid in &selected.unmatched_uuids {
+        eprintln!("No city analyzed by the run {:?}.", uuid);
+    }
+    let mut cities = selected.cities;
+    if let Some(count) = args.sample {
+        let seed = args.seed.unwrap_or_else(random_seed);
+        let sample = cities.sample(count, seed);
+        eprintln!(
+            "Sampled {} cities out of {} (--seed {}).",
+            sample.len(),
+            cities.len(),
+            seed
+        );
+        cities = sample;
+    }
+
+    Ok(Plan::new(cities.as_slice(), datasets)?)
+}
+
+/// Return the aliases of the city names given on the command line.
+fn aliases(args: &FetchArgs) -> Result<Aliases, Report> {
+    let mut aliases = match &args.aliases_file {
+        Some(path) => Aliases::from_csv(path)?,
+        None => Aliases::new(),
+    };
+    for (alias, name) in &args.aliases {
+        aliases.insert(alias, name);
+    }
+
+    Ok(aliases)
+}
+
+/// Return a seed which changes at every run.
+fn random_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+/// Plan the downloads of the latest run of a city, resolved from the city
+/// ratings API.
+async fn plan_lookup(args: &FetchArgs, query: &str, datasets: &[Dataset]) -> Result<Plan, Report> {
+    let mut query = Query::parse(query)?;
+    query.name = aliases(args)?.resolve(&query.name).to_string();
+    let api = RatingsApi::new(&http_client(&args.download)?, args.ratings_api.clone());
+    let selection = Selection {
+        run_after: args.run_after.clone(),
+        run_before: args.run_before.clone(),
+        ..Default::default()
+    };
+    let city = api
+        .ratings(&query)
+        .await?
+        .into_iter()
+        .map(City::from)
+        .find(|c| selection.matches_run(&c.run))
+        .ok_or_else(|| eyre!("no run found for {:?}", query.name))?;
+    if !args.yes {
+        eprint!("Retrieve the datasets of {}? [y/N] ", city);
+        let answer = tokio::task::spawn_blocking(|| {
+            let mut answer = String::new();
+            io::stdin().read_line(&mut answer).map(|_| answer)
         })
-        .collect::<Vec<Download>>();
+        .await??;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            return Err(eyre!("cancelled"));
+        }
+    }
+
+    Ok(Plan::new(&[city], datasets)?)
+}
+
+/// Plan the downloads of the run UUIDs, without any city metadata.
+fn plan_uuids(args: &FetchArgs, datasets: &[Dataset]) -> Result<Plan, Report> {
+    let mut uuids = args.uuids.clone();
+    if let Some(path) = &args.from_uuids {
+        uuids.extend(read_uuids(path)?);
+    }
+    if args.run_after.is_some() || args.run_before.is_some() {
+        return Err(eyre!(
+            "--run-after and --run-before require --from-csv or --lookup, the run UUIDs are not dated"
+        ));
+    }
+    if args.name_hint.is_some() && uuids.len() > 1 {
+        return Err(eyre!("--name-hint can only be used with a single --uuid"));
+    }
+    let mut plan = Plan::default();
+    for uuid in &uuids {
+        plan.extend(Plan::from_uuid(uuid, args.name_hint.as_deref(), datasets)?);
+    }
+
+    Ok(plan)
+}
+
+/// Post-process the retrieved files, and record them in the manifest, along
+/// with the version of the inventory they were planned from.
+///
+/// A file which cannot be post-processed is still recorded.
+fn record(
+    args: &DownloadArgs,
+    report: &DownloadReport,
+    inventory: Option<InventorySource>,
+) -> Result<(), Report> {
+    let folder = &args.destination_folder;
+    let mut manifest = Manifest::load(folder)?;
+    manifest.inventory = inventory;
+    let options = ConvertOptions {
+        clip: args.clip.as_ref().map(read_boundary).transpose()?,
+        crs: args.to_crs.clone(),
+    };
+    let mut recorded = Vec::new();
+    for outcome in &report.outcomes {
+        let mut entry = match ManifestEntry::from_outcome(outcome)? {
+            Some(entry) => entry,
+            None => continue,
+        };
+        if let Err(e) = post_process(args, &options, outcome, &mut entry) {
+            eprintln!("Failed to post-process {}: {}", outcome.path.display(), e);
+        }
+        recorded.push((entry.path.clone(), &outcome.path));
+        manifest.upsert(entry);
+    }
+    manifest.save(folder)?;
+
+    // Describe the provenance of the files, as recorded in the manifest. The
+    // sidecars can be written again from the manifest, so that a failure does
+    // not fail the batch.
+    if args.provenance {
+        for (path, file) in recorded {
+            let Some(entry) = manifest.get(&path) else {
+                continue;
+            };
+            if let Err(e) = Provenance::from_entry(entry).write(file) {
+                eprintln!(
+                    "Failed to write the provenance of {}: {}",
+                    file.display(),
+                    e
+                );
+            }
+        }
+    }
+
+    // Sign the manifest and the checksums, once they are complete.
+    #[cfg(feature = "sign")]
+    if let Some(key) = &args.sign_key {
+        let mut paths = vec![folder.join(MANIFEST)];
+        let sums = folder.join(SHA256SUMS);
+        if sums.is_file() {
+            paths.push(sums);
+        }
+        signature::sign_files(&paths, key)?;
+    }
+
+    Ok(())
+}
+
+/// Extract a retrieved file and compute its metadata.
+///
+/// The files stored as compressed by the server are not post-processed.
+fn post_process(
+    args: &DownloadArgs,
+    options: &ConvertOptions,
+    outcome: &DownloadOutcome,
+    entry: &mut ManifestEntry,
+) -> Result<(), Report> {
+    if !args.extract || outcome.download.dataset.extension() != "zip" {
+        return Ok(());
+    }
+    if let Some(encoding) = &outcome.content_encoding {
+        eprintln!(
+            "Skipping the extraction of {}: stored with the {} encoding",
+            outcome.path.display(),
+            encoding
+        );
+        return Ok(());
+    }
+
+    // Extract the archive.
+    let extracted = extraction_folder(&outcome.path);
+    let filter = args.extract_only.clone().unwrap_or_default();
+    extract_zip_filtered(&outcome.path, &extracted, &filter)?;
+    entry.extracted = Some(extraction_folder(&entry.path));
+    let shapefile = match find_shapefile(&extracted)? {
+        Some(shapefile) => shapefile,
+        None => return Ok(()),
+    };
+
+    // Compute the statistics of the network.
+    if args.stats && outcome.download.dataset == Dataset::NeighborhoodWays {
+        let stats = WaysStats::from_shapefile(&shapefile)?;
+        if args.output_format == OutputFormat::Text && !args.quiet {
+            println!("{}: {}", entry.path.display(), stats);
+        }
+        entry.stats = Some(stats);
+    }
+
+    // Render the thumbnail of the network. It is only a preview, so that a
+    // failure does not prevent the rest of the post-processing.
+    #[cfg(feature = "thumbnail")]
+    if args.thumbnail && outcome.download.dataset == Dataset::NeighborhoodWays {
+        let thumbnail = thumbnail_path(&outcome.path);
+        match render_ways(&shapefile, &thumbnail, &ThumbnailOptions::default()) {
+            Ok(()) => entry.thumbnail = Some(thumbnail_path(&entry.path)),
+            Err(e) => eprintln!("Failed to render {}: {}", thumbnail.display(), e),
+        }
+    }
+
+    // Compute the bounding box of the geometries.
+    if args.compute_bbox {
+        entry.bbox = Some(BoundingBox::from_shapefile(&shapefile)?);
+    }
 
-    // Start the download operations.
-    let _dl_result = downloader.download(&downloads);
-    // dbg!(&dl_result);
+    // Convert the dataset, clipping it if needed.
+    if let Some(format) = args.convert {
+        let output = output_path(&shapefile, format.into());
+        match format.into() {
+            Format::GeoJson => to_geojson(&shapefile, &output, options)?,
+        };
+        entry.converted = output
+            .strip_prefix(&args.destination_folder)
+            .ok()
+            .map(Path::to_path_buf);
+    }
+
+    Ok(())
+}
+
+/// Validate a CSV file of cities, and list its problems.
+fn check(args: CheckArgs) -> Result<(), Report> {
+    let problems = check_file(&args.cities)
+        .wrap_err_with(|| format!("cannot read {}", args.cities.display()))?;
+    for problem in &problems {
+        println!("{}:{}", args.cities.display(), problem);
+    }
+    let errors = problems
+        .iter()
+        .filter(|p| p.severity == Severity::Error)
+        .count();
+    let warnings = problems.len() - errors;
+    eprintln!("{} error(s), {} warning(s).", errors, warnings);
+
+    if errors > 0 || (args.deny_warnings && warnings > 0) {
+        return Err(eyre!("{} is invalid", args.cities.display()));
+    }
+    Ok(())
+}
+
+/// List the cities of a CSV file.
+fn list(args: ListArgs) -> Result<(), Report> {
+    let mut cities = Cities::from_csv(&args.cities)?;
+    #[cfg(feature = "collation")]
+    let collation = match &args.locale {
+        Some(locale) => Collation::for_locale(locale)?,
+        None => Collation::new(),
+    };
+    #[cfg(not(feature = "collation"))]
+    let collation = Collation::new();
+    if args.sort {
+        let mut sorted: Vec<City> = cities.into_iter().collect();
+        sorted.sort_by(|a, b| collation.compare(&a.name, &b.name));
+        cities = Cities::new(sorted);
+    }
+    if !args.tree {
+        for city in &cities {
+            println!("{}", city);
+        }
+        return Ok(());
+    }
+    let mut countries: Vec<_> = cities.group_by_state().into_iter().collect();
+    countries.sort_by(|a, b| collation.compare(&a.0, &b.0));
+    for (country, states) in countries {
+        let count: usize = states.values().map(Cities::len).sum();
+        println!("{} ({})", country, count);
+        let mut states: Vec<_> = states.into_iter().collect();
+        states.sort_by(|a, b| collation.compare(&a.0, &b.0));
+        for (state, cities) in states {
+            // Countries without states use their own name as state.
+            let indent = if state == country {
+                "  "
+            } else {
+                println!("  {} ({})", state, cities.len());
+                "    "
+            };
+            for city in &cities {
+                println!("{}{}", indent, city.name);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Export the city inventory or the scores of a CSV file as a table.
+#[cfg(feature = "parquet")]
+fn export(args: ExportArgs) -> Result<(), Report> {
+    let file = File::create(&args.output)?;
+    match (args.table, args.format) {
+        (Table::Cities, TableFormat::Parquet) => {
+            let cities = Cities::from_csv(&args.cities)?;
+            table::write_cities(cities.as_slice(), file)?;
+        }
+        (Table::Scores, TableFormat::Parquet) => {
+            let scorecards = ScoreCard::from_csv(&args.cities)?;
+            table::write_scorecards(&scorecards, file)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Load the tabular datasets of a download folder into a SQLite database.
+#[cfg(feature = "sqlite")]
+fn load(args: LoadArgs) -> Result<(), Report> {
+    let mut connection = Connection::open(&args.db)?;
+    let report = load_folder(&args.dir, &mut connection)?;
+    eprintln!(
+        "Loaded {} rows from {} file(s) into {}.",
+        report.rows,
+        report.files,
+        args.db.display()
+    );
+
+    Ok(())
+}
+
+/// Load the extracted geometries of a download folder into a PostGIS database.
+///
+/// The PostgreSQL client runs its own runtime, so it must be called from a
+/// blocking task.
+#[cfg(feature = "postgis")]
+fn postgis(args: PostgisArgs) -> Result<(), Report> {
+    let mut client = postgres::Client::connect(&args.url, postgres::NoTls)?;
+    let report = load_geometries(&args.dir, &mut client)?;
+    eprintln!(
+        "Loaded {} features from {} file(s).",
+        report.features, report.files
+    );
+
+    Ok(())
+}
+
+/// Build vector tiles from the neighborhood ways of a download folder.
+#[cfg(feature = "tiles")]
+fn tiles(args: TilesArgs) -> Result<(), Report> {
+    let options = TileOptions {
+        min_zoom: args.min_zoom,
+        max_zoom: args.max_zoom,
+        layer: args.layer,
+        tippecanoe: args.tippecanoe,
+    };
+    let count = build_tiles(&args.dir, &args.output, &options)?;
+    eprintln!(
+        "Built the tiles of {} dataset(s) into {}.",
+        count,
+        args.output.display()
+    );
+
+    Ok(())
+}
+
+/// Report which datasets of a list of cities are present in a download folder.
+fn status(args: StatusArgs) -> Result<(), Report> {
+    let cities = Cities::from_csv(&args.cities)?;
+    let datasets: Vec<Dataset> = if args.datasets.is_empty() {
+        CliDataset::value_variants()
+            .iter()
+            .map(|&d| d.into())
+            .collect()
+    } else {
+        args.datasets.iter().map(|&d| d.into()).collect()
+    };
+    let manifest = Manifest::load(&args.dir)?;
+    let report = CoverageReport::new(&cities, &datasets, &manifest, &args.dir);
+    for entry in &report.entries {
+        match &entry.coverage {
+            Coverage::Present if args.all => {
+                println!("PRESENT  {} for {}", entry.dataset, entry.city)
+            }
+            Coverage::Present => (),
+            Coverage::Stale(uuid) => println!(
+                "STALE    {} for {} (uuid {} retrieved)",
+                entry.dataset, entry.city, uuid
+            ),
+            Coverage::Missing => println!("MISSING  {} for {}", entry.dataset, entry.city),
+        }
+    }
+    eprintln!(
+        "{} dataset(s) present, {} stale, {} missing.",
+        report.present(),
+        report.stale(),
+        report.missing()
+    );
+
+    Ok(())
+}
+
+/// Verify the files of a download folder against their checksums.
+fn verify(args: VerifyArgs) -> Result<(), Report> {
+    let checksums = args
+        .checksums
+        .unwrap_or_else(|| args.folder.join(SHA256SUMS));
+    let sums = checksum::read_sums(&checksums)?;
+    let verification = checksum::verify(&args.folder, &sums)?;
+    report_verification(&verification)
+}
+
+/// Verify the signatures of the manifest and the checksums of a download
+/// folder, then the files listed in the manifest.
+#[cfg(feature = "sign")]
+fn verify_manifest(args: VerifyManifestArgs) -> Result<(), Report> {
+    let public_key = signature::read_public_key(&args.public_key)?;
+    let manifest_path = args.folder.join(MANIFEST);
+    signature::verify_file(&manifest_path, &public_key)?;
+    eprintln!("Valid signature of {}.", manifest_path.display());
+    // The checksums are signed along with the manifest, so that a missing
+    // signature means the file was tampered with.
+    let sums_path = args.folder.join(SHA256SUMS);
+    if sums_path.is_file() {
+        signature::verify_file(&sums_path, &public_key)?;
+        eprintln!("Valid signature of {}.", sums_path.display());
+    }
+
+    // The manifest is trusted from now on.
+    let manifest = Manifest::load(&args.folder)?;
+    let sums: BTreeMap<PathBuf, String> = manifest
+        .files
+        .iter()
+        .filter_map(|e| Some((e.path.clone(), e.sha256.clone()?)))
+        .collect();
+    let verification = checksum::verify(&args.folder, &sums)?;
+    report_verification(&verification)
+}
+
+/// Print the files which failed a verification.
+fn report_verification(verification: &Verification) -> Result<(), Report> {
+    for path in &verification.mismatched {
+        println!("{}: FAILED", path.display());
+    }
+    for path in &verification.missing {
+        println!("{}: MISSING", path.display());
+    }
+    eprintln!(
+        "{} file(s) verified, {} mismatched, {} missing.",
+        verification.verified.len(),
+        verification.mismatched.len(),
+        verification.missing.len()
+    );
+
+    if !verification.is_ok() {
+        return Err(eyre!("verification failed"));
+    }
+    Ok(())
+}
+
+/// Evict files from a download folder.
+fn clean(args: CleanArgs) -> Result<(), Report> {
+    let policy = EvictionPolicy {
+        older_than: args.older_than,
+        max_size: args.max_size,
+    };
+    let _lock = match args.dry_run {
+        true => None,
+        false => Some(FolderLock::acquire(&args.folder, false)?),
+    };
+    let eviction = policy.apply(&args.folder, args.dry_run)?;
+    for path in &eviction.removed {
+        println!("{}", path.display());
+    }
+    eprintln!(
+        "{} {} file(s), {} byte(s) reclaimed, {} byte(s) remaining.",
+        if args.dry_run {
+            "Would evict"
+        } else {
+            "Evicted"
+        },
+        eviction.removed.len(),
+        eviction.reclaimed,
+        eviction.remaining
+    );
+
+    Ok(())
+}
+
+/// Record the datasets of a folder populated by other means in its manifest.
+fn adopt_folder(args: AdoptArgs) -> Result<(), Report> {
+    let cities = Cities::from_csv(&args.cities)?;
+    let datasets: Vec<Dataset> = if args.datasets.is_empty() {
+        CliDataset::value_variants()
+            .iter()
+            .map(|&d| d.into())
+            .collect()
+    } else {
+        args.datasets.iter().map(|&d| d.into()).collect()
+    };
+    let external = args.manifest.as_ref().map(Manifest::read).transpose()?;
+    let _lock = FolderLock::acquire(&args.folder, false)?;
+    let mut manifest = Manifest::load(&args.folder)?;
+    let adoption = adopt(
+        &args.folder,
+        &cities,
+        &datasets,
+        &manifest,
+        external.as_ref(),
+    )?;
+    for entry in &adoption.adopted {
+        println!(
+            "ADOPTED   {} ({} for {})",
+            entry.path.display(),
+            entry.dataset,
+            entry.city
+        );
+    }
+    for path in &adoption.leftovers {
+        println!("LEFTOVER  {}", path.display());
+    }
+    eprintln!(
+        "{} {} file(s), {} leftover(s).",
+        if args.dry_run {
+            "Would adopt"
+        } else {
+            "Adopted"
+        },
+        adoption.adopted.len(),
+        adoption.leftovers.len()
+    );
+    if !args.dry_run && !adoption.adopted.is_empty() {
+        for entry in adoption.adopted {
+            manifest.upsert(entry);
+        }
+        manifest.save(&args.folder)?;
+    }
+
+    Ok(())
+}
+
+/// Build the client reading the remote datasets, authenticated with the stored
+/// tokens of the storages located at `urls`, if any.
+fn remote_client(urls: &[&Url]) -> Result<HttpClient, Report> {
+    let builder = Downloader::builder();
+    #[cfg(feature = "keyring")]
+    let builder = urls.iter().fold(builder, |builder, url| {
+        match (credentials::load(url), host_key(url)) {
+            (Ok(Some(token)), Some(host)) => builder.bearer_token(host, token),
+            (Err(e), _) => {
+                eprintln!("Cannot read the token of {}: {}", url, e);
+                builder
+            }
+            _ => builder,
+        }
+    });
+    #[cfg(not(feature = "keyring"))]
+    let _ = urls;
+
+    Ok(builder.client()?)
+}
+
+/// Return the URL of a remote dataset.
+fn remote_url(args: &RemoteArgs) -> Result<Url, Report> {
+    Ok(remote_download(args)?.url)
+}
+
+/// Return the download of the remote dataset identified by the arguments.
+fn remote_download(args: &RemoteArgs) -> Result<PlannedDownload, Report> {
+    let dataset: Dataset = args.dataset.into();
+    if let Some(uuid) = &args.uuid {
+        let mut plan = Plan::from_uuid(uuid, None, &[dataset])?;
+        return Ok(plan.downloads.remove(0));
+    }
+    let (Some(name), Some(path)) = (&args.city, &args.from_csv) else {
+        return Err(eyre!(
+            "either --city and --from-csv, or --uuid must be specified"
+        ));
+    };
+    let cities = Cities::from_csv(path)?;
+    let city = cities
+        .get_by_full_name(name)
+        .or_else(|| cities.get_by_name(name))
+        .ok_or_else(|| eyre!("no city named {:?}", name))?;
+
+    Ok(PlannedDownload {
+        city: city.clone(),
+        run: city.run.clone(),
+        dataset,
+        url: city.run.url(dataset)?,
+        file_name: PathBuf::from(format!("{}.{}", dataset, dataset.extension())),
+    })
+}
+
+/// Download a dataset with each combination of segments and parallel
+/// requests, and recommend the settings of the fastest one.
+async fn bench(args: BenchArgs) -> Result<(), Report> {
+    let planned = remote_download(&args.remote)?;
+    let folder = args
+        .scratch_dir
+        .clone()
+        .unwrap_or_else(env::temp_dir)
+        .join(format!("retrieve-bench-{}", process::id()));
+    let mut trials = Vec::new();
+    println!(
+        "{:>8}  {:>11}  {:>12}  {:>10}  FAILURES",
+        "SEGMENTS", "CONCURRENCY", "THROUGHPUT", "DURATION"
+    );
+    for &concurrency in &args.concurrency {
+        for &segments in &args.segments {
+            let trial = bench::run_trial(&planned, segments, concurrency, &folder).await?;
+            println!(
+                "{:>8}  {:>11}  {:>10}/s  {:>9.1}s  {}",
+                trial.segments,
+                trial.concurrency,
+                HumanBytes(trial.throughput() as u64),
+                trial.elapsed.as_secs_f64(),
+                trial.failures
+            );
+            trials.push(trial);
+        }
+    }
+    let _ = fs::remove_dir_all(&folder);
+    match bench::recommend(&trials) {
+        Some(trial) => eprintln!(
+            "Recommended settings: --parallel-requests {} --segments {} ({}/s).",
+            trial.concurrency,
+            trial.segments,
+            HumanBytes(trial.throughput() as u64)
+        ),
+        None => return Err(eyre!("every trial failed")),
+    }
+
+    Ok(())
+}
+
+/// Compare the datasets of two storages, and report the ones which differ.
+///
+/// Fail if any dataset differs.
+async fn compare_remotes(args: CompareRemotesArgs) -> Result<(), Report> {
+    let datasets: Vec<Dataset> = if args.datasets.is_empty() {
+        CliDataset::value_variants()
+            .iter()
+            .map(|&d| d.into())
+            .collect()
+    } else {
+        args.datasets.iter().map(|&d| d.into()).collect()
+    };
+    let plan = Plan::from_csv(&args.cities, &Selection::default(), &datasets)?;
+    let client = remote_client(&[&args.left, &args.right])?;
+    let mut layout = match args.discover_layout {
+        true => discover_layout(&client, &args.left).await?,
+        false => Layout::new(),
+    };
+    for (dataset, pattern) in &args.layout {
+        layout.set(*dataset, pattern.clone());
+    }
+    let comparisons = drift::compare_remotes(
+        &client,
+        &plan,
+        &layout,
+        &args.left,
+        &args.right,
+        args.parallel_requests as usize,
+    )
+    .await?;
+    let mut drifted = 0;
+    for comparison in &comparisons {
+        let download = &comparison.download;
+        let label = match comparison.drift() {
+            Drift::Identical if args.all => "SAME",
+            Drift::Identical => continue,
+            Drift::MissingLeft => "MISSING-LEFT",
+            Drift::MissingRight => "MISSING-RIGHT",
+            Drift::MissingBoth => "MISSING",
+            Drift::Size => "SIZE",
+            Drift::Etag => "ETAG",
+        };
+        if comparison.drift() != Drift::Identical {
+            drifted += 1;
+        }
+        println!(
+            "{:<13}  {} for {} (left: {}, right: {})",
+            label, download.dataset, download.city, comparison.left, comparison.right
+        );
+    }
+    eprintln!(
+        "{} dataset(s) compared, {} differ.",
+        comparisons.len(),
+        drifted
+    );
+    if drifted > 0 {
+        return Err(eyre!("the storages differ"));
+    }
+
+    Ok(())
+}
+
+/// Assemble the reproduction bundle of a city of a download folder, or
+/// inspect or extract a bundle.
+#[cfg(feature = "bundle")]
+fn bundle(args: BundleArgs) -> Result<(), Report> {
+    match args.command {
+        Some(BundleCommand::Inspect(args)) => return inspect_bundle(args),
+        Some(BundleCommand::Extract(args)) => return extract_bundle(args),
+        None => (),
+    }
+    let (Some(city), Some(output)) = (&args.city, &args.output) else {
+        return Err(eyre!("--city and --output must be specified"));
+    };
+    let manifest = Manifest::load(&args.folder)?;
+    let entries = bundle::city_entries(&manifest, city)?;
+    let file =
+        File::create(output).wrap_err_with(|| format!("cannot create {}", output.display()))?;
+    if let Err(e) = bundle::write_bundle(&args.folder, &entries, io::BufWriter::new(file)) {
+        let _ = fs::remove_file(output);
+        return Err(e.into());
+    }
+    eprintln!(
+        "Bundled {} files of {} into {}.",
+        entries.len(),
+        entries[0].city,
+        output.display()
+    );
+
+    Ok(())
+}
+
+/// List the members of a bundle, and verify them against its checksums.
+#[cfg(feature = "bundle")]
+fn inspect_bundle(args: BundleInspectArgs) -> Result<(), Report> {
+    let file = File::open(&args.bundle)
+        .wrap_err_with(|| format!("cannot open {}", args.bundle.display()))?;
+    let content = bundle::inspect(io::BufReader::new(file))?;
+    if let Some(entry) = content.manifest.files.first() {
+        eprintln!("Bundle of {} (run {}).", entry.city, entry.uuid);
+    }
+    println!("{:>12}  NAME", "SIZE");
+    for (path, size) in &content.members {
+        println!("{:>12}  {}", HumanBytes(*size).to_string(), path.display());
+    }
+    report_verification(&content.verification)
+}
+
+/// Verify a bundle, and extract it into a download folder.
+#[cfg(feature = "bundle")]
+fn extract_bundle(args: BundleExtractArgs) -> Result<(), Report> {
+    let file = File::open(&args.bundle)
+        .wrap_err_with(|| format!("cannot open {}", args.bundle.display()))?;
+    let _lock = FolderLock::acquire(&args.folder, false)?;
+    let content = bundle::extract(io::BufReader::new(file), &args.folder)?;
+    report_verification(&content.verification)?;
+    eprintln!(
+        "Extracted {} datasets into {}.",
+        content.manifest.files.len(),
+        args.folder.display()
+    );
+
+    Ok(())
+}
+
+/// Preview a remote dataset: the first rows of the text files, or the members
+/// of the zip archives.
+async fn peek(args: PeekArgs) -> Result<(), Report> {
+    let dataset: Dataset = args.remote.dataset.into();
+    let url = remote_url(&args.remote)?;
+    let file = RemoteFile::open(&remote_client(&[&url])?, url).await?;
+    if dataset.extension() == "zip" {
+        for entry in file.zip_entries().await? {
+            println!("{}", entry.name);
+        }
+    } else {
+        for line in file.head(args.rows + 1, PEEK_BYTES).await? {
+            println!("{}", line);
+        }
+    }
+
+    Ok(())
+}
+
+/// List the members of a remote zip archive, with their sizes.
+async fn ls(args: LsArgs) -> Result<(), Report> {
+    let url = remote_url(&args.remote)?;
+    let file = RemoteFile::open(&remote_client(&[&url])?, url).await?;
+    let filter = args.only.unwrap_or_default();
+    let entries: Vec<ZipEntry> = file
+        .zip_entries()
+        .await?
+        .into_iter()
+        .filter(|e| filter.matches(&e.name))
+        .collect();
+    let format = |size: u64| {
+        if args.bytes {
+            size.to_string()
+        } else {
+            HumanBytes(size).to_string()
+        }
+    };
+    println!("{:>12}  {:>12}  NAME", "SIZE", "COMPRESSED");
+    for entry in &entries {
+        println!(
+            "{:>12}  {:>12}  {}",
+            format(entry.size),
+            format(entry.compressed_size),
+            entry.name
+        );
+    }
+    let total: u64 = entries.iter().map(|e| e.size).sum();
+    println!(
+        "{:>12}  {:>12}  {} members",
+        format(total),
+        format(file.len()),
+        entries.len()
+    );
+
+    // Retrieve the listed members.
+    if let Some(folder) = &args.extract_to {
+        let extracted = extract_remote(&file, &filter, folder).await?;
+        eprintln!(
+            "Retrieved {} members into {}.",
+            extracted.len(),
+            folder.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Serve the datasets over an HTTP API.
+#[cfg(feature = "server")]
+async fn serve(args: ServeArgs) -> Result<(), Report> {
+    let cities = match &args.from_csv {
+        Some(csv) => Cities::from_csv(csv)?,
+        None => Cities::default(),
+    };
+    let cache = match &args.cache_dir {
+        Some(cache_dir) => Some(Cache::open(cache_dir)?),
+        None => None,
+    };
+    let downloader = Downloader::builder()
+        .destination_folder(&args.destination_folder)
+        .parallel_requests(args.parallel_requests)
+        .retries(args.retries)
+        .cache(cache)
+        .build()?;
+    let (server, queue) = Server::new(downloader, cities);
+    eprintln!("Listening on http://{}", args.listen);
+    server.serve(args.listen, queue).await?;
+
+    Ok(())
+}
+
+/// Store or remove the token of an authenticated mirror.
+#[cfg(feature = "keyring")]
+fn auth(args: AuthArgs) -> Result<(), Report> {
+    match args.command {
+        AuthCommand::Login(mirror) => {
+            let token = if io::stdin().is_terminal() {
+                eprint!("Token for {}: ", mirror.base_url);
+                Term::stderr().read_secure_line()?
+            } else {
+                let mut line = String::new();
+                io::stdin().read_line(&mut line)?;
+                line
+            };
+            let token = token.trim();
+            if token.is_empty() {
+                return Err(eyre!("empty token"));
+            }
+            credentials::store(&mirror.base_url, token)?;
+            eprintln!("Stored the token of {}.", mirror.base_url);
+        }
+        AuthCommand::Logout(mirror) => {
+            if credentials::remove(&mirror.base_url)? {
+                eprintln!("Removed the token of {}.", mirror.base_url);
+            } else {
+                eprintln!("No token stored for {}.", mirror.base_url);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Update the binary from the GitHub releases.
+///
+/// The update is blocking, and must not run on the asynchronous runtime.
+#[cfg(feature = "self-update")]
+fn self_update(args: SelfUpdateArgs) -> Result<(), Report> {
+    let current = self_update::cargo_crate_version!();
+    let mut builder = self_update::backends::github::Update::configure();
+    builder
+        .repo_owner("PeopleForBikes")
+        .repo_name("retrieve")
+        .bin_name("retrieve")
+        .current_version(current)
+        .show_download_progress(true)
+        .no_confirm(args.yes);
+    if let Some(version) = &args.version {
+        builder.target_version_tag(version);
+    }
+    let update = builder.build()?;
+
+    if args.check {
+        let latest = update.get_latest_release()?;
+        if self_update::version::bump_is_greater(current, &latest.version)? {
+            println!(
+                "retrieve {} is available (current: {}).",
+                latest.version, current
+            );
+        } else {
+            println!("retrieve {} is up to date.", current);
+        }
+        return Ok(());
+    }
+
+    let status = update.update()?;
+    if status.updated() {
+        eprintln!("Updated retrieve from {} to {}.", current, status.version());
+    } else {
+        eprintln!("retrieve {} is up to date.", current);
+    }
 
     Ok(())
 }
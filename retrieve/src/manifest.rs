@@ -0,0 +1,365 @@
+//! Describe the content of a download folder.
+//!
+//! The manifest is a JSON file, stored at the root of the download folder,
+//! which records where each file comes from along with the metadata computed
+//! after its retrieval.
+//!
+//! During a batch, each retrieved file can be recorded as soon as it completes
+//! with a [`Checkpoint`], so that the manifest reflects the progress of a
+//! batch which is interrupted or killed. The files are appended to a journal,
+//! replayed when the manifest is loaded, and cleared when it is saved.
+use crate::bbox::BoundingBox;
+use crate::checksum;
+use crate::download::{DownloadOutcome, DownloadStatus};
+use crate::inventory::InventorySource;
+use crate::plan::PlannedDownload;
+use crate::provenance;
+use crate::stats::WaysStats;
+use crate::timestamp;
+use crate::Error;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Name of the manifest file.
+pub const MANIFEST: &str = "manifest.json";
+
+/// Name of the journal of the files recorded since the manifest was saved.
+pub const JOURNAL: &str = "manifest.journal";
+
+/// Describe a retrieved file.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Path of the file, relative to the download folder.
+    pub path: PathBuf,
+    /// Name of the city.
+    pub city: String,
+    /// State of the city.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub state: String,
+    /// Country of the city.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub country: String,
+    /// Run UUID.
+    pub uuid: String,
+    /// Name of the dataset.
+    pub dataset: String,
+    /// Size of the file, in bytes.
+    pub size: u64,
+    /// SHA256 hash of the file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// SHA256 hash of the local file the file replaced, if its content was
+    /// different.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_sha256: Option<String>,
+    /// URL the file was downloaded from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// ETag announced by the server the file was downloaded from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// Content encoding of the file, e.g. "gzip", if it is stored as
+    /// compressed by the server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_encoding: Option<String>,
+    /// Folder the archive was extracted to, relative to the download folder.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extracted: Option<PathBuf>,
+    /// Converted version of the dataset, relative to the download folder.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub converted: Option<PathBuf>,
+    /// Thumbnail of the dataset, relative to the download folder.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<PathBuf>,
+    /// Statistics of a `neighborhood_ways` dataset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<WaysStats>,
+    /// Bounding box of the extracted geometry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bbox: Option<BoundingBox>,
+    /// Time the file content was first retrieved, as an RFC 3339 timestamp.
+    ///
+    /// The timestamp is kept as long as the content does not change, and can
+    /// be set with `SOURCE_DATE_EPOCH`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retrieved_at: Option<String>,
+}
+
+impl ManifestEntry {
+    /// Describe the file of a successful download.
+    ///
+    /// Return `None` if the download failed, was interrupted or timed out.
+    pub fn from_outcome(outcome: &DownloadOutcome) -> Result<Option<Self>, Error> {
+        if let DownloadStatus::Failed(_) | DownloadStatus::Interrupted | DownloadStatus::TimedOut =
+            outcome.status
+        {
+            return Ok(None);
+        }
+        let download = &outcome.download;
+
+        Ok(Some(ManifestEntry {
+            path: download.file_name.clone(),
+            city: download.city.name.clone(),
+            state: download.city.state.clone(),
+            country: download.city.country.clone(),
+            uuid: download.run.uuid.clone(),
+            dataset: download.dataset.to_string(),
+            size: fs::metadata(&outcome.path)?.len(),
+            sha256: outcome.sha256.clone(),
+            previous_sha256: outcome.previous_sha256.clone(),
+            source: outcome.source.as_ref().map(|u| u.to_string()),
+            etag: outcome.etag.clone(),
+            content_encoding: outcome.content_encoding.clone(),
+            extracted: None,
+            converted: None,
+            thumbnail: None,
+            stats: None,
+            bbox: None,
+            retrieved_at: Some(timestamp::rfc3339(timestamp::now())),
+        }))
+    }
+}
+
+/// Represent the manifest of a download folder.
+///
+/// The manifest is reproducible: the entries are sorted by path, and only
+/// change along with the files they describe.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Version of the city inventory the last batch was planned from, if it
+    /// is hosted in Git. It is cleared by the batches planned otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inventory: Option<InventorySource>,
+    /// Retrieved files, sorted by path.
+    pub files: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// Load the manifest of `folder`.
+    ///
+    /// Return an empty manifest if the folder does not have one yet.
+    pub fn load<P>(folder: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let folder = folder.as_ref();
+        let path = folder.join(MANIFEST);
+        let mut manifest = match path.exists() {
+            true => Manifest::read(path)?,
+            false => Manifest::default(),
+        };
+
+        // Replay the files recorded by a batch which did not complete. A line
+        // interrupted while being written is ignored.
+        let journal = folder.join(JOURNAL);
+        if journal.exists() {
+            for line in fs::read_to_string(journal)?.lines() {
+                if let Ok(entry) = serde_json::from_str(line) {
+                    manifest.upsert(entry);
+                }
+            }
+        }
+
+        Ok(manifest)
+    }
+
+    /// Read the manifest file located at `path`.
+    pub fn read<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let content = fs::read_to_string(path)?;
+        let mut manifest: Manifest = serde_json::from_str(&content)?;
+        // The manifests edited by hand or by older versions may not be sorted.
+        manifest.files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(manifest)
+    }
+
+    /// Save the manifest into `folder`.
+    ///
+    /// The content only depends on the entries, so that saving the same
+    /// manifest twice produces the same file.
+    pub fn save<P>(&self, folder: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = folder.as_ref().join(MANIFEST);
+        let tmp = path.with_extension("tmp");
+        let mut content = serde_json::to_string_pretty(self)?;
+        content.push('\n');
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, &path)?;
+
+        // The journaled files are part of the saved manifest.
+        match fs::remove_file(folder.as_ref().join(JOURNAL)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Return the entry describing the file located at `path`.
+    pub fn get<P>(&self, path: P) -> Option<&ManifestEntry>
+    where
+        P: AsRef<Path>,
+    {
+        self.files.iter().find(|e| e.path == path.as_ref())
+    }
+
+    /// Return `true` if a planned download was already retrieved from the same
+    /// run, and is still present in `folder`.
+    pub fn is_current<P>(&self, planned: &PlannedDownload, folder: P) -> bool
+    where
+        P: AsRef<Path>,
+    {
+        self.get(&planned.file_name).is_some_and(|e| {
+            e.uuid.eq_ignore_ascii_case(&planned.run.uuid)
+                && e.dataset == planned.dataset.to_string()
+                && folder.as_ref().join(&e.path).is_file()
+        })
+    }
+
+    /// Return the SHA256 hash recorded for the `dataset` of the run `uuid`, if
+    /// any.
+    pub fn sha256(&self, uuid: &str, dataset: &str) -> Option<&str> {
+        self.files
+            .iter()
+            .filter(|e| e.uuid.eq_ignore_ascii_case(uuid) && e.dataset == dataset)
+            .find_map(|e| e.sha256.as_deref())
+    }
+
+    /// Return the bounding box recorded for the run `uuid`, if any.
+    pub fn bbox(&self, uuid: &str) -> Option<BoundingBox> {
+        self.files
+            .iter()
+            .filter(|e| e.uuid.eq_ignore_ascii_case(uuid))
+            .find_map(|e| e.bbox)
+    }
+
+    /// Add an entry, replacing the one describing the same file if any.
+    ///
+    /// If the file content did not change, the metadata which were computed
+    /// previously but not this time are kept.
+    pub fn upsert(&mut self, mut entry: ManifestEntry) {
+        match self.files.binary_search_by(|e| e.path.cmp(&entry.path)) {
+            Ok(i) => {
+                let previous = &self.files[i];
+                if previous.sha256.is_some() && previous.sha256 == entry.sha256 {
+                    entry.previous_sha256 = entry
+                        .previous_sha256
+                        .or_else(|| previous.previous_sha256.clone());
+                    entry.extracted = entry.extracted.or_else(|| previous.extracted.clone());
+                    entry.converted = entry.converted.or_else(|| previous.converted.clone());
+                    entry.thumbnail = entry.thumbnail.or_else(|| previous.thumbnail.clone());
+                    entry.stats = entry.stats.or_else(|| previous.stats.clone());
+                    entry.bbox = entry.bbox.or(previous.bbox);
+                    entry.retrieved_at = previous.retrieved_at.clone().or(entry.retrieved_at);
+                    entry.source = entry.source.or_else(|| previous.source.clone());
+                    entry.etag = entry.etag.or_else(|| previous.etag.clone());
+                }
+                self.files[i] = entry;
+            }
+            Err(i) => self.files.insert(i, entry),
+        }
+    }
+
+    /// Return the file located at `path` along with its checksum and
+    /// provenance sidecars, and the extracted folder, the converted file and
+    /// the thumbnail recorded for it, relative to the download folder.
+    pub fn artifacts<P>(&self, path: P) -> Vec<PathBuf>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let mut artifacts = vec![
+            path.to_path_buf(),
+            checksum::sidecar_path(path),
+            provenance::sidecar_path(path),
+        ];
+        if let Some(entry) = self.get(path) {
+            artifacts.extend(entry.extracted.iter().cloned());
+            artifacts.extend(entry.converted.iter().cloned());
+            artifacts.extend(entry.thumbnail.iter().cloned());
+        }
+
+        artifacts
+    }
+
+    /// Delete the files located at `paths` from `folder`, along with their
+    /// [artifacts](Manifest::artifacts), and remove their entries.
+    ///
+    /// The missing files are ignored. The manifest is not saved.
+    pub fn remove<P>(&mut self, folder: P, paths: &[PathBuf]) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let folder = folder.as_ref();
+        for path in paths {
+            for artifact in self.artifacts(path) {
+                let artifact = folder.join(artifact);
+                let result = match artifact.is_dir() {
+                    true => fs::remove_dir_all(&artifact),
+                    false => fs::remove_file(&artifact),
+                };
+                match result {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                    _ => {}
+                }
+            }
+            self.files.retain(|e| e.path != *path);
+        }
+
+        Ok(())
+    }
+}
+
+/// Record the retrieved files in the manifest of a folder as they complete.
+///
+/// Each file is appended to the journal of the manifest, one JSON entry per
+/// line, so that recording a file does not depend on the size of the
+/// manifest. The checkpoint can be cloned and shared between tasks.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    path: PathBuf,
+    journal: Arc<Mutex<File>>,
+}
+
+impl Checkpoint {
+    /// Open the journal of the manifest of `folder`, creating the folder if
+    /// needed.
+    pub fn open<P>(folder: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let folder = folder.as_ref();
+        fs::create_dir_all(folder)?;
+        let path = folder.join(JOURNAL);
+        let journal = OpenOptions::new().create(true).append(true).open(&path)?;
+
+        Ok(Checkpoint {
+            path,
+            journal: Arc::new(Mutex::new(journal)),
+        })
+    }
+
+    /// Append the file of a download to the journal.
+    ///
+    /// The failed and interrupted downloads are ignored.
+    pub fn record(&self, outcome: &DownloadOutcome) -> Result<(), Error> {
+        let Some(entry) = ManifestEntry::from_outcome(outcome)? else {
+            return Ok(());
+        };
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        let mut journal = self
+            .journal
+            .lock()
+            .map_err(|_| Error::Parse(format!("journal {} poisoned", self.path.display())))?;
+        journal.write_all(line.as_bytes())?;
+
+        Ok(())
+    }
+}
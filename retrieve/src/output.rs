@@ -0,0 +1,172 @@
+//! Render the human readable output of the command line interface.
+use console::{style, Color};
+use indicatif::HumanBytes;
+use retrieve::anomaly::SizeAnomaly;
+use retrieve::download::{DownloadOutcome, DownloadReport, DownloadStatus};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::env;
+
+/// Disable the colors if `no_color` is set, or if the `NO_COLOR` environment
+/// variable is set and not empty.
+///
+/// Refer to <https://no-color.org> for the convention.
+pub fn setup_colors(no_color: bool) {
+    let disabled = no_color || env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    if disabled {
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    }
+}
+
+/// Print the status of each city of a report, as aligned columns.
+///
+/// A city is OK if all its datasets were retrieved, FAILED if none of them
+/// was, TIMEOUT if its time budget was exhausted, and PARTIAL otherwise.
+pub fn print_cities(report: &DownloadReport) {
+    // Group the outcomes by city, sorted by name.
+    let mut cities: BTreeMap<String, Vec<&DownloadOutcome>> = BTreeMap::new();
+    for outcome in &report.outcomes {
+        cities
+            .entry(outcome.download.city.to_string())
+            .or_default()
+            .push(outcome);
+    }
+    if cities.is_empty() {
+        return;
+    }
+
+    let width = cities.keys().map(|c| c.chars().count()).max().unwrap_or(0);
+    eprintln!("{:<7}  {:<width$}  DATASETS", "STATUS", "CITY");
+    for (city, outcomes) in &cities {
+        let retrieved = outcomes.iter().filter(|o| is_retrieved(o)).count();
+        let (label, color) = if retrieved == outcomes.len() {
+            ("OK", Color::Green)
+        } else if retrieved == 0 && outcomes.iter().all(|o| is_failed(o)) {
+            ("FAILED", Color::Red)
+        } else if outcomes
+            .iter()
+            .any(|o| o.status == DownloadStatus::TimedOut)
+        {
+            ("TIMEOUT", Color::Yellow)
+        } else {
+            ("PARTIAL", Color::Yellow)
+        };
+        let datasets: Vec<String> = outcomes.iter().map(|o| describe(o)).collect();
+        eprintln!(
+            "{}  {:<width$}  {}",
+            style(format!("{:<7}", label)).for_stderr().fg(color),
+            city,
+            datasets.join(", ")
+        );
+    }
+}
+
+/// Print the datasets whose size changed suspiciously since the previous run.
+pub fn print_anomalies(anomalies: &[SizeAnomaly]) {
+    if anomalies.is_empty() {
+        return;
+    }
+    eprintln!(
+        "{}",
+        style("Suspicious size changes since the previous run:")
+            .for_stderr()
+            .red()
+            .bold()
+    );
+    for anomaly in anomalies {
+        eprintln!(
+            "  {:>+6.0}%  {:>10} -> {:>10}  {} ({})",
+            anomaly.change() * 100.0,
+            HumanBytes(anomaly.previous_size).to_string(),
+            HumanBytes(anomaly.size).to_string(),
+            anomaly.path.display(),
+            anomaly.city
+        );
+    }
+}
+
+/// Print the report as a single line JSON document on the standard output.
+pub fn print_json(
+    report: &DownloadReport,
+    anomalies: &[SizeAnomaly],
+) -> Result<(), serde_json::Error> {
+    let files: Vec<Value> = report
+        .outcomes
+        .iter()
+        .map(|o| {
+            let city = &o.download.city;
+            let (status, reason) = match &o.status {
+                DownloadStatus::Downloaded => ("downloaded", None),
+                DownloadStatus::Cached => ("cached", None),
+                DownloadStatus::Failed(reason) => ("failed", Some(reason.as_str())),
+                DownloadStatus::Interrupted => ("interrupted", None),
+                DownloadStatus::TimedOut => ("timed_out", None),
+            };
+            json!({
+                "path": o.download.file_name,
+                "city": city.name,
+                "state": city.state,
+                "country": city.country,
+                "uuid": o.download.run.uuid,
+                "dataset": o.download.dataset.to_string(),
+                "status": status,
+                "reason": reason,
+                "source": o.source.as_ref().map(|u| u.as_str()),
+                "sha256": o.sha256,
+                "bytes": o.bytes,
+                "duration_ms": o.duration.as_millis() as u64,
+                "retries": o.retries,
+                "statuses": o.statuses,
+                "injected": o.injected,
+                "s3_error": o.s3_error.as_ref().map(|e| e.code.to_string()),
+            })
+        })
+        .collect();
+    let document = json!({
+        "files": files,
+        "bytes": report.total_bytes(),
+        "elapsed_ms": report.elapsed.as_millis() as u64,
+        "failures": report.failures().count(),
+        "timed_out": report.timed_out().count(),
+        "injected": report.injected(),
+        "anomalies": anomalies,
+    });
+    println!("{}", serde_json::to_string(&document)?);
+
+    Ok(())
+}
+
+/// Return `true` if the file of the outcome was retrieved.
+fn is_retrieved(outcome: &DownloadOutcome) -> bool {
+    matches!(
+        outcome.status,
+        DownloadStatus::Downloaded | DownloadStatus::Cached
+    )
+}
+
+/// Return `true` if the download of the outcome failed.
+fn is_failed(outcome: &DownloadOutcome) -> bool {
+    matches!(outcome.status, DownloadStatus::Failed(_))
+}
+
+/// Describe the status of a dataset, e.g. `neighborhood_ways (cached)`.
+fn describe(outcome: &DownloadOutcome) -> String {
+    let dataset = outcome.download.dataset;
+    match &outcome.status {
+        DownloadStatus::Downloaded => dataset.to_string(),
+        DownloadStatus::Cached => format!("{} (cached)", dataset),
+        DownloadStatus::Failed(reason) => style(format!("{} (failed: {})", dataset, reason))
+            .for_stderr()
+            .red()
+            .to_string(),
+        DownloadStatus::Interrupted => style(format!("{} (interrupted)", dataset))
+            .for_stderr()
+            .yellow()
+            .to_string(),
+        DownloadStatus::TimedOut => style(format!("{} (timed out)", dataset))
+            .for_stderr()
+            .yellow()
+            .to_string(),
+    }
+}
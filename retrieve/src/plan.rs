@@ -0,0 +1,240 @@
+//! Plan the datasets to retrieve.
+//!
+//! A plan lists, for each selected city and each requested dataset, where the
+//! artifact is located and where it must be saved.
+use crate::inventory::InventorySource;
+use crate::layout::Layout;
+use crate::portable::portable_path;
+#[cfg(feature = "csv")]
+use crate::selection::Selection;
+use crate::template::FileNameTemplate;
+use crate::Error;
+use pfbcore::scorecard::{City, Run};
+use pfbcore::{is_valid_uuid, Dataset, PFB_S3_STORAGE_BASE_URL};
+use std::fs;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// Represent a dataset to download for a specific city.
+#[derive(Debug, Clone)]
+pub struct PlannedDownload {
+    /// City the dataset belongs to.
+    pub city: City,
+    /// Run which produced the dataset.
+    ///
+    /// It identifies the download: the URL, the file name and the records of
+    /// the manifest are derived from it, and not from the run the `city` was
+    /// listed with.
+    pub run: Run,
+    /// Dataset to download.
+    pub dataset: Dataset,
+    /// URL of the dataset.
+    pub url: Url,
+    /// Name of the file, relative to the destination folder.
+    pub file_name: PathBuf,
+}
+
+/// Represent the list of downloads to perform.
+#[derive(Debug, Default, Clone)]
+pub struct Plan {
+    /// Downloads to perform.
+    pub downloads: Vec<PlannedDownload>,
+    /// Cities which cannot be downloaded, because they do not have a run UUID.
+    pub skipped: Vec<City>,
+    /// Version of the city inventory the plan was made from, if it is hosted
+    /// in Git.
+    pub inventory: Option<InventorySource>,
+}
+
+impl Plan {
+    /// Plan the download of the `datasets` for each of the `cities`.
+    pub fn new(cities: &[City], datasets: &[Dataset]) -> Result<Self, Error> {
+        let mut plan = Plan::default();
+        for city in cities {
+            plan.push_city(city, datasets)?;
+        }
+
+        Ok(plan)
+    }
+
+    /// Plan the download of the `datasets` for each of the cities of a CSV
+    /// file, which is read one city at a time.
+    #[cfg(feature = "csv")]
+    pub fn from_csv<P>(path: P, selection: &Selection, datasets: &[Dataset]) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut plan = Plan::default();
+        let matcher = selection.matcher();
+        for city in City::iter_csv(path)? {
+            let city = city?;
+            if matcher.matches(&city) {
+                plan.push_city(&city, datasets)?;
+            }
+        }
+
+        Ok(plan)
+    }
+
+    /// Add the download of the `datasets` for a city.
+    ///
+    /// The city is skipped if it does not have a run UUID.
+    pub fn push_city(&mut self, city: &City, datasets: &[Dataset]) -> Result<(), Error> {
+        if city.run.uuid.is_empty() {
+            self.skipped.push(city.clone());
+            return Ok(());
+        }
+        for dataset in datasets {
+            self.downloads.push(PlannedDownload {
+                city: city.clone(),
+                run: city.run.clone(),
+                dataset: *dataset,
+                url: city.run.url(*dataset)?,
+                file_name: file_name(city, *dataset),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Plan the download of the `datasets` produced by the run `uuid`.
+    ///
+    /// No city metadata is required: the files are named after `name`, or
+    /// after the UUID if no name is provided, e.g. `austin-neighborhood_ways.zip`.
+    /// The name must not contain any path separator, nor be `.` or `..`.
+    pub fn from_uuid(uuid: &str, name: Option<&str>, datasets: &[Dataset]) -> Result<Self, Error> {
+        if !is_valid_uuid(uuid) {
+            return Err(Error::Parse(format!("invalid run UUID {:?}", uuid)));
+        }
+        if let Some(name) = name {
+            if name.is_empty() || name.contains(['/', '\\']) || name == "." || name == ".." {
+                return Err(Error::Parse(format!("invalid name hint {:?}", name)));
+            }
+        }
+        let name = name.unwrap_or(uuid);
+        let city = City::new(name, "", None, uuid, 0, 0.0, 0);
+        let mut plan = Plan::default();
+        for dataset in datasets {
+            plan.downloads.push(PlannedDownload {
+                city: city.clone(),
+                run: city.run.clone(),
+                dataset: *dataset,
+                url: city.run.url(*dataset)?,
+                file_name: PathBuf::from(format!("{}-{}.{}", name, dataset, dataset.extension())),
+            });
+        }
+
+        Ok(plan)
+    }
+
+    /// Add the downloads of another plan to this one.
+    pub fn extend(&mut self, other: Plan) {
+        self.downloads.extend(other.downloads);
+        self.skipped.extend(other.skipped);
+    }
+
+    /// Name the files after a template instead of the default file names.
+    ///
+    /// Fail if a rendered file name is not relative to the destination folder.
+    pub fn rename(&mut self, template: &FileNameTemplate) -> Result<(), Error> {
+        for download in &mut self.downloads {
+            download.file_name =
+                template.render(&download.city, &download.run, download.dataset)?;
+        }
+
+        Ok(())
+    }
+
+    /// Locate the datasets in the PFB storage after a custom layout.
+    pub fn relayout(&mut self, layout: &Layout) -> Result<(), Error> {
+        for download in &mut self.downloads {
+            download.url = layout.url(
+                PFB_S3_STORAGE_BASE_URL,
+                &download.city,
+                &download.run,
+                download.dataset,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Make the file names valid on every platform, Windows included.
+    ///
+    /// Refer to [`portable_path`] for the rules.
+    pub fn make_portable(&mut self) {
+        for download in &mut self.downloads {
+            download.file_name = portable_path(&download.file_name);
+        }
+    }
+
+    /// Sort the downloads by file name, and the skipped cities by full name.
+    ///
+    /// The order of a sorted plan does not depend on the order of the cities
+    /// in the CSV file.
+    pub fn sort(&mut self) {
+        self.downloads.sort_by(|a, b| {
+            a.file_name
+                .cmp(&b.file_name)
+                .then_with(|| a.run.uuid.cmp(&b.run.uuid))
+        });
+        self.skipped.sort_by_key(|c| c.full_name());
+    }
+
+    /// Order the downloads by a `key`, in ascending order.
+    ///
+    /// The sort is stable, so the downloads with the same key keep their
+    /// relative order, e.g. the one of [`Plan::sort`].
+    pub fn prioritize<K, F>(&mut self, key: F)
+    where
+        K: Ord,
+        F: FnMut(&PlannedDownload) -> K,
+    {
+        self.downloads.sort_by_key(key);
+    }
+
+    /// Return `true` if there is nothing to download.
+    pub fn is_empty(&self) -> bool {
+        self.downloads.is_empty()
+    }
+}
+
+/// Return the priority of a city, read from the `column` of the CSV file.
+///
+/// Return `None` if the column is missing, empty or not an integer.
+pub fn priority(city: &City, column: &str) -> Option<i64> {
+    city.extra.get(column)?.trim().parse().ok()
+}
+
+/// Read a list of run UUIDs from a file.
+///
+/// The file contains one UUID per line. Blank lines and lines starting with `#`
+/// are ignored.
+pub fn read_uuids<P>(path: P) -> Result<Vec<String>, Error>
+where
+    P: AsRef<Path>,
+{
+    let content = fs::read_to_string(path)?;
+    let uuids = content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(String::from)
+        .collect();
+
+    Ok(uuids)
+}
+
+/// Return the name of the file used to store a city dataset.
+///
+/// The file name has the following format:
+/// `{COUNTRY}-{STATE}-{CITY_NAME}-{DATASET}.{EXTENSION}`. Use a
+/// [`FileNameTemplate`] to name the files differently.
+pub fn file_name(city: &City, dataset: Dataset) -> PathBuf {
+    PathBuf::from(format!(
+        "{}-{}.{}",
+        city.full_name(),
+        dataset,
+        dataset.extension()
+    ))
+}
@@ -0,0 +1,85 @@
+//! Keep the file names valid on every platform.
+//!
+//! Windows is the most restrictive platform: it reserves device names like
+//! `CON` or `LPT1` (with any extension), forbids a few characters, and limits
+//! the length of the paths to 260 characters unless they use the extended
+//! length prefix `\\?\`. Names which are valid on Windows are valid on the
+//! other platforms, so the same layout can be shared by everyone.
+use std::path::{Component, Path, PathBuf};
+
+/// Device names reserved by Windows, regardless of their extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Characters forbidden by Windows in file names.
+const FORBIDDEN_CHARS: [char; 7] = ['<', '>', ':', '"', '|', '?', '*'];
+
+/// Maximum length of a path without the extended length prefix.
+#[cfg(windows)]
+const MAX_PATH: usize = 260;
+
+/// Return a version of the relative `path` valid on every platform.
+///
+/// In each component of the path:
+///
+/// - the forbidden and control characters are replaced by `_`
+/// - the trailing dots and spaces are removed
+/// - the reserved names are suffixed with `_`, e.g. `con.zip` becomes
+///   `con_.zip`
+///
+/// The components which would escape the destination folder (`..`, roots and
+/// prefixes) are removed.
+pub fn portable_path(path: &Path) -> PathBuf {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(portable_name(&name.to_string_lossy())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Return a version of a file name valid on every platform.
+///
+/// Refer to [`portable_path`] for the rules.
+pub fn portable_name(name: &str) -> String {
+    let mut name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || FORBIDDEN_CHARS.contains(&c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    let trimmed = name.trim_end_matches(['.', ' ']).len();
+    name.truncate(trimmed);
+    if name.is_empty() {
+        return String::from("_");
+    }
+    let stem = name.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+        name.insert(stem.len(), '_');
+    }
+
+    name
+}
+
+/// Return a version of `path` which may exceed the length limit of Windows.
+///
+/// On Windows, the paths longer than the limit are made absolute, and get the
+/// extended length prefix `\\?\`. The path is returned unchanged otherwise.
+pub fn extended_length_path(path: &Path) -> PathBuf {
+    #[cfg(windows)]
+    if path.as_os_str().len() >= MAX_PATH && !path.starts_with(r"\\?\") {
+        if let Ok(absolute) = std::path::absolute(path) {
+            let mut extended = std::ffi::OsString::from(r"\\?\");
+            extended.push(absolute.as_os_str());
+            return PathBuf::from(extended);
+        }
+    }
+
+    path.to_path_buf()
+}
@@ -0,0 +1,145 @@
+//! Load the extracted geometries into a PostGIS database.
+//!
+//! The shapefile extracted from each dataset recorded in the manifest of a
+//! download folder is loaded into a table named after the dataset, e.g.
+//! `neighborhood_ways`, with the following columns:
+//!
+//! | Column                     | Description                                  |
+//! |----------------------------|----------------------------------------------|
+//! | `uuid`                     | Run UUID                                     |
+//! | `city`, `state`, `country` | City analyzed by the run                     |
+//! | `properties`               | Attributes of the feature, as `jsonb`        |
+//! | `geom`                     | Geometry, in WGS84 longitudes and latitudes  |
+//!
+//! The tables are indexed by run UUID and by geometry. Loading a run again
+//! replaces its rows, so the database can be refreshed after each fetch.
+//!
+//! The `postgis` extension is created if it is missing, which requires a
+//! superuser, or a user allowed to create it. Otherwise, the user only needs to
+//! be allowed to create tables.
+use crate::convert::{reproject, to_json};
+use crate::manifest::{Manifest, ManifestEntry};
+use crate::projection::{shapefile_projection, wgs84, Reprojection};
+use crate::stats::find_shapefile;
+use crate::Error;
+use geo_types::Geometry;
+use postgres::Client;
+use serde_json::{Map, Value};
+use std::path::Path;
+use wkt::ToWkt;
+
+/// Spatial reference identifier of the geometries, WGS84.
+pub const SRID: i32 = 4326;
+
+/// Describe the result of a load.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PostgisReport {
+    /// Number of shapefiles loaded.
+    pub files: usize,
+    /// Number of features inserted.
+    pub features: u64,
+}
+
+/// Load the extracted geometries of the download `folder` into the database.
+///
+/// The datasets which were not extracted are skipped.
+pub fn load_geometries<P>(folder: P, client: &mut Client) -> Result<PostgisReport, Error>
+where
+    P: AsRef<Path>,
+{
+    let folder = folder.as_ref();
+    let manifest = Manifest::load(folder)?;
+    let installed = client
+        .query_opt("SELECT 1 FROM pg_extension WHERE extname = 'postgis'", &[])?
+        .is_some();
+    if !installed {
+        client.batch_execute("CREATE EXTENSION postgis")?;
+    }
+    let mut report = PostgisReport::default();
+    for entry in &manifest.files {
+        let Some(extracted) = &entry.extracted else {
+            continue;
+        };
+        let Some(shapefile) = find_shapefile(folder.join(extracted))? else {
+            continue;
+        };
+        report.features += load_shapefile(client, &shapefile, entry)?;
+        report.files += 1;
+    }
+
+    Ok(report)
+}
+
+/// Load a shapefile, replacing the rows of its run.
+///
+/// Return the number of features inserted.
+fn load_shapefile(client: &mut Client, path: &Path, entry: &ManifestEntry) -> Result<u64, Error> {
+    let table = quote(&entry.dataset);
+    let mut transaction = client.transaction()?;
+    transaction.batch_execute(&format!(
+        "CREATE TABLE IF NOT EXISTS {table} (
+            id bigserial PRIMARY KEY,
+            uuid text NOT NULL,
+            city text,
+            state text,
+            country text,
+            properties jsonb,
+            geom geometry(Geometry, {srid})
+        );
+        CREATE INDEX IF NOT EXISTS {uuid_index} ON {table} (uuid);
+        CREATE INDEX IF NOT EXISTS {geom_index} ON {table} USING gist (geom);",
+        table = table,
+        srid = SRID,
+        uuid_index = quote(&format!("{}_uuid", entry.dataset)),
+        geom_index = quote(&format!("{}_geom", entry.dataset)),
+    ))?;
+    transaction.execute(
+        &format!("DELETE FROM {} WHERE uuid = $1", table),
+        &[&entry.uuid],
+    )?;
+    let statement = transaction.prepare(&format!(
+        "INSERT INTO {} (uuid, city, state, country, properties, geom) \
+         VALUES ($1, $2, $3, $4, $5, ST_SetSRID(ST_GeomFromText($6), {}))",
+        table, SRID
+    ))?;
+
+    // Store the geometries as WGS84 longitudes and latitudes.
+    let projection = shapefile_projection(path)?.unwrap_or_else(wgs84);
+    let reprojection = (!projection.is_latlong()).then(|| Reprojection::new(projection, wgs84()));
+    let mut reader = shapefile::Reader::from_path(path)?;
+    let mut count: u64 = 0;
+    for item in reader.iter_shapes_and_records() {
+        let (shape, record) = item?;
+        let Ok(geometry) = Geometry::<f64>::try_from(shape) else {
+            continue;
+        };
+        let geometry = match &reprojection {
+            Some(reprojection) => reproject(&geometry, reprojection)?,
+            None => geometry,
+        };
+        let properties: Map<String, Value> = record
+            .into_iter()
+            .map(|(name, value)| (name, to_json(value)))
+            .collect();
+        transaction.execute(
+            &statement,
+            &[
+                &entry.uuid,
+                &entry.city,
+                &entry.state,
+                &entry.country,
+                &Value::Object(properties),
+                &geometry.wkt_string(),
+            ],
+        )?;
+        count += 1;
+    }
+    transaction.commit()?;
+
+    Ok(count)
+}
+
+/// Quote an SQL identifier.
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
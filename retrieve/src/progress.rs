@@ -0,0 +1,115 @@
+//! Report the progress of the downloads on the terminal.
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+/// Display a progress bar for each download, and one for the whole batch.
+#[derive(Debug, Clone)]
+pub struct Progress {
+    group: MultiProgress,
+    overall: ProgressBar,
+}
+
+impl Progress {
+    /// Create the progress bars for a batch of `count` downloads.
+    ///
+    /// Nothing is displayed if `visible` is `false`.
+    pub fn new(count: u64, visible: bool) -> Self {
+        let target = if visible {
+            ProgressDrawTarget::stderr_with_hz(4)
+        } else {
+            ProgressDrawTarget::hidden()
+        };
+        let group = MultiProgress::with_draw_target(target);
+        let overall = group.add(ProgressBar::new(count));
+        overall.set_style(
+            ProgressStyle::with_template("[{bar:20.green/white}] {pos}/{len} files ({elapsed})")
+                .unwrap()
+                .progress_chars("#- "),
+        );
+
+        Progress { group, overall }
+    }
+
+    /// Add `count` downloads to the batch.
+    pub fn add(&self, count: u64) {
+        self.overall.inc_length(count);
+    }
+
+    /// Create the progress bar of a single download.
+    pub fn start(&self, message: String) -> FileProgress {
+        let bar = self.group.insert_before(&self.overall, ProgressBar::new(1));
+        bar.set_style(
+            ProgressStyle::with_template("{spinner:.blue} {msg}")
+                .unwrap()
+                .tick_strings(&[
+                    "▹▹▹▹▹",
+                    "▸▹▹▹▹",
+                    "▹▸▹▹▹",
+                    "▹▹▸▹▹",
+                    "▹▹▹▸▹",
+                    "▹▹▹▹▸",
+                    "▪▪▪▪▪",
+                ]),
+        );
+        bar.set_message(message);
+
+        FileProgress {
+            bar,
+            overall: self.overall.clone(),
+        }
+    }
+
+    /// Remove the progress bars from the terminal.
+    pub fn finish(&self) {
+        self.overall.finish_and_clear();
+    }
+}
+
+/// Display the progress of a single download.
+#[derive(Debug, Clone)]
+pub struct FileProgress {
+    bar: ProgressBar,
+    overall: ProgressBar,
+}
+
+impl FileProgress {
+    /// Set the size of the file, once it is known.
+    pub fn set_length(&self, length: Option<u64>) {
+        if let Some(length) = length {
+            self.bar.set_length(length);
+            self.bar.set_style(
+                ProgressStyle::with_template(
+                    "[{bar:20.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta}) - {msg}",
+                )
+                .unwrap()
+                .progress_chars("#- "),
+            );
+            self.bar.reset_eta();
+        }
+        self.bar.set_position(0);
+    }
+
+    /// Advance the progress by `bytes`.
+    pub fn advance(&self, bytes: u64) {
+        self.bar.inc(bytes);
+    }
+
+    /// Move the progress back by `bytes`, e.g. when a segment is retried.
+    pub fn rewind(&self, bytes: u64) {
+        self.bar.dec(bytes);
+    }
+
+    /// Mark the download as successful.
+    pub fn success(&self) {
+        self.bar.finish_and_clear();
+        self.overall.inc(1);
+    }
+
+    /// Mark the download as failed, keeping the reason on the terminal.
+    pub fn failure(&self, reason: &str) {
+        let message = format!("{} - FAILED: {}", self.bar.message(), reason);
+        self.bar
+            .set_style(ProgressStyle::with_template("{msg}").unwrap());
+        self.bar.abandon_with_message(message);
+        self.overall.inc(1);
+    }
+}
@@ -0,0 +1,85 @@
+//! Reproject coordinates between coordinate reference systems.
+//!
+//! The projections are handled by `proj4rs`, a pure Rust port of PROJ.4, so
+//! that no system library is required.
+use crate::Error;
+use proj4rs::proj::Proj;
+use std::fs;
+use std::path::Path;
+
+/// Projection of the WGS84 longitudes and latitudes.
+const WGS84: &str = "+proj=longlat +ellps=WGS84 +datum=WGS84 +no_defs";
+
+/// Return the WGS84 projection.
+pub fn wgs84() -> Proj {
+    Proj::from_proj_string(WGS84).expect("the WGS84 definition is valid")
+}
+
+/// Return the projection of the shapefile located at `path`.
+///
+/// The projection is read from the `.prj` file next to the shapefile. Return
+/// `None` if there is no such file.
+pub fn shapefile_projection(path: &Path) -> Result<Option<Proj>, Error> {
+    let prj = match fs::read_to_string(path.with_extension("prj")) {
+        Ok(prj) => prj,
+        Err(_) => return Ok(None),
+    };
+    let projection = proj4wkt::wkt_to_projstring(prj.trim())
+        .map_err(|e| Error::Parse(format!("unsupported projection: {}", e)))?;
+    let proj = Proj::from_proj_string(&projection)
+        .map_err(|e| Error::Parse(format!("unsupported projection: {}", e)))?;
+
+    Ok(Some(proj))
+}
+
+/// Parse a coordinate reference system.
+///
+/// The system is either an EPSG code, like `EPSG:4326`, or a PROJ.4 string,
+/// like `+proj=lcc +lat_1=39.71666666666667 ...` for a local state plane.
+pub fn parse_crs(crs: &str) -> Result<Proj, Error> {
+    let crs = crs.trim();
+    let proj = match crs.split_once(':') {
+        Some((authority, code)) if authority.eq_ignore_ascii_case("epsg") => {
+            let code = code
+                .parse()
+                .map_err(|_| Error::Parse(format!("invalid EPSG code {:?}", crs)))?;
+            Proj::from_epsg_code(code)
+        }
+        _ => Proj::from_proj_string(crs),
+    };
+
+    proj.map_err(|e| Error::Parse(format!("unsupported CRS {:?}: {}", crs, e)))
+}
+
+/// Reproject coordinates from one projection to another.
+///
+/// The longitudes and latitudes are expressed in degrees.
+#[derive(Debug, Clone)]
+pub struct Reprojection {
+    from: Proj,
+    to: Proj,
+}
+
+impl Reprojection {
+    /// Create a reprojection from `from` to `to`.
+    pub fn new(from: Proj, to: Proj) -> Self {
+        Reprojection { from, to }
+    }
+
+    /// Reproject a point.
+    pub fn point(&self, x: f64, y: f64) -> Result<(f64, f64), Error> {
+        let mut point = if self.from.is_latlong() {
+            (x.to_radians(), y.to_radians(), 0.0)
+        } else {
+            (x, y, 0.0)
+        };
+        proj4rs::transform::transform(&self.from, &self.to, &mut point)
+            .map_err(|e| Error::Parse(format!("cannot reproject ({}, {}): {}", x, y, e)))?;
+
+        if self.to.is_latlong() {
+            Ok((point.0.to_degrees(), point.1.to_degrees()))
+        } else {
+            Ok((point.0, point.1))
+        }
+    }
+}
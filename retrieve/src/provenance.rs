@@ -0,0 +1,137 @@
+//! Describe the provenance of the retrieved files.
+//!
+//! A provenance record is written next to each file, as
+//! `{FILE}.provenance.json`, so that it travels with the file when it is
+//! copied elsewhere. The records follow the schema identified by
+//! [`PROVENANCE_SCHEMA`]:
+//!
+//! | Field          | Description                                              |
+//! |----------------|----------------------------------------------------------|
+//! | `schema`       | Identifier of the schema, [`PROVENANCE_SCHEMA`]          |
+//! | `file`         | Name of the file the record describes                    |
+//! | `sha256`       | SHA256 hash of the file                                  |
+//! | `size`         | Size of the file, in bytes                               |
+//! | `city`         | Name, `state`, `country` and run `uuid` of the city      |
+//! | `dataset`      | Name of the dataset, e.g. `neighborhood_ways`            |
+//! | `source`       | URL the file was downloaded from, if known               |
+//! | `etag`         | ETag announced by the server, if any                     |
+//! | `retrieved_at` | Time the file was first retrieved, in RFC 3339 format    |
+//! | `tool`         | Name and version of the tool which retrieved the file    |
+//!
+//! The optional fields are omitted when they are unknown.
+use crate::manifest::ManifestEntry;
+use crate::Error;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Identifier of the schema of the provenance records.
+pub const PROVENANCE_SCHEMA: &str = "https://github.com/PeopleForBikes/retrieve/provenance/v1";
+
+/// Suffix of the provenance records.
+pub const PROVENANCE_SUFFIX: &str = ".provenance.json";
+
+/// Describe the city a file belongs to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProvenanceCity {
+    /// Name of the city.
+    pub name: String,
+    /// State of the city.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub state: String,
+    /// Country of the city.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub country: String,
+    /// Run UUID.
+    pub uuid: String,
+}
+
+/// Describe the tool which retrieved a file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tool {
+    /// Name of the tool.
+    pub name: String,
+    /// Version of the tool.
+    pub version: String,
+}
+
+impl Default for Tool {
+    fn default() -> Self {
+        Tool {
+            name: String::from(env!("CARGO_PKG_NAME")),
+            version: String::from(env!("CARGO_PKG_VERSION")),
+        }
+    }
+}
+
+/// Describe the provenance of a retrieved file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// Identifier of the schema.
+    pub schema: String,
+    /// Name of the file.
+    pub file: String,
+    /// SHA256 hash of the file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Size of the file, in bytes.
+    pub size: u64,
+    /// City the file belongs to.
+    pub city: ProvenanceCity,
+    /// Name of the dataset.
+    pub dataset: String,
+    /// URL the file was downloaded from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// ETag announced by the server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// Time the file was first retrieved, as an RFC 3339 timestamp.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retrieved_at: Option<String>,
+    /// Tool which retrieved the file.
+    pub tool: Tool,
+}
+
+impl Provenance {
+    /// Describe the provenance of the file recorded by a manifest entry.
+    pub fn from_entry(entry: &ManifestEntry) -> Self {
+        Provenance {
+            schema: String::from(PROVENANCE_SCHEMA),
+            file: entry
+                .path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            sha256: entry.sha256.clone(),
+            size: entry.size,
+            city: ProvenanceCity {
+                name: entry.city.clone(),
+                state: entry.state.clone(),
+                country: entry.country.clone(),
+                uuid: entry.uuid.clone(),
+            },
+            dataset: entry.dataset.clone(),
+            source: entry.source.clone(),
+            etag: entry.etag.clone(),
+            retrieved_at: entry.retrieved_at.clone(),
+            tool: Tool::default(),
+        }
+    }
+
+    /// Write the record next to the file located at `path`.
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let mut content = serde_json::to_string_pretty(self)?;
+        content.push('\n');
+        fs::write(sidecar_path(path), content)?;
+
+        Ok(())
+    }
+}
+
+/// Return the path of the provenance record of `path`.
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(PROVENANCE_SUFFIX);
+    PathBuf::from(sidecar)
+}
@@ -0,0 +1,144 @@
+//! Feed a running [`Downloader`](crate::download::Downloader) with downloads.
+//!
+//! Unlike a [`Plan`], which is fixed once the downloads start, a
+//! [`DownloadQueue`] can be updated while it is being processed: downloads can
+//! be added, reprioritized, or cancelled as long as they did not start.
+use crate::plan::{Plan, PlannedDownload};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+/// Identify a download added to a [`DownloadQueue`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct QueueId(u64);
+
+/// Represent a pending download.
+#[derive(Debug, Clone)]
+struct QueuedDownload {
+    id: QueueId,
+    priority: i32,
+    download: PlannedDownload,
+}
+
+/// Represent the content of the queue.
+#[derive(Debug, Default)]
+struct State {
+    pending: Vec<QueuedDownload>,
+    next_id: u64,
+    closed: bool,
+}
+
+/// Share a list of pending downloads with a running downloader.
+///
+/// The downloads with the highest priority start first, and the ones with the
+/// same priority start in the order they were added. The queue is a handle:
+/// its clones all refer to the same downloads.
+///
+/// Once every download is added, the queue must be [closed](DownloadQueue::close)
+/// for [`Downloader::run`](crate::download::Downloader::run) to return.
+#[derive(Debug, Clone, Default)]
+pub struct DownloadQueue {
+    state: Arc<Mutex<State>>,
+    notify: Arc<Notify>,
+}
+
+impl DownloadQueue {
+    /// Create an empty queue.
+    pub fn new() -> Self {
+        DownloadQueue::default()
+    }
+
+    /// Add a download with the given `priority`.
+    ///
+    /// The downloads added after the queue was closed are ignored.
+    pub fn enqueue(&self, download: PlannedDownload, priority: i32) -> QueueId {
+        let mut state = self.state.lock().unwrap();
+        let id = QueueId(state.next_id);
+        state.next_id += 1;
+        if !state.closed {
+            state.pending.push(QueuedDownload {
+                id,
+                priority,
+                download,
+            });
+            self.notify.notify_waiters();
+        }
+
+        id
+    }
+
+    /// Add all the downloads of a plan with the given `priority`.
+    pub fn enqueue_plan(&self, plan: &Plan, priority: i32) -> Vec<QueueId> {
+        plan.downloads
+            .iter()
+            .map(|d| self.enqueue(d.clone(), priority))
+            .collect()
+    }
+
+    /// Change the priority of a pending download.
+    ///
+    /// Return `false` if the download already started or was cancelled.
+    pub fn set_priority(&self, id: QueueId, priority: i32) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.pending.iter_mut().find(|q| q.id == id) {
+            Some(queued) => {
+                queued.priority = priority;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Cancel a pending download.
+    ///
+    /// Return `false` if the download already started or was cancelled.
+    pub fn cancel(&self, id: QueueId) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let count = state.pending.len();
+        state.pending.retain(|q| q.id != id);
+        state.pending.len() != count
+    }
+
+    /// Stop accepting new downloads.
+    ///
+    /// The pending downloads are still processed.
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.notify.notify_waiters();
+    }
+
+    /// Return the number of pending downloads.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().pending.len()
+    }
+
+    /// Return `true` if there is no pending download.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Wait for the next download to start.
+    ///
+    /// Return `None` once the queue is closed and empty.
+    pub(crate) async fn next(&self) -> Option<PlannedDownload> {
+        loop {
+            // Register before checking the state, not to miss a notification.
+            let notified = self.notify.notified();
+            {
+                let mut state = self.state.lock().unwrap();
+                let next = state
+                    .pending
+                    .iter()
+                    .enumerate()
+                    .max_by(|(_, a), (_, b)| a.priority.cmp(&b.priority).then(b.id.cmp(&a.id)))
+                    .map(|(i, _)| i);
+                if let Some(i) = next {
+                    return Some(state.pending.remove(i).download);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            notified.await;
+        }
+    }
+}
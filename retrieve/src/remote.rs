@@ -0,0 +1,350 @@
+//! Read parts of the remote datasets without downloading them.
+//!
+//! The PFB storage supports range requests, so the beginning of a CSV file can
+//! be previewed, and the table of contents of a zip archive, located in the
+//! central directory at the end of the file, can be read with a couple of
+//! small requests. The archives larger than 4 GiB, using the Zip64 extensions,
+//! are supported.
+use crate::http::HttpClient;
+use crate::s3::error_for_status;
+use crate::Error;
+use futures::stream::{self, StreamExt};
+use reqwest::header::{CONTENT_LENGTH, ETAG, RANGE};
+use reqwest::StatusCode;
+use std::collections::HashMap;
+use url::Url;
+
+/// Signature of the end of central directory record.
+const EOCD_SIGNATURE: u32 = 0x0605_4b50;
+
+/// Size of the end of central directory record, without the comment.
+const EOCD_SIZE: u64 = 22;
+
+/// Maximum size of the comment of a zip archive.
+const MAX_COMMENT_SIZE: u64 = u16::MAX as u64;
+
+/// Signature of the Zip64 end of central directory locator.
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+
+/// Size of the Zip64 end of central directory locator.
+const ZIP64_LOCATOR_SIZE: usize = 20;
+
+/// Signature of the Zip64 end of central directory record.
+const ZIP64_EOCD_SIGNATURE: u32 = 0x0606_4b50;
+
+/// Size of the Zip64 end of central directory record, without the extensible
+/// data.
+const ZIP64_EOCD_SIZE: u64 = 56;
+
+/// Identifier of the Zip64 extended information extra field.
+const ZIP64_EXTRA_FIELD: u16 = 0x0001;
+
+/// Value of the 32-bit fields whose actual value is in the Zip64 fields.
+const ZIP64_MARKER: u32 = u32::MAX;
+
+/// Signature of a local file header.
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+
+/// Size of a local file header, without the variable fields.
+const LOCAL_HEADER_SIZE: u64 = 30;
+
+/// Signature of a central directory file header.
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+
+/// Size of a central directory file header, without the variable fields.
+const CENTRAL_HEADER_SIZE: usize = 46;
+
+/// Describe a member of a zip archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZipEntry {
+    /// Path of the member in the archive.
+    pub name: String,
+    /// Uncompressed size, in bytes.
+    pub size: u64,
+    /// Compressed size, in bytes.
+    pub compressed_size: u64,
+    /// Compression method, e.g. 0 for stored and 8 for deflated.
+    pub method: u16,
+    /// CRC-32 checksum of the uncompressed data.
+    pub crc32: u32,
+    /// Offset of the local header of the member in the archive.
+    pub offset: u64,
+}
+
+/// Retrieve the sizes of the files located at `urls` with HEAD requests sent
+/// by `client`, at most `parallel` at once.
+///
+/// The files whose size is unknown, e.g. because the request failed, are
+/// omitted.
+pub async fn content_lengths(
+    client: &HttpClient,
+    urls: &[Url],
+    parallel: usize,
+) -> Result<HashMap<Url, u64>, Error> {
+    let lengths = stream::iter(urls)
+        .map(|url| async move {
+            let response = client.send(client.head(url)).await.ok()?;
+            let response = error_for_status(response).await.ok()?;
+            let length = response
+                .headers()
+                .get(CONTENT_LENGTH)?
+                .to_str()
+                .ok()?
+                .parse()
+                .ok()?;
+            Some((url.clone(), length))
+        })
+        .buffer_unordered(parallel.max(1))
+        .filter_map(|length| async move { length })
+        .collect()
+        .await;
+
+    Ok(lengths)
+}
+
+/// Retrieve the ETags of the files located at `urls` with HEAD requests sent
+/// by `client`, at most `parallel` at once.
+///
+/// The ETags are returned without their quotes. The files whose ETag is
+/// unknown, e.g. because the request failed, are omitted.
+pub async fn etags(
+    client: &HttpClient,
+    urls: &[Url],
+    parallel: usize,
+) -> Result<HashMap<Url, String>, Error> {
+    let etags = stream::iter(urls)
+        .map(|url| async move {
+            let response = client.send(client.head(url)).await.ok()?;
+            let response = error_for_status(response).await.ok()?;
+            let etag = response.headers().get(ETAG)?.to_str().ok()?;
+            Some((url.clone(), etag.trim_matches('"').to_string()))
+        })
+        .buffer_unordered(parallel.max(1))
+        .filter_map(|etag| async move { etag })
+        .collect()
+        .await;
+
+    Ok(etags)
+}
+
+/// Represent a file of the PFB storage, read with range requests.
+#[derive(Debug, Clone)]
+pub struct RemoteFile {
+    client: HttpClient,
+    url: Url,
+    length: u64,
+}
+
+impl RemoteFile {
+    /// Open the file located at `url`, and retrieve its size.
+    ///
+    /// The file is read with the requests sent by `client`.
+    pub async fn open(client: &HttpClient, url: Url) -> Result<Self, Error> {
+        let response = error_for_status(client.send(client.head(&url)).await?).await?;
+        let length = response
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| Error::Parse(format!("unknown size of {}", url)))?;
+
+        Ok(RemoteFile {
+            client: client.clone(),
+            url,
+            length,
+        })
+    }
+
+    /// Return the size of the file, in bytes.
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    /// Return `true` if the file is empty.
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Read the bytes `start..end` of the file.
+    ///
+    /// The range is truncated to the size of the file.
+    pub async fn read(&self, start: u64, end: u64) -> Result<Vec<u8>, Error> {
+        let end = end.min(self.length);
+        if start >= end {
+            return Ok(Vec::new());
+        }
+        let request = self
+            .client
+            .get(&self.url)
+            .header(RANGE, format!("bytes={}-{}", start, end - 1));
+        let response = self.client.send(request).await?;
+        let response = error_for_status(response).await?;
+        let partial = response.status() == StatusCode::PARTIAL_CONTENT;
+        let bytes = response.bytes().await?;
+        // A server ignoring the range returns the whole file.
+        let bytes = if partial {
+            bytes.to_vec()
+        } else {
+            bytes
+                .get(start as usize..end as usize)
+                .unwrap_or_default()
+                .to_vec()
+        };
+
+        Ok(bytes)
+    }
+
+    /// Return the first `count` lines of a text file, read from its first
+    /// `max_bytes` bytes.
+    ///
+    /// The last line is dropped if it is incomplete.
+    pub async fn head(&self, count: usize, max_bytes: u64) -> Result<Vec<String>, Error> {
+        let bytes = self.read(0, max_bytes).await?;
+        let text = String::from_utf8_lossy(&bytes);
+        let mut lines: Vec<&str> = text.lines().collect();
+        if (bytes.len() as u64) < self.length && !text.ends_with('\n') {
+            lines.pop();
+        }
+
+        Ok(lines.into_iter().take(count).map(String::from).collect())
+    }
+
+    /// Return the members of a zip archive, read from its central directory.
+    pub async fn zip_entries(&self) -> Result<Vec<ZipEntry>, Error> {
+        // Locate the end of central directory record, followed by the comment.
+        let tail_start = self.length.saturating_sub(EOCD_SIZE + MAX_COMMENT_SIZE);
+        let tail = self.read(tail_start, self.length).await?;
+        let eocd = (0..tail.len().saturating_sub(EOCD_SIZE as usize - 1))
+            .rev()
+            .find(|&i| read_u32(&tail, i) == Some(EOCD_SIGNATURE))
+            .ok_or_else(|| Error::Parse(format!("{} is not a zip archive", self.url)))?;
+        let invalid = || Error::Parse(format!("invalid central directory in {}", self.url));
+        let mut size = read_u32(&tail, eocd + 12).ok_or_else(invalid)? as u64;
+        let mut offset = read_u32(&tail, eocd + 16).ok_or_else(invalid)? as u64;
+
+        // The Zip64 record, located by the locator preceding the record,
+        // holds the actual values.
+        let locator = eocd.checked_sub(ZIP64_LOCATOR_SIZE);
+        if let Some(locator) =
+            locator.filter(|&l| read_u32(&tail, l) == Some(ZIP64_LOCATOR_SIGNATURE))
+        {
+            let record_offset = read_u64(&tail, locator + 8).ok_or_else(invalid)?;
+            let record = self
+                .read(record_offset, record_offset + ZIP64_EOCD_SIZE)
+                .await?;
+            if read_u32(&record, 0) != Some(ZIP64_EOCD_SIGNATURE) {
+                return Err(invalid());
+            }
+            size = read_u64(&record, 40).ok_or_else(invalid)?;
+            offset = read_u64(&record, 48).ok_or_else(invalid)?;
+        }
+
+        // Read the central directory, unless it was part of the tail.
+        let directory = match offset.checked_sub(tail_start) {
+            Some(start) if start + size <= eocd as u64 => {
+                tail[start as usize..(start + size) as usize].to_vec()
+            }
+            _ => self.read(offset, offset + size).await?,
+        };
+
+        parse_central_directory(&directory).ok_or_else(invalid)
+    }
+
+    /// Return the data of a member of a zip archive, as stored in the
+    /// archive, i.e. compressed with the method of the entry.
+    pub async fn zip_member(&self, entry: &ZipEntry) -> Result<Vec<u8>, Error> {
+        let header = self
+            .read(entry.offset, entry.offset + LOCAL_HEADER_SIZE)
+            .await?;
+        let invalid = || Error::Parse(format!("invalid header of {} in {}", entry.name, self.url));
+        if read_u32(&header, 0) != Some(LOCAL_HEADER_SIGNATURE) {
+            return Err(invalid());
+        }
+        let name_length = read_u16(&header, 26).ok_or_else(invalid)? as u64;
+        let extra_length = read_u16(&header, 28).ok_or_else(invalid)? as u64;
+        let start = entry.offset + LOCAL_HEADER_SIZE + name_length + extra_length;
+
+        self.read(start, start + entry.compressed_size).await
+    }
+}
+
+/// Parse the file headers of a central directory.
+fn parse_central_directory(directory: &[u8]) -> Option<Vec<ZipEntry>> {
+    let mut entries = Vec::new();
+    let mut position = 0;
+    while position + CENTRAL_HEADER_SIZE <= directory.len() {
+        if read_u32(directory, position)? != CENTRAL_HEADER_SIGNATURE {
+            return None;
+        }
+        let name_length = read_u16(directory, position + 28)? as usize;
+        let extra_length = read_u16(directory, position + 30)? as usize;
+        let comment_length = read_u16(directory, position + 32)? as usize;
+        let name_start = position + CENTRAL_HEADER_SIZE;
+        let name = directory.get(name_start..name_start + name_length)?;
+        let extra_start = name_start + name_length;
+        let extra = directory.get(extra_start..extra_start + extra_length)?;
+        let mut zip64 = zip64_values(extra).into_iter();
+        // The Zip64 values are only present for the fields set to the marker,
+        // in this order.
+        let mut field = |offset: usize| -> Option<u64> {
+            match read_u32(directory, position + offset)? {
+                ZIP64_MARKER => zip64.next(),
+                value => Some(value as u64),
+            }
+        };
+        let size = field(24)?;
+        let compressed_size = field(20)?;
+        let offset = field(42)?;
+        entries.push(ZipEntry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            size,
+            compressed_size,
+            method: read_u16(directory, position + 10)?,
+            crc32: read_u32(directory, position + 16)?,
+            offset,
+        });
+        position = name_start + name_length + extra_length + comment_length;
+    }
+
+    Some(entries)
+}
+
+/// Return the values of the Zip64 extended information extra field, if any.
+fn zip64_values(extra: &[u8]) -> Vec<u64> {
+    let mut position = 0;
+    while let (Some(id), Some(length)) = (read_u16(extra, position), read_u16(extra, position + 2))
+    {
+        let data_start = position + 4;
+        let data_end = data_start + length as usize;
+        if id == ZIP64_EXTRA_FIELD {
+            return (data_start..data_end.min(extra.len()))
+                .step_by(8)
+                .map_while(|o| read_u64(extra, o))
+                .collect();
+        }
+        position = data_end;
+    }
+
+    Vec::new()
+}
+
+/// Read a little endian `u16` at `offset`.
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+/// Read a little endian `u32` at `offset`.
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+/// Read a little endian `u64` at `offset`.
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        bytes.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
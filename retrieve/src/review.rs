@@ -0,0 +1,366 @@
+//! Review a plan before applying it.
+//!
+//! In governed data environments, the downloads must be approved before they
+//! happen. A plan file describes what a fetch would do to a download folder:
+//! the files it would download, overwrite or skip, and the files an eviction
+//! policy would delete. Once reviewed, the plan file is applied as is, and
+//! refuses to run if the download folder changed in the meantime.
+//!
+//! The plan files are JSON documents following the schema identified by
+//! [`PLAN_SCHEMA`].
+//!
+//! Two plans, or a plan and the manifest of the download folder, can be
+//! compared to summarize what changed since the last sync, e.g. to attach to a
+//! change-management ticket.
+use crate::manifest::Manifest;
+use crate::plan::{Plan, PlannedDownload};
+use crate::timestamp;
+use crate::Error;
+use pfbcore::scorecard::City;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use url::Url;
+
+/// Identifier of the schema of the plan files.
+pub const PLAN_SCHEMA: &str = "https://github.com/PeopleForBikes/retrieve/plan/v1";
+
+/// Describe what happens to a planned file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// The file is downloaded for the first time.
+    Download,
+    /// The file replaces a local file.
+    Overwrite,
+    /// The local file is current, and is kept.
+    Skip,
+}
+
+/// Describe a planned file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedAction {
+    /// What happens to the file.
+    pub action: Action,
+    /// Name of the city.
+    pub city: String,
+    /// State of the city.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub state: String,
+    /// Country of the city.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub country: String,
+    /// Run UUID.
+    pub uuid: String,
+    /// Name of the dataset.
+    pub dataset: String,
+    /// URL of the dataset.
+    pub url: String,
+    /// Path of the file, relative to the download folder.
+    pub path: PathBuf,
+    /// SHA256 hash recorded in the manifest for the local file which is
+    /// overwritten, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replaces: Option<String>,
+}
+
+impl PlannedAction {
+    /// Return the full name of the city, e.g. "Austin, Texas, United States".
+    pub fn full_name(&self) -> String {
+        let mut name = self.city.clone();
+        if !self.state.is_empty() && self.state != self.country {
+            name.push_str(", ");
+            name.push_str(&self.state);
+        }
+        if !self.country.is_empty() {
+            name.push_str(", ");
+            name.push_str(&self.country);
+        }
+        name
+    }
+}
+
+/// Represent a plan file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlanFile {
+    /// Identifier of the schema, [`PLAN_SCHEMA`].
+    pub schema: String,
+    /// Time the plan was made, as an RFC 3339 timestamp.
+    pub created_at: String,
+    /// Download folder the plan applies to.
+    pub destination_folder: PathBuf,
+    /// Planned files.
+    pub actions: Vec<PlannedAction>,
+    /// Cities which cannot be downloaded, because they do not have a run UUID.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped_cities: Vec<String>,
+    /// Files deleted by the eviction policy, relative to the download folder.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deletions: Vec<PathBuf>,
+}
+
+impl PlanFile {
+    /// Describe what fetching the `plan` into `folder` would do.
+    ///
+    /// When `sync` is set, the files which did not change since the last sync
+    /// are skipped, refer to [`Manifest::is_current`].
+    pub fn new(plan: &Plan, folder: &Path, manifest: &Manifest, sync: bool) -> Self {
+        let actions = plan
+            .downloads
+            .iter()
+            .map(|d| {
+                let action = if sync && manifest.is_current(d, folder) {
+                    Action::Skip
+                } else if folder.join(&d.file_name).exists() {
+                    Action::Overwrite
+                } else {
+                    Action::Download
+                };
+                let replaces = match action {
+                    Action::Overwrite => manifest.get(&d.file_name).and_then(|e| e.sha256.clone()),
+                    _ => None,
+                };
+                PlannedAction {
+                    action,
+                    city: d.city.name.clone(),
+                    state: d.city.state.clone(),
+                    country: d.city.country.clone(),
+                    uuid: d.run.uuid.clone(),
+                    dataset: d.dataset.to_string(),
+                    url: d.url.to_string(),
+                    path: d.file_name.clone(),
+                    replaces,
+                }
+            })
+            .collect();
+
+        PlanFile {
+            schema: String::from(PLAN_SCHEMA),
+            created_at: timestamp::rfc3339(timestamp::now()),
+            destination_folder: folder.to_path_buf(),
+            actions,
+            skipped_cities: plan.skipped.iter().map(|c| c.to_string()).collect(),
+            deletions: Vec::new(),
+        }
+    }
+
+    /// Describe the files recorded in the `manifest` of the download `folder`
+    /// as a plan where every file is current.
+    pub fn from_manifest(manifest: &Manifest, folder: &Path) -> Self {
+        let actions = manifest
+            .files
+            .iter()
+            .map(|e| PlannedAction {
+                action: Action::Skip,
+                city: e.city.clone(),
+                state: e.state.clone(),
+                country: e.country.clone(),
+                uuid: e.uuid.clone(),
+                dataset: e.dataset.clone(),
+                url: e.source.clone().unwrap_or_default(),
+                path: e.path.clone(),
+                replaces: None,
+            })
+            .collect();
+
+        PlanFile {
+            schema: String::from(PLAN_SCHEMA),
+            created_at: timestamp::rfc3339(timestamp::now()),
+            destination_folder: folder.to_path_buf(),
+            actions,
+            skipped_cities: Vec::new(),
+            deletions: Vec::new(),
+        }
+    }
+
+    /// Read the plan file located at `path`.
+    ///
+    /// The plan is rejected if one of its files is not located in the download
+    /// folder, e.g. an absolute path or a path containing `..`.
+    pub fn read<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let plan: PlanFile = serde_json::from_str(&fs::read_to_string(path)?)?;
+        if plan.schema != PLAN_SCHEMA {
+            return Err(Error::Parse(format!(
+                "{} is not a plan file (schema {:?})",
+                path.display(),
+                plan.schema
+            )));
+        }
+        let paths = plan.actions.iter().map(|a| &a.path).chain(&plan.deletions);
+        for file in paths {
+            if file.as_os_str().is_empty()
+                || !file.components().all(|c| matches!(c, Component::Normal(_)))
+            {
+                return Err(Error::Parse(format!(
+                    "invalid file {} in the plan {}",
+                    file.display(),
+                    path.display()
+                )));
+            }
+        }
+
+        Ok(plan)
+    }
+
+    /// Write the plan file to `writer`.
+    pub fn write<W>(&self, mut writer: W) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer)?;
+
+        Ok(())
+    }
+
+    /// Return the number of files planned for an `action`.
+    pub fn count(&self, action: Action) -> usize {
+        self.actions.iter().filter(|a| a.action == action).count()
+    }
+
+    /// Check that the download folder did not change since the plan was made.
+    ///
+    /// The files to download must still be missing, and the files to
+    /// overwrite must still have the recorded content.
+    pub fn check(&self, manifest: &Manifest) -> Result<(), Error> {
+        let folder = &self.destination_folder;
+        for planned in &self.actions {
+            let changed = match planned.action {
+                Action::Download => folder.join(&planned.path).exists(),
+                Action::Overwrite => {
+                    manifest.get(&planned.path).and_then(|e| e.sha256.as_ref())
+                        != planned.replaces.as_ref()
+                }
+                Action::Skip => false,
+            };
+            if changed {
+                return Err(Error::Parse(format!(
+                    "{} changed since the plan was made, make a new plan",
+                    folder.join(&planned.path).display()
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Return the downloads of the plan file, skipping the current files.
+    pub fn to_plan(&self) -> Result<Plan, Error> {
+        let mut plan = Plan::default();
+        for planned in self.actions.iter().filter(|a| a.action != Action::Skip) {
+            let city = City::new(
+                &planned.city,
+                &planned.country,
+                Some(&planned.state),
+                &planned.uuid,
+                0,
+                0.0,
+                0,
+            );
+            plan.downloads.push(PlannedDownload {
+                run: city.run.clone(),
+                city,
+                dataset: planned.dataset.parse()?,
+                url: Url::parse(&planned.url)?,
+                file_name: planned.path.clone(),
+            });
+        }
+
+        Ok(plan)
+    }
+}
+
+/// Describe a city whose run UUID changed between two plans.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UpdatedCity {
+    /// Full name of the city.
+    pub city: String,
+    /// Run UUID of the old plan.
+    pub old_uuid: String,
+    /// Run UUID of the new plan.
+    pub new_uuid: String,
+}
+
+/// Summarize what changed between two plans.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct PlanDiff {
+    /// Cities which were not in the old plan.
+    pub new_cities: Vec<String>,
+    /// Cities whose run UUID changed.
+    pub updated_cities: Vec<UpdatedCity>,
+    /// Cities which are not in the new plan anymore.
+    pub removed_cities: Vec<String>,
+    /// Files which were not in the old plan.
+    pub new_files: Vec<PathBuf>,
+    /// Files which are not in the new plan anymore.
+    pub removed_files: Vec<PathBuf>,
+    /// Files which the new plan evicts.
+    pub deletions: Vec<PathBuf>,
+}
+
+impl PlanDiff {
+    /// Compare an `old` plan with a `new` one.
+    ///
+    /// The files of both plans are compared, whatever their action: a file the
+    /// new plan skips because it is current is still part of it.
+    pub fn new(old: &PlanFile, new: &PlanFile) -> Self {
+        let old_runs = runs(old);
+        let new_runs = runs(new);
+        let old_files: BTreeSet<&PathBuf> = old.actions.iter().map(|a| &a.path).collect();
+        let new_files: BTreeSet<&PathBuf> = new.actions.iter().map(|a| &a.path).collect();
+
+        let mut diff = PlanDiff::default();
+        for (city, uuid) in &new_runs {
+            match old_runs.get(city) {
+                None => diff.new_cities.push(city.clone()),
+                Some(old_uuid) if old_uuid != uuid => diff.updated_cities.push(UpdatedCity {
+                    city: city.clone(),
+                    old_uuid: old_uuid.to_string(),
+                    new_uuid: uuid.to_string(),
+                }),
+                Some(_) => (),
+            }
+        }
+        diff.removed_cities = old_runs
+            .keys()
+            .filter(|c| !new_runs.contains_key(*c))
+            .cloned()
+            .collect();
+        diff.new_files = new_files
+            .difference(&old_files)
+            .map(|p| (*p).clone())
+            .collect();
+        diff.removed_files = old_files
+            .difference(&new_files)
+            .map(|p| (*p).clone())
+            .collect();
+        diff.deletions = new.deletions.clone();
+
+        diff
+    }
+
+    /// Return `true` if nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self.new_cities.is_empty()
+            && self.updated_cities.is_empty()
+            && self.removed_cities.is_empty()
+            && self.new_files.is_empty()
+            && self.removed_files.is_empty()
+            && self.deletions.is_empty()
+    }
+}
+
+/// Return the run UUID of each city of a plan, indexed by the full name of the
+/// city.
+fn runs(plan: &PlanFile) -> BTreeMap<String, &str> {
+    plan.actions
+        .iter()
+        .map(|a| (a.full_name(), a.uuid.as_str()))
+        .collect()
+}
@@ -0,0 +1,174 @@
+//! Classify the error responses of S3.
+//!
+//! S3 describes its errors with an XML document, e.g.
+//!
+//! ```xml
+//! <Error>
+//!   <Code>NoSuchKey</Code>
+//!   <Message>The specified key does not exist.</Message>
+//!   <RequestId>4442587FB7D0A2F9</RequestId>
+//! </Error>
+//! ```
+//!
+//! The code tells a missing file from a denied access or a throttled request,
+//! which the status code alone does not, and decides whether the request is
+//! worth retrying.
+use crate::Error;
+use reqwest::Response;
+use std::fmt;
+
+/// Maximum number of bytes of an error body which are read.
+const MAX_ERROR_BODY: usize = 16 * 1024;
+
+/// Define the classes of S3 errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum S3ErrorCode {
+    /// The file does not exist.
+    NoSuchKey,
+    /// The bucket does not exist.
+    NoSuchBucket,
+    /// The access to the file is denied, e.g. because the bucket is private
+    /// or a presigned URL is invalid.
+    AccessDenied,
+    /// The presigned URL or the token expired.
+    ExpiredToken,
+    /// The requests are throttled.
+    SlowDown,
+    /// The service failed to process the request.
+    InternalError,
+    /// The service is unavailable.
+    ServiceUnavailable,
+    /// The connection was idle for too long.
+    RequestTimeout,
+    /// Any other code.
+    Other(String),
+}
+
+impl S3ErrorCode {
+    /// Return the class of a `code`.
+    pub fn parse(code: &str) -> Self {
+        match code {
+            "NoSuchKey" => S3ErrorCode::NoSuchKey,
+            "NoSuchBucket" => S3ErrorCode::NoSuchBucket,
+            "AccessDenied" => S3ErrorCode::AccessDenied,
+            "ExpiredToken" => S3ErrorCode::ExpiredToken,
+            "SlowDown" => S3ErrorCode::SlowDown,
+            "InternalError" => S3ErrorCode::InternalError,
+            "ServiceUnavailable" => S3ErrorCode::ServiceUnavailable,
+            "RequestTimeout" => S3ErrorCode::RequestTimeout,
+            _ => S3ErrorCode::Other(code.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for S3ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let code = match self {
+            S3ErrorCode::NoSuchKey => "NoSuchKey",
+            S3ErrorCode::NoSuchBucket => "NoSuchBucket",
+            S3ErrorCode::AccessDenied => "AccessDenied",
+            S3ErrorCode::ExpiredToken => "ExpiredToken",
+            S3ErrorCode::SlowDown => "SlowDown",
+            S3ErrorCode::InternalError => "InternalError",
+            S3ErrorCode::ServiceUnavailable => "ServiceUnavailable",
+            S3ErrorCode::RequestTimeout => "RequestTimeout",
+            S3ErrorCode::Other(code) => code,
+        };
+        f.write_str(code)
+    }
+}
+
+/// Describe an error response of S3.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{code} (HTTP {status}): {message}")]
+pub struct S3Error {
+    /// Status code of the response.
+    pub status: u16,
+    /// Class of the error.
+    pub code: S3ErrorCode,
+    /// Message of the error.
+    pub message: String,
+    /// Identifier of the request, to report the error to the operators of the
+    /// storage.
+    pub request_id: Option<String>,
+}
+
+impl S3Error {
+    /// Parse the `body` of an error response.
+    ///
+    /// Return `None` if the body is not an S3 error document.
+    pub fn parse(status: u16, body: &str) -> Option<Self> {
+        let error = element(body, "Error")?;
+
+        Some(S3Error {
+            status,
+            code: S3ErrorCode::parse(element(error, "Code")?.trim()),
+            message: element(error, "Message")
+                .map(|m| m.trim().to_string())
+                .unwrap_or_default(),
+            request_id: element(error, "RequestId").map(|r| r.trim().to_string()),
+        })
+    }
+
+    /// Read and parse the body of an error `response`.
+    ///
+    /// Only the beginning of the body is read. Return `None` if the body is
+    /// not an S3 error document.
+    pub async fn from_response(mut response: Response) -> Option<Self> {
+        let status = response.status().as_u16();
+        let mut body = Vec::new();
+        while let Ok(Some(chunk)) = response.chunk().await {
+            body.extend_from_slice(&chunk);
+            if body.len() >= MAX_ERROR_BODY {
+                break;
+            }
+        }
+
+        S3Error::parse(status, &String::from_utf8_lossy(&body))
+    }
+
+    /// Return `true` if the request may succeed when retried.
+    pub fn is_retryable(&self) -> bool {
+        match self.code {
+            S3ErrorCode::SlowDown
+            | S3ErrorCode::InternalError
+            | S3ErrorCode::ServiceUnavailable
+            | S3ErrorCode::RequestTimeout => true,
+            S3ErrorCode::NoSuchKey
+            | S3ErrorCode::NoSuchBucket
+            | S3ErrorCode::AccessDenied
+            | S3ErrorCode::ExpiredToken => false,
+            S3ErrorCode::Other(_) => self.status >= 500 || self.status == 429,
+        }
+    }
+
+    /// Return `true` if the requests are throttled, and must be slowed down.
+    pub fn is_throttled(&self) -> bool {
+        self.code == S3ErrorCode::SlowDown
+    }
+}
+
+/// Turn an unsuccessful `response` into an error.
+///
+/// The S3 error documents are turned into an [`S3Error`], the other responses
+/// into an HTTP error.
+pub async fn error_for_status(response: Response) -> Result<Response, Error> {
+    let error = match response.error_for_status_ref() {
+        Ok(_) => return Ok(response),
+        Err(e) => e,
+    };
+    match S3Error::from_response(response).await {
+        Some(s3) => Err(s3.into()),
+        None => Err(error.into()),
+    }
+}
+
+/// Return the content of the first element named `name` in a XML document.
+fn element<'a>(document: &'a str, name: &str) -> Option<&'a str> {
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+    let start = document.find(&open)? + open.len();
+    let end = document[start..].find(&close)? + start;
+
+    Some(&document[start..end])
+}
@@ -0,0 +1,282 @@
+//! Select the cities to process.
+use crate::aliases::Aliases;
+use crate::cities::Cities;
+use crate::collation::fold;
+use crate::filter::Filter;
+use crate::Error;
+use pfbcore::scorecard::{City, Run};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+
+/// Describe the criteria used to select cities.
+///
+/// A city is selected if it matches one of the values of each criteria which
+/// is set, and all the filters. An empty selection selects all the cities.
+#[derive(Debug, Default, Clone)]
+pub struct Selection {
+    /// Names of the cities to select (case insensitive).
+    pub names: Vec<String>,
+    /// Run UUIDs of the cities to select (case insensitive).
+    pub uuids: Vec<String>,
+    /// States of the cities to select (case insensitive).
+    pub states: Vec<String>,
+    /// Countries of the cities to select (case insensitive).
+    pub countries: Vec<String>,
+    /// Select all the places matching a name, instead of reporting the names
+    /// matching several places as ambiguous.
+    pub all_matches: bool,
+    /// Aliases of the city names, consulted when the names are matched.
+    pub aliases: Aliases,
+    /// Filter expressions the cities must match, refer to [`Filter`].
+    pub filters: Vec<Filter>,
+    /// Only select the runs dated on or after this date, as `YYYY-MM-DD`.
+    pub run_after: Option<String>,
+    /// Only select the runs dated strictly before this date, as `YYYY-MM-DD`.
+    pub run_before: Option<String>,
+}
+
+/// Represent the result of a selection.
+#[derive(Debug, Default, Clone)]
+pub struct Selected {
+    /// Selected cities, in the order of the input.
+    pub cities: Cities,
+    /// Names which did not match any city.
+    pub unmatched: Vec<String>,
+    /// UUIDs which did not match any city.
+    pub unmatched_uuids: Vec<String>,
+    /// Names which matched several places, e.g. "Springfield" in several
+    /// states, along with the matching cities. They are not selected.
+    pub ambiguous: Vec<(String, Cities)>,
+}
+
+impl Selection {
+    /// Return `true` if no criteria is set.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+            && self.uuids.is_empty()
+            && self.states.is_empty()
+            && self.countries.is_empty()
+            && self.filters.is_empty()
+            && !self.has_run_dates()
+    }
+
+    /// Return `true` if the runs are selected by date.
+    pub fn has_run_dates(&self) -> bool {
+        self.run_after.is_some() || self.run_before.is_some()
+    }
+
+    /// Return `true` if the run falls between the selected dates.
+    ///
+    /// The runs without a date never match once a date is set.
+    pub fn matches_run(&self, run: &Run) -> bool {
+        if !self.has_run_dates() {
+            return true;
+        }
+        let Some(date) = run.date.as_deref() else {
+            return false;
+        };
+        let date = date.get(..10).unwrap_or(date);
+        let after = self.run_after.as_deref().is_none_or(|a| date >= a);
+        let before = self.run_before.as_deref().is_none_or(|b| date < b);
+        after && before
+    }
+
+    /// Return `true` if the city matches the criteria.
+    ///
+    /// Use a [`Matcher`] to match many cities.
+    pub fn matches(&self, city: &City) -> bool {
+        self.matcher().matches(city)
+    }
+
+    /// Prepare the criteria to match many cities, folding the names once.
+    pub fn matcher(&self) -> Matcher<'_> {
+        let fold_all =
+            |values: &[String]| -> Vec<String> { values.iter().map(|v| fold(v)).collect() };
+        Matcher {
+            selection: self,
+            names: self
+                .names
+                .iter()
+                .map(|n| fold(self.aliases.resolve(n)))
+                .collect(),
+            states: fold_all(&self.states),
+            countries: fold_all(&self.countries),
+        }
+    }
+
+    /// Select the matching cities.
+    ///
+    /// When the runs are selected by date, only the latest matching run of
+    /// each city is kept.
+    ///
+    /// Unless all the matches are requested, a name which matches cities
+    /// located in several states or countries is ambiguous, and none of
+    /// these cities is selected.
+    pub fn apply(&self, cities: &Cities) -> Selected {
+        let matcher = self.matcher();
+        let mut selected: Cities = cities
+            .iter()
+            .filter(|c| matcher.matches(c))
+            .cloned()
+            .collect();
+        if self.has_run_dates() {
+            selected = latest_runs(&selected);
+        }
+        let mut ambiguous = Vec::new();
+        if !self.all_matches {
+            for name in &self.names {
+                let matching: Cities = selected
+                    .iter()
+                    .filter(|c| self.aliases.matches(name, &c.name))
+                    .cloned()
+                    .collect();
+                let places: HashSet<(String, String)> = matching
+                    .iter()
+                    .map(|c| (fold(&c.country), fold(&c.state)))
+                    .collect();
+                if places.len() > 1 {
+                    ambiguous.push((name.clone(), matching));
+                }
+            }
+            selected = selected
+                .iter()
+                .filter(|c| {
+                    !ambiguous
+                        .iter()
+                        .any(|(n, _)| self.aliases.matches(n, &c.name))
+                })
+                .cloned()
+                .collect();
+        }
+        let unmatched = self
+            .names
+            .iter()
+            .filter(|n| {
+                !selected.iter().any(|c| self.aliases.matches(n, &c.name))
+                    && !ambiguous.iter().any(|(a, _)| a == *n)
+            })
+            .cloned()
+            .collect();
+        let unmatched_uuids = self
+            .uuids
+            .iter()
+            .filter(|u| selected.get_by_uuid(u).is_none())
+            .cloned()
+            .collect();
+
+        Selected {
+            cities: selected,
+            unmatched,
+            unmatched_uuids,
+            ambiguous,
+        }
+    }
+}
+
+/// Match the cities against a [`Selection`], whose names are folded once.
+#[derive(Debug)]
+pub struct Matcher<'a> {
+    selection: &'a Selection,
+    names: Vec<String>,
+    states: Vec<String>,
+    countries: Vec<String>,
+}
+
+impl Matcher<'_> {
+    /// Return `true` if the city matches the criteria.
+    pub fn matches(&self, city: &City) -> bool {
+        let selection = self.selection;
+        let name = self.names.is_empty()
+            || self
+                .names
+                .contains(&fold(selection.aliases.resolve(&city.name)));
+        let uuid = selection.uuids.is_empty()
+            || selection
+                .uuids
+                .iter()
+                .any(|u| u.eq_ignore_ascii_case(&city.run.uuid));
+        let state = self.states.is_empty() || self.states.contains(&fold(&city.state));
+        let country = self.countries.is_empty() || self.countries.contains(&fold(&city.country));
+        let filters = selection.filters.iter().all(|f| f.matches(city));
+        name && uuid && state && country && filters && selection.matches_run(&city.run)
+    }
+}
+
+/// Keep the latest run of each city, in the order of the input.
+fn latest_runs(cities: &Cities) -> Cities {
+    let cities = cities.as_slice();
+    let mut latest: HashMap<String, usize> = HashMap::new();
+    for (i, city) in cities.iter().enumerate() {
+        match latest.entry(city.full_name()) {
+            Entry::Vacant(entry) => {
+                entry.insert(i);
+            }
+            Entry::Occupied(mut entry) => {
+                if city.run.date > cities[*entry.get()].run.date {
+                    entry.insert(i);
+                }
+            }
+        }
+    }
+    let kept: HashSet<usize> = latest.into_values().collect();
+
+    cities
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| kept.contains(i))
+        .map(|(_, city)| city.clone())
+        .collect()
+}
+
+/// Parse a date specified as `YYYY-MM-DD`, e.g. `2023-01-01`.
+pub fn parse_date(value: &str) -> Result<String, Error> {
+    let parts: Vec<&str> = value.split('-').collect();
+    let valid = parts.len() == 3
+        && parts
+            .iter()
+            .zip([4, 2, 2])
+            .all(|(p, len)| p.len() == len && p.chars().all(|c| c.is_ascii_digit()))
+        && matches!(parts[1].parse::<u8>(), Ok(1..=12))
+        && matches!(parts[2].parse::<u8>(), Ok(1..=31));
+    if !valid {
+        return Err(Error::Parse(format!(
+            "invalid date {:?}, expected `YYYY-MM-DD`",
+            value
+        )));
+    }
+
+    Ok(value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_valid_dates() {
+        for date in ["2023-01-01", "1999-12-31", "2024-02-29"] {
+            assert_eq!(parse_date(date).unwrap(), date);
+        }
+    }
+
+    #[test]
+    fn parse_invalid_dates() {
+        for date in [
+            "",
+            "2023",
+            "2023-01",
+            "2023-1-01",
+            "23-01-01",
+            "2023-01-01-01",
+            "2023-00-01",
+            "2023-13-01",
+            "2023-01-00",
+            "2023-01-32",
+            "2023-01-0a",
+            "2023/01/01",
+            "+023-01-01",
+        ] {
+            assert!(matches!(parse_date(date), Err(Error::Parse(_))), "{}", date);
+        }
+    }
+}
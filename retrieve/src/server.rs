@@ -0,0 +1,264 @@
+//! Serve the datasets over a small HTTP API.
+//!
+//! The server turns `retrieve` into a dataset fetching service: clients
+//! enqueue the datasets of a city, then poll the status of the job. The jobs
+//! are processed one at a time, in the order they were submitted, each of them
+//! downloading its files concurrently.
+//!
+//! The endpoints are:
+//!
+//! - `POST /jobs`: enqueue a [`JobRequest`], and return the created [`Job`]
+//! - `GET /jobs/{id}`: return a [`Job`]
+//! - `GET /manifest`: return the [`Manifest`] of the download folder
+use crate::cities::Cities;
+use crate::download::{DownloadStatus, Downloader};
+use crate::manifest::{Manifest, ManifestEntry};
+use crate::plan::Plan;
+use crate::{Dataset, Error};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+/// Describe the datasets requested by a client.
+///
+/// The city is identified either by its name (or full name, refer to
+/// [`City::full_name`](crate::City::full_name)), looked up in the list of
+/// cities of the server, or directly by a run UUID.
+#[derive(Debug, Clone, Deserialize)]
+pub struct JobRequest {
+    /// Name or full name of the city.
+    #[serde(default)]
+    pub city: Option<String>,
+    /// Run UUID, used when no city name is provided.
+    #[serde(default)]
+    pub uuid: Option<String>,
+    /// Names of the datasets to retrieve, e.g. `neighborhood_ways`.
+    pub datasets: Vec<String>,
+}
+
+/// Describe the state of a job.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "state")]
+pub enum JobStatus {
+    /// The job is waiting for the previous ones to complete.
+    Queued,
+    /// The files are being retrieved.
+    Running,
+    /// The job is complete, though some of its files may have failed.
+    Completed,
+    /// The job could not be processed.
+    Failed { reason: String },
+}
+
+/// Represent a download job.
+#[derive(Debug, Clone, Serialize)]
+pub struct Job {
+    /// Identifier of the job.
+    pub id: usize,
+    /// Status of the job.
+    #[serde(flatten)]
+    pub status: JobStatus,
+    /// Retrieved files, relative to the download folder.
+    pub files: Vec<PathBuf>,
+    /// Reason of each failed download.
+    pub failures: Vec<String>,
+}
+
+/// Run the dataset fetching service.
+#[derive(Debug, Clone)]
+pub struct Server {
+    downloader: Downloader,
+    cities: Arc<Cities>,
+    jobs: Arc<Mutex<Vec<Job>>>,
+    queue: UnboundedSender<(usize, Plan)>,
+}
+
+impl Server {
+    /// Create a server downloading the files with `downloader`.
+    ///
+    /// The `cities` are used to resolve the city names of the requests.
+    ///
+    /// Return the server, and the receiving end of its job queue, to pass to
+    /// [`Server::serve`].
+    pub fn new(downloader: Downloader, cities: Cities) -> (Self, UnboundedReceiver<(usize, Plan)>) {
+        let (queue, receiver) = mpsc::unbounded_channel();
+        let server = Server {
+            downloader,
+            cities: Arc::new(cities),
+            jobs: Arc::new(Mutex::new(Vec::new())),
+            queue,
+        };
+
+        (server, receiver)
+    }
+
+    /// Return the routes of the API.
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/jobs", post(create_job))
+            .route("/jobs/:id", get(get_job))
+            .route("/manifest", get(get_manifest))
+            .with_state(self.clone())
+    }
+
+    /// Listen on `address`, and process the jobs of the `queue`.
+    ///
+    /// Only return if the server cannot be started.
+    pub async fn serve(
+        self,
+        address: SocketAddr,
+        queue: UnboundedReceiver<(usize, Plan)>,
+    ) -> Result<(), Error> {
+        let listener = TcpListener::bind(address).await?;
+        tokio::spawn(self.clone().work(queue));
+        axum::serve(listener, self.router()).await?;
+
+        Ok(())
+    }
+
+    /// Enqueue the downloads requested by a client.
+    pub fn enqueue(&self, request: &JobRequest) -> Result<Job, Error> {
+        let plan = self.plan(request)?;
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = Job {
+            id: jobs.len(),
+            status: JobStatus::Queued,
+            files: Vec::new(),
+            failures: Vec::new(),
+        };
+        jobs.push(job.clone());
+        self.queue
+            .send((job.id, plan))
+            .map_err(|_| Error::Parse(String::from("the job queue is closed")))?;
+
+        Ok(job)
+    }
+
+    /// Return the job identified by `id`.
+    pub fn job(&self, id: usize) -> Option<Job> {
+        self.jobs.lock().unwrap().get(id).cloned()
+    }
+
+    /// Plan the downloads of a request.
+    fn plan(&self, request: &JobRequest) -> Result<Plan, Error> {
+        let datasets = request
+            .datasets
+            .iter()
+            .map(|d| {
+                d.parse()
+                    .map_err(|e: pfbcore::Error| Error::Parse(e.to_string()))
+            })
+            .collect::<Result<Vec<Dataset>, _>>()?;
+        if datasets.is_empty() {
+            return Err(Error::Parse(String::from("no dataset requested")));
+        }
+        match (&request.city, &request.uuid) {
+            (Some(name), _) => {
+                let city = self
+                    .cities
+                    .get_by_full_name(name)
+                    .or_else(|| self.cities.get_by_name(name))
+                    .ok_or_else(|| Error::Parse(format!("no city named {:?}", name)))?;
+                Plan::new(std::slice::from_ref(city), &datasets)
+            }
+            (None, Some(uuid)) => Plan::from_uuid(uuid, None, &datasets),
+            (None, None) => Err(Error::Parse(String::from(
+                "either a city or a run UUID is required",
+            ))),
+        }
+    }
+
+    /// Process the jobs of the queue, one at a time.
+    async fn work(self, mut queue: UnboundedReceiver<(usize, Plan)>) {
+        while let Some((id, plan)) = queue.recv().await {
+            self.update(id, |job| job.status = JobStatus::Running);
+            match self.run(&plan).await {
+                Ok((files, failures)) => self.update(id, |job| {
+                    job.status = JobStatus::Completed;
+                    job.files = files;
+                    job.failures = failures;
+                }),
+                Err(e) => self.update(id, |job| {
+                    job.status = JobStatus::Failed {
+                        reason: e.to_string(),
+                    }
+                }),
+            }
+        }
+    }
+
+    /// Retrieve the files of a plan, and record them in the manifest.
+    ///
+    /// Return the retrieved files and the reason of each failure.
+    async fn run(&self, plan: &Plan) -> Result<(Vec<PathBuf>, Vec<String>), Error> {
+        let report = self.downloader.fetch_all(plan).await?;
+        let folder = self.downloader.destination_folder();
+        let mut manifest = Manifest::load(folder)?;
+        let mut files = Vec::new();
+        for outcome in &report.outcomes {
+            if let Some(entry) = ManifestEntry::from_outcome(outcome)? {
+                files.push(entry.path.clone());
+                manifest.upsert(entry);
+            }
+        }
+        manifest.save(folder)?;
+        let failures = report
+            .outcomes
+            .iter()
+            .filter_map(|o| match &o.status {
+                DownloadStatus::Failed(reason) => Some(format!(
+                    "{} for {}: {}",
+                    o.download.dataset, o.download.city, reason
+                )),
+                _ => None,
+            })
+            .collect();
+
+        Ok((files, failures))
+    }
+
+    /// Apply `f` to the job identified by `id`.
+    fn update<F>(&self, id: usize, f: F)
+    where
+        F: FnOnce(&mut Job),
+    {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(id) {
+            f(job);
+        }
+    }
+}
+
+/// Handle `POST /jobs`.
+async fn create_job(
+    State(server): State<Server>,
+    Json(request): Json<JobRequest>,
+) -> Result<(StatusCode, Json<Job>), (StatusCode, String)> {
+    server
+        .enqueue(&request)
+        .map(|job| (StatusCode::ACCEPTED, Json(job)))
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))
+}
+
+/// Handle `GET /jobs/{id}`.
+async fn get_job(
+    State(server): State<Server>,
+    Path(id): Path<usize>,
+) -> Result<Json<Job>, StatusCode> {
+    server.job(id).map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Handle `GET /manifest`.
+async fn get_manifest(
+    State(server): State<Server>,
+) -> Result<Json<Manifest>, (StatusCode, String)> {
+    Manifest::load(server.downloader.destination_folder())
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
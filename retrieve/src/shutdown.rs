@@ -0,0 +1,47 @@
+//! Stop a batch of downloads cleanly.
+//!
+//! Once a shutdown is requested, the downloads which did not start are
+//! reported as interrupted, and the transfers in progress are given a grace
+//! period to complete before being aborted.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+/// Signal a shutdown to the downloaders sharing it.
+///
+/// The clones of a `Shutdown` all refer to the same signal.
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown {
+    requested: Arc<AtomicBool>,
+    notify: Arc<Notify>,
+}
+
+impl Shutdown {
+    /// Create a signal which was not requested yet.
+    pub fn new() -> Self {
+        Shutdown::default()
+    }
+
+    /// Request the shutdown.
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    /// Return `true` if the shutdown was requested.
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    /// Wait until the shutdown is requested.
+    pub async fn requested(&self) {
+        loop {
+            // Register before checking the flag, not to miss a notification.
+            let notified = self.notify.notified();
+            if self.is_requested() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
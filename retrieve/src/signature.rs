@@ -0,0 +1,94 @@
+//! Sign the manifests and checksum files with minisign.
+//!
+//! The signature of a file is written next to it, with a `.minisig`
+//! extension, in the format of [minisign](https://jedisct1.github.io/minisign/),
+//! so it can also be verified with `minisign -V -p retrieve.pub -m manifest.json`.
+use crate::Error;
+use minisign::{PublicKey, PublicKeyBox, SecretKeyBox, SignatureBox};
+use std::env;
+use std::fs::{self, File};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+/// Suffix of the signature files.
+pub const SIGNATURE_SUFFIX: &str = ".minisig";
+
+/// Environment variable holding the password of the secret key.
+///
+/// The password is prompted for if the variable is not set.
+pub const PASSWORD_ENV: &str = "RETRIEVE_SIGN_PASSWORD";
+
+/// Return the path of the signature of `path`.
+pub fn signature_path(path: &Path) -> PathBuf {
+    let mut signature = path.as_os_str().to_owned();
+    signature.push(SIGNATURE_SUFFIX);
+    PathBuf::from(signature)
+}
+
+/// Sign the files located at `paths` with the secret key stored in
+/// `secret_key`.
+///
+/// The key is only decrypted once.
+pub fn sign_files<P>(paths: &[P], secret_key: &Path) -> Result<(), Error>
+where
+    P: AsRef<Path>,
+{
+    let secret_key = SecretKeyBox::from_string(&fs::read_to_string(secret_key)?)?
+        .into_secret_key(env::var(PASSWORD_ENV).ok())?;
+    for path in paths {
+        let path = path.as_ref();
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let trusted_comment = format!("file:{}", name);
+        let signature = minisign::sign(
+            None,
+            &secret_key,
+            File::open(path)?,
+            Some(&trusted_comment),
+            Some("signature from retrieve"),
+        )?;
+        fs::write(signature_path(path), signature.into_string())?;
+    }
+
+    Ok(())
+}
+
+/// Read a public key, either from a file in the minisign format, or given
+/// directly as a base64 string.
+pub fn read_public_key(key: &str) -> Result<PublicKey, Error> {
+    let path = Path::new(key);
+    let key = if path.is_file() {
+        PublicKeyBox::from_string(&fs::read_to_string(path)?)?.into_public_key()?
+    } else {
+        PublicKey::from_base64(key)?
+    };
+
+    Ok(key)
+}
+
+/// Verify the signature of the file located at `path`.
+///
+/// Fail if the signature is missing or invalid.
+pub fn verify_file(path: &Path, public_key: &PublicKey) -> Result<(), Error> {
+    let signature_path = signature_path(path);
+    if !signature_path.is_file() {
+        return Err(Error::Parse(format!(
+            "missing signature {}",
+            signature_path.display()
+        )));
+    }
+    let signature = SignatureBox::from_string(&fs::read_to_string(&signature_path)?)?;
+    let content = fs::read(path)?;
+    minisign::verify(
+        public_key,
+        &signature,
+        Cursor::new(content),
+        true,
+        false,
+        false,
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,493 @@
+//! Land each sync in a timestamped, immutable snapshot folder.
+//!
+//! In snapshot mode, the download folder holds one subfolder per sync, named
+//! after the time it started, e.g. `data/2024-05-01T02:00Z/`. A snapshot is
+//! never written again once its sync is over. The `current` symbolic link
+//! points to the last successful snapshot, and is only updated once a sync
+//! succeeds, so that the consumers never see a partial sync.
+//!
+//! The old snapshots can be pruned, keeping the most recent ones and the
+//! current one.
+//!
+//! Like `rsync --link-dest`, a new snapshot is seeded with the files of the
+//! current one which its sync does not download again, and the files it
+//! downloads which are identical to the files of the previous snapshots,
+//! according to the SHA256 hashes recorded in their manifests, are hardlinked
+//! to them, so that the unchanged files take no additional space.
+use crate::checksum::{self, SHA256SUMS};
+use crate::manifest::Manifest;
+use crate::plan::Plan;
+use crate::provenance;
+use crate::timestamp;
+use crate::Error;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Name of the link to the current snapshot.
+pub const CURRENT: &str = "current";
+
+/// Describe the files of a snapshot hardlinked to the previous snapshots.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Deduplication {
+    /// Number of linked files.
+    pub files: usize,
+    /// Space saved, in bytes.
+    pub bytes: u64,
+}
+
+/// Manage the snapshots of a download folder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshots {
+    base: PathBuf,
+}
+
+impl Snapshots {
+    /// Manage the snapshots stored in the `base` folder.
+    pub fn new<P>(base: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Snapshots {
+            base: base.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Return the folder holding the snapshots.
+    pub fn base(&self) -> &Path {
+        &self.base
+    }
+
+    /// Return the path of the snapshot of a sync started at `time`.
+    pub fn path(&self, time: SystemTime) -> PathBuf {
+        self.base.join(snapshot_name(time))
+    }
+
+    /// Create the snapshot of a sync started at `time`.
+    ///
+    /// Fail if the snapshot already exists, as it must not be altered.
+    pub fn create(&self, time: SystemTime) -> Result<PathBuf, Error> {
+        fs::create_dir_all(&self.base)?;
+        let path = self.path(time);
+        match fs::create_dir(&path) {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(Error::Parse(format!(
+                    "the snapshot {} already exists",
+                    path.display()
+                )))
+            }
+            result => result?,
+        }
+
+        Ok(path)
+    }
+
+    /// Return the snapshots, from the oldest to the most recent.
+    pub fn list(&self) -> Result<Vec<PathBuf>, Error> {
+        let mut snapshots = Vec::new();
+        let entries = match fs::read_dir(&self.base) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(snapshots),
+            entries => entries?,
+        };
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() && is_snapshot_name(&entry.file_name().to_string_lossy())
+            {
+                snapshots.push(entry.path());
+            }
+        }
+        snapshots.sort();
+
+        Ok(snapshots)
+    }
+
+    /// Return the current snapshot, if any.
+    pub fn current(&self) -> Option<PathBuf> {
+        let target = fs::read_link(self.base.join(CURRENT)).ok()?;
+        Some(self.base.join(target))
+    }
+
+    /// Point the `current` link to a snapshot.
+    ///
+    /// The link is replaced atomically, and is relative so that the folder can
+    /// be moved.
+    pub fn promote(&self, snapshot: &Path) -> Result<(), Error> {
+        let name = snapshot
+            .file_name()
+            .ok_or_else(|| Error::Parse(format!("invalid snapshot {}", snapshot.display())))?;
+        let link = self.base.join(CURRENT);
+        let tmp = self.base.join(format!(".{}.tmp", CURRENT));
+        let _ = fs::remove_file(&tmp);
+        symlink(Path::new(name), &tmp)?;
+        fs::rename(&tmp, &link)?;
+
+        Ok(())
+    }
+
+    /// Seed a new snapshot with the files of the current snapshot which the
+    /// `plan` does not download, along with their sidecars, extracted folders,
+    /// converted files and thumbnails.
+    ///
+    /// The files are hardlinked, or copied if they cannot be linked, and are
+    /// recorded in the manifest of the snapshot. The `SHA256SUMS` file is
+    /// copied, since it is updated in place.
+    pub fn seed(&self, snapshot: &Path, plan: &Plan) -> Result<Deduplication, Error> {
+        let mut deduplication = Deduplication::default();
+        let Some(current) = self.current() else {
+            return Ok(deduplication);
+        };
+        let planned: HashSet<&Path> = plan
+            .downloads
+            .iter()
+            .map(|d| d.file_name.as_path())
+            .collect();
+        let mut manifest = Manifest::default();
+        for entry in Manifest::load(&current)?.files {
+            if planned.contains(entry.path.as_path()) || !current.join(&entry.path).is_file() {
+                continue;
+            }
+            let mut paths = vec![
+                entry.path.clone(),
+                checksum::sidecar_path(&entry.path),
+                provenance::sidecar_path(&entry.path),
+            ];
+            paths.extend(entry.extracted.iter().cloned());
+            paths.extend(entry.converted.iter().cloned());
+            paths.extend(entry.thumbnail.iter().cloned());
+            for path in paths {
+                link_tree(
+                    &current.join(&path),
+                    &snapshot.join(&path),
+                    &mut deduplication,
+                )?;
+            }
+            manifest.upsert(entry);
+        }
+        let sums = current.join(SHA256SUMS);
+        if sums.is_file() {
+            fs::copy(&sums, snapshot.join(SHA256SUMS))?;
+        }
+        manifest.save(snapshot)?;
+
+        Ok(deduplication)
+    }
+
+    /// Hardlink the files of a snapshot to the identical files of the previous
+    /// snapshots.
+    ///
+    /// The files are identified by the SHA256 hash recorded in the manifests,
+    /// and the most recent snapshot holding a file is preferred. The files
+    /// which cannot be linked, e.g. because the snapshots are located on
+    /// different file systems, are kept as is.
+    pub fn deduplicate(&self, snapshot: &Path) -> Result<Deduplication, Error> {
+        // Index the files of the previous snapshots by hash.
+        let mut sources: HashMap<String, (PathBuf, u64)> = HashMap::new();
+        for previous in self
+            .list()?
+            .iter()
+            .rev()
+            .filter(|s| s.as_path() != snapshot)
+        {
+            for entry in Manifest::load(previous)?.files {
+                if let Some(hash) = entry.sha256 {
+                    sources
+                        .entry(hash)
+                        .or_insert_with(|| (previous.join(&entry.path), entry.size));
+                }
+            }
+        }
+
+        let mut deduplication = Deduplication::default();
+        for entry in Manifest::load(snapshot)?.files {
+            let Some((source, size)) = entry.sha256.and_then(|h| sources.get(&h).cloned()) else {
+                continue;
+            };
+            let path = snapshot.join(&entry.path);
+            if size != entry.size || !source.is_file() || same_file(&source, &path) {
+                continue;
+            }
+            let mut tmp = path.clone().into_os_string();
+            tmp.push(".link");
+            let _ = fs::remove_file(&tmp);
+            if fs::hard_link(&source, &tmp).is_err() {
+                continue;
+            }
+            fs::rename(&tmp, &path)?;
+            deduplication.files += 1;
+            deduplication.bytes += size;
+        }
+
+        Ok(deduplication)
+    }
+
+    /// Remove the old snapshots, keeping the `keep` most recent ones and the
+    /// current one.
+    ///
+    /// Return the removed snapshots.
+    pub fn prune(&self, keep: usize) -> Result<Vec<PathBuf>, Error> {
+        let current = self.current();
+        let snapshots = self.list()?;
+        let count = snapshots.len().saturating_sub(keep);
+        let mut removed = Vec::new();
+        for snapshot in snapshots.into_iter().take(count) {
+            if current.as_ref() == Some(&snapshot) {
+                continue;
+            }
+            fs::remove_dir_all(&snapshot)?;
+            removed.push(snapshot);
+        }
+
+        Ok(removed)
+    }
+}
+
+/// Return the name of the snapshot of a sync started at `time`, e.g.
+/// `2024-05-01T02:00Z`.
+///
+/// The colon is replaced by `_` on Windows, where it is forbidden.
+pub fn snapshot_name(time: SystemTime) -> String {
+    let name = format!("{}Z", &timestamp::rfc3339(time)[..16]);
+    if cfg!(windows) {
+        name.replace(':', "_")
+    } else {
+        name
+    }
+}
+
+/// Return `true` if a folder name is the name of a snapshot.
+fn is_snapshot_name(name: &str) -> bool {
+    let pattern = "0000-00-00T00:00Z";
+    name.len() == pattern.len()
+        && name.chars().zip(pattern.chars()).all(|(c, p)| match p {
+            '0' => c.is_ascii_digit(),
+            ':' => c == ':' || c == '_',
+            _ => c == p,
+        })
+}
+
+/// Hardlink the file or the folder tree located at `source` to `target`,
+/// copying the files which cannot be linked.
+///
+/// A missing `source` is skipped, and so are the symbolic links.
+fn link_tree(source: &Path, target: &Path, deduplication: &mut Deduplication) -> Result<(), Error> {
+    let metadata = match fs::symlink_metadata(source) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        metadata => metadata?,
+    };
+    if metadata.is_dir() {
+        fs::create_dir_all(target)?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            link_tree(
+                &entry.path(),
+                &target.join(entry.file_name()),
+                deduplication,
+            )?;
+        }
+        return Ok(());
+    }
+    if !metadata.is_file() {
+        return Ok(());
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::hard_link(source, target) {
+        Ok(()) => {
+            deduplication.files += 1;
+            deduplication.bytes += metadata.len();
+        }
+        Err(_) => {
+            fs::copy(source, target)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Return `true` if two paths designate the same file.
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+/// Return `true` if two paths designate the same file.
+#[cfg(not(unix))]
+fn same_file(_a: &Path, _b: &Path) -> bool {
+    false
+}
+
+/// Create a symbolic link to a folder.
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+/// Create a symbolic link to a folder.
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_dir(target, link)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::ManifestEntry;
+    use pfbcore::scorecard::City;
+    use pfbcore::Dataset;
+    use std::time::{Duration, UNIX_EPOCH};
+    use std::{env, process};
+
+    /// Return the start time of the `n`-th sync, one minute apart.
+    fn time(n: u64) -> SystemTime {
+        // 2024-05-01T02:00:00Z.
+        UNIX_EPOCH + Duration::from_secs(1_714_528_800 + 60 * n)
+    }
+
+    /// Create an empty folder in the temporary folder.
+    fn folder(name: &str) -> PathBuf {
+        let folder = env::temp_dir().join(format!("retrieve-snapshot-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    #[test]
+    fn name_snapshots() {
+        let name = snapshot_name(time(0));
+        if cfg!(windows) {
+            assert_eq!(name, "2024-05-01T02_00Z");
+        } else {
+            assert_eq!(name, "2024-05-01T02:00Z");
+        }
+        assert!(is_snapshot_name(&name));
+        assert!(is_snapshot_name("2024-05-01T02_00Z"));
+        for name in [
+            CURRENT,
+            "2024-05-01",
+            "2024-05-01T02:00",
+            "2024-05-01T02:00:00Z",
+        ] {
+            assert!(!is_snapshot_name(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn manage_snapshots() {
+        let base = folder("manage");
+        let snapshots = Snapshots::new(&base);
+        let created: Vec<PathBuf> = (0..3).map(|n| snapshots.create(time(n)).unwrap()).collect();
+        assert!(snapshots.create(time(1)).is_err());
+        fs::create_dir(base.join("other")).unwrap();
+        assert_eq!(snapshots.list().unwrap(), created);
+        assert_eq!(snapshots.current(), None);
+
+        snapshots.promote(&created[0]).unwrap();
+        snapshots.promote(&created[1]).unwrap();
+        assert_eq!(snapshots.current(), Some(created[1].clone()));
+        assert_eq!(snapshots.list().unwrap(), created);
+
+        // The current snapshot is kept along with the most recent one.
+        assert_eq!(snapshots.prune(1).unwrap(), vec![created[0].clone()]);
+        assert_eq!(snapshots.list().unwrap(), created[1..]);
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn seed_snapshots() {
+        let base = folder("seed");
+        let snapshots = Snapshots::new(&base);
+        let city = City::new(
+            "Austin",
+            "USA",
+            Some("TX"),
+            "8d2a8a6c-0c2b-4c4d-9b4e-3b6f5e8c1a2b",
+            950_000,
+            40.5,
+            41,
+        );
+        let plan = Plan::new(&[city], &[Dataset::NeighborhoodWays]).unwrap();
+        let planned = plan.downloads[0].file_name.clone();
+
+        // Without a current snapshot, there is nothing to seed.
+        let previous = snapshots.create(time(0)).unwrap();
+        assert_eq!(
+            snapshots.seed(&previous, &plan).unwrap(),
+            Deduplication::default()
+        );
+
+        let kept = PathBuf::from("kept.zip");
+        let mut manifest = Manifest::default();
+        for path in [&planned, &kept] {
+            fs::write(previous.join(path), "data").unwrap();
+            fs::write(previous.join(checksum::sidecar_path(path)), "hash").unwrap();
+            manifest.upsert(ManifestEntry {
+                path: path.clone(),
+                size: 4,
+                extracted: (path == &kept).then(|| PathBuf::from("kept")),
+                ..Default::default()
+            });
+        }
+        fs::create_dir(previous.join("kept")).unwrap();
+        fs::write(previous.join("kept").join("ways.shp"), "data").unwrap();
+        manifest.save(&previous).unwrap();
+        fs::write(previous.join(SHA256SUMS), "sums").unwrap();
+        snapshots.promote(&previous).unwrap();
+
+        let snapshot = snapshots.create(time(1)).unwrap();
+        let deduplication = snapshots.seed(&snapshot, &plan).unwrap();
+        assert_eq!(deduplication.files, 3);
+        for path in ["kept.zip", "kept.zip.sha256", "kept/ways.shp", SHA256SUMS] {
+            assert!(snapshot.join(path).is_file(), "{}", path);
+        }
+        assert!(!snapshot.join(&planned).exists());
+        let seeded = Manifest::load(&snapshot).unwrap();
+        assert_eq!(seeded.files.len(), 1);
+        assert_eq!(seeded.files[0].path, kept);
+        assert_eq!(seeded.files[0].extracted, Some(PathBuf::from("kept")));
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn deduplicate_snapshots() {
+        let base = folder("deduplicate");
+        let snapshots = Snapshots::new(&base);
+        let entry = |path: &str, hash: &str| ManifestEntry {
+            path: PathBuf::from(path),
+            size: 4,
+            sha256: Some(hash.to_string()),
+            ..Default::default()
+        };
+        for (n, hashes) in [(0, ["same", "old"]), (1, ["same", "new"])] {
+            let snapshot = snapshots.create(time(n)).unwrap();
+            let mut manifest = Manifest::default();
+            for (path, hash) in ["a.zip", "b.zip"].into_iter().zip(hashes) {
+                fs::write(snapshot.join(path), "data").unwrap();
+                manifest.upsert(entry(path, hash));
+            }
+            manifest.save(&snapshot).unwrap();
+        }
+        let [previous, snapshot] = <[PathBuf; 2]>::try_from(snapshots.list().unwrap()).unwrap();
+
+        let deduplication = snapshots.deduplicate(&snapshot).unwrap();
+        assert_eq!(deduplication, Deduplication { files: 1, bytes: 4 });
+        #[cfg(unix)]
+        assert!(same_file(&previous.join("a.zip"), &snapshot.join("a.zip")));
+        #[cfg(unix)]
+        assert!(!same_file(&previous.join("b.zip"), &snapshot.join("b.zip")));
+        assert_eq!(
+            snapshots.deduplicate(&snapshot).unwrap(),
+            Deduplication::default()
+        );
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
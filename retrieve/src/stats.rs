@@ -0,0 +1,164 @@
+//! Compute summary statistics about the datasets.
+//!
+//! The statistics are a quick sanity check that the retrieved data is
+//! plausible, e.g. that a city network is not empty.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+#[cfg(feature = "extract")]
+use {
+    crate::Error,
+    shapefile::dbase::FieldValue,
+    shapefile::{Point, Shape},
+    std::collections::HashMap,
+    std::fs,
+    std::path::{Path, PathBuf},
+};
+
+/// Names of the fields containing the stress of a segment, in both directions.
+#[cfg(feature = "extract")]
+const STRESS_FIELDS: [&str; 2] = ["ft_seg_str", "tf_seg_str"];
+
+/// Mean radius of the Earth, in meters.
+#[cfg(feature = "extract")]
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// Summarize a `neighborhood_ways` dataset.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WaysStats {
+    /// Number of features.
+    pub features: u64,
+    /// Total length of the segments, in meters (or in the units of the
+    /// projection of the dataset).
+    pub total_length: f64,
+    /// Number of segments per stress category (`low`, `high` or `unknown`).
+    ///
+    /// The stress of a segment is the highest stress of its two directions.
+    pub stress: BTreeMap<String, u64>,
+}
+
+#[cfg(feature = "extract")]
+impl WaysStats {
+    /// Compute the statistics of the shapefile located at `path`.
+    ///
+    /// The lengths are computed on the sphere if the shapefile uses geographic
+    /// coordinates, and in the units of its projection otherwise.
+    pub fn from_shapefile<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let geographic = fs::read_to_string(path.with_extension("prj"))
+            .map(|prj| prj.trim_start().starts_with("GEOGCS"))
+            .unwrap_or(false);
+        let mut reader = shapefile::Reader::from_path(path)?;
+        let mut stats = WaysStats::default();
+        for item in reader.iter_shapes_and_records() {
+            let (shape, record) = item?;
+            stats.features += 1;
+            if let Shape::Polyline(polyline) = shape {
+                for part in polyline.parts() {
+                    stats.total_length += part
+                        .windows(2)
+                        .map(|w| distance(&w[0], &w[1], geographic))
+                        .sum::<f64>();
+                }
+            }
+            let record: HashMap<String, FieldValue> = record.into();
+            let stress = record
+                .iter()
+                .filter(|(name, _)| STRESS_FIELDS.iter().any(|f| name.eq_ignore_ascii_case(f)))
+                .filter_map(|(_, value)| number(value))
+                .fold(None, |max: Option<f64>, v| {
+                    Some(max.map_or(v, |m| m.max(v)))
+                });
+            *stats
+                .stress
+                .entry(stress_category(stress).to_string())
+                .or_default() += 1;
+        }
+
+        Ok(stats)
+    }
+}
+
+impl fmt::Display for WaysStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} features, {:.1} km",
+            self.features,
+            self.total_length / 1000.0
+        )?;
+        for (category, count) in &self.stress {
+            write!(f, ", {} {} stress", count, category)?;
+        }
+        Ok(())
+    }
+}
+
+/// Return the first shapefile found in `folder`, including its sub-folders.
+#[cfg(feature = "extract")]
+pub fn find_shapefile<P>(folder: P) -> Result<Option<PathBuf>, Error>
+where
+    P: AsRef<Path>,
+{
+    let mut entries = fs::read_dir(folder)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|e| e.path());
+    for entry in entries {
+        let path = entry.path();
+        if path.is_dir() {
+            if let Some(shapefile) = find_shapefile(&path)? {
+                return Ok(Some(shapefile));
+            }
+        } else if path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("shp"))
+        {
+            return Ok(Some(path));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Return the stress category of a segment.
+///
+/// The PFB stress levels are 1 (low) and 3 (high).
+#[cfg(feature = "extract")]
+fn stress_category(stress: Option<f64>) -> &'static str {
+    match stress {
+        Some(1.0) => "low",
+        Some(3.0) => "high",
+        _ => "unknown",
+    }
+}
+
+/// Return the numeric value of a field.
+#[cfg(feature = "extract")]
+fn number(value: &FieldValue) -> Option<f64> {
+    match value {
+        FieldValue::Numeric(n) => *n,
+        FieldValue::Float(n) => n.map(f64::from),
+        FieldValue::Integer(n) => Some(f64::from(*n)),
+        FieldValue::Double(n) => Some(*n),
+        FieldValue::Character(s) => s.as_deref().and_then(|s| s.trim().parse().ok()),
+        _ => None,
+    }
+}
+
+/// Return the distance between two points.
+///
+/// The points are longitudes and latitudes in degrees if `geographic` is
+/// `true`, and planar coordinates otherwise.
+#[cfg(feature = "extract")]
+fn distance(a: &Point, b: &Point, geographic: bool) -> f64 {
+    if !geographic {
+        return (b.x - a.x).hypot(b.y - a.y);
+    }
+    let (lat1, lat2) = (a.y.to_radians(), b.y.to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = (b.x - a.x).to_radians();
+    let h = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * h.sqrt().asin()
+}
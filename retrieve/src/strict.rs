@@ -0,0 +1,197 @@
+//! Fail on any unexpected remote metadata.
+//!
+//! Regulated pipelines must not silently ingest an upstream anomaly. In strict
+//! mode, a download fails unless the response announces its length and its
+//! ETag, has a content type matching the dataset, and its size stays close to
+//! the size recorded in the manifest for the file it replaces.
+use crate::Error;
+use pfbcore::Dataset;
+
+/// Maximum deviation of the size of a file from the size of the file it
+/// replaces, as a share of the latter, used by default.
+pub const DEFAULT_MAX_SIZE_DEVIATION: f64 = 0.2;
+
+/// Content types accepted for every dataset, as served by default by the
+/// storages.
+const GENERIC_CONTENT_TYPES: [&str; 2] = ["application/octet-stream", "binary/octet-stream"];
+
+/// Describe the metadata of a response checked in strict mode.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ResponseMetadata<'a> {
+    /// Announced length of the body.
+    pub content_length: Option<u64>,
+    /// Announced ETag.
+    pub etag: Option<&'a str>,
+    /// Announced content type.
+    pub content_type: Option<&'a str>,
+}
+
+/// Check the remote metadata of the datasets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StrictMode {
+    /// Maximum deviation of the size of a file from the size of the file it
+    /// replaces, as a share of the latter, e.g. 0.2 for 20%.
+    pub max_size_deviation: f64,
+}
+
+impl Default for StrictMode {
+    fn default() -> Self {
+        StrictMode {
+            max_size_deviation: DEFAULT_MAX_SIZE_DEVIATION,
+        }
+    }
+}
+
+impl StrictMode {
+    /// Check the metadata of the response serving a `dataset`, which replaces
+    /// a local file of `previous_size` bytes, if any.
+    ///
+    /// Return the description of the first anomaly found.
+    pub fn check(
+        &self,
+        dataset: Dataset,
+        metadata: &ResponseMetadata,
+        previous_size: Option<u64>,
+    ) -> Result<(), String> {
+        let Some(length) = metadata.content_length else {
+            return Err(String::from("strict mode: missing Content-Length"));
+        };
+        if metadata.etag.filter(|e| !e.is_empty()).is_none() {
+            return Err(String::from("strict mode: missing ETag"));
+        }
+        let content_type = metadata.content_type.unwrap_or_default();
+        let essence = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        if !content_types(dataset)
+            .iter()
+            .chain(GENERIC_CONTENT_TYPES.iter())
+            .any(|&t| t == essence)
+        {
+            return Err(format!(
+                "strict mode: unexpected content type {:?} for {}",
+                content_type, dataset
+            ));
+        }
+        if let Some(previous) = previous_size.filter(|&s| s > 0) {
+            let deviation = (length as f64 - previous as f64).abs() / previous as f64;
+            if deviation > self.max_size_deviation {
+                return Err(format!(
+                    "strict mode: size of {} bytes deviates by {:.0}% from the previous {} bytes",
+                    length,
+                    deviation * 100.0,
+                    previous
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Return the content types expected for a dataset.
+fn content_types(dataset: Dataset) -> &'static [&'static str] {
+    match dataset.extension().as_str() {
+        "zip" => &["application/zip", "application/x-zip-compressed"],
+        "csv" => &["text/csv", "application/csv", "text/plain"],
+        "osm" => &[
+            "application/x-protobuf",
+            "application/vnd.openstreetmap.data+xml",
+        ],
+        _ => &[],
+    }
+}
+
+/// Parse a size deviation given as a percentage, e.g. "20%" or "20".
+pub fn parse_deviation(value: &str) -> Result<f64, Error> {
+    let number = value.trim().trim_end_matches('%').trim();
+    match number.parse::<f64>() {
+        Ok(percent) if percent.is_finite() && percent >= 0.0 => Ok(percent / 100.0),
+        _ => Err(Error::Parse(format!(
+            "invalid size deviation {:?}, expected a percentage like \"20%\"",
+            value
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METADATA: ResponseMetadata = ResponseMetadata {
+        content_length: Some(1000),
+        etag: Some("\"abc\""),
+        content_type: Some("application/zip"),
+    };
+
+    #[test]
+    fn accept_expected_metadata() {
+        let strict = StrictMode::default();
+        assert_eq!(
+            strict.check(Dataset::NeighborhoodWays, &METADATA, None),
+            Ok(())
+        );
+        assert_eq!(
+            strict.check(Dataset::NeighborhoodWays, &METADATA, Some(900)),
+            Ok(())
+        );
+        let metadata = ResponseMetadata {
+            content_type: Some("Binary/Octet-Stream; charset=binary"),
+            ..METADATA
+        };
+        assert_eq!(
+            strict.check(Dataset::NeighborhoodWays, &metadata, Some(0)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn reject_unexpected_metadata() {
+        let strict = StrictMode::default();
+        for metadata in [
+            ResponseMetadata {
+                content_length: None,
+                ..METADATA
+            },
+            ResponseMetadata {
+                etag: None,
+                ..METADATA
+            },
+            ResponseMetadata {
+                etag: Some(""),
+                ..METADATA
+            },
+            ResponseMetadata {
+                content_type: Some("text/html"),
+                ..METADATA
+            },
+            ResponseMetadata {
+                content_type: None,
+                ..METADATA
+            },
+        ] {
+            assert!(strict
+                .check(Dataset::NeighborhoodWays, &metadata, None)
+                .is_err());
+        }
+        assert!(strict
+            .check(Dataset::NeighborhoodWays, &METADATA, Some(500))
+            .is_err());
+        assert!(strict
+            .check(Dataset::NeighborhoodWays, &METADATA, Some(2000))
+            .is_err());
+    }
+
+    #[test]
+    fn parse_deviations() {
+        assert_eq!(parse_deviation("20%").unwrap(), 0.2);
+        assert_eq!(parse_deviation(" 5 % ").unwrap(), 0.05);
+        assert_eq!(parse_deviation("0").unwrap(), 0.0);
+        for value in ["", "%", "-5%", "twenty", "inf"] {
+            assert!(parse_deviation(value).is_err(), "{}", value);
+        }
+    }
+}
@@ -0,0 +1,259 @@
+//! Synchronize a download folder with a list of cities, like `rsync`.
+//!
+//! A planned file is transferred unless the local file is identical to the
+//! remote one. By default, the files are compared by size. With
+//! [`Comparison::Checksum`], the ETag of the remote file is compared with the
+//! ETag recorded in the manifest when the local file was retrieved, which
+//! identifies the remote content, and the hash of the local file with the
+//! SHA256 hash recorded along with it, which identifies the local content.
+//!
+//! The files recorded in the manifest which are not planned anymore, e.g.
+//! because their city was removed from the list or was analyzed again, can be
+//! deleted, along with the files derived from them.
+use crate::cache::sha256;
+use crate::manifest::Manifest;
+use crate::plan::{Plan, PlannedDownload};
+use crate::Error;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// Describe how a local file is compared with the remote one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Comparison {
+    /// Compare the size of the local file with the length announced for the
+    /// remote file, indexed by URL.
+    Size(HashMap<Url, u64>),
+    /// Compare the ETag of the remote file, indexed by URL, with the ETag
+    /// recorded in the manifest for the local file, and the hash of the local
+    /// file with the recorded hash.
+    Checksum(HashMap<Url, String>),
+}
+
+/// Describe what a sync does to a download folder.
+#[derive(Debug, Default, Clone)]
+pub struct Synchronization {
+    /// Files to transfer.
+    pub plan: Plan,
+    /// Files which are identical to the remote ones, relative to the download
+    /// folder.
+    pub unchanged: Vec<PathBuf>,
+    /// Files to delete, relative to the download folder.
+    pub deletions: Vec<PathBuf>,
+}
+
+impl Synchronization {
+    /// Compare the planned files with the local files of `folder`.
+    ///
+    /// When `delete` is set, the files recorded in the `manifest` which are
+    /// not planned are listed for deletion.
+    pub fn new(
+        mut plan: Plan,
+        folder: &Path,
+        manifest: &Manifest,
+        comparison: &Comparison,
+        delete: bool,
+    ) -> Result<Self, Error> {
+        let planned: HashSet<PathBuf> =
+            plan.downloads.iter().map(|d| d.file_name.clone()).collect();
+        let deletions = match delete {
+            true => manifest
+                .files
+                .iter()
+                .filter(|e| !planned.contains(&e.path))
+                .map(|e| e.path.clone())
+                .collect(),
+            false => Vec::new(),
+        };
+
+        let mut unchanged = Vec::new();
+        let mut transfers = Vec::new();
+        for download in plan.downloads.drain(..) {
+            if is_identical(&download, folder, manifest, comparison)? {
+                unchanged.push(download.file_name);
+            } else {
+                transfers.push(download);
+            }
+        }
+        plan.downloads = transfers;
+
+        Ok(Synchronization {
+            plan,
+            unchanged,
+            deletions,
+        })
+    }
+
+    /// Delete the files which are not planned anymore from `folder`, along
+    /// with their checksum and provenance sidecars, their extracted folders,
+    /// their converted files and their thumbnails, and remove them from its
+    /// `manifest`.
+    pub fn delete(&self, folder: &Path, manifest: &mut Manifest) -> Result<(), Error> {
+        manifest.remove(folder, &self.deletions)
+    }
+}
+
+/// Return `true` if the local file of a planned download is identical to the
+/// remote one.
+fn is_identical(
+    download: &PlannedDownload,
+    folder: &Path,
+    manifest: &Manifest,
+    comparison: &Comparison,
+) -> Result<bool, Error> {
+    let path = folder.join(&download.file_name);
+    let Some(metadata) = fs::metadata(&path).ok().filter(|m| m.is_file()) else {
+        return Ok(false);
+    };
+    let identical = match comparison {
+        Comparison::Size(lengths) => lengths.get(&download.url) == Some(&metadata.len()),
+        Comparison::Checksum(etags) => {
+            let (Some(etag), Some(entry)) =
+                (etags.get(&download.url), manifest.get(&download.file_name))
+            else {
+                return Ok(false);
+            };
+            match entry.sha256.as_deref() {
+                Some(expected) if entry.etag.as_ref() == Some(etag) => sha256(&path)? == expected,
+                _ => false,
+            }
+        }
+    };
+
+    Ok(identical)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum;
+    use crate::manifest::ManifestEntry;
+    use pfbcore::scorecard::City;
+    use pfbcore::Dataset;
+    use std::{env, process};
+
+    /// Create an empty folder in the temporary folder.
+    fn folder(name: &str) -> PathBuf {
+        let folder = env::temp_dir().join(format!("retrieve-sync-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    /// Plan the download of a single dataset.
+    fn plan() -> Plan {
+        let city = City::new(
+            "Austin",
+            "USA",
+            Some("TX"),
+            "8d2a8a6c-0c2b-4c4d-9b4e-3b6f5e8c1a2b",
+            950_000,
+            40.5,
+            41,
+        );
+        Plan::new(&[city], &[Dataset::NeighborhoodWays]).unwrap()
+    }
+
+    #[test]
+    fn compare_sizes() {
+        let folder = folder("sizes");
+        let plan = plan();
+        let download = plan.downloads[0].clone();
+        let manifest = Manifest::default();
+        let sizes = |size| Comparison::Size(HashMap::from([(download.url.clone(), size)]));
+
+        let sync =
+            Synchronization::new(plan.clone(), &folder, &manifest, &sizes(4), false).unwrap();
+        assert_eq!(sync.plan.downloads.len(), 1);
+
+        fs::write(folder.join(&download.file_name), "data").unwrap();
+        let sync =
+            Synchronization::new(plan.clone(), &folder, &manifest, &sizes(4), false).unwrap();
+        assert!(sync.plan.downloads.is_empty());
+        assert_eq!(sync.unchanged, vec![download.file_name.clone()]);
+        let sync = Synchronization::new(plan, &folder, &manifest, &sizes(5), false).unwrap();
+        assert_eq!(sync.plan.downloads.len(), 1);
+        assert!(sync.unchanged.is_empty());
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn compare_checksums() {
+        let folder = folder("checksums");
+        let plan = plan();
+        let download = plan.downloads[0].clone();
+        let path = folder.join(&download.file_name);
+        fs::write(&path, "data").unwrap();
+        let mut manifest = Manifest::default();
+        manifest.upsert(ManifestEntry {
+            path: download.file_name.clone(),
+            sha256: Some(sha256(&path).unwrap()),
+            etag: Some(String::from("abc")),
+            ..Default::default()
+        });
+        let etags = |etag: &str| {
+            Comparison::Checksum(HashMap::from([(download.url.clone(), etag.to_string())]))
+        };
+
+        let sync =
+            Synchronization::new(plan.clone(), &folder, &manifest, &etags("abc"), false).unwrap();
+        assert_eq!(sync.unchanged, vec![download.file_name.clone()]);
+        let sync =
+            Synchronization::new(plan.clone(), &folder, &manifest, &etags("def"), false).unwrap();
+        assert_eq!(sync.plan.downloads.len(), 1);
+
+        // The local file was altered since it was retrieved.
+        fs::write(&path, "other").unwrap();
+        let sync = Synchronization::new(plan, &folder, &manifest, &etags("abc"), false).unwrap();
+        assert_eq!(sync.plan.downloads.len(), 1);
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn delete_unplanned_files() {
+        let folder = folder("delete");
+        let plan = plan();
+        let planned = plan.downloads[0].file_name.clone();
+        let old = PathBuf::from("old.zip");
+        let mut manifest = Manifest::default();
+        for path in [&planned, &old] {
+            fs::write(folder.join(path), "data").unwrap();
+            fs::write(folder.join(checksum::sidecar_path(path)), "hash").unwrap();
+        }
+        fs::create_dir(folder.join("old")).unwrap();
+        fs::write(folder.join("old").join("ways.shp"), "data").unwrap();
+        fs::write(folder.join("old.parquet"), "data").unwrap();
+        manifest.upsert(ManifestEntry {
+            path: planned.clone(),
+            ..Default::default()
+        });
+        manifest.upsert(ManifestEntry {
+            path: old.clone(),
+            extracted: Some(PathBuf::from("old")),
+            converted: Some(PathBuf::from("old.parquet")),
+            thumbnail: Some(PathBuf::from("old.png")),
+            ..Default::default()
+        });
+        let comparison = Comparison::Size(HashMap::new());
+
+        let sync =
+            Synchronization::new(plan.clone(), &folder, &manifest, &comparison, false).unwrap();
+        assert!(sync.deletions.is_empty());
+        let sync = Synchronization::new(plan, &folder, &manifest, &comparison, true).unwrap();
+        assert_eq!(sync.deletions, vec![old.clone()]);
+
+        sync.delete(&folder, &mut manifest).unwrap();
+        assert!(manifest.get(&old).is_none());
+        assert!(manifest.get(&planned).is_some());
+        for path in ["old.zip", "old.zip.sha256", "old", "old.parquet"] {
+            assert!(!folder.join(path).exists(), "{}", path);
+        }
+        assert!(folder.join(&planned).is_file());
+        assert!(folder.join(checksum::sidecar_path(&planned)).is_file());
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,174 @@
+//! Export the city inventory and the scores as Parquet files.
+//!
+//! The tables hold one row per city run. Every column is nullable, so the
+//! schema stays the same whether or not a CSV file fills the optional fields.
+//!
+//! | Table    | Columns                                                          |
+//! |----------|------------------------------------------------------------------|
+//! | `cities` | `name`, `state`, `country`, `uuid`, `date`, `version`, `population`, `ratings`, `ratings_rounded` |
+//! | `scores` | The columns of `cities`, followed by the community survey, BNA and infrastructure scores |
+use crate::Error;
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray, UInt32Array, UInt8Array};
+use arrow_schema::{Field, Schema};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use pfbcore::scorecard::{City, ScoreCard};
+use std::io::Write;
+use std::sync::Arc;
+
+/// Accumulate the columns of a table.
+#[derive(Default)]
+struct Columns {
+    fields: Vec<Field>,
+    arrays: Vec<ArrayRef>,
+}
+
+impl Columns {
+    /// Add a column.
+    fn push(&mut self, name: &str, array: ArrayRef) {
+        self.fields
+            .push(Field::new(name, array.data_type().clone(), true));
+        self.arrays.push(array);
+    }
+
+    /// Add a text column.
+    fn strings<I, S>(&mut self, name: &str, values: I)
+    where
+        I: IntoIterator<Item = Option<S>>,
+        S: AsRef<str>,
+    {
+        self.push(name, Arc::new(values.into_iter().collect::<StringArray>()));
+    }
+
+    /// Add a floating point column.
+    fn floats<I>(&mut self, name: &str, values: I)
+    where
+        I: IntoIterator<Item = Option<f64>>,
+    {
+        self.push(name, Arc::new(values.into_iter().collect::<Float64Array>()));
+    }
+
+    /// Add the columns describing the cities.
+    fn cities<'a, I>(&mut self, cities: I)
+    where
+        I: Iterator<Item = &'a City> + Clone,
+    {
+        self.strings("name", cities.clone().map(|c| Some(&c.name)));
+        self.strings("state", cities.clone().map(|c| Some(&c.state)));
+        self.strings("country", cities.clone().map(|c| Some(&c.country)));
+        self.strings("uuid", cities.clone().map(|c| Some(&c.run.uuid)));
+        self.strings("date", cities.clone().map(|c| c.run.date.as_ref()));
+        self.strings("version", cities.clone().map(|c| c.run.version.as_ref()));
+        self.push(
+            "population",
+            Arc::new(
+                cities
+                    .clone()
+                    .map(|c| Some(c.population))
+                    .collect::<UInt32Array>(),
+            ),
+        );
+        self.floats("ratings", cities.clone().map(|c| Some(c.ratings)));
+        self.push(
+            "ratings_rounded",
+            Arc::new(
+                cities
+                    .map(|c| Some(c.ratings_rounded))
+                    .collect::<UInt8Array>(),
+            ),
+        );
+    }
+
+    /// Write the table as a Parquet file, compressed with Snappy.
+    fn write<W>(self, writer: W) -> Result<(), Error>
+    where
+        W: Write + Send,
+    {
+        let schema = Arc::new(Schema::new(self.fields));
+        let batch = RecordBatch::try_new(schema.clone(), self.arrays)?;
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let mut writer = ArrowWriter::try_new(writer, schema, Some(properties))?;
+        writer.write(&batch)?;
+        writer.close()?;
+
+        Ok(())
+    }
+}
+
+/// Write the city inventory as a Parquet file.
+pub fn write_cities<W>(cities: &[City], writer: W) -> Result<(), Error>
+where
+    W: Write + Send,
+{
+    let mut columns = Columns::default();
+    columns.cities(cities.iter());
+    columns.write(writer)
+}
+
+/// Write the cities along with their scores as a Parquet file.
+pub fn write_scorecards<W>(scorecards: &[ScoreCard], writer: W) -> Result<(), Error>
+where
+    W: Write + Send,
+{
+    let mut columns = Columns::default();
+    columns.cities(scorecards.iter().map(|s| &s.city));
+
+    // Community survey.
+    let survey = scorecards.iter().map(|s| &s.community_survey);
+    columns.floats("survey_network", survey.clone().map(|s| Some(s.network)));
+    columns.floats(
+        "survey_awareness",
+        survey.clone().map(|s| Some(s.awareness)),
+    );
+    columns.floats("survey_safety", survey.clone().map(|s| Some(s.safety)));
+    columns.floats(
+        "survey_ridership",
+        survey.clone().map(|s| Some(s.ridership)),
+    );
+    columns.floats("survey_total", survey.clone().map(|s| Some(s.total)));
+    columns.push(
+        "survey_total_rounded",
+        Arc::new(
+            survey
+                .clone()
+                .map(|s| Some(s.total_rounded))
+                .collect::<UInt32Array>(),
+        ),
+    );
+    columns.push(
+        "survey_responses",
+        Arc::new(survey.map(|s| Some(s.responses)).collect::<UInt32Array>()),
+    );
+
+    // Bicycle Network Analysis.
+    let bna = scorecards.iter().map(|s| &s.bna);
+    columns.floats(
+        "bna_neighborhoods",
+        bna.clone().map(|b| Some(b.neighborhoods)),
+    );
+    columns.floats("bna_opportunity", bna.clone().map(|b| Some(b.opportunity)));
+    columns.floats(
+        "bna_essential_services",
+        bna.clone().map(|b| b.essential_services),
+    );
+    columns.floats("bna_retail", bna.clone().map(|b| Some(b.retail)));
+    columns.floats("bna_recreation", bna.clone().map(|b| b.recreation));
+    columns.floats("bna_transit", bna.clone().map(|b| Some(b.transit)));
+    columns.floats("bna_overall_score", bna.map(|b| Some(b.overall_score)));
+
+    // Infrastructure.
+    let infrastructure = scorecards.iter().map(|s| &s.infrastructure);
+    columns.floats(
+        "low_stress_miles",
+        infrastructure.clone().map(|i| i.low_stress_miles),
+    );
+    columns.floats(
+        "high_stress_miles",
+        infrastructure.map(|i| i.high_stress_miles),
+    );
+
+    columns.write(writer)
+}
@@ -0,0 +1,38 @@
+//! Export the traces of the downloads to an OpenTelemetry collector.
+use color_eyre::Report;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::runtime::Tokio;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::Resource;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use url::Url;
+
+/// Name of the service reported to the collector.
+const SERVICE_NAME: &str = env!("CARGO_PKG_NAME");
+
+/// Send the spans and events of the application to the OTLP `endpoint`.
+///
+/// The returned provider must be shut down before exiting, to flush the
+/// pending spans.
+pub fn init(endpoint: &Url) -> Result<TracerProvider, Report> {
+    let exporter = SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint.as_str())
+        .build()?;
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, Tokio)
+        .with_resource(Resource::new(vec![
+            KeyValue::new("service.name", SERVICE_NAME),
+            KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+        ]))
+        .build();
+    let tracer = provider.tracer(SERVICE_NAME);
+    tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .try_init()?;
+
+    Ok(provider)
+}
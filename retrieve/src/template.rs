@@ -0,0 +1,162 @@
+//! Name the retrieved files after a template.
+//!
+//! A template is a path where the placeholders between braces are replaced by
+//! the details of the city and of the dataset, e.g.
+//! `{country}/{city}_{state}_{dataset}_{uuid8}.{ext}`. The available
+//! placeholders are:
+//!
+//! - `{city}`: name of the city
+//! - `{state}`: state of the city
+//! - `{country}`: country of the city
+//! - `{full_name}`: full name of the city, refer to [`City::full_name`]
+//! - `{slug}`: file system friendly full name, refer to [`City::slug`]
+//! - `{uuid}`: run UUID
+//! - `{uuid8}`: first 8 characters of the run UUID
+//! - `{date}`: date of the run, if known
+//! - `{version}`: version of the BNA which computed the run, if known
+//! - `{dataset}`: name of the dataset, e.g. `neighborhood_ways`
+//! - `{ext}`: extension of the dataset, e.g. `zip`
+//!
+//! The other placeholders refer to the fields of the city by name, refer to
+//! [`City::field`], including the extra columns of the CSV file, e.g.
+//! `{population}` or `{region}`. They are replaced by an empty string if the
+//! city does not have such a field.
+//!
+//! The path separators contained in the values are replaced by hyphens, so
+//! that a city name never creates a folder. The rendered paths must stay
+//! relative to the destination folder: the absolute paths and the `..`
+//! components are rejected.
+use crate::Error;
+use pfbcore::scorecard::{City, Run};
+use pfbcore::Dataset;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+/// Names of the supported placeholders.
+const PLACEHOLDERS: [&str; 11] = [
+    "city",
+    "state",
+    "country",
+    "full_name",
+    "slug",
+    "uuid",
+    "uuid8",
+    "date",
+    "version",
+    "dataset",
+    "ext",
+];
+
+/// Represent a part of a template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    /// Text copied as is.
+    Literal(String),
+    /// Name of a placeholder.
+    Placeholder(String),
+}
+
+/// Represent a file name template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileNameTemplate {
+    template: String,
+    parts: Vec<Part>,
+}
+
+impl FileNameTemplate {
+    /// Return the name of the file storing the `dataset` produced by the `run`
+    /// of the `city`, relative to the destination folder.
+    ///
+    /// Fail if the rendered path is absolute or contains a `..` component, e.g.
+    /// because of a city named `..`.
+    pub fn render(&self, city: &City, run: &Run, dataset: Dataset) -> Result<PathBuf, Error> {
+        let mut name = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => name.push_str(text),
+                Part::Placeholder(placeholder) => {
+                    let value = match placeholder.as_str() {
+                        "city" => city.name.clone(),
+                        "state" => city.state.clone(),
+                        "country" => city.country.clone(),
+                        "full_name" => city.full_name(),
+                        "slug" => city.slug(),
+                        "uuid" => run.uuid.clone(),
+                        "uuid8" => run.uuid.chars().take(8).collect(),
+                        "date" => run.date.clone().unwrap_or_default(),
+                        "version" => run.version.clone().unwrap_or_default(),
+                        "dataset" => dataset.to_string(),
+                        "ext" => dataset.extension(),
+                        field => city.field(field).unwrap_or_default(),
+                    };
+                    name.push_str(&value.replace(['/', '\\'], "-"));
+                }
+            }
+        }
+        check_relative(&name)?;
+
+        Ok(PathBuf::from(name))
+    }
+}
+
+/// Fail if `path` is absolute or contains a `..` component.
+fn check_relative(path: &str) -> Result<(), Error> {
+    let escapes = Path::new(path)
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir));
+    if escapes {
+        return Err(Error::Parse(format!(
+            "invalid file name {:?}, expected a path relative to the destination folder",
+            path
+        )));
+    }
+
+    Ok(())
+}
+
+impl FromStr for FileNameTemplate {
+    type Err = Error;
+
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..].find('}').ok_or_else(|| {
+                Error::Parse(format!("unclosed placeholder in template {:?}", template))
+            })?;
+            let placeholder = &rest[start + 1..start + end];
+            if placeholder.is_empty() || placeholder.contains('{') {
+                return Err(Error::Parse(format!(
+                    "invalid placeholder {{{}}} in template {:?}, expected a field name like: {}",
+                    placeholder,
+                    template,
+                    PLACEHOLDERS.join(", ")
+                )));
+            }
+            parts.push(Part::Placeholder(placeholder.to_string()));
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        if parts.is_empty() {
+            return Err(Error::Parse(String::from("empty file name template")));
+        }
+        check_relative(template)?;
+
+        Ok(FileNameTemplate {
+            template: template.to_string(),
+            parts,
+        })
+    }
+}
+
+impl fmt::Display for FileNameTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.template)
+    }
+}
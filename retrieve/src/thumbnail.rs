@@ -0,0 +1,148 @@
+//! Render a thumbnail of the neighborhood ways of a city.
+//!
+//! The segments are drawn on a white background and colored by stress level:
+//! blue for low stress, red for high stress, and grey when the stress is
+//! unknown. The thumbnails are meant for quick visual checks, e.g. on a QA
+//! dashboard, not for publication.
+use crate::projection::shapefile_projection;
+use crate::ways::{Stress, WaysReader};
+use crate::Error;
+use geo::CoordsIter;
+use geo_types::{Geometry, LineString};
+use std::path::{Path, PathBuf};
+use tiny_skia::{Color, Paint, PathBuilder, Pixmap, Stroke, Transform};
+
+/// Suffix of the thumbnails.
+pub const THUMBNAIL_SUFFIX: &str = ".png";
+
+/// Margin around the network, in pixels.
+const MARGIN: f64 = 8.0;
+
+/// Return the path of the thumbnail of `path`.
+pub fn thumbnail_path(path: &Path) -> PathBuf {
+    let mut thumbnail = path.as_os_str().to_owned();
+    thumbnail.push(THUMBNAIL_SUFFIX);
+    PathBuf::from(thumbnail)
+}
+
+/// Describe the options of a thumbnail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThumbnailOptions {
+    /// Length of the longest side of the image, in pixels.
+    pub size: u32,
+}
+
+impl Default for ThumbnailOptions {
+    fn default() -> Self {
+        ThumbnailOptions { size: 512 }
+    }
+}
+
+/// Return the color of a stress level.
+fn color(stress: Option<Stress>) -> Color {
+    match stress {
+        Some(Stress::Low) => Color::from_rgba8(0x1f, 0x78, 0xb4, 0xff),
+        Some(Stress::High) => Color::from_rgba8(0xe3, 0x1a, 0x1c, 0xff),
+        None => Color::from_rgba8(0xbd, 0xbd, 0xbd, 0xff),
+    }
+}
+
+/// Render the neighborhood ways of the dataset located at `path` as a PNG
+/// image saved at `output`.
+///
+/// The longitudes are scaled by the cosine of the latitude when the dataset
+/// uses geographic coordinates, so that the network is not stretched.
+pub fn render_ways<P, Q>(path: P, output: Q, options: &ThumbnailOptions) -> Result<(), Error>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let output = output.as_ref();
+    let latlong = shapefile_projection(path)?.map_or(true, |p| p.is_latlong());
+
+    // Read the segments, along with their stress.
+    let mut reader = WaysReader::open(path)?;
+    let mut segments: Vec<(Option<Stress>, Geometry<f64>)> = Vec::new();
+    for way in reader.ways() {
+        let way = way?;
+        let stress = way.stress();
+        if let Some(geometry) = way.geometry {
+            segments.push((stress, geometry));
+        }
+    }
+
+    // Fit the network into the image.
+    let mut coords = segments.iter().flat_map(|(_, g)| g.coords_iter());
+    let Some(first) = coords.next() else {
+        return Err(Error::Parse(format!("no geometry in {}", path.display())));
+    };
+    let (mut min, mut max) = (first, first);
+    for c in coords {
+        min.x = min.x.min(c.x);
+        min.y = min.y.min(c.y);
+        max.x = max.x.max(c.x);
+        max.y = max.y.max(c.y);
+    }
+    let x_scale = if latlong {
+        ((min.y + max.y) / 2.0).to_radians().cos()
+    } else {
+        1.0
+    };
+    let width = ((max.x - min.x) * x_scale).max(f64::EPSILON);
+    let height = (max.y - min.y).max(f64::EPSILON);
+    let drawable = f64::from(options.size) - 2.0 * MARGIN;
+    let scale = drawable / width.max(height);
+    let image_width = (width * scale + 2.0 * MARGIN).ceil() as u32;
+    let image_height = (height * scale + 2.0 * MARGIN).ceil() as u32;
+    let mut pixmap = Pixmap::new(image_width, image_height)
+        .ok_or_else(|| Error::Parse(format!("invalid thumbnail size {}", options.size)))?;
+    pixmap.fill(Color::WHITE);
+    let project = |x: f64, y: f64| {
+        (
+            (MARGIN + (x - min.x) * x_scale * scale) as f32,
+            (MARGIN + (max.y - y) * scale) as f32,
+        )
+    };
+
+    // Draw the high stress segments first, so that the low stress network
+    // stands out.
+    let stroke = Stroke {
+        width: 1.0,
+        ..Default::default()
+    };
+    for stress in [None, Some(Stress::High), Some(Stress::Low)] {
+        let mut builder = PathBuilder::new();
+        for (_, geometry) in segments.iter().filter(|(s, _)| *s == stress) {
+            for line in lines(geometry) {
+                let mut points = line.points().map(|p| project(p.x(), p.y()));
+                if let Some((x, y)) = points.next() {
+                    builder.move_to(x, y);
+                    for (x, y) in points {
+                        builder.line_to(x, y);
+                    }
+                }
+            }
+        }
+        if let Some(path) = builder.finish() {
+            let mut paint = Paint::default();
+            paint.set_color(color(stress));
+            paint.anti_alias = true;
+            pixmap.stroke_path(&path, &paint, &stroke, Transform::identity(), None);
+        }
+    }
+    pixmap
+        .save_png(output)
+        .map_err(|e| Error::Parse(format!("cannot write {}: {}", output.display(), e)))?;
+
+    Ok(())
+}
+
+/// Return the lines of a geometry.
+fn lines(geometry: &Geometry<f64>) -> Vec<&LineString<f64>> {
+    match geometry {
+        Geometry::LineString(line) => vec![line],
+        Geometry::MultiLineString(lines) => lines.0.iter().collect(),
+        _ => Vec::new(),
+    }
+}
@@ -0,0 +1,112 @@
+//! Build vector tiles from the converted neighborhood ways.
+//!
+//! The tiles are built by [tippecanoe](https://github.com/felt/tippecanoe),
+//! which merges the GeoJSON versions of the `neighborhood_ways` datasets of a
+//! download folder into a single layer. The output is an MBTiles or a PMTiles
+//! archive, depending on its extension.
+//!
+//! tippecanoe expects WGS84 longitudes and latitudes: convert the datasets with
+//! `--convert geojson --to-crs EPSG:4326`.
+use crate::manifest::Manifest;
+use crate::Error;
+use pfbcore::Dataset;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Name of the tippecanoe executable.
+pub const TIPPECANOE: &str = "tippecanoe";
+
+/// Describe the options of the tiles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileOptions {
+    /// Lowest zoom level.
+    pub min_zoom: u8,
+    /// Highest zoom level.
+    pub max_zoom: u8,
+    /// Name of the layer holding the ways.
+    pub layer: String,
+    /// Path of the tippecanoe executable.
+    pub tippecanoe: PathBuf,
+}
+
+impl Default for TileOptions {
+    fn default() -> Self {
+        TileOptions {
+            min_zoom: 8,
+            max_zoom: 14,
+            layer: Dataset::NeighborhoodWays.to_string(),
+            tippecanoe: PathBuf::from(TIPPECANOE),
+        }
+    }
+}
+
+/// Return the converted GeoJSON versions of the `neighborhood_ways` datasets of
+/// the download `folder`.
+pub fn ways_sources<P>(folder: P) -> Result<Vec<PathBuf>, Error>
+where
+    P: AsRef<Path>,
+{
+    let folder = folder.as_ref();
+    let dataset = Dataset::NeighborhoodWays.to_string();
+    let sources = Manifest::load(folder)?
+        .files
+        .iter()
+        .filter(|e| e.dataset == dataset)
+        .filter_map(|e| e.converted.as_ref())
+        .filter(|p| p.extension().is_some_and(|e| e == "geojson"))
+        .map(|p| folder.join(p))
+        .collect();
+
+    Ok(sources)
+}
+
+/// Return the tippecanoe command building the tiles of the `sources` into
+/// `output`.
+pub fn tippecanoe_command(sources: &[PathBuf], output: &Path, options: &TileOptions) -> Command {
+    let mut command = Command::new(&options.tippecanoe);
+    command
+        .arg("--output")
+        .arg(output)
+        .arg("--force")
+        .arg(format!("--minimum-zoom={}", options.min_zoom))
+        .arg(format!("--maximum-zoom={}", options.max_zoom))
+        .arg(format!("--layer={}", options.layer))
+        .arg("--drop-densest-as-needed")
+        .args(sources);
+    command
+}
+
+/// Build the tiles of the neighborhood ways of the download `folder` into
+/// `output`.
+///
+/// Return the number of datasets merged into the tiles.
+pub fn build_tiles<P>(folder: P, output: &Path, options: &TileOptions) -> Result<usize, Error>
+where
+    P: AsRef<Path>,
+{
+    let folder = folder.as_ref();
+    if options.min_zoom > options.max_zoom {
+        return Err(Error::Parse(format!(
+            "invalid zoom range {}-{}",
+            options.min_zoom, options.max_zoom
+        )));
+    }
+    let sources = ways_sources(folder)?;
+    if sources.is_empty() {
+        return Err(Error::Parse(format!(
+            "no neighborhood_ways dataset converted to GeoJSON in {}",
+            folder.display()
+        )));
+    }
+    let status = tippecanoe_command(&sources, output, options)
+        .status()
+        .map_err(|source| Error::TippecanoeSpawn {
+            program: options.tippecanoe.clone(),
+            source,
+        })?;
+    if !status.success() {
+        return Err(Error::TippecanoeStatus { status });
+    }
+
+    Ok(sources.len())
+}
@@ -0,0 +1,54 @@
+//! Format the timestamps recorded in the output files.
+//!
+//! The builds which must be reproducible set the `SOURCE_DATE_EPOCH`
+//! environment variable, following the
+//! [convention](https://reproducible-builds.org/specs/source-date-epoch/) of
+//! the reproducible builds: it replaces the current time in the manifests.
+use std::env;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Name of the environment variable overriding the current time.
+pub const SOURCE_DATE_EPOCH: &str = "SOURCE_DATE_EPOCH";
+
+/// Return the time set by `SOURCE_DATE_EPOCH`, in seconds since the Unix
+/// epoch, if any.
+pub fn source_date_epoch() -> Option<SystemTime> {
+    let seconds = env::var(SOURCE_DATE_EPOCH).ok()?.trim().parse().ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+/// Return the time recorded in the output files: the time set by
+/// `SOURCE_DATE_EPOCH`, or the current time.
+pub fn now() -> SystemTime {
+    source_date_epoch().unwrap_or_else(SystemTime::now)
+}
+
+/// Format a time as an RFC 3339 timestamp in UTC, with milliseconds.
+pub fn rfc3339(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (days, time_of_day) = (seconds / 86_400, seconds % 86_400);
+
+    // Convert the days since the epoch to a civil date.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        time_of_day / 3_600,
+        time_of_day % 3_600 / 60,
+        time_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
@@ -0,0 +1,234 @@
+//! Read the features of a `neighborhood_ways` dataset.
+//!
+//! The dataset describes the road network of a city, each feature being a
+//! road segment. Its main attributes are:
+//!
+//! | Attribute          | Shapefile field | Description                                  |
+//! | ------------------ | --------------- | -------------------------------------------- |
+//! | `road_id`          | `ROAD_ID`       | Identifier of the segment                    |
+//! | `osm_id`           | `OSM_ID`        | Identifier of the OpenStreetMap way          |
+//! | `name`             | `NAME`          | Name of the road                             |
+//! | `functional_class` | `FUNCTIONAL`    | Functional class, e.g. `primary`, `tertiary` |
+//! | `speed_limit`      | `SPEED_LIMI`    | Speed limit, in mph                          |
+//! | `one_way`          | `ONE_WAY`       | One-way direction for bikes (`ft` or `tf`)   |
+//! | `ft_bike_infra`    | `FT_BIKE_IN`    | Bike infrastructure, from-to direction       |
+//! | `tf_bike_infra`    | `TF_BIKE_IN`    | Bike infrastructure, to-from direction       |
+//! | `ft_lanes`         | `FT_LANES`      | Number of travel lanes, from-to direction    |
+//! | `tf_lanes`         | `TF_LANES`      | Number of travel lanes, to-from direction    |
+//! | `ft_seg_stress`    | `FT_SEG_STR`    | Segment stress, from-to direction            |
+//! | `ft_int_stress`    | `FT_INT_STR`    | Intersection stress, from-to direction       |
+//! | `tf_seg_stress`    | `TF_SEG_STR`    | Segment stress, to-from direction            |
+//! | `tf_int_stress`    | `TF_INT_STR`    | Intersection stress, to-from direction       |
+//! | `xwalk`            | `XWALK`         | Crosswalk at the intersection (`1` if any)   |
+//!
+//! The fields of the shapefiles are truncated to 10 characters, while the
+//! converted GeoJSON files may use either name: both are recognized, case
+//! insensitively.
+use crate::convert::to_json;
+use crate::Error;
+use geo_types::Geometry;
+use serde_json::{Map, Value};
+use shapefile::dbase::FieldValue;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Describe the stress level of a segment or an intersection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stress {
+    /// Low stress (1), comfortable for most people.
+    Low,
+    /// High stress (3).
+    High,
+}
+
+impl Stress {
+    /// Return the stress matching a PFB stress level.
+    pub fn from_level(level: i64) -> Option<Self> {
+        match level {
+            1 => Some(Stress::Low),
+            3 => Some(Stress::High),
+            _ => None,
+        }
+    }
+}
+
+/// Represent a road segment of a `neighborhood_ways` dataset.
+///
+/// The attributes which are missing or cannot be parsed are set to `None`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct NeighborhoodWay {
+    /// Identifier of the segment.
+    pub road_id: Option<i64>,
+    /// Identifier of the OpenStreetMap way.
+    pub osm_id: Option<i64>,
+    /// Name of the road.
+    pub name: Option<String>,
+    /// Functional class of the road, e.g. `primary` or `tertiary`.
+    pub functional_class: Option<String>,
+    /// Speed limit, in mph.
+    pub speed_limit: Option<i64>,
+    /// One-way direction for bikes, `ft` or `tf`.
+    pub one_way: Option<String>,
+    /// Bike infrastructure in the from-to direction, e.g. `lane`.
+    pub ft_bike_infra: Option<String>,
+    /// Bike infrastructure in the to-from direction.
+    pub tf_bike_infra: Option<String>,
+    /// Number of travel lanes in the from-to direction.
+    pub ft_lanes: Option<i64>,
+    /// Number of travel lanes in the to-from direction.
+    pub tf_lanes: Option<i64>,
+    /// Segment stress in the from-to direction.
+    pub ft_seg_stress: Option<Stress>,
+    /// Intersection stress in the from-to direction.
+    pub ft_int_stress: Option<Stress>,
+    /// Segment stress in the to-from direction.
+    pub tf_seg_stress: Option<Stress>,
+    /// Intersection stress in the to-from direction.
+    pub tf_int_stress: Option<Stress>,
+    /// `true` if there is a crosswalk at the intersection.
+    pub xwalk: Option<bool>,
+    /// Geometry of the segment, in the coordinate reference system of the
+    /// dataset.
+    pub geometry: Option<Geometry<f64>>,
+}
+
+impl NeighborhoodWay {
+    /// Create a segment from the attributes of a feature.
+    pub fn from_properties(
+        properties: &Map<String, Value>,
+        geometry: Option<Geometry<f64>>,
+    ) -> Self {
+        let get = |names: &[&str]| {
+            properties
+                .iter()
+                .find(|(key, _)| names.iter().any(|n| key.eq_ignore_ascii_case(n)))
+                .map(|(_, value)| value)
+                .filter(|value| !value.is_null())
+        };
+        let integer = |names: &[&str]| get(names).and_then(as_integer);
+        let string = |names: &[&str]| get(names).and_then(as_string);
+        let stress = |names: &[&str]| integer(names).and_then(Stress::from_level);
+
+        NeighborhoodWay {
+            road_id: integer(&["road_id"]),
+            osm_id: integer(&["osm_id"]),
+            name: string(&["name"]),
+            functional_class: string(&["functional_class", "functional"]),
+            speed_limit: integer(&["speed_limit", "speed_limi"]),
+            one_way: string(&["one_way"]),
+            ft_bike_infra: string(&["ft_bike_infra", "ft_bike_in"]),
+            tf_bike_infra: string(&["tf_bike_infra", "tf_bike_in"]),
+            ft_lanes: integer(&["ft_lanes"]),
+            tf_lanes: integer(&["tf_lanes"]),
+            ft_seg_stress: stress(&["ft_seg_stress", "ft_seg_str"]),
+            ft_int_stress: stress(&["ft_int_stress", "ft_int_str"]),
+            tf_seg_stress: stress(&["tf_seg_stress", "tf_seg_str"]),
+            tf_int_stress: stress(&["tf_int_stress", "tf_int_str"]),
+            xwalk: integer(&["xwalk"]).map(|x| x == 1),
+            geometry,
+        }
+    }
+
+    /// Return the stress of the segment, the highest of its two directions.
+    pub fn stress(&self) -> Option<Stress> {
+        self.ft_seg_stress.max(self.tf_seg_stress)
+    }
+}
+
+/// Read the segments of a `neighborhood_ways` dataset.
+///
+/// Both the extracted shapefiles and their GeoJSON conversions can be read.
+pub struct WaysReader {
+    source: Source,
+}
+
+/// Describe the format of a dataset.
+enum Source {
+    Shapefile(Box<shapefile::Reader<BufReader<File>, BufReader<File>>>),
+    GeoJson(Option<BufReader<File>>),
+}
+
+impl WaysReader {
+    /// Open the dataset located at `path`.
+    ///
+    /// The format is detected from the file extension, `.shp` for the
+    /// shapefiles and `.geojson` or `.json` for GeoJSON.
+    pub fn open<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let source = match extension.as_str() {
+            "shp" => Source::Shapefile(Box::new(shapefile::Reader::from_path(path)?)),
+            "geojson" | "json" => Source::GeoJson(Some(BufReader::new(File::open(path)?))),
+            _ => {
+                return Err(Error::Parse(format!(
+                    "unsupported dataset format: {}",
+                    path.display()
+                )))
+            }
+        };
+
+        Ok(WaysReader { source })
+    }
+
+    /// Iterate over the segments of the dataset.
+    ///
+    /// A GeoJSON dataset can only be iterated once.
+    pub fn ways(&mut self) -> Box<dyn Iterator<Item = Result<NeighborhoodWay, Error>> + '_> {
+        match &mut self.source {
+            Source::Shapefile(reader) => Box::new(reader.iter_shapes_and_records().map(|item| {
+                let (shape, record) = item?;
+                let record: HashMap<String, FieldValue> = record.into();
+                let properties = record
+                    .into_iter()
+                    .map(|(name, value)| (name, to_json(value)))
+                    .collect();
+                let geometry = Geometry::<f64>::try_from(shape).ok();
+                Ok(NeighborhoodWay::from_properties(&properties, geometry))
+            })),
+            Source::GeoJson(reader) => match reader.take() {
+                Some(reader) => {
+                    Box::new(geojson::FeatureReader::from_reader(reader).features().map(
+                        |feature| {
+                            let feature = feature.map_err(|e| {
+                                Error::Parse(format!("invalid GeoJSON feature: {}", e))
+                            })?;
+                            let geometry = feature
+                                .geometry
+                                .and_then(|g| Geometry::<f64>::try_from(g).ok());
+                            let properties = feature.properties.unwrap_or_default();
+                            Ok(NeighborhoodWay::from_properties(&properties, geometry))
+                        },
+                    ))
+                }
+                None => Box::new(std::iter::empty()),
+            },
+        }
+    }
+}
+
+/// Return the integer value of an attribute.
+fn as_integer(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(n) => n.as_i64().or_else(|| n.as_f64().map(|f| f as i64)),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Return the string value of an attribute.
+fn as_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) if !s.trim().is_empty() => Some(s.trim().to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
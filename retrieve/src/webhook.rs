@@ -0,0 +1,245 @@
+//! Post the summary of a batch to a webhook.
+//!
+//! The summary is posted as JSON, either as is to a generic endpoint, or
+//! formatted for the incoming webhooks of Slack (with
+//! [Block Kit](https://api.slack.com/block-kit) blocks) or Discord (with an
+//! embed). The format is detected from the host of the webhook URL unless it
+//! is given explicitly.
+use crate::download::{DownloadReport, DownloadStatus};
+use crate::http::HttpClient;
+use crate::Error;
+use reqwest::header::CONTENT_TYPE;
+use serde::Serialize;
+use serde_json::{json, Value};
+use url::Url;
+
+/// Maximum number of failures listed in a formatted message.
+const MAX_FAILURES: usize = 10;
+
+/// Define the formats of the webhook payloads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookFormat {
+    /// Post the [`RunSummary`] as is.
+    Generic,
+    /// Format the summary for a Slack incoming webhook.
+    Slack,
+    /// Format the summary for a Discord webhook.
+    Discord,
+}
+
+impl WebhookFormat {
+    /// Detect the format of a webhook from its URL.
+    pub fn detect(url: &Url) -> Self {
+        match url.host_str() {
+            Some("hooks.slack.com") => WebhookFormat::Slack,
+            Some(host)
+                if (host == "discord.com" || host.ends_with(".discord.com"))
+                    && url.path().starts_with("/api/webhooks/") =>
+            {
+                WebhookFormat::Discord
+            }
+            _ => WebhookFormat::Generic,
+        }
+    }
+}
+
+/// Describe a failed download.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FailureSummary {
+    /// City of the download.
+    pub city: String,
+    /// Name of the dataset.
+    pub dataset: String,
+    /// Reason of the failure.
+    pub reason: String,
+}
+
+/// Summarize a batch.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct RunSummary {
+    /// Number of files downloaded.
+    pub downloaded: usize,
+    /// Number of files restored from the cache or already present.
+    pub cached: usize,
+    /// Number of failed downloads.
+    pub failed: usize,
+    /// Number of interrupted downloads.
+    pub interrupted: usize,
+    /// Number of downloads skipped because the time budget of their city was
+    /// exhausted.
+    pub timed_out: usize,
+    /// Number of bytes downloaded.
+    pub bytes: u64,
+    /// Duration of the batch, in seconds.
+    pub duration_s: f64,
+    /// Failed downloads.
+    pub failures: Vec<FailureSummary>,
+    /// Error which aborted the batch, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl RunSummary {
+    /// Summarize the downloads of a report.
+    pub fn from_report(report: &DownloadReport) -> Self {
+        let mut summary = RunSummary {
+            bytes: report.total_bytes(),
+            duration_s: report.elapsed.as_secs_f64(),
+            ..Default::default()
+        };
+        for outcome in &report.outcomes {
+            match &outcome.status {
+                DownloadStatus::Downloaded => summary.downloaded += 1,
+                DownloadStatus::Cached => summary.cached += 1,
+                DownloadStatus::Interrupted => summary.interrupted += 1,
+                DownloadStatus::TimedOut => summary.timed_out += 1,
+                DownloadStatus::Failed(reason) => {
+                    summary.failed += 1;
+                    summary.failures.push(FailureSummary {
+                        city: outcome.download.city.to_string(),
+                        dataset: outcome.download.dataset.to_string(),
+                        reason: reason.clone(),
+                    });
+                }
+            }
+        }
+        summary
+    }
+
+    /// Summarize a batch aborted by an `error`.
+    pub fn from_error(error: &str) -> Self {
+        RunSummary {
+            error: Some(error.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Return `true` if the batch was aborted or some downloads failed.
+    pub fn is_failure(&self) -> bool {
+        self.error.is_some() || self.failed > 0
+    }
+
+    /// Return the headline of the summary.
+    pub fn title(&self) -> String {
+        match (&self.error, self.failed) {
+            (Some(_), _) => String::from("retrieve: batch failed"),
+            (None, 0) => format!(
+                "retrieve: {} files retrieved",
+                self.downloaded + self.cached
+            ),
+            (None, failed) => format!(
+                "retrieve: {} of {} files failed",
+                failed,
+                self.downloaded + self.cached + failed + self.interrupted + self.timed_out
+            ),
+        }
+    }
+
+    /// Return the counts, the number of bytes and the duration, as one line of
+    /// text.
+    fn totals(&self) -> String {
+        format!(
+            "{} downloaded, {} cached, {} failed, {} interrupted, {} timed out. {} bytes in {:.1}s.",
+            self.downloaded,
+            self.cached,
+            self.failed,
+            self.interrupted,
+            self.timed_out,
+            self.bytes,
+            self.duration_s
+        )
+    }
+
+    /// Return the failures, or the error which aborted the batch, as a list,
+    /// truncated to [`MAX_FAILURES`] lines.
+    fn details(&self) -> Option<String> {
+        if let Some(error) = &self.error {
+            return Some(error.clone());
+        }
+        if self.failures.is_empty() {
+            return None;
+        }
+        let mut lines: Vec<String> = self
+            .failures
+            .iter()
+            .take(MAX_FAILURES)
+            .map(|f| format!("• {} for {}: {}", f.dataset, f.city, f.reason))
+            .collect();
+        if self.failures.len() > MAX_FAILURES {
+            lines.push(format!("… and {} more", self.failures.len() - MAX_FAILURES));
+        }
+        Some(lines.join("\n"))
+    }
+
+    /// Build the payload of the summary in a webhook `format`.
+    pub fn payload(&self, format: WebhookFormat) -> Result<Value, Error> {
+        let payload = match format {
+            WebhookFormat::Generic => serde_json::to_value(self)?,
+            WebhookFormat::Slack => {
+                let mut blocks = vec![
+                    json!({
+                        "type": "header",
+                        "text": {"type": "plain_text", "text": self.title()},
+                    }),
+                    json!({
+                        "type": "section",
+                        "text": {"type": "mrkdwn", "text": self.totals()},
+                    }),
+                ];
+                if let Some(details) = self.details() {
+                    blocks.push(json!({
+                        "type": "section",
+                        "text": {"type": "mrkdwn", "text": details},
+                    }));
+                }
+                json!({"text": self.title(), "blocks": blocks})
+            }
+            WebhookFormat::Discord => {
+                let color = if self.is_failure() {
+                    0xcf222e
+                } else {
+                    0x1a7f37
+                };
+                let mut fields = vec![
+                    json!({"name": "Downloaded", "value": self.downloaded.to_string(), "inline": true}),
+                    json!({"name": "Cached", "value": self.cached.to_string(), "inline": true}),
+                    json!({"name": "Failed", "value": self.failed.to_string(), "inline": true}),
+                    json!({"name": "Bytes", "value": self.bytes.to_string(), "inline": true}),
+                    json!({"name": "Duration", "value": format!("{:.1}s", self.duration_s), "inline": true}),
+                ];
+                if let Some(details) = self.details() {
+                    fields.push(json!({"name": "Failures", "value": details}));
+                }
+                json!({
+                    "embeds": [{
+                        "title": self.title(),
+                        "color": color,
+                        "fields": fields,
+                    }]
+                })
+            }
+        };
+
+        Ok(payload)
+    }
+}
+
+/// Post a summary to the webhook located at `url`, with `client`.
+///
+/// The format is detected from the URL if `format` is `None`.
+pub async fn post_summary(
+    client: &HttpClient,
+    url: &Url,
+    format: Option<WebhookFormat>,
+    summary: &RunSummary,
+) -> Result<(), Error> {
+    let format = format.unwrap_or_else(|| WebhookFormat::detect(url));
+    let payload = summary.payload(format)?;
+    let request = client
+        .post(url)
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::to_vec(&payload)?);
+    client.send(request).await?.error_for_status()?;
+
+    Ok(())
+}